@@ -3,8 +3,17 @@ use std::{env, error::Error};
 use dotenv::dotenv;
 pub struct DBConfig{}
 
+// database of the default academy, also holds the tenant registry
+pub const DEFAULT_DATABASE:&str = "k_admin";
+
 impl DBConfig {
     pub async fn init() -> Result<Database, Box<dyn Error>> {
+        let client = Self::client().await?;
+        Ok(client.database(DEFAULT_DATABASE))
+    }
+
+    // every tenant database is opened from this one client
+    pub async fn client() -> Result<Client, Box<dyn Error>> {
         dotenv().ok();
 
         let uri = match env::var("MONGOURI") {
@@ -12,8 +21,7 @@ impl DBConfig {
             Err(err) => return Err(Box::new(err)),
         };
         let client = Client::with_uri_str(uri).await?;
-        let db = client.database("k_admin");
         println!("Connection has been established");
-        Ok(db)
+        Ok(client)
     }
 }
\ No newline at end of file
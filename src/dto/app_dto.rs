@@ -1,23 +1,104 @@
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde::de::{self, Visitor};
-use crate::models::app::{Branches, Courses, Enquiries, Facilities, Fees};
+use crate::models::app::{Amenity, BranchAddress, Branches, HolidayClosure, OperatingHours, Courses, EnrollmentStatus, Enrollments, Enquiries, EnquiryFollowUp, EnquiryNote, EnquirySource, EnquiryStatus, Facilities, FacilityImage, Fees};
+use crate::models::finance::TaxConfig;
+use crate::models::money::{format_rate, parse_rate, Discount, DiscountKind};
+use crate::helper::app_errors::AppError;
+use crate::helper::storage;
+use crate::models::media::ImageVariants;
+use crate::models::settings::lookup_key;
 use std::fmt::{self};
 use validator::Validate;
 use chrono::prelude::*;
 
 
-#[derive(Serialize, Deserialize)]
+lazy_static! {
+    static ref BRANCH_CODE_REGEX: Regex = Regex::new(r"^[A-Z0-9]{2,10}$").unwrap();
+    static ref PHONE_REGEX: Regex = Regex::new(r"^\+?[0-9 -]{7,20}$").unwrap();
+}
+
+#[derive(Serialize, Deserialize, Validate)]
 pub struct CreateBranchDTO{
     pub name:String,
     pub address:String,
     pub is_active:bool,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    #[validate(regex(path="BRANCH_CODE_REGEX", message="code must be 2 to 10 upper case letters or digits"))]
+    pub code:Option<String>,
+    #[validate]
+    pub location:Option<GeoLocationDTO>,
+    pub postal_address:Option<BranchAddress>,
+    #[validate(regex(path="PHONE_REGEX", message="Invalid phone number"))]
+    pub phone:Option<String>,
+    #[validate(email)]
+    pub email:Option<String>,
+    pub hours:Option<Vec<OperatingHours>>,
+    pub holidays:Option<Vec<HolidayClosure>>
+}
+
+impl CreateBranchDTO {
+    // checks what the derive can not, slot times and closure dates
+    pub fn validate_schedule(&self) -> Result<(), AppError> {
+        for slot in self.hours.iter().flatten() {
+            let opens = NaiveTime::parse_from_str(&slot.opens, "%H:%M");
+            let closes = NaiveTime::parse_from_str(&slot.closes, "%H:%M");
+            match (opens, closes) {
+                (Ok(opens), Ok(closes)) if opens < closes => {},
+                (Ok(_), Ok(_)) => return Err(AppError::CustomError(format!("{} closes before it opens", slot.day))),
+                _ => return Err(AppError::CustomError(format!("{} hours must be HH:MM", slot.day))),
+            }
+        }
+
+        for holiday in self.holidays.iter().flatten() {
+            let from = NaiveDate::parse_from_str(&holiday.from, "%Y-%m-%d");
+            let to = NaiveDate::parse_from_str(&holiday.to, "%Y-%m-%d");
+            match (from, to) {
+                (Ok(from), Ok(to)) if from <= to => {},
+                (Ok(_), Ok(_)) => return Err(AppError::CustomError(format!("closure from {} ends before it starts", holiday.from))),
+                _ => return Err(AppError::CustomError("closure dates must be YYYY-MM-DD".to_string())),
+            }
+        }
+
+        if let Some(address) = &self.postal_address {
+            if [&address.line1, &address.city, &address.state, &address.postal_code, &address.country].iter().any(|v| v.trim().is_empty()) {
+                return Err(AppError::CustomError("postal address is incomplete".to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate, Clone, Copy)]
+pub struct GeoLocationDTO {
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub lat:f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub lng:f64
 }
+
 #[derive(Serialize, Deserialize)]
 pub struct GetBranchDTO {
     pub id:String,
     pub name:String,
     pub address:String,
     pub is_active:bool,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub code:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub location:Option<GeoLocationDTO>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub postal_address:Option<BranchAddress>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub phone:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub email:Option<String>,
+    pub hours:Vec<OperatingHours>,
+    pub holidays:Vec<HolidayClosure>,
     pub created_at:String,
     pub updated_at:String
 }
@@ -29,12 +110,36 @@ impl GetBranchDTO {
             name: branch.name,
             address: branch.address,
             is_active: branch.is_active,
+            timezone: branch.timezone,
+            code: branch.code,
+            location: branch.location.map(|l| GeoLocationDTO { lat: l.lat(), lng: l.lng() }),
+            postal_address: branch.postal_address,
+            phone: branch.phone,
+            email: branch.email,
+            hours: branch.hours,
+            holidays: branch.holidays,
             created_at: branch.created_at.to_string(),
             updated_at: branch.updated_at.to_string(),
         }
     }
 }
 
+#[derive(Deserialize)]
+pub struct NearbyBranchQueryDTO {
+    pub lat:f64,
+    pub lng:f64,
+    // search radius, 50 km when left out
+    pub max_km:Option<f64>,
+    pub limit:Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct NearbyBranchDTO {
+    #[serde(flatten)]
+    pub branch:GetBranchDTO,
+    pub distance_km:f64
+}
+
 #[derive(Serialize, Deserialize)]
 #[allow(non_snake_case)]
 pub struct AppCountDTO {
@@ -46,90 +151,209 @@ pub struct AppCountDTO {
 }
 #[derive(Serialize, Deserialize)]
 pub struct CreateFeesDTO {
-    #[serde(deserialize_with="deserialize_fee_types")]
-    pub fee_type:FeeTypes,
-    pub fee_amount:i64,
-    pub fee_discount:f64
+    // a fee cycle key of the academy's settings
+    pub fee_type:String,
+    #[serde(deserialize_with="deserialize_amount")]
+    pub fee_amount:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub currency:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fee_discount:Option<CreateDiscountDTO>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub tax:Option<CreateTaxDTO>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateTaxDTO {
+    pub hsn_sac:String,
+    #[serde(deserialize_with="deserialize_amount")]
+    pub cgst_rate:String,
+    #[serde(deserialize_with="deserialize_amount")]
+    pub sgst_rate:String,
+    #[serde(deserialize_with="deserialize_amount")]
+    pub igst_rate:String
+}
+
+impl CreateTaxDTO {
+    pub fn to_tax_config(&self) -> Result<TaxConfig, AppError> {
+        let hsn_sac = self.hsn_sac.trim();
+        if hsn_sac.len() < 4 || hsn_sac.len() > 8 || !hsn_sac.chars().all(|c| c.is_ascii_digit()) {
+            return Err(AppError::CustomError("HSN/SAC code must be 4 to 8 digits".to_string()));
+        }
+
+        let tax = TaxConfig {
+            hsn_sac: hsn_sac.to_string(),
+            cgst_rate: parse_rate(&self.cgst_rate)?,
+            sgst_rate: parse_rate(&self.sgst_rate)?,
+            igst_rate: parse_rate(&self.igst_rate)?,
+        };
+
+        if tax.cgst_rate != tax.sgst_rate || tax.igst_rate != tax.cgst_rate + tax.sgst_rate {
+            return Err(AppError::CustomError("CGST and SGST must be equal and add up to IGST".to_string()));
+        }
+
+        Ok(tax)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-pub enum FeeTypes {
-    MONTHLY,
-    YEARLY,
-    THREEMONTH,
-    SIXMONTH
+pub struct CreateDiscountDTO {
+    #[serde(deserialize_with="deserialize_discount_kind")]
+    pub discount_type:DiscountKind,
+    #[serde(deserialize_with="deserialize_amount")]
+    pub value:String
+}
+
+impl CreateDiscountDTO {
+    pub fn to_discount(&self) -> Result<Discount, AppError> {
+        match self.discount_type {
+            DiscountKind::PERCENT => Discount::percent(&self.value),
+            DiscountKind::FIXED => Discount::fixed(&self.value),
+        }
+    }
 }
 
-fn deserialize_fee_types<'de, D>(deserializer: D) -> Result<FeeTypes, D::Error>
+// amounts are accepted as decimal strings ("1500.50") or whole numbers, never as floats
+pub(crate) fn deserialize_amount<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    struct StudentLevelVisitor;
+    struct AmountVisitor;
 
-    impl<'de> Visitor<'de> for StudentLevelVisitor {
-        type Value = FeeTypes;
+    impl<'de> Visitor<'de> for AmountVisitor {
+        type Value = String;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("a valid student level string")
+            formatter.write_str("a decimal amount string or a whole number")
         }
 
-        fn visit_str<E>(self, value: &str) -> Result<FeeTypes, E>
+        fn visit_str<E>(self, value: &str) -> Result<String, E>
         where
             E: de::Error,
         {
-            match value {
-                "monthly" => Ok(FeeTypes::MONTHLY),
-                "yearly" => Ok(FeeTypes::YEARLY),
-                "three_month" => Ok(FeeTypes::THREEMONTH),
-                "six_month" => Ok(FeeTypes::SIXMONTH),
-                _ => Err(E::custom(format!("Invalid Student Level: {}", value))),
+            Ok(value.to_string())
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<String, E>
+        where
+            E: de::Error,
+        {
+            Ok(value.to_string())
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<String, E>
+        where
+            E: de::Error,
+        {
+            if value < 0 {
+                return Err(E::custom(format!("Invalid amount: {}", value)));
             }
+            Ok(value.to_string())
+        }
+
+        fn visit_f64<E>(self, value: f64) -> Result<String, E>
+        where
+            E: de::Error,
+        {
+            Err(E::custom(format!("Amount {} must be sent as a string", value)))
         }
     }
 
-    deserializer.deserialize_str(StudentLevelVisitor)
+    deserializer.deserialize_any(AmountVisitor)
 }
 
+fn deserialize_discount_kind<'de, D>(deserializer: D) -> Result<DiscountKind, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct DiscountKindVisitor;
 
-impl Default for FeeTypes {
-    fn default() -> Self {
-        FeeTypes::MONTHLY
-    }
-}
+    impl<'de> Visitor<'de> for DiscountKindVisitor {
+        type Value = DiscountKind;
 
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a valid discount type string")
+        }
 
-impl ToString for FeeTypes {
-    fn to_string(&self) -> String {
-        match self {
-            FeeTypes::MONTHLY => String::from("MONTHLY"),
-            FeeTypes::YEARLY => String::from("YEARLY"),
-            FeeTypes::THREEMONTH => String::from("THREEMONTH"),
-            FeeTypes::SIXMONTH => String::from("SIXMONTH"),
+        fn visit_str<E>(self, value: &str) -> Result<DiscountKind, E>
+        where
+            E: de::Error,
+        {
+            match value {
+                "percent" | "PERCENT" => Ok(DiscountKind::PERCENT),
+                "fixed" | "FIXED" => Ok(DiscountKind::FIXED),
+                _ => Err(E::custom(format!("Invalid discount type: {}", value))),
+            }
         }
     }
-}
 
+    deserializer.deserialize_str(DiscountKindVisitor)
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct FeesDTO {
     pub id:String,
     pub fee_type:String,
-    pub fee_amount:i64,
+    pub fee_amount:String,
+    pub currency:String,
     pub is_discount:bool,
-    pub fee_discount:f64,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fee_discount:Option<DiscountDTO>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub discount_review:Option<String>,
+    pub net_amount:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub tax:Option<TaxDTO>,
     pub created_at:String,
     pub updated_at:String
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct TaxDTO {
+    pub hsn_sac:String,
+    pub cgst_rate:String,
+    pub sgst_rate:String,
+    pub igst_rate:String
+}
+
+impl TaxDTO {
+    pub fn init(tax:TaxConfig) -> Self {
+        TaxDTO {
+            hsn_sac: tax.hsn_sac,
+            cgst_rate: format_rate(tax.cgst_rate),
+            sgst_rate: format_rate(tax.sgst_rate),
+            igst_rate: format_rate(tax.igst_rate),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiscountDTO {
+    pub discount_type:String,
+    pub value:String,
+    pub discount_amount:String
+}
+
 #[allow(non_snake_case)]
 impl FeesDTO {
     pub fn init(feeModel:Fees) -> Self {
+        let net_amount = feeModel.net_amount().to_decimal_string();
+        let fee_discount = feeModel.fee_discount.as_ref().map(|discount| DiscountDTO {
+            discount_type: discount.kind.to_string(),
+            value: discount.value_string(),
+            discount_amount: discount.amount_off(&feeModel.fee_amount).to_decimal_string(),
+        });
+
         Self {
             id: feeModel.id.unwrap().to_string(),
             fee_type: feeModel.fee_type,
-            fee_amount: feeModel.fee_amount,
+            fee_amount: feeModel.fee_amount.to_decimal_string(),
+            currency: feeModel.fee_amount.currency,
             is_discount: feeModel.is_discount,
-            fee_discount: feeModel.fee_discount,
+            fee_discount,
+            discount_review: feeModel.discount_review,
+            net_amount,
+            tax: feeModel.tax.map(TaxDTO::init),
             created_at: feeModel.created_at.to_string(),
             updated_at: feeModel.updated_at.to_string(),
         }
@@ -160,6 +384,9 @@ pub struct CoursesDTO {
     pub description:String,
     pub course_duration:String,
     pub is_active:bool,
+    pub branch_capacity:Vec<BranchCapacityDTO>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub prerequisites:Option<CoursePrerequisitesDTO>,
     pub created_at:String,
     pub updated_at:String
 }
@@ -171,20 +398,142 @@ impl CoursesDTO {
             name: course.name.to_string(),
             description: course.description.to_string(),
             course_duration: course.course_duration.to_string(),
+            branch_capacity: course.branch_capacity.iter().map(|c| BranchCapacityDTO {
+                branch_id: c.branch_id.to_hex(),
+                capacity: c.capacity,
+            }).collect(),
+            prerequisites: course.prerequisites.map(|p| CoursePrerequisitesDTO {
+                min_level: p.min_level,
+                min_age: p.min_age,
+                max_age: p.max_age,
+                completed_courses: p.completed_courses.iter().map(|c| c.to_hex()).collect(),
+            }),
             created_at: course.created_at.to_string(),
             updated_at: course.updated_at.to_string(),
             is_active: course.is_active,
         }
     }
 }
+#[derive(Serialize, Deserialize, Validate)]
+pub struct BranchCapacityDTO {
+    #[validate(length(min=1, message="branch_id can not be empty"))]
+    pub branch_id:String,
+    #[validate(range(min=0, message="capacity can not be negative"))]
+    pub capacity:i64
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CoursePrerequisitesDTO {
+    // belt name such as "green"
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub min_level:Option<String>,
+    #[validate(range(min=0, message="min_age can not be negative"))]
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub min_age:Option<i64>,
+    #[validate(range(min=0, message="max_age can not be negative"))]
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub max_age:Option<i64>,
+    #[serde(default)]
+    pub completed_courses:Vec<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EligibleCourseDTO {
+    pub id:String,
+    pub name:String,
+    pub eligible:bool,
+    // prerequisites the student does not meet
+    pub unmet:Vec<String>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateEnrollmentDTO {
+    #[validate(required, length(min=1, message="student_id can not be empty"))]
+    pub student_id:Option<String>,
+    #[validate(required, length(min=1, message="course_id can not be empty"))]
+    pub course_id:Option<String>,
+    #[validate(required, length(min=1, message="branch_id can not be empty"))]
+    pub branch_id:Option<String>,
+    // YYYY-MM-DD
+    #[validate(required)]
+    pub start_date:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub end_date:Option<String>,
+    // admins may enroll a student who does not meet the prerequisites
+    #[serde(default)]
+    pub override_prerequisites:bool
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CloseEnrollmentDTO {
+    #[serde(deserialize_with="deserialize_closed_status")]
+    pub status:EnrollmentStatus
+}
+
+fn deserialize_closed_status<'de, D>(deserializer: D) -> Result<EnrollmentStatus, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    match value.to_lowercase().as_str() {
+        "completed" => Ok(EnrollmentStatus::COMPLETED),
+        "dropped" => Ok(EnrollmentStatus::DROPPED),
+        _ => Err(de::Error::custom(format!("Invalid enrollment status: {}", value))),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnrollmentQueryDTO {
+    pub student_id:Option<String>,
+    pub course_id:Option<String>,
+    pub branch_id:Option<String>,
+    pub status:Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnrollmentDTO {
+    pub id:String,
+    pub student_id:String,
+    pub course_id:String,
+    pub branch_id:String,
+    pub start_date:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub end_date:Option<String>,
+    pub status:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub prerequisite_override_by:Option<String>,
+    pub created_at:String,
+    pub updated_at:String
+}
+
+impl EnrollmentDTO {
+    pub fn init(enrollment:Enrollments) -> Self {
+        EnrollmentDTO {
+            id: enrollment.id.unwrap().to_hex(),
+            student_id: enrollment.student_id.to_hex(),
+            course_id: enrollment.course_id.to_hex(),
+            branch_id: enrollment.branch_id.to_hex(),
+            start_date: enrollment.start_date.to_string(),
+            end_date: enrollment.end_date.map(|e| e.to_string()),
+            status: enrollment.status,
+            prerequisite_override_by: enrollment.prerequisite_override_by,
+            created_at: enrollment.created_at.to_string(),
+            updated_at: enrollment.updated_at.to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Validate)]
 pub struct CreateFacilities {
     #[validate(required,length(min=1,message="title can not be empty"))]
     pub title:Option<String>,
     #[validate(required, length(min=1,message="description can not be empty"))]
     pub description:Option<String>,
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub image_url:Option<String>,
+    // branch ids the facility is part of
+    pub branches:Option<Vec<String>>,
+    pub amenities:Option<Vec<Amenity>>,
+    #[validate(length(max=1000, message="availability notes can be at most 1000 characters"))]
+    pub availability_notes:Option<String>,
 }
 
 
@@ -195,10 +544,37 @@ pub struct FacilitiesDTO {
     pub title:String,
     pub description:String,
     pub imageUrl:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub image_variants:Option<ImageVariants>,
+    pub branches:Vec<String>,
+    pub gallery:Vec<FacilityImageDTO>,
+    pub amenities:Vec<Amenity>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub availability_notes:Option<String>,
     pub created_at:String,
     pub updated_at:String
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct FacilityImageDTO {
+    pub id:String,
+    pub url:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub variants:Option<ImageVariants>,
+    pub created_at:String
+}
+
+impl FacilityImageDTO {
+    pub fn init(image:FacilityImage) -> Self {
+        FacilityImageDTO {
+            id: image.id.to_hex(),
+            url: storage::file_url(&image.key),
+            variants: image.variants.map(storage::variant_urls),
+            created_at: image.created_at.to_string(),
+        }
+    }
+}
+
 #[allow(non_snake_case)]
 impl FacilitiesDTO {
     
@@ -208,12 +584,17 @@ impl FacilitiesDTO {
             title: facilities.title,
             description: facilities.description,
             imageUrl: None,
+            image_variants: facilities.image_variants.map(storage::variant_urls),
+            branches: facilities.branches.iter().map(|id| id.to_hex()).collect(),
+            gallery: facilities.gallery.into_iter().map(FacilityImageDTO::init).collect(),
+            amenities: facilities.amenities,
+            availability_notes: facilities.availability_notes,
             created_at: facilities.created_at.to_string(),
             updated_at: facilities.updated_at.to_string(),
         };
 
-        if !facilities.imageUrl.is_none() {
-            f.imageUrl = Some(format!("http://192.168.0.119:8000{}",facilities.imageUrl.unwrap().to_string()))
+        if let Some(imageUrl) = &facilities.imageUrl {
+            f.imageUrl = Some(storage::file_url(imageUrl))
         };
 
         f
@@ -225,7 +606,97 @@ pub struct CreateEnquiryDTO {
     pub email:String,
     pub contact:String,
     pub subject:String,
-    pub message:String
+    pub message:String,
+    pub branch:Option<String>,
+    #[serde(default, deserialize_with="deserialize_enquiry_source")]
+    pub source:Option<EnquirySource>,
+    // the child's name when a parent enquires
+    pub student_name:Option<String>
+}
+
+// "walk-in", "Walk In" and "WALK_IN" are one source
+fn deserialize_enquiry_source<'de, D>(deserializer: D) -> Result<Option<EnquirySource>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = match Option::<String>::deserialize(deserializer)? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    match lookup_key(&value).as_str() {
+        "walkin" => Ok(Some(EnquirySource::WALKIN)),
+        "website" => Ok(Some(EnquirySource::WEBSITE)),
+        "instagram" => Ok(Some(EnquirySource::INSTAGRAM)),
+        "referral" => Ok(Some(EnquirySource::REFERRAL)),
+        _ => Err(de::Error::custom(format!("Invalid enquiry source: {}", value))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateEnquiryStatusDTO {
+    #[serde(deserialize_with="deserialize_enquiry_status")]
+    pub status:EnquiryStatus,
+    // why the enquiry was lost, required for LOST
+    pub reason:Option<String>,
+    // date and time of the trial class, required for TRIAL_SCHEDULED
+    pub trial_at:Option<String>
+}
+
+fn deserialize_enquiry_status<'de, D>(deserializer: D) -> Result<EnquiryStatus, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    match lookup_key(&value).as_str() {
+        "new" => Ok(EnquiryStatus::NEW),
+        "contacted" => Ok(EnquiryStatus::CONTACTED),
+        "trialscheduled" => Ok(EnquiryStatus::TRIALSCHEDULED),
+        "converted" => Ok(EnquiryStatus::CONVERTED),
+        "lost" => Ok(EnquiryStatus::LOST),
+        _ => Err(de::Error::custom(format!("Invalid enquiry status: {}", value))),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AssignEnquiryDTO {
+    pub user_id:String
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateFollowUpDTO {
+    pub due_at:String,
+    #[validate(length(min=1, max=500, message="note must be 1 to 500 characters"))]
+    pub note:String,
+    // staff user id, defaults to the user the enquiry is assigned to
+    pub assigned_to:Option<String>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct AddEnquiryNoteDTO {
+    #[validate(length(min=1, max=2000, message="note must be 1 to 2000 characters"))]
+    pub text:String
+}
+
+// Fields the enquiry does not have, the rest is taken from it. The registration
+// is completed later like any PENDING one.
+#[derive(Serialize, Deserialize, Validate)]
+pub struct ConvertEnquiryDTO {
+    #[validate(length(min=1, message="student name can not be empty"))]
+    pub student_name:Option<String>,
+    pub age:Option<i64>,
+    pub date_of_birth:Option<String>,
+    pub address:Option<String>,
+    // branch id, defaults to the branch the enquiry was for
+    pub class_branch:Option<String>,
+    // guardian's number when the enquiry contact is not one
+    pub guardian_mobile:Option<i64>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnquiryQueryDTO {
+    pub status:Option<String>,
+    pub source:Option<String>,
+    pub assigned_to:Option<String>
 }
 
 #[derive(Serialize, Deserialize)]
@@ -236,6 +707,25 @@ pub struct EnquiriesDTO {
     pub contact:String,
     pub subject:String,
     pub message:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub branch:Option<String>,
+    pub status:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub lost_reason:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub trial_at:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub source:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub assigned_to:Option<String>,
+    pub follow_ups:Vec<EnquiryFollowUpDTO>,
+    pub notes:Vec<EnquiryNoteDTO>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub student_name:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub student_id:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub converted_at:Option<String>,
     pub created_at:String,
     pub updated_at:String
 }
@@ -253,8 +743,109 @@ impl EnquiriesDTO {
             contact: enquire.contact,
             subject: enquire.subject,
             message: enquire.message,
+            branch: enquire.branch,
+            status: enquire.status,
+            lost_reason: enquire.lost_reason,
+            trial_at: enquire.trial_at.map(|t| t.to_string()),
+            source: enquire.source,
+            assigned_to: enquire.assigned_to,
+            follow_ups: enquire.follow_ups.into_iter().map(EnquiryFollowUpDTO::init).collect(),
+            notes: enquire.notes.into_iter().map(EnquiryNoteDTO::init).collect(),
+            student_name: enquire.student_name,
+            student_id: enquire.student_id.map(|id| id.to_hex()),
+            converted_at: enquire.converted_at.map(|c| c.to_string()),
             created_at: date_only.to_string(),
             updated_at: enquire.updated_at.to_string()
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnquiryFollowUpDTO {
+    pub id:String,
+    pub due_at:String,
+    pub note:String,
+    pub assigned_to:String,
+    pub created_by:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub done_at:Option<String>
+}
+
+impl EnquiryFollowUpDTO {
+    pub fn init(follow_up:EnquiryFollowUp) -> Self {
+        EnquiryFollowUpDTO {
+            id: follow_up.id.to_hex(),
+            due_at: follow_up.due_at.to_string(),
+            note: follow_up.note,
+            assigned_to: follow_up.assigned_to,
+            created_by: follow_up.created_by,
+            done_at: follow_up.done_at.map(|d| d.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnquiryNoteDTO {
+    pub id:String,
+    pub text:String,
+    pub author:String,
+    pub created_at:String
+}
+
+impl EnquiryNoteDTO {
+    pub fn init(note:EnquiryNote) -> Self {
+        EnquiryNoteDTO {
+            id: note.id.to_hex(),
+            text: note.text,
+            author: note.author,
+            created_at: note.created_at.to_string(),
+        }
+    }
+}
+
+// an open follow-up of the caller with the enquiry it belongs to
+#[derive(Serialize, Deserialize)]
+pub struct DueFollowUpDTO {
+    pub enquiry_id:String,
+    pub name:String,
+    pub contact:String,
+    pub status:String,
+    pub follow_up:EnquiryFollowUpDTO,
+    // due before today
+    pub overdue:bool
+}
+
+// query of a signed private file url
+#[derive(Serialize, Deserialize)]
+pub struct SignedFileQueryDTO {
+    pub expires:i64,
+    pub signature:String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileGcQueryDTO {
+    // nothing is deleted unless dry_run=false is passed
+    pub dry_run:Option<bool>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrphanFileDTO {
+    pub key:String,
+    pub size:u64,
+    pub modified:String,
+    // older than the grace period
+    pub expired:bool
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileGcReportDTO {
+    pub dry_run:bool,
+    pub grace_hours:i64,
+    pub scanned:usize,
+    pub referenced:usize,
+    // stored but not referenced by any document
+    pub orphans:Vec<OrphanFileDTO>,
+    // referenced by a document but not in storage
+    pub missing:Vec<String>,
+    pub deleted:usize
+}
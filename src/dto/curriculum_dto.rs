@@ -0,0 +1,144 @@
+use serde::{de, Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::curriculum::{Curriculums, SyllabusCategory, SyllabusItem, SyllabusProgress};
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateCurriculumDTO {
+    #[validate(length(min=1, message="course_id can not be empty"))]
+    pub course_id:String,
+    // a belt key of the academy's ladder
+    pub level:String,
+    #[validate(length(min=1, message="syllabus needs at least one item"))]
+    pub items:Vec<CreateSyllabusItemDTO>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateSyllabusItemDTO {
+    #[serde(deserialize_with="deserialize_category")]
+    pub category:SyllabusCategory,
+    pub name:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub description:Option<String>
+}
+
+fn deserialize_category<'de, D>(deserializer: D) -> Result<SyllabusCategory, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    match value.to_lowercase().as_str() {
+        "kata" => Ok(SyllabusCategory::KATA),
+        "kihon" => Ok(SyllabusCategory::KIHON),
+        "kumite" => Ok(SyllabusCategory::KUMITE),
+        "theory" => Ok(SyllabusCategory::THEORY),
+        _ => Err(de::Error::custom(format!("Invalid syllabus category: {}", value))),
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct TickProgressDTO {
+    #[validate(required, length(min=1, message="student_id can not be empty"))]
+    pub student_id:Option<String>,
+    #[validate(required, length(min=1, message="course_id can not be empty"))]
+    pub course_id:Option<String>,
+    #[validate(required, length(min=1, message="item_id can not be empty"))]
+    pub item_id:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub notes:Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CurriculumDTO {
+    pub id:String,
+    pub course_id:String,
+    pub level:String,
+    pub version:i64,
+    pub is_current:bool,
+    pub items:Vec<SyllabusItem>,
+    pub created_by:String,
+    pub created_at:String
+}
+
+impl CurriculumDTO {
+    pub fn init(curriculum:Curriculums) -> Self {
+        CurriculumDTO {
+            id: curriculum.id.unwrap().to_hex(),
+            course_id: curriculum.course_id.to_hex(),
+            level: curriculum.level,
+            version: curriculum.version,
+            is_current: curriculum.is_current,
+            items: curriculum.items,
+            created_by: curriculum.created_by,
+            created_at: curriculum.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProgressItemDTO {
+    pub item_id:String,
+    pub category:String,
+    pub name:String,
+    pub completed:bool,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub completed_by:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub completed_at:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub notes:Option<String>
+}
+
+// A student's standing against the current syllabus of their belt.
+#[derive(Serialize, Deserialize)]
+pub struct StudentProgressDTO {
+    pub student_id:String,
+    pub course_id:String,
+    pub level:String,
+    pub curriculum_version:i64,
+    pub completed:usize,
+    pub total:usize,
+    pub items:Vec<ProgressItemDTO>
+}
+
+impl StudentProgressDTO {
+    pub fn init(student_id:String, curriculum:&Curriculums, progress:&[SyllabusProgress]) -> Self {
+        let items:Vec<ProgressItemDTO> = curriculum.items.iter().map(|item| {
+            let ticked = progress.iter().find(|p| p.item_id == item.item_id);
+            ProgressItemDTO {
+                item_id: item.item_id.to_string(),
+                category: item.category.to_string(),
+                name: item.name.to_string(),
+                completed: ticked.is_some(),
+                completed_by: ticked.map(|p| p.completed_by.to_string()),
+                completed_at: ticked.map(|p| p.completed_at.to_string()),
+                notes: ticked.and_then(|p| p.notes.clone()),
+            }
+        }).collect();
+
+        StudentProgressDTO {
+            student_id,
+            course_id: curriculum.course_id.to_hex(),
+            level: curriculum.level.to_string(),
+            curriculum_version: curriculum.version,
+            completed: items.iter().filter(|i| i.completed).count(),
+            total: items.len(),
+            items,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GradingEligibilityDTO {
+    pub student_id:String,
+    pub course_id:String,
+    pub current_level:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub next_level:Option<String>,
+    pub curriculum_version:i64,
+    pub completed:usize,
+    pub total:usize,
+    pub eligible:bool,
+    // syllabus items still to be ticked
+    pub missing:Vec<String>
+}
@@ -1,13 +1,26 @@
 use serde::{Deserialize, Serialize};
-use crate::models::events::Events;
+use validator::Validate;
+use crate::models::events::{EventRegistrations, Events, FileData, RegistrationSettings, VideoEmbed};
+use crate::helper::storage;
+use crate::models::media::ImageVariants;
 
 #[derive(Serialize,Deserialize)]
 pub struct CreateEventDTO {
     pub title:String,
     pub discription:String,
     pub location:String,
+    // RFC 3339 with an offset, or local time (YYYYMMDDHHMM) in `timezone`
     pub start_date:String,
     pub end_date:String,
+    // IANA name, defaults to the timezone of the first branch
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    // branch ids, empty for academy-wide events
+    #[serde(default)]
+    pub branches:Vec<String>,
+    // RFC 5545 RRULE, e.g. "FREQ=WEEKLY;BYDAY=TU,TH;COUNT=20"
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub rrule:Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -19,23 +32,68 @@ pub struct GetEventsDTO {
     pub file_data:Option<Vec<GetFileData>>,
     pub start_date:String,
     pub end_date:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    #[serde(skip_serializing_if="Vec::is_empty")]
+    pub branches:Vec<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub registration:Option<EventRegistrationSettingsDTO>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub rrule:Option<String>,
     pub created_at:String,
     pub updated_at:String
 }
 
 #[derive(Serialize,Deserialize)]
 pub struct GetFileData {
+    pub id:String,
     pub file_type:String,
     pub file_path:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub caption:Option<String>,
+    pub is_cover:bool,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub embed:Option<VideoEmbed>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub variants:Option<ImageVariants>,
     pub created_at:String
 }
 
+impl GetFileData {
+    pub fn init(file:FileData) -> Self {
+        GetFileData {
+            id: file.id.map(|id| id.to_hex()).unwrap_or_default(),
+            file_type: file.file_type,
+            file_path: storage::file_url(&file.file_path),
+            caption: file.caption,
+            is_cover: file.is_cover,
+            embed: file.embed,
+            variants: file.variants.map(storage::variant_urls),
+            created_at: file.created_at.map(|d| d.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+// a YouTube or Vimeo link in file_path
 #[derive(Serialize, Deserialize)]
 pub struct CreateFileDataDTO {
     pub file_type:String,
-    pub file_path:String 
+    pub file_path:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub caption:Option<String>
 }    
 
+#[derive(Serialize, Deserialize)]
+pub struct ReorderMediaDTO {
+    pub media_ids:Vec<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CaptionMediaDTO {
+    // null or empty removes the caption
+    pub caption:Option<String>
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UpdateEventDTO {
     pub title:String,
@@ -43,7 +101,13 @@ pub struct UpdateEventDTO {
     pub location:String,
     pub is_active:bool,
     pub start_date:String,
-    pub end_date:String
+    pub end_date:String,
+    // defaults to the timezone the event was created in
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    // left as is when not sent
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub branches:Option<Vec<String>>
 }
 
 
@@ -60,28 +124,187 @@ impl GetEventsDTO {
             created_at: event.created_at.unwrap().to_string(),
             updated_at: event.updated_at.unwrap().to_string(),
             file_data: None,
+            timezone: event.timezone,
+            branches: event.branches,
+            registration: event.registration.map(EventRegistrationSettingsDTO::init),
+            rrule: event.recurrence.map(|r| r.rrule),
         };
 
-        if !event.file_data.is_none() {
-            let mut data:Vec<GetFileData> = Vec::new();
-            for i in event.file_data.unwrap() {
-                if i.file_path.contains("http") {
-                    data.push( GetFileData {
-                        file_type: i.file_type,
-                        file_path:  i.file_path,
-                        created_at: i.created_at.unwrap().to_string(),
-                    });
-                    continue
-                }
-                data.push( GetFileData {
-                    file_type: i.file_type,
-                    file_path:  format!("http://192.168.0.119:8000{}",i.file_path),
-                    created_at: i.created_at.unwrap().to_string(),
-                })
-            }
-            event_dto.file_data = Some(data)
+        if let Some(file_data) = event.file_data {
+            event_dto.file_data = Some(file_data.into_iter().map(GetFileData::init).collect())
         }
 
         event_dto
     }
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct EventRegistrationSettingsDTO {
+    #[validate(range(min=0, message="capacity can not be negative"))]
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub capacity:Option<i64>,
+    // same formats as the event dates, in the event timezone
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub opens_at:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub closes_at:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fee:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub currency:Option<String>,
+    #[serde(default)]
+    pub levels:Vec<String>,
+    #[validate(range(min=0, message="min_age can not be negative"))]
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub min_age:Option<i64>,
+    #[validate(range(min=0, message="max_age can not be negative"))]
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub max_age:Option<i64>,
+    #[serde(default)]
+    pub branches:Vec<String>
+}
+
+impl EventRegistrationSettingsDTO {
+    pub fn init(settings:RegistrationSettings) -> Self {
+        Self {
+            capacity: settings.capacity,
+            opens_at: settings.opens_at.map(|d| d.to_string()),
+            closes_at: settings.closes_at.map(|d| d.to_string()),
+            currency: settings.fee.as_ref().map(|f| f.currency.to_string()),
+            fee: settings.fee.map(|f| f.to_decimal_string()),
+            levels: settings.levels,
+            min_age: settings.min_age,
+            max_age: settings.max_age,
+            branches: settings.branches,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct RegisterEventDTO {
+    #[validate(length(min=1, message="student_id can not be empty"))]
+    pub student_id:String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ParticipantQueryDTO {
+    // csv returns a file instead of json
+    pub format:Option<String>,
+    // include cancelled registrations
+    #[serde(default)]
+    pub all:bool
+}
+
+impl ParticipantQueryDTO {
+    pub fn is_csv(&self) -> bool {
+        self.format.as_deref() == Some("csv")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EventRegistrationDTO {
+    pub id:String,
+    pub event_id:String,
+    pub student_id:String,
+    pub status:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fee:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub currency:Option<String>,
+    pub fee_paid:bool,
+    pub registered_by:String,
+    pub created_at:String,
+    pub updated_at:String
+}
+
+impl EventRegistrationDTO {
+    pub fn init(registration:EventRegistrations) -> Self {
+        Self {
+            id: registration.id.map(|id| id.to_hex()).unwrap_or_default(),
+            event_id: registration.event_id.to_hex(),
+            student_id: registration.student_id.to_hex(),
+            status: registration.status,
+            currency: registration.fee.as_ref().map(|f| f.currency.to_string()),
+            fee: registration.fee.map(|f| f.to_decimal_string()),
+            fee_paid: registration.fee_paid,
+            registered_by: registration.registered_by,
+            created_at: registration.created_at.to_string(),
+            updated_at: registration.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ParticipantDTO {
+    pub registration_id:String,
+    pub student_id:String,
+    pub name:String,
+    pub level:String,
+    pub age:i64,
+    pub branch:String,
+    pub status:String,
+    pub fee_paid:bool,
+    pub registered_at:String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StudentEventDTO {
+    pub event:GetEventsDTO,
+    pub registration:EventRegistrationDTO
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecurrenceDTO {
+    // null turns the series back into a one-off event
+    pub rrule:Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OccurrenceQueryDTO {
+    // "%Y%m%d", `to` inclusive
+    pub from:String,
+    pub to:String
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateOccurrenceDTO {
+    // original start of the occurrence as listed by /occurrences, "%Y%m%d%H%M"
+    #[validate(length(min=1, message="occurrence can not be empty"))]
+    pub occurrence:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub title:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub location:Option<String>,
+    // same formats as the event dates, in the event timezone
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub start_date:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub end_date:Option<String>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CancelOccurrenceDTO {
+    #[validate(length(min=1, message="occurrence can not be empty"))]
+    pub occurrence:String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OccurrenceDTO {
+    pub event_id:String,
+    // key of the occurrence for edits and cancellation
+    pub occurrence:String,
+    pub title:String,
+    pub discription:String,
+    pub location:String,
+    pub start_date:String,
+    pub end_date:String,
+    pub is_recurring:bool,
+    pub is_modified:bool
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CalendarFeedDTO {
+    pub student_id:String,
+    // secret feed path for calendar apps, anyone with it can read the feed
+    pub url:String
+}
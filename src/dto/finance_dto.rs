@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::{finance::{CreditNotes, Invoices, LedgerEntries, Refunds, TaxLine}, money::{format_rate, Money}};
+
+use super::app_dto::deserialize_amount;
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateInvoiceDTO {
+    #[validate(required, length(min=1, message="student_id can not be empty"))]
+    pub student_id:Option<String>,
+    #[validate(required, length(min=1, message="fee_id can not be empty"))]
+    pub fee_id:Option<String>,
+    // course the fee is charged for, used by revenue reports
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub course_id:Option<String>,
+    // YYYY-MM-DD, defaults to a week from today
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub due_date:Option<String>,
+    // two digit GST state code of the buyer, defaults to the academy state
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub place_of_supply:Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InvoiceDTO {
+    pub id:String,
+    pub invoice_number:String,
+    pub student_id:String,
+    pub fee_id:String,
+    pub fee_type:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub course_id:Option<String>,
+    pub amount:String,
+    pub currency:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub discount_amount:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub taxable_amount:Option<String>,
+    pub tax_lines:Vec<TaxLineDTO>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub hsn_sac:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gstin:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub place_of_supply:Option<String>,
+    pub status:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gateway_order_id:Option<String>,
+    pub due_date:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub paid_at:Option<String>,
+    pub created_at:String,
+    pub updated_at:String
+}
+
+impl InvoiceDTO {
+    pub fn init(invoice:Invoices) -> Self {
+        InvoiceDTO {
+            id: invoice.id.unwrap().to_hex(),
+            invoice_number: invoice.invoice_number,
+            student_id: invoice.student_id.to_hex(),
+            fee_id: invoice.fee_id.to_hex(),
+            fee_type: invoice.fee_type,
+            course_id: invoice.course_id.map(|c| c.to_hex()),
+            amount: invoice.amount.to_decimal_string(),
+            currency: invoice.amount.currency,
+            discount_amount: invoice.discount_amount.map(|d| d.to_decimal_string()),
+            taxable_amount: invoice.taxable_amount.map(|t| t.to_decimal_string()),
+            tax_lines: invoice.tax_lines.into_iter().map(TaxLineDTO::init).collect(),
+            hsn_sac: invoice.hsn_sac,
+            gstin: invoice.gstin,
+            place_of_supply: invoice.place_of_supply,
+            status: invoice.status,
+            gateway_order_id: invoice.gateway_order_id,
+            due_date: invoice.due_date.to_string(),
+            paid_at: invoice.paid_at.map(|p| p.to_string()),
+            created_at: invoice.created_at.to_string(),
+            updated_at: invoice.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TaxLineDTO {
+    pub tax_type:String,
+    pub rate:String,
+    pub amount:String
+}
+
+impl TaxLineDTO {
+    pub fn init(line:TaxLine) -> Self {
+        TaxLineDTO {
+            tax_type: line.tax_type,
+            rate: format_rate(line.rate),
+            amount: line.amount.to_decimal_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PaymentOrderDTO {
+    pub invoice_id:String,
+    pub gateway:String,
+    pub order_id:String,
+    pub amount:String,
+    pub currency:String,
+    pub checkout:serde_json::Value
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportQueryDTO {
+    // "csv" for a file download, JSON otherwise
+    pub format:Option<String>
+}
+
+impl ExportQueryDTO {
+    pub fn is_csv(&self) -> bool {
+        self.format.as_deref() == Some("csv")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GstSummaryDTO {
+    pub hsn_sac:String,
+    pub invoice_count:u64,
+    pub credit_note_count:u64,
+    pub taxable_value:String,
+    pub cgst:String,
+    pub sgst:String,
+    pub igst:String,
+    pub total_value:String
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateRefundDTO {
+    #[validate(required, length(min=1, message="payment_id can not be empty"))]
+    pub payment_id:Option<String>,
+    // explicit amount to refund, otherwise the unused portion as of leave_date
+    #[serde(default, deserialize_with="deserialize_optional_amount", skip_serializing_if="Option::is_none")]
+    pub amount:Option<String>,
+    // YYYY-MM-DD
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub leave_date:Option<String>,
+    #[validate(required, length(min=1, message="reason can not be empty"))]
+    pub reason:Option<String>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateCreditNoteDTO {
+    #[validate(required, length(min=1, message="student_id can not be empty"))]
+    pub student_id:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub invoice_id:Option<String>,
+    #[serde(deserialize_with="deserialize_amount")]
+    pub amount:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub currency:Option<String>,
+    #[validate(required, length(min=1, message="reason can not be empty"))]
+    pub reason:Option<String>
+}
+
+fn deserialize_optional_amount<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_amount(deserializer).map(Some)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RefundQueryDTO {
+    pub status:Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProRataQueryDTO {
+    // YYYY-MM-DD, defaults to today
+    pub leave_date:Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProRataDTO {
+    pub invoice_id:String,
+    pub fee_type:String,
+    pub period_start:String,
+    pub period_end:String,
+    pub leave_date:String,
+    pub paid_amount:String,
+    pub unused_amount:String,
+    pub currency:String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RefundDTO {
+    pub id:String,
+    pub payment_id:String,
+    pub invoice_id:String,
+    pub student_id:String,
+    pub amount:String,
+    pub currency:String,
+    pub reason:String,
+    pub status:String,
+    pub requested_by:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub approved_by:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub approved_at:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub credit_note_id:Option<String>,
+    pub created_at:String,
+    pub updated_at:String
+}
+
+impl RefundDTO {
+    pub fn init(refund:Refunds) -> Self {
+        RefundDTO {
+            id: refund.id.unwrap().to_hex(),
+            payment_id: refund.payment_id.to_hex(),
+            invoice_id: refund.invoice_id.to_hex(),
+            student_id: refund.student_id.to_hex(),
+            amount: refund.amount.to_decimal_string(),
+            currency: refund.amount.currency,
+            reason: refund.reason,
+            status: refund.status,
+            requested_by: refund.requested_by,
+            approved_by: refund.approved_by,
+            approved_at: refund.approved_at.map(|a| a.to_string()),
+            credit_note_id: refund.credit_note_id.map(|c| c.to_hex()),
+            created_at: refund.created_at.to_string(),
+            updated_at: refund.updated_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreditNoteDTO {
+    pub id:String,
+    pub credit_note_number:String,
+    pub student_id:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub invoice_id:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub refund_id:Option<String>,
+    pub amount:String,
+    pub currency:String,
+    pub reason:String,
+    pub issued_by:String,
+    pub created_at:String
+}
+
+impl CreditNoteDTO {
+    pub fn init(credit_note:CreditNotes) -> Self {
+        CreditNoteDTO {
+            id: credit_note.id.unwrap().to_hex(),
+            credit_note_number: credit_note.credit_note_number,
+            student_id: credit_note.student_id.to_hex(),
+            invoice_id: credit_note.invoice_id.map(|i| i.to_hex()),
+            refund_id: credit_note.refund_id.map(|r| r.to_hex()),
+            amount: credit_note.amount.to_decimal_string(),
+            currency: credit_note.amount.currency,
+            reason: credit_note.reason,
+            issued_by: credit_note.issued_by,
+            created_at: credit_note.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LedgerEntryDTO {
+    pub entry_type:String,
+    pub reference_id:String,
+    pub amount:String,
+    // running balance after this entry
+    pub balance:String,
+    pub description:String,
+    pub created_at:String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StudentLedgerDTO {
+    pub student_id:String,
+    // positive when the student owes the academy, negative when in credit
+    pub balance:String,
+    pub currency:String,
+    pub entries:Vec<LedgerEntryDTO>
+}
+
+impl StudentLedgerDTO {
+    pub fn init(student_id:String, currency:&str, entries:Vec<LedgerEntries>) -> Self {
+        let mut balance = Money::zero(currency);
+        let entries = entries.into_iter().map(|entry| {
+            balance = Money::new(balance.amount_minor + entry.amount.amount_minor, &balance.currency);
+            LedgerEntryDTO {
+                entry_type: entry.entry_type,
+                reference_id: entry.reference_id.to_hex(),
+                amount: entry.amount.to_decimal_string(),
+                balance: balance.to_decimal_string(),
+                description: entry.description,
+                created_at: entry.created_at.to_string(),
+            }
+        }).collect();
+
+        StudentLedgerDTO {
+            student_id,
+            balance: balance.to_decimal_string(),
+            currency: balance.currency,
+            entries,
+        }
+    }
+}
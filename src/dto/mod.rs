@@ -1,4 +1,9 @@
 pub mod user_dto;
 pub mod student_dto;
 pub mod event_dto;
-pub mod app_dto;
\ No newline at end of file
+pub mod app_dto;
+pub mod finance_dto;
+pub mod settings_dto;
+pub mod report_dto;
+pub mod curriculum_dto;
+pub mod tenant_dto;
\ No newline at end of file
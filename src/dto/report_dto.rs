@@ -0,0 +1,168 @@
+use bson::{Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::models::money::Money;
+
+#[derive(Serialize, Deserialize)]
+pub struct ReportQueryDTO {
+    // YYYY-MM-DD, both inclusive
+    pub from:Option<String>,
+    pub to:Option<String>,
+    // class branch of the student, branch id or name
+    pub branch:Option<String>,
+    pub group_by:Option<String>,
+    // "csv" for a file download, JSON otherwise
+    pub format:Option<String>
+}
+
+impl ReportQueryDTO {
+    pub fn is_csv(&self) -> bool {
+        self.format.as_deref() == Some("csv")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionReportDTO {
+    pub key:String,
+    pub currency:String,
+    pub payment_count:i64,
+    pub collected:String,
+    pub refunded:String,
+    pub net:String
+}
+
+impl CollectionReportDTO {
+    pub fn init(row:&Document) -> Self {
+        let (key, currency) = group_id(row);
+        let collected = int_field(row, "collected");
+        let refunded = int_field(row, "refunded");
+
+        CollectionReportDTO {
+            key,
+            payment_count: int_field(row, "payment_count"),
+            collected: Money::new(collected, &currency).to_decimal_string(),
+            refunded: Money::new(refunded, &currency).to_decimal_string(),
+            net: Money::new(collected - refunded, &currency).to_decimal_string(),
+            currency,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AgeingReportDTO {
+    pub bucket:String,
+    pub currency:String,
+    pub invoice_count:i64,
+    pub outstanding:String
+}
+
+impl AgeingReportDTO {
+    pub fn init(row:&Document) -> Self {
+        let (bucket, currency) = group_id(row);
+
+        AgeingReportDTO {
+            bucket,
+            invoice_count: int_field(row, "invoice_count"),
+            outstanding: Money::new(int_field(row, "outstanding"), &currency).to_decimal_string(),
+            currency,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RevenueReportDTO {
+    pub key:String,
+    pub currency:String,
+    pub invoice_count:i64,
+    pub invoiced:String,
+    pub credited:String,
+    pub net:String
+}
+
+impl RevenueReportDTO {
+    pub fn init(row:&Document) -> Self {
+        let (key, currency) = group_id(row);
+        let invoiced = int_field(row, "invoiced");
+        let credited = int_field(row, "credited");
+
+        RevenueReportDTO {
+            key,
+            invoice_count: int_field(row, "invoice_count"),
+            invoiced: Money::new(invoiced, &currency).to_decimal_string(),
+            credited: Money::new(credited, &currency).to_decimal_string(),
+            net: Money::new(invoiced - credited, &currency).to_decimal_string(),
+            currency,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiscountReportDTO {
+    pub fee_type:String,
+    pub currency:String,
+    pub invoice_count:i64,
+    pub discount:String
+}
+
+impl DiscountReportDTO {
+    pub fn init(row:&Document) -> Self {
+        let (fee_type, currency) = group_id(row);
+
+        DiscountReportDTO {
+            fee_type,
+            invoice_count: int_field(row, "invoice_count"),
+            discount: Money::new(int_field(row, "discount"), &currency).to_decimal_string(),
+            currency,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConversionReportDTO {
+    pub key:String,
+    pub enquiry_count:i64,
+    pub converted:i64,
+    pub registered:i64,
+    pub lost:i64,
+    // converted enquiries in percent, one decimal
+    pub conversion_rate:String
+}
+
+impl ConversionReportDTO {
+    pub fn init(row:&Document) -> Self {
+        let (key, _) = group_id(row);
+        let enquiry_count = int_field(row, "enquiry_count");
+        let converted = int_field(row, "converted");
+        let rate = if enquiry_count > 0 { converted as f64 * 100.0 / enquiry_count as f64 } else { 0.0 };
+
+        ConversionReportDTO {
+            key,
+            enquiry_count,
+            converted,
+            registered: int_field(row, "registered"),
+            lost: int_field(row, "lost"),
+            conversion_rate: format!("{:.1}", rate),
+        }
+    }
+}
+
+// every report groups on { key, currency }
+fn group_id(row:&Document) -> (String, String) {
+    let id = row.get_document("_id").ok();
+    let text = |field:&str| match id.and_then(|id| id.get(field)) {
+        Some(Bson::String(value)) => value.to_string(),
+        Some(Bson::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    };
+    (text("key"), text("currency"))
+}
+
+// $sum yields int32 or int64 depending on the magnitude
+fn int_field(row:&Document, field:&str) -> i64 {
+    match row.get(field) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(Bson::Double(value)) => *value as i64,
+        _ => 0,
+    }
+}
@@ -0,0 +1,185 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{helper::{app_errors::AppError, storage}, models::{media::ImageVariants, settings::{lookup_key, AcademySettings, BeltLevel, FeeCycle, RegistrationOptions}}};
+
+lazy_static! {
+    // 2 digit state code, PAN, entity number, Z, checksum character
+    static ref GSTIN_REGEX: Regex = Regex::new(r"^[0-9]{2}[A-Z]{5}[0-9]{4}[A-Z][1-9A-Z]Z[0-9A-Z]$").unwrap();
+    static ref CURRENCY_REGEX: Regex = Regex::new(r"^[A-Z]{3}$").unwrap();
+    static ref SETTING_KEY_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_-]{1,20}$").unwrap();
+    static ref COLOR_REGEX: Regex = Regex::new(r"^#[0-9A-Fa-f]{6}$").unwrap();
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateTaxSettingsDTO {
+    #[validate(required, regex(path="GSTIN_REGEX", message="Invalid GSTIN"))]
+    pub gstin:Option<String>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateAcademyProfileDTO {
+    #[validate(length(min=1, max=100))]
+    pub academy_name:String,
+    #[validate(email)]
+    pub contact_email:Option<String>,
+    #[validate(length(min=6, max=20))]
+    pub contact_phone:Option<String>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateLocaleDTO {
+    #[validate(regex(path="CURRENCY_REGEX", message="currency must be a 3 letter ISO code"))]
+    pub currency:String,
+    pub timezone:String
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateBeltsDTO {
+    #[validate(length(min=1, message="the ladder needs at least one belt"))]
+    #[validate]
+    pub belts:Vec<BeltLevelDTO>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct BeltLevelDTO {
+    #[validate(regex(path="SETTING_KEY_REGEX", message="belt key must be up to 20 letters, digits, _ or -"))]
+    pub key:String,
+    #[validate(length(min=1, max=50))]
+    pub name:String,
+    #[validate(regex(path="COLOR_REGEX", message="colour must be #RRGGBB"))]
+    pub color:String,
+    pub order:i32
+}
+
+impl UpdateBeltsDTO {
+    pub fn to_belts(&self) -> Result<Vec<BeltLevel>, AppError> {
+        unique_keys(self.belts.iter().map(|b| b.key.as_str()), "belt")?;
+        let mut orders:Vec<i32> = self.belts.iter().map(|b| b.order).collect();
+        orders.sort();
+        orders.dedup();
+        if orders.len() != self.belts.len() {
+            return Err(AppError::CustomError("two belts have the same order".to_string()));
+        }
+
+        Ok(self.belts.iter().map(|belt| BeltLevel {
+            key: belt.key.to_uppercase(),
+            name: belt.name.trim().to_string(),
+            color: belt.color.to_uppercase(),
+            order: belt.order,
+        }).collect())
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateFeeCyclesDTO {
+    #[validate(length(min=1, message="at least one fee cycle is required"))]
+    #[validate]
+    pub fee_cycles:Vec<FeeCycleDTO>
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct FeeCycleDTO {
+    #[validate(regex(path="SETTING_KEY_REGEX", message="fee cycle key must be up to 20 letters, digits, _ or -"))]
+    pub key:String,
+    #[validate(length(min=1, max=50))]
+    pub name:String,
+    #[validate(range(min=1, max=60))]
+    pub months:u32
+}
+
+impl UpdateFeeCyclesDTO {
+    pub fn to_fee_cycles(&self) -> Result<Vec<FeeCycle>, AppError> {
+        unique_keys(self.fee_cycles.iter().map(|c| c.key.as_str()), "fee cycle")?;
+        Ok(self.fee_cycles.iter().map(|cycle| FeeCycle {
+            key: cycle.key.to_uppercase(),
+            name: cycle.name.trim().to_string(),
+            months: cycle.months,
+        }).collect())
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateRegistrationOptionsDTO {
+    #[validate(length(min=1, max=50))]
+    pub default_nationality:String,
+    #[validate(length(min=1, message="at least one gender is required"))]
+    pub genders:Vec<String>,
+    #[serde(default)]
+    pub blood_groups:Vec<String>,
+    #[serde(default)]
+    pub require_aadhaar:bool
+}
+
+impl UpdateRegistrationOptionsDTO {
+    pub fn to_options(&self) -> RegistrationOptions {
+        let clean = |values:&[String]| values.iter().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect::<Vec<_>>();
+        RegistrationOptions {
+            default_nationality: self.default_nationality.trim().to_uppercase(),
+            genders: clean(&self.genders).into_iter().map(|g| g.to_lowercase()).collect(),
+            blood_groups: clean(&self.blood_groups).into_iter().map(|b| b.to_uppercase()).collect(),
+            require_aadhaar: self.require_aadhaar,
+        }
+    }
+}
+
+// keys are compared the loose way lookups match them
+fn unique_keys<'a>(keys:impl Iterator<Item = &'a str>, what:&str) -> Result<(), AppError> {
+    let mut seen:Vec<String> = Vec::new();
+    for key in keys {
+        let normalized = lookup_key(key);
+        if seen.contains(&normalized) {
+            return Err(AppError::CustomError(format!("{} {} is listed twice", what, key)));
+        }
+        seen.push(normalized);
+    }
+    Ok(())
+}
+
+// Effective settings, defaults filled in for anything the academy has not set.
+#[derive(Serialize, Deserialize)]
+pub struct SettingsDTO {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub academy_name:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub contact_email:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub contact_phone:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub logo_url:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub logo_variants:Option<ImageVariants>,
+    pub currency:String,
+    pub timezone:String,
+    pub belts:Vec<BeltLevel>,
+    pub fee_cycles:Vec<FeeCycle>,
+    pub registration:RegistrationOptions,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gstin:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub state_code:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub updated_at:Option<String>
+}
+
+impl SettingsDTO {
+    pub fn init(settings:AcademySettings) -> Self {
+        SettingsDTO {
+            currency: settings.currency().to_string(),
+            timezone: settings.timezone().name().to_string(),
+            belts: settings.belts(),
+            fee_cycles: settings.fee_cycles(),
+            registration: settings.registration(),
+            logo_url: settings.logo.as_deref().map(storage::file_url),
+            logo_variants: settings.logo_variants.map(storage::variant_urls),
+            academy_name: settings.academy_name,
+            contact_email: settings.contact_email,
+            contact_phone: settings.contact_phone,
+            gstin: settings.gstin,
+            state_code: settings.state_code,
+            updated_at: settings.updated_at.map(|u| u.to_string()),
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use actix_multipart::form::{tempfile::TempFile, MultipartForm};
 use serde::{Deserialize, Serialize};
-use serde::de::{self, Visitor};
-use std::fmt::{self};
+use crate::helper::app_errors::AppError;
+use crate::models::settings::AcademySettings;
 use crate::models::student_model::{Parents, Students};
+use crate::helper::storage;
+use crate::models::media::ImageVariants;
 
 
 
@@ -15,8 +16,8 @@ pub struct CreateStudentDTO {
     pub address:String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub class_branch:Option<String>,
-    #[serde(deserialize_with="deserialize_student_level")]
-    pub level:StudentLevels,
+    // a belt key of the academy's ladder
+    pub level:String,
     pub blood_group:String,
     pub weight:i64,
     pub school_name:String,
@@ -24,83 +25,25 @@ pub struct CreateStudentDTO {
     pub geneder:String,
 }
 
-#[derive(Serialize, Deserialize)]
-pub enum StudentLevels {
-    OFFWHITE,
-    YELLOW,
-    ORANGE,
-    GREEN,
-    BLUE,
-    PURPLE,
-    BROWN,
-    BROWNII,
-    BROWNIII,
-    BLACK
-}
-
-
-fn deserialize_student_level<'de, D>(deserializer: D) -> Result<StudentLevels, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    struct StudentLevelVisitor;
-
-    impl<'de> Visitor<'de> for StudentLevelVisitor {
-        type Value = StudentLevels;
-
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("a valid student level string")
+impl CreateStudentDTO {
+    // checks the form against the academy's registration options, gives the stored belt
+    pub fn validate_registration(&self, settings:&AcademySettings) -> Result<String, AppError> {
+        let options = settings.registration();
+        if !options.genders.iter().any(|g| g.eq_ignore_ascii_case(self.geneder.trim())) {
+            return Err(AppError::CustomError("Invalid gender".to_string()));
         }
-
-        fn visit_str<E>(self, value: &str) -> Result<StudentLevels, E>
-        where
-            E: de::Error,
-        {
-            match value {
-                "Off_White" | "OffWhite" | "offwhite" => Ok(StudentLevels::OFFWHITE),
-                "yellow" => Ok(StudentLevels::YELLOW),
-                "orange" => Ok(StudentLevels::ORANGE),
-                "green" => Ok(StudentLevels::GREEN),
-                "blue" => Ok(StudentLevels::BLUE),
-                "purple" => Ok(StudentLevels::PURPLE),
-                "brown" => Ok(StudentLevels::BROWN),
-                "brownII" => Ok(StudentLevels::BROWNII),
-                "brownIII" => Ok(StudentLevels::BROWNIII),
-                "black" => Ok(StudentLevels::BLACK),
-                _ => Err(E::custom(format!("Invalid Student Level: {}", value))),
-            }
+        if !options.blood_groups.is_empty() && !options.blood_groups.iter().any(|b| b.eq_ignore_ascii_case(self.blood_group.trim())) {
+            return Err(AppError::CustomError(format!("Invalid blood group: {}", self.blood_group)));
         }
-    }
-
-    deserializer.deserialize_str(StudentLevelVisitor)
-}
-
-
-impl Default for StudentLevels {
-    fn default() -> Self {
-        StudentLevels::OFFWHITE
-    }
-}
-
-
-impl ToString for StudentLevels {
-    fn to_string(&self) -> String {
-        match self {
-            StudentLevels::OFFWHITE => String::from("OFFWHITE"),
-            StudentLevels::YELLOW => String::from("YELLOW"),
-            StudentLevels::ORANGE => String::from("ORANGE"),
-            StudentLevels::GREEN => String::from("GREEN"),
-            StudentLevels::BLUE => String::from("BLUE"),
-            StudentLevels::PURPLE => String::from("PURPLE"),
-            StudentLevels::BROWN => String::from("BROWN"),
-            StudentLevels::BROWNII => String::from("BROWNII"),
-            StudentLevels::BROWNIII => String::from("BROWNIII"),
-            StudentLevels::BLACK => String::from("BLACK"),
+        if options.require_aadhaar && self.addhar_number.trim().is_empty() {
+            return Err(AppError::CustomError("aadhaar number is required".to_string()));
         }
+
+        settings.level_key(&self.level)
+            .ok_or_else(|| AppError::CustomError(format!("Invalid Student Level: {}", self.level)))
     }
 }
 
-
 #[derive(Serialize,Deserialize)]
 pub struct StudentsDTO  {
     pub id:String,
@@ -113,6 +56,8 @@ pub struct StudentsDTO  {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile_pic:Option<String>,
     #[serde(skip_serializing_if="Option::is_none")]
+    pub profile_pic_variants:Option<ImageVariants>,
+    #[serde(skip_serializing_if="Option::is_none")]
     pub class_branch:Option<String>,
     #[serde(skip_serializing_if="Option::is_none")]
     pub parent:Option<GetParentDTO>,
@@ -129,6 +74,8 @@ pub struct StudentsDTO  {
     pub registration_status:Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub access_token:Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enquiry_id:Option<String>,
     pub created_at:String,
     pub updated_at:String
 }
@@ -145,6 +92,7 @@ impl StudentsDTO {
             address: student.address,
             is_active_student: student.is_active_student,
             profile_pic: student.profile_pic,
+            profile_pic_variants: student.profile_pic_variants.map(storage::variant_urls),
             class_branch: student.class_branch,
             parent: None,
             created_at: student.created_at.unwrap().to_string(),
@@ -159,6 +107,7 @@ impl StudentsDTO {
             student_id: None,
             registration_status: None,
             access_token: None,
+            enquiry_id: student.enquiry_id.map(|id| id.to_hex()),
         };
 
         if !student.parent.is_none() {
@@ -166,7 +115,7 @@ impl StudentsDTO {
         }
 
         if !s.profile_pic.is_none() {
-            s.profile_pic = Some(format!("http://192.168.0.119:8000{}" , s.profile_pic.unwrap()))
+            s.profile_pic = Some(storage::file_url(&s.profile_pic.unwrap()))
         }
 
         if !accessToken.is_empty() {
@@ -209,11 +158,6 @@ pub struct GetParentDTO {
     pub updated_at:String
 }
 
-#[derive(MultipartForm)]
-pub struct UploadProfileDTO {
-    pub file:TempFile,
-}
-
 impl GetParentDTO {
     pub fn init(parent:Parents) -> Self {
 
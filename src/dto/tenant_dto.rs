@@ -0,0 +1,70 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::tenant::Tenants;
+
+lazy_static! {
+    // used as a subdomain, so lower case letters, digits and dashes
+    static ref TENANT_SLUG_REGEX: Regex = Regex::new(r"^[a-z0-9][a-z0-9-]{1,30}[a-z0-9]$").unwrap();
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CreateTenantDTO {
+    #[validate(regex(path="TENANT_SLUG_REGEX", message="slug must be 3 to 32 lower case letters, digits or dashes"))]
+    pub slug:String,
+    #[validate(length(min=1, max=100))]
+    pub name:String,
+    #[validate(email)]
+    pub contact_email:Option<String>,
+    #[validate(length(min=6, max=20))]
+    pub contact_phone:Option<String>,
+    // first ADMIN user of the academy
+    #[validate]
+    pub admin:TenantAdminDTO
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct TenantAdminDTO {
+    #[validate(length(min=1))]
+    pub name:String,
+    #[validate(email)]
+    pub email:String,
+    #[validate(length(min=6, max=20))]
+    pub mobile_number:String,
+    #[validate(length(min=8))]
+    pub password:String
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UpdateTenantDTO {
+    #[validate(length(min=1, max=100))]
+    pub name:Option<String>,
+    pub is_active:Option<bool>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetTenantDTO {
+    pub id:String,
+    pub slug:String,
+    pub name:String,
+    pub database:String,
+    pub is_active:bool,
+    pub created_at:Option<String>,
+    pub updated_at:Option<String>
+}
+
+impl GetTenantDTO {
+    pub fn init(tenant:Tenants) -> Self {
+        GetTenantDTO {
+            id: tenant.id.map(|id| id.to_hex()).unwrap_or_default(),
+            slug: tenant.slug,
+            name: tenant.name,
+            database: tenant.database,
+            is_active: tenant.is_active,
+            created_at: tenant.created_at.map(|c| c.to_string()),
+            updated_at: tenant.updated_at.map(|u| u.to_string()),
+        }
+    }
+}
\ No newline at end of file
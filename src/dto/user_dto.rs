@@ -17,7 +17,9 @@ pub struct UpdateUserRequestDTO {
     pub name:String,
     pub email:String,
     pub mobile_number:String,
-    pub is_active:bool
+    pub is_active:bool,
+    // replaces the branches of a SUBADMIN when sent
+    pub branches:Option<Vec<String>>
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,6 +36,7 @@ pub struct GetUserDTO {
     pub mobile_number:String,
     pub user_type:String,
     pub is_active:bool,
+    pub branches:Vec<String>,
     #[serde(skip_serializing_if="Option::is_none")]
     pub access_token:Option<String>,
     pub created_at:Option<String>,
@@ -53,6 +56,7 @@ impl GetUserDTO {
             mobile_number: user.mobile_number,
             user_type: user.user_type.to_string(),
             is_active: user.is_active,
+            branches: user.branches,
             created_at: Some(user.created_at.unwrap().to_string()),
             updated_at: Some(user.updated_at.unwrap().to_string()),
             access_token,
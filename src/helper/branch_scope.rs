@@ -0,0 +1,124 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{dev::Payload, web::Data, FromRequest, HttpMessage, HttpRequest};
+use bson::{doc, oid::ObjectId, Document};
+
+use crate::{repo::tenant_repo::TenantRegistry, service::jwt_service::JwtService};
+
+use super::{app_errors::AppError, tenant::tenant_of};
+
+// The branches a request may read and change. Repositories narrow their filters
+// with it, so a handler only has to pass it along. Only ADMIN users are not
+// limited, a SUBADMIN reaches the branches on their user, a student token its
+// class branch and anything else no branch at all.
+#[derive(Clone)]
+pub enum BranchScope {
+    All,
+    Branches(Vec<String>)
+}
+
+impl BranchScope {
+    // the scope the token claims, a SUBADMIN's branches are as of their login
+    pub fn of(req:&HttpRequest) -> Self {
+        let user = match JwtService::current_user(req) {
+            Some(user) => user,
+            None => return BranchScope::Branches(Vec::new()),
+        };
+
+        if user.is_admin() {
+            return BranchScope::All;
+        }
+        if user.is_sub_admin() {
+            return BranchScope::Branches(user.branches);
+        }
+
+        let class_branch = req.extensions().get::<serde_json::Value>()
+            .and_then(|claims| claims.pointer("/user/class_branch"))
+            .and_then(|branch| branch.as_str())
+            .filter(|branch| !branch.is_empty())
+            .map(|branch| branch.to_string());
+        BranchScope::Branches(class_branch.into_iter().collect())
+    }
+
+    pub fn allows(&self, branch:&str) -> bool {
+        match self {
+            BranchScope::All => true,
+            BranchScope::Branches(branches) => branches.iter().any(|b| b == branch),
+        }
+    }
+
+    // an error unless every branch is in scope, a limited user can not write academy-wide data
+    pub fn check(&self, branches:&[String]) -> Result<(), AppError> {
+        match self {
+            BranchScope::All => Ok(()),
+            BranchScope::Branches(_) if branches.is_empty() => Err(AppError::CustomError("a branch is required".to_string())),
+            BranchScope::Branches(_) => match branches.iter().find(|b| !self.allows(b)) {
+                Some(branch) => Err(AppError::CustomError(format!("branch {} is not assigned to you", branch))),
+                None => Ok(()),
+            },
+        }
+    }
+
+    // documents whose `field` holds a branch id in scope, `field` may be a single id or an array
+    pub fn restrict(&self, filter:Document, field:&str) -> Document {
+        match self {
+            BranchScope::All => filter,
+            BranchScope::Branches(branches) => doc! { "$and": [filter, { field: { "$in":branches } }] },
+        }
+    }
+
+    // events: academy-wide events (no branches) can be read by everyone
+    pub fn restrict_events(&self, filter:Document) -> Document {
+        match self {
+            BranchScope::All => filter,
+            BranchScope::Branches(branches) => doc! {
+                "$and": [filter, { "$or": [{ "branches": { "$in":branches } }, { "branches": { "$in": [[], null] } }] }]
+            },
+        }
+    }
+
+    // the branch ids to filter on, None when the scope is not limited
+    pub fn branches(&self) -> Option<&[String]> {
+        match self {
+            BranchScope::All => None,
+            BranchScope::Branches(branches) => Some(branches),
+        }
+    }
+}
+
+// A SUBADMIN's branches are read from their user on every request, so an
+// unassigned branch or a deactivated user stops working before the token expires.
+async fn current_branches(registry:Option<Data<TenantRegistry>>, slug:Result<String, AppError>, user_id:Option<String>) -> Vec<String> {
+    let (registry, slug, user_id) = match (registry, slug, user_id.and_then(|id| ObjectId::parse_str(id).ok())) {
+        (Some(registry), Ok(slug), Some(user_id)) => (registry, slug, user_id),
+        _ => return Vec::new(),
+    };
+
+    let repos = match registry.repos(&slug).await {
+        Ok(repos) => repos,
+        Err(_) => return Vec::new(),
+    };
+    match repos.user.get_user(user_id).await {
+        Ok(user) if user.is_active => user.branches,
+        _ => Vec::new(),
+    }
+}
+
+impl FromRequest for BranchScope {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req:&HttpRequest, _:&mut Payload) -> Self::Future {
+        let scope = BranchScope::of(req);
+        let sub_admin = JwtService::current_user(req).filter(|user| user.is_sub_admin());
+        let registry = req.app_data::<Data<TenantRegistry>>().cloned();
+        let slug = tenant_of(req);
+
+        Box::pin(async move {
+            match sub_admin {
+                Some(user) => Ok(BranchScope::Branches(current_branches(registry, slug, user.id).await)),
+                None => Ok(scope),
+            }
+        })
+    }
+}
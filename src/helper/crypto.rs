@@ -0,0 +1,30 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct Crypto{}
+
+impl Crypto {
+    pub fn hmac_sha256(key:&[u8], data:&[u8]) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    pub fn hmac_sha256_hex(key:&[u8], data:&[u8]) -> String {
+        hex::encode(Self::hmac_sha256(key, data))
+    }
+
+    pub fn sha256_hex(data:&[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    // compare two signatures without leaking where they differ
+    pub fn constant_time_eq(a:&[u8], b:&[u8]) -> bool {
+        if a.len() != b.len() {
+            return false;
+        }
+        a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
@@ -0,0 +1,35 @@
+use actix_web::HttpResponse;
+
+// Small CSV builder for report downloads, quotes every field that needs it.
+pub struct CsvBuilder {
+    content:String
+}
+
+impl CsvBuilder {
+    pub fn new(headers:&[&str]) -> Self {
+        let mut csv = CsvBuilder { content: String::new() };
+        csv.add_row(headers.iter().map(|h| h.to_string()).collect());
+        csv
+    }
+
+    pub fn add_row(&mut self, values:Vec<String>) {
+        let row:Vec<String> = values.iter().map(|v| Self::escape(v)).collect();
+        self.content.push_str(&row.join(","));
+        self.content.push_str("\r\n");
+    }
+
+    pub fn into_response(self, file_name:&str) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", file_name)))
+            .body(self.content)
+    }
+
+    fn escape(value:&str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+}
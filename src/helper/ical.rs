@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+
+use actix_web::HttpResponse;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+
+use super::timezone;
+
+// zones are described this many years past the last date in the feed, rules
+// without an end keep repeating after it
+const ZONE_YEARS_AHEAD:i32 = 10;
+
+// One VEVENT. Times are UTC, or local to `timezone` when it is set so a series
+// repeats at the same wall clock time. An entry with a recurrence_id overrides
+// that single occurrence of the series with the same uid.
+pub struct ICalEvent {
+    pub uid:String,
+    pub timezone:Option<String>,
+    pub dtstamp:NaiveDateTime,
+    pub start:NaiveDateTime,
+    pub end:NaiveDateTime,
+    pub summary:String,
+    pub description:String,
+    pub location:String,
+    pub rrule:Option<String>,
+    pub exdates:Vec<NaiveDateTime>,
+    pub recurrence_id:Option<NaiveDateTime>
+}
+
+// Minimal RFC 5545 writer for the subscribable calendar feeds. Every TZID used
+// by an event gets a VTIMEZONE with the zone's offset changes over the years
+// the feed covers.
+pub struct ICalendar {
+    name:String,
+    content:String,
+    // zone name => first and last year a time in that zone appears
+    zones:BTreeMap<String, (i32, i32)>
+}
+
+impl ICalendar {
+    pub fn new(name:&str) -> Self {
+        ICalendar { name: name.to_string(), content: String::new(), zones: BTreeMap::new() }
+    }
+
+    pub fn add_event(&mut self, event:&ICalEvent) {
+        if let Some(zone) = event.timezone.as_ref() {
+            let times = [event.start, event.end].into_iter().chain(event.exdates.iter().copied()).chain(event.recurrence_id);
+            for year in times.map(|time| time.year()) {
+                let years = self.zones.entry(zone.to_string()).or_insert((year, year));
+                *years = (years.0.min(year), years.1.max(year));
+            }
+        }
+
+        self.line("BEGIN:VEVENT");
+        self.line(&format!("UID:{}", Self::escape(&event.uid)));
+        self.line(&format!("DTSTAMP:{}", Self::format_time(event.dtstamp)));
+        self.line(&format!("LAST-MODIFIED:{}", Self::format_time(event.dtstamp)));
+        if let Some(recurrence_id) = event.recurrence_id {
+            self.line(&Self::time_property("RECURRENCE-ID", recurrence_id, event.timezone.as_deref()));
+        }
+        self.line(&Self::time_property("DTSTART", event.start, event.timezone.as_deref()));
+        self.line(&Self::time_property("DTEND", event.end, event.timezone.as_deref()));
+        if let Some(rrule) = event.rrule.as_ref() {
+            self.line(&format!("RRULE:{}", rrule));
+        }
+        for exdate in event.exdates.iter() {
+            self.line(&Self::time_property("EXDATE", *exdate, event.timezone.as_deref()));
+        }
+        self.line(&format!("SUMMARY:{}", Self::escape(&event.summary)));
+        if !event.description.is_empty() {
+            self.line(&format!("DESCRIPTION:{}", Self::escape(&event.description)));
+        }
+        if !event.location.is_empty() {
+            self.line(&format!("LOCATION:{}", Self::escape(&event.location)));
+        }
+        self.line("END:VEVENT");
+    }
+
+    pub fn into_response(self, file_name:&str) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("text/calendar; charset=utf-8")
+            .insert_header(("Content-Disposition", format!("inline; filename=\"{}\"", file_name)))
+            // calendar apps poll the feed, let them see edits soon
+            .insert_header(("Cache-Control", "no-cache, max-age=0"))
+            .body(self.finish())
+    }
+
+    // the header and zones go before the events written so far
+    fn finish(self) -> String {
+        let mut calendar = ICalendar::new(&self.name);
+        calendar.line("BEGIN:VCALENDAR");
+        calendar.line("VERSION:2.0");
+        calendar.line("PRODID:-//K Admin//Academy Calendar//EN");
+        calendar.line("CALSCALE:GREGORIAN");
+        calendar.line("METHOD:PUBLISH");
+        calendar.line(&format!("X-WR-CALNAME:{}", Self::escape(&calendar.name)));
+        for (name, (from, to)) in self.zones.iter() {
+            if let Ok(zone) = timezone::parse_timezone(name) {
+                calendar.add_timezone(zone, *from, (*to).max(Utc::now().year()) + ZONE_YEARS_AHEAD);
+            }
+        }
+        calendar.content.push_str(&self.content);
+        calendar.line("END:VCALENDAR");
+        calendar.content
+    }
+
+    // The offset in force at the start of `from_year`, then one observance per
+    // offset change up to the end of `to_year`.
+    fn add_timezone(&mut self, zone:Tz, from_year:i32, to_year:i32) {
+        let start = NaiveDate::from_ymd_opt(from_year, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let end = NaiveDate::from_ymd_opt(to_year + 1, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let initial = zone.offset_from_utc_datetime(&start);
+
+        self.line("BEGIN:VTIMEZONE");
+        self.line(&format!("TZID:{}", zone.name()));
+        self.observance(&initial, &initial, start);
+        for (at, before, after) in zone_transitions(zone, start, end) {
+            self.observance(&before, &after, at);
+        }
+        self.line("END:VTIMEZONE");
+    }
+
+    fn observance(&mut self, before:&<Tz as TimeZone>::Offset, after:&<Tz as TimeZone>::Offset, at:NaiveDateTime) {
+        let kind = if after.dst_offset().is_zero() { "STANDARD" } else { "DAYLIGHT" };
+        let from = before.fix().local_minus_utc();
+        self.line(&format!("BEGIN:{}", kind));
+        // the start is the local time before the change
+        self.line(&format!("DTSTART:{}", (at + Duration::seconds(from as i64)).format("%Y%m%dT%H%M%S")));
+        self.line(&format!("TZOFFSETFROM:{}", format_offset(from)));
+        self.line(&format!("TZOFFSETTO:{}", format_offset(after.fix().local_minus_utc())));
+        self.line(&format!("TZNAME:{}", Self::escape(after.abbreviation())));
+        self.line(&format!("END:{}", kind));
+    }
+
+    pub fn format_time(time:NaiveDateTime) -> String {
+        time.format("%Y%m%dT%H%M%SZ").to_string()
+    }
+
+    fn time_property(name:&str, time:NaiveDateTime, timezone:Option<&str>) -> String {
+        match timezone {
+            Some(timezone) => format!("{};TZID={}:{}", name, timezone, time.format("%Y%m%dT%H%M%S")),
+            None => format!("{}:{}", name, Self::format_time(time)),
+        }
+    }
+
+    // content lines are folded at 75 octets without splitting a character
+    fn line(&mut self, line:&str) {
+        let mut width = 0;
+        for c in line.chars() {
+            if width + c.len_utf8() > 75 {
+                self.content.push_str("\r\n ");
+                width = 1;
+            }
+            self.content.push(c);
+            width += c.len_utf8();
+        }
+        self.content.push_str("\r\n");
+    }
+
+    fn escape(value:&str) -> String {
+        value.replace('\\', "\\\\")
+            .replace(';', "\\;")
+            .replace(',', "\\,")
+            .replace("\r\n", "\\n")
+            .replace('\n', "\\n")
+    }
+}
+
+// UTC instants in [start, end) at which the offset or its name changes, with the
+// offsets either side. Days are scanned and each change is narrowed to the second.
+fn zone_transitions(zone:Tz, start:NaiveDateTime, end:NaiveDateTime) -> Vec<(NaiveDateTime, <Tz as TimeZone>::Offset, <Tz as TimeZone>::Offset)> {
+    let offset = |at:NaiveDateTime| zone.offset_from_utc_datetime(&at);
+    let same = |a:&<Tz as TimeZone>::Offset, b:&<Tz as TimeZone>::Offset| a.fix() == b.fix() && a.abbreviation() == b.abbreviation();
+
+    let mut transitions = Vec::new();
+    let mut day = start;
+    while day < end {
+        let next = day + Duration::days(1);
+        let (before, after) = (offset(day), offset(next));
+        if !same(&before, &after) {
+            let (mut low, mut high) = (day, next);
+            while high - low > Duration::seconds(1) {
+                let middle = low + (high - low) / 2;
+                if same(&offset(middle), &before) { low = middle } else { high = middle }
+            }
+            transitions.push((high, before, offset(high)));
+        }
+        day = next;
+    }
+    transitions
+}
+
+// +0530, seconds only when the offset has them
+fn format_offset(seconds:i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.unsigned_abs();
+    match seconds % 60 {
+        0 => format!("{}{:02}{:02}", sign, seconds / 3600, seconds / 60 % 60),
+        rest => format!("{}{:02}{:02}{:02}", sign, seconds / 3600, seconds / 60 % 60, rest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use super::{format_offset, ICalEvent, ICalendar};
+
+    fn at(y:i32, m:u32, d:u32, h:u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, 0, 0).unwrap()
+    }
+
+    fn event(timezone:Option<&str>, start:NaiveDateTime) -> ICalEvent {
+        ICalEvent {
+            uid: "1@k-admin".to_string(),
+            timezone: timezone.map(str::to_string),
+            dtstamp: at(2026, 1, 1, 0),
+            start,
+            end: at(2026, 3, 1, 19),
+            summary: "Training, juniors".to_string(),
+            description: String::new(),
+            location: String::new(),
+            rrule: Some("FREQ=WEEKLY".to_string()),
+            exdates: Vec::new(),
+            recurrence_id: None,
+        }
+    }
+
+    #[test]
+    fn local_times_come_with_their_vtimezone() {
+        let mut calendar = ICalendar::new("Academy");
+        calendar.add_event(&event(Some("Europe/London"), at(2026, 3, 1, 18)));
+        let content = calendar.finish();
+
+        assert!(!content.contains("X-WR-TIMEZONE"));
+        assert!(content.contains("DTSTART;TZID=Europe/London:20260301T180000\r\n"));
+        assert!(content.contains("BEGIN:VTIMEZONE\r\nTZID:Europe/London\r\n"));
+        // clocks go forward at 01:00 GMT on the last Sunday of March 2026
+        assert!(content.contains("BEGIN:DAYLIGHT\r\nDTSTART:20260329T010000\r\nTZOFFSETFROM:+0000\r\nTZOFFSETTO:+0100\r\nTZNAME:BST\r\nEND:DAYLIGHT\r\n"));
+        assert!(content.contains("BEGIN:STANDARD\r\nDTSTART:20261025T020000\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0000\r\nTZNAME:GMT\r\nEND:STANDARD\r\n"));
+        assert!(content.find("END:VTIMEZONE").unwrap() < content.find("BEGIN:VEVENT").unwrap());
+        assert!(content.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn zones_without_changes_have_one_observance() {
+        let mut calendar = ICalendar::new("Academy");
+        calendar.add_event(&event(Some("Asia/Kolkata"), at(2026, 3, 1, 18)));
+        let content = calendar.finish();
+
+        assert_eq!(content.matches("BEGIN:STANDARD").count(), 1);
+        assert!(!content.contains("BEGIN:DAYLIGHT"));
+        assert!(content.contains("TZOFFSETFROM:+0530\r\nTZOFFSETTO:+0530\r\nTZNAME:IST\r\n"));
+    }
+
+    #[test]
+    fn utc_events_need_no_vtimezone() {
+        let mut calendar = ICalendar::new("Academy");
+        calendar.add_event(&event(None, at(2026, 3, 1, 18)));
+        let content = calendar.finish();
+
+        assert!(!content.contains("VTIMEZONE"));
+        assert!(content.contains("DTSTART:20260301T180000Z\r\n"));
+        assert!(content.contains("SUMMARY:Training\\, juniors\r\n"));
+    }
+
+    #[test]
+    fn offsets_are_signed_hours_and_minutes() {
+        assert_eq!(format_offset(19_800), "+0530");
+        assert_eq!(format_offset(-12_600), "-0330");
+        assert_eq!(format_offset(0), "+0000");
+        assert_eq!(format_offset(-1_475), "-002435");
+    }
+}
@@ -0,0 +1,103 @@
+use std::{fs::File, io::BufWriter, path::{Path, PathBuf}};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageReader, Limits};
+use uuid::Uuid;
+
+use super::app_errors::AppError;
+
+// longest side of each rendition, the original keeps its size
+const SIZES:[(&str, Option<u32>); 3] = [("thumbnail", Some(320)), ("medium", Some(1280)), ("original", None)];
+const JPEG_QUALITY:u8 = 85;
+// refuse decompression bombs before any pixels are allocated
+const MAX_DIMENSION:u32 = 12_000;
+
+// one size of an uploaded image written to temporary files
+pub struct Rendition {
+    pub label:&'static str,
+    pub width:u32,
+    pub height:u32,
+    pub jpeg:PathBuf
+}
+
+impl Rendition {
+    pub fn remove(&self) {
+        if let Err(e) = std::fs::remove_file(&self.jpeg) {
+            println!("Could not remove rendition {:?} {}", self.jpeg, e);
+        }
+    }
+}
+
+// gif is stored as it is so animations survive, heic can not be decoded
+pub fn can_process(mime:&str) -> bool {
+    matches!(mime, "image/jpeg" | "image/png" | "image/webp")
+}
+
+// Decodes the image, turns it upright from its EXIF orientation and writes the
+// thumbnail, medium and original sizes as JPEG. The image crate only encodes
+// lossless WebP, which comes out larger than the JPEG, so there is no WebP
+// rendition. Re-encoding drops all metadata, GPS position included. Blocking,
+// run it on the blocking pool.
+pub fn render(source:&Path) -> Result<Vec<Rendition>, AppError> {
+    let image = decode(source)?;
+    let mut renditions:Vec<Rendition> = Vec::new();
+
+    for (label, max_side) in SIZES {
+        let resized = match max_side {
+            Some(max_side) if image.width() > max_side || image.height() > max_side => image.resize(max_side, max_side, FilterType::Lanczos3),
+            _ => image.clone(),
+        };
+
+        match write_rendition(&resized, label) {
+            Ok(rendition) => renditions.push(rendition),
+            Err(e) => {
+                renditions.iter().for_each(|r| r.remove());
+                return Err(e);
+            },
+        }
+    }
+
+    Ok(renditions)
+}
+
+fn decode(source:&Path) -> Result<DynamicImage, AppError> {
+    let invalid = |e:image::ImageError| AppError::CustomError(format!("Could not read image: {}", e));
+
+    let mut reader = ImageReader::open(source)
+        .map_err(|e| AppError::CustomError(e.to_string()))?
+        .with_guessed_format()
+        .map_err(|e| AppError::CustomError(e.to_string()))?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder().map_err(invalid)?;
+    let orientation = decoder.orientation().map_err(invalid)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+fn write_rendition(image:&DynamicImage, label:&'static str) -> Result<Rendition, AppError> {
+    let name = format!("{}_{}", Uuid::new_v4(), label);
+    let rendition = Rendition {
+        label,
+        width: image.width(),
+        height: image.height(),
+        jpeg: std::env::temp_dir().join(format!("{}.jpg", name)),
+    };
+
+    let encoded = File::create(&rendition.jpeg)
+        .map(BufWriter::new)
+        .map_err(|e| AppError::CustomError(e.to_string()))
+        // JPEG has no alpha channel
+        .and_then(|file| image.to_rgb8().write_with_encoder(JpegEncoder::new_with_quality(file, JPEG_QUALITY))
+            .map_err(|e| AppError::CustomError(e.to_string())));
+
+    if let Err(e) = encoded {
+        let _ = std::fs::remove_file(&rendition.jpeg);
+        return Err(e);
+    }
+
+    Ok(rendition)
+}
@@ -1,3 +1,14 @@
 pub mod app_errors;
 pub mod response;
-pub mod helper;
\ No newline at end of file
+pub mod unique_number;
+pub mod crypto;
+pub mod csv;
+pub mod rrule;
+pub mod ical;
+pub mod timezone;
+pub mod video_link;
+pub mod upload;
+pub mod storage;
+pub mod image_pipeline;
+pub mod branch_scope;
+pub mod tenant;
\ No newline at end of file
@@ -0,0 +1,315 @@
+use core::fmt;
+
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, Weekday};
+
+use super::app_errors::AppError;
+
+// stops a rule without UNTIL or COUNT from expanding forever on a wide range
+const MAX_PERIODS:u32 = 50_000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Frequency {
+    DAILY,
+    WEEKLY,
+    MONTHLY,
+    YEARLY
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frequency::DAILY => write!(f, "DAILY"),
+            Frequency::WEEKLY => write!(f, "WEEKLY"),
+            Frequency::MONTHLY => write!(f, "MONTHLY"),
+            Frequency::YEARLY => write!(f, "YEARLY"),
+        }
+    }
+}
+
+// The subset of RFC 5545 RRULE the academy calendar needs: FREQ, INTERVAL,
+// BYDAY (weekly, plain weekdays), BYMONTHDAY (monthly), UNTIL and COUNT.
+// Times are UTC, every occurrence keeps the time of day of the first one.
+#[derive(Clone)]
+pub struct RRule {
+    pub freq:Frequency,
+    pub interval:u32,
+    pub by_day:Vec<Weekday>,
+    pub by_month_day:Vec<i32>,
+    pub until:Option<NaiveDateTime>,
+    // UNTIL was given as a date, the caller resolves the end of that day in the
+    // event's zone before the rule is stored
+    pub until_date:bool,
+    pub count:Option<u32>
+}
+
+impl RRule {
+    pub fn parse(rule:&str) -> Result<Self, AppError> {
+        let rule = rule.trim();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+
+        let mut freq:Option<Frequency> = None;
+        let mut rrule = RRule { freq: Frequency::DAILY, interval: 1, by_day: Vec::new(), by_month_day: Vec::new(), until: None, until_date: false, count: None };
+
+        for part in rule.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part.split_once('=')
+                .ok_or_else(|| Self::invalid(part))?;
+
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => freq = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Frequency::DAILY,
+                    "WEEKLY" => Frequency::WEEKLY,
+                    "MONTHLY" => Frequency::MONTHLY,
+                    "YEARLY" => Frequency::YEARLY,
+                    _ => return Err(Self::invalid(part)),
+                }),
+                "INTERVAL" => rrule.interval = value.parse::<u32>().ok().filter(|i| *i > 0)
+                    .ok_or_else(|| Self::invalid(part))?,
+                "COUNT" => rrule.count = Some(value.parse::<u32>().ok().filter(|c| *c > 0)
+                    .ok_or_else(|| Self::invalid(part))?),
+                "UNTIL" => {
+                    rrule.until = Some(Self::parse_until(value).ok_or_else(|| Self::invalid(part))?);
+                    rrule.until_date = !value.contains('T');
+                },
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        rrule.by_day.push(Self::parse_weekday(day).ok_or_else(|| Self::invalid(part))?);
+                    }
+                },
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        let day = day.parse::<i32>().ok().filter(|d| (1..=31).contains(&d.abs()))
+                            .ok_or_else(|| Self::invalid(part))?;
+                        rrule.by_month_day.push(day);
+                    }
+                },
+                "WKST" => {},
+                _ => return Err(AppError::CustomError(format!("recurrence rule part {} is not supported", part))),
+            }
+        }
+
+        rrule.freq = freq.ok_or_else(|| AppError::CustomError("recurrence rule needs a FREQ".to_string()))?;
+
+        if rrule.until.is_some() && rrule.count.is_some() {
+            return Err(AppError::CustomError("recurrence rule can not have both UNTIL and COUNT".to_string()));
+        }
+        if !rrule.by_day.is_empty() && rrule.freq != Frequency::WEEKLY {
+            return Err(AppError::CustomError("BYDAY is only supported for weekly rules".to_string()));
+        }
+        if !rrule.by_month_day.is_empty() && rrule.freq != Frequency::MONTHLY {
+            return Err(AppError::CustomError("BYMONTHDAY is only supported for monthly rules".to_string()));
+        }
+
+        rrule.by_day.sort_by_key(|d| d.num_days_from_monday());
+        rrule.by_day.dedup();
+        Ok(rrule)
+    }
+
+    // Occurrence starts of the series beginning at `start` that fall in [from, to).
+    pub fn occurrences(&self, start:NaiveDateTime, from:NaiveDateTime, to:NaiveDateTime) -> Vec<NaiveDateTime> {
+        let mut occurrences:Vec<NaiveDateTime> = Vec::new();
+        let mut seen:u32 = 0;
+
+        for period in 0..MAX_PERIODS {
+            let candidates = self.period_dates(start.date(), period);
+            // a monthly or yearly period can be empty (31st of a short month)
+            if candidates.is_empty() && self.period_start(start.date(), period).is_none() {
+                break;
+            }
+
+            for date in candidates {
+                let occurrence = date.and_time(start.time());
+                if occurrence < start {
+                    continue;
+                }
+                if self.until.is_some_and(|until| occurrence > until) || self.count.is_some_and(|count| seen >= count) || occurrence >= to {
+                    return occurrences;
+                }
+
+                seen += 1;
+                if occurrence >= from {
+                    occurrences.push(occurrence);
+                }
+            }
+        }
+
+        occurrences
+    }
+
+    // true when `occurrence` is one of the starts of the series
+    pub fn includes(&self, start:NaiveDateTime, occurrence:NaiveDateTime) -> bool {
+        self.occurrences(start, occurrence, occurrence + Duration::seconds(1)).first() == Some(&occurrence)
+    }
+
+    fn period_start(&self, start:NaiveDate, period:u32) -> Option<NaiveDate> {
+        let step = period.checked_mul(self.interval)?;
+        match self.freq {
+            Frequency::DAILY => start.checked_add_signed(Duration::days(step as i64)),
+            Frequency::WEEKLY => start.checked_add_signed(Duration::weeks(step as i64) - Duration::days(start.weekday().num_days_from_monday() as i64)),
+            Frequency::MONTHLY => start.with_day(1)?.checked_add_months(Months::new(step)),
+            Frequency::YEARLY => start.with_day(1)?.with_month(1)?.checked_add_months(Months::new(step.checked_mul(12)?)),
+        }
+    }
+
+    fn period_dates(&self, start:NaiveDate, period:u32) -> Vec<NaiveDate> {
+        let period_start = match self.period_start(start, period) {
+            Some(date) => date,
+            None => return Vec::new(),
+        };
+
+        match self.freq {
+            Frequency::DAILY => vec![period_start],
+            Frequency::WEEKLY => {
+                let days = if self.by_day.is_empty() { vec![start.weekday()] } else { self.by_day.clone() };
+                days.iter().map(|d| period_start + Duration::days(d.num_days_from_monday() as i64)).collect()
+            },
+            Frequency::MONTHLY => {
+                let days = if self.by_month_day.is_empty() { vec![start.day() as i32] } else { self.by_month_day.clone() };
+                let month_length = Self::month_length(period_start);
+                let mut dates:Vec<NaiveDate> = days.iter()
+                    .map(|d| if *d < 0 { month_length as i32 + d + 1 } else { *d })
+                    .filter_map(|d| period_start.with_day(u32::try_from(d).ok()?))
+                    .collect();
+                dates.sort();
+                dates.dedup();
+                dates
+            },
+            // Feb 29 only comes around in leap years
+            Frequency::YEARLY => NaiveDate::from_ymd_opt(period_start.year(), start.month(), start.day()).into_iter().collect(),
+        }
+    }
+
+    fn month_length(first_day:NaiveDate) -> u32 {
+        first_day.checked_add_months(Months::new(1))
+            .map(|next| (next - first_day).num_days() as u32)
+            .unwrap_or(31)
+    }
+
+    fn parse_weekday(day:&str) -> Option<Weekday> {
+        match day.to_ascii_uppercase().as_str() {
+            "MO" => Some(Weekday::Mon),
+            "TU" => Some(Weekday::Tue),
+            "WE" => Some(Weekday::Wed),
+            "TH" => Some(Weekday::Thu),
+            "FR" => Some(Weekday::Fri),
+            "SA" => Some(Weekday::Sat),
+            "SU" => Some(Weekday::Sun),
+            _ => None,
+        }
+    }
+
+    // UNTIL is a date (inclusive for the whole day) or a UTC date-time
+    fn parse_until(value:&str) -> Option<NaiveDateTime> {
+        let value = value.trim_end_matches('Z');
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+            .or_else(|| NaiveDate::parse_from_str(value, "%Y%m%d").ok()?.and_hms_opt(23, 59, 59))
+    }
+
+    fn invalid(part:&str) -> AppError {
+        AppError::CustomError(format!("Invalid recurrence rule part: {}", part))
+    }
+}
+
+// normalised form that is stored on the event
+impl fmt::Display for RRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FREQ={}", self.freq)?;
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days:Vec<String> = self.by_day.iter().map(|d| d.to_string()[..2].to_ascii_uppercase()).collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if !self.by_month_day.is_empty() {
+            let days:Vec<String> = self.by_month_day.iter().map(|d| d.to_string()).collect();
+            write!(f, ";BYMONTHDAY={}", days.join(","))?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime, Weekday};
+
+    use super::{Frequency, RRule};
+
+    fn at(y:i32, m:u32, d:u32, h:u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn rules_parse_to_a_normalised_form() {
+        let rule = RRule::parse("RRULE:freq=weekly;BYDAY=FR,MO,MO;INTERVAL=2;UNTIL=20260630").unwrap();
+        assert!(rule.freq == Frequency::WEEKLY);
+        assert_eq!(rule.by_day, vec![Weekday::Mon, Weekday::Fri]);
+        assert_eq!(rule.to_string(), "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR;UNTIL=20260630T235959Z");
+        assert_eq!(RRule::parse(&rule.to_string()).unwrap().to_string(), rule.to_string());
+    }
+
+    #[test]
+    fn invalid_rules_are_refused() {
+        for rule in [
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;COUNT=3;UNTIL=20260101",
+            "FREQ=DAILY;BYDAY=MO",
+            "FREQ=WEEKLY;BYMONTHDAY=1",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "FREQ=DAILY;BYSETPOS=1",
+            "FREQ",
+        ] {
+            assert!(RRule::parse(rule).is_err(), "{} should be refused", rule);
+        }
+    }
+
+    #[test]
+    fn weekly_rules_expand_on_their_days() {
+        // 2026-06-01 is a Monday
+        let rule = RRule::parse("FREQ=WEEKLY;BYDAY=MO,WE;COUNT=5").unwrap();
+        let occurrences = rule.occurrences(at(2026, 6, 3, 18), at(2026, 1, 1, 0), at(2027, 1, 1, 0));
+        assert_eq!(occurrences, vec![
+            at(2026, 6, 3, 18), at(2026, 6, 8, 18), at(2026, 6, 10, 18), at(2026, 6, 15, 18), at(2026, 6, 17, 18),
+        ]);
+
+        // COUNT counts from the start of the series, not the window
+        let window = rule.occurrences(at(2026, 6, 3, 18), at(2026, 6, 9, 0), at(2027, 1, 1, 0));
+        assert_eq!(window, vec![at(2026, 6, 10, 18), at(2026, 6, 15, 18), at(2026, 6, 17, 18)]);
+    }
+
+    #[test]
+    fn monthly_rules_skip_missing_days_and_count_from_the_end() {
+        let rule = RRule::parse("FREQ=MONTHLY;UNTIL=20260501").unwrap();
+        let occurrences = rule.occurrences(at(2026, 1, 31, 9), at(2026, 1, 1, 0), at(2027, 1, 1, 0));
+        assert_eq!(occurrences, vec![at(2026, 1, 31, 9), at(2026, 3, 31, 9)]);
+
+        let last_day = RRule::parse("FREQ=MONTHLY;BYMONTHDAY=-1;COUNT=3").unwrap();
+        let occurrences = last_day.occurrences(at(2026, 1, 1, 9), at(2026, 1, 1, 0), at(2027, 1, 1, 0));
+        assert_eq!(occurrences, vec![at(2026, 1, 31, 9), at(2026, 2, 28, 9), at(2026, 3, 31, 9)]);
+    }
+
+    #[test]
+    fn yearly_rules_keep_leap_days_in_leap_years() {
+        let rule = RRule::parse("FREQ=YEARLY;COUNT=2").unwrap();
+        let occurrences = rule.occurrences(at(2024, 2, 29, 10), at(2024, 1, 1, 0), at(2040, 1, 1, 0));
+        assert_eq!(occurrences, vec![at(2024, 2, 29, 10), at(2028, 2, 29, 10)]);
+    }
+
+    #[test]
+    fn includes_matches_only_occurrence_starts() {
+        let rule = RRule::parse("FREQ=DAILY;INTERVAL=2").unwrap();
+        let start = at(2026, 6, 1, 7);
+        assert!(rule.includes(start, at(2026, 6, 5, 7)));
+        assert!(!rule.includes(start, at(2026, 6, 4, 7)));
+        assert!(!rule.includes(start, at(2026, 6, 5, 8)));
+        assert!(!rule.includes(start, at(2026, 5, 30, 7)));
+    }
+}
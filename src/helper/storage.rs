@@ -0,0 +1,545 @@
+use std::{env, fs, io::ErrorKind, path::{Path, PathBuf}, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::models::media::ImageVariants;
+
+use super::{app_errors::AppError, crypto::Crypto};
+
+// the public folders uploads used to be written to, their names are the key prefixes
+pub const UPLOAD_PREFIXES:[&str; 4] = ["student", "event", "facilities", "academy"];
+// student files are never public, they are read through signed urls
+pub const PRIVATE_PREFIXES:[&str; 1] = ["student"];
+const LEGACY_PREFIX:&str = "/static/";
+const DEFAULT_URL_TTL:i64 = 60 * 60;
+// x-amz-content-sha256 of a streamed body, the TLS connection protects it
+const UNSIGNED_PAYLOAD:&str = "UNSIGNED-PAYLOAD";
+
+lazy_static! {
+    static ref STORAGE: Arc<dyn Storage> = init_storage();
+    // entries of an S3 ListObjectsV2 response
+    static ref S3_CONTENTS_REGEX: Regex = Regex::new(r"(?s)<Contents>.*?<Key>(.*?)</Key>.*?<LastModified>(.*?)</LastModified>.*?<Size>(\d+)</Size>.*?</Contents>").unwrap();
+    static ref S3_CONTINUATION_REGEX: Regex = Regex::new(r"<NextContinuationToken>(.*?)</NextContinuationToken>").unwrap();
+}
+
+// Uploaded files are stored under a key such as event/<uuid>.jpg, the models keep
+// only the key and the DTOs resolve it to a URL of whichever backend is in use.
+pub trait Storage: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // copies the file at `source` to `key`, the source is left in place
+    fn put<'a>(&'a self, key:&'a str, source:&'a Path, content_type:&'a str) -> BoxFuture<'a, Result<(), AppError>>;
+
+    // removing a key that is not there is not an error
+    fn delete<'a>(&'a self, key:&'a str) -> BoxFuture<'a, Result<(), AppError>>;
+
+    // every stored file whose key starts with `prefix`
+    fn list<'a>(&'a self, prefix:&'a str) -> BoxFuture<'a, Result<Vec<StoredObject>, AppError>>;
+
+    fn url(&self, key:&str) -> String;
+
+    // url of a private key that stops working at `expires` (unix seconds)
+    fn signed_url(&self, key:&str, expires:i64) -> String;
+}
+
+pub struct StoredObject {
+    pub key:String,
+    pub size:u64,
+    pub modified:DateTime<Utc>
+}
+
+// STORAGE_BACKEND=s3 uses the S3 compatible store, anything else ./static
+pub fn init_storage() -> Arc<dyn Storage> {
+    match env::var("STORAGE_BACKEND").unwrap_or_default().as_str() {
+        "s3" => Arc::new(S3Storage::init()),
+        _ => Arc::new(LocalStorage::init()),
+    }
+}
+
+pub fn storage() -> Arc<dyn Storage> {
+    STORAGE.clone()
+}
+
+// The storage key of a stored value, None for external links. Values written
+// before keys were stored are /static/ paths.
+pub fn key_of(value:&str) -> Option<&str> {
+    if value.starts_with("http://") || value.starts_with("https://") {
+        return None;
+    }
+    let key = value.strip_prefix(LEGACY_PREFIX).unwrap_or(value);
+    if key.is_empty() || key.starts_with('/') || key.split('/').any(|part| part == ".." || part == ".") {
+        return None;
+    }
+    Some(key)
+}
+
+pub fn is_private(key:&str) -> bool {
+    key.split('/').next().is_some_and(|prefix| PRIVATE_PREFIXES.contains(&prefix))
+}
+
+// URL the apps load a stored value from, signed for private keys. Links are
+// returned as they are.
+pub fn file_url(value:&str) -> String {
+    match key_of(value) {
+        Some(key) if is_private(key) => storage().signed_url(key, url_expiry(Utc::now().timestamp())),
+        Some(key) => storage().url(key),
+        None => value.to_string(),
+    }
+}
+
+// every rendition of an image with its url
+pub fn variant_urls(variants:ImageVariants) -> ImageVariants {
+    variants.map_keys(file_url)
+}
+
+// Expiry rounded up to a whole FILE_URL_TTL window so the same url is handed
+// out for a while and the apps can cache the file, it is valid at least one TTL.
+fn url_expiry(now:i64) -> i64 {
+    let ttl = env::var("FILE_URL_TTL").ok()
+        .and_then(|ttl| ttl.parse::<i64>().ok())
+        .filter(|ttl| *ttl > 0)
+        .unwrap_or(DEFAULT_URL_TTL);
+    (now / ttl + 2) * ttl
+}
+
+// FILE_URL_SECRET, or the JWT secret when it is not set, main refuses to start
+// without one so file urls are never signed with an empty key
+pub fn file_url_secret() -> Result<String, AppError> {
+    env::var("FILE_URL_SECRET").ok()
+        .or_else(|| env::var("Jwt_Secrete_Key").ok())
+        .filter(|secret| !secret.trim().is_empty())
+        .ok_or_else(|| AppError::CustomError("FILE_URL_SECRET is not set".to_string()))
+}
+
+// HMAC of the key and expiry, rotating FILE_URL_SECRET revokes every signed url
+fn file_signature(secret:&str, key:&str, expires:i64) -> String {
+    Crypto::hmac_sha256_hex(secret.as_bytes(), format!("file:{}:{}", key, expires).as_bytes())
+}
+
+pub fn verify_signature(key:&str, expires:i64, signature:&str) -> bool {
+    file_url_secret().is_ok_and(|secret| signature_valid(&secret, key, expires, signature, Utc::now().timestamp()))
+}
+
+fn signature_valid(secret:&str, key:&str, expires:i64, signature:&str, now:i64) -> bool {
+    expires > now
+        && Crypto::constant_time_eq(file_signature(secret, key, expires).as_bytes(), signature.trim().to_lowercase().as_bytes())
+}
+
+// Copies every file under the local upload folders to `target`, used to move
+// an existing install to S3. Returns the number of files copied.
+pub async fn migrate_local_files(target:&dyn Storage) -> Result<u64, AppError> {
+    let local = LocalStorage::init();
+    let mut copied = 0;
+
+    for prefix in UPLOAD_PREFIXES {
+        let entries = match fs::read_dir(local.dir(prefix)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        for entry in entries {
+            let entry = entry.map_err(|e| AppError::CustomError(e.to_string()))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || !entry.path().is_file() {
+                continue;
+            }
+
+            let key = format!("{}/{}", prefix, name);
+            target.put(&key, &entry.path(), content_type(&name)).await?;
+            println!("Copied {} to {}", key, target.name());
+            copied += 1;
+        }
+    }
+
+    Ok(copied)
+}
+
+fn content_type(name:&str) -> &'static str {
+    match name.rsplit('.').next().unwrap_or_default().to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+// Public files under ./static, served by actix_files at /static. Private files
+// are kept in ./private and only read through /api/files with a signature.
+pub struct LocalStorage {
+    root:PathBuf,
+    private_root:PathBuf,
+    base_url:String
+}
+
+impl LocalStorage {
+    pub fn init() -> Self {
+        LocalStorage {
+            root: PathBuf::from("static"),
+            private_root: PathBuf::from("private"),
+            base_url: env::var("PUBLIC_BASE_URL").unwrap_or("http://192.168.0.119:8000".to_string()),
+        }
+    }
+
+    pub fn path(&self, key:&str) -> Result<PathBuf, AppError> {
+        key_of(key)
+            .map(|key| self.dir(key))
+            .ok_or_else(|| AppError::CustomError(format!("Invalid storage key: {}", key)))
+    }
+
+    fn dir(&self, key:&str) -> PathBuf {
+        if is_private(key) {
+            self.private_root.join(key)
+        } else {
+            self.root.join(key)
+        }
+    }
+
+    // Private uploads written to ./static before they were protected are moved
+    // out of the public folder. Returns the number of files moved.
+    pub fn move_private_files(&self) -> Result<u64, AppError> {
+        let mut moved = 0;
+        for prefix in PRIVATE_PREFIXES {
+            let entries = match fs::read_dir(self.root.join(prefix)) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(AppError::CustomError(e.to_string())),
+            };
+
+            fs::create_dir_all(self.dir(prefix)).map_err(|e| AppError::CustomError(e.to_string()))?;
+            for entry in entries {
+                let entry = entry.map_err(|e| AppError::CustomError(e.to_string()))?;
+                if !entry.path().is_file() {
+                    continue;
+                }
+                fs::rename(entry.path(), self.dir(prefix).join(entry.file_name())).map_err(|e| AppError::CustomError(e.to_string()))?;
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+}
+
+impl Storage for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn put<'a>(&'a self, key:&'a str, source:&'a Path, _content_type:&'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+            }
+            tokio::fs::copy(source, path).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, key:&'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(key)?).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(AppError::CustomError(e.to_string())),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn list<'a>(&'a self, prefix:&'a str) -> BoxFuture<'a, Result<Vec<StoredObject>, AppError>> {
+        Box::pin(async move {
+            let prefix = prefix.trim_end_matches('/');
+            let entries = match fs::read_dir(self.dir(prefix)) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(AppError::CustomError(e.to_string())),
+            };
+
+            let mut objects:Vec<StoredObject> = Vec::new();
+            for entry in entries {
+                let entry = entry.map_err(|e| AppError::CustomError(e.to_string()))?;
+                let metadata = entry.metadata().map_err(|e| AppError::CustomError(e.to_string()))?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') || !metadata.is_file() {
+                    continue;
+                }
+                objects.push(StoredObject {
+                    key: format!("{}/{}", prefix, name),
+                    size: metadata.len(),
+                    modified: metadata.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now()),
+                });
+            }
+            Ok(objects)
+        })
+    }
+
+    fn url(&self, key:&str) -> String {
+        format!("{}/static/{}", self.base_url, key)
+    }
+
+    fn signed_url(&self, key:&str, expires:i64) -> String {
+        // without a secret the url carries no signature and is refused
+        let signature = file_url_secret()
+            .map(|secret| file_signature(&secret, key, expires))
+            .unwrap_or_default();
+        format!("{}/api/files/{}?expires={}&signature={}", self.base_url, key, expires, signature)
+    }
+}
+
+// S3 or a compatible store such as MinIO, addressed path style
+// ({endpoint}/{bucket}/{key}) and signed with AWS Signature Version 4. The
+// bucket should only allow public reads of the public prefixes, private keys
+// are handed out as presigned urls.
+pub struct S3Storage {
+    endpoint:String,
+    bucket:String,
+    region:String,
+    access_key:String,
+    secret_key:String,
+    // CDN or bucket website in front of the bucket, the endpoint when not set
+    public_url:Option<String>,
+    client:reqwest::Client
+}
+
+impl S3Storage {
+    pub fn init() -> Self {
+        S3Storage {
+            endpoint: env::var("S3_ENDPOINT").unwrap_or("https://s3.amazonaws.com".to_string()).trim_end_matches('/').to_string(),
+            bucket: env::var("S3_BUCKET").unwrap_or_default(),
+            region: env::var("S3_REGION").unwrap_or("us-east-1".to_string()),
+            access_key: env::var("S3_ACCESS_KEY").unwrap_or_default(),
+            secret_key: env::var("S3_SECRET_KEY").unwrap_or_default(),
+            public_url: env::var("S3_PUBLIC_URL").ok().map(|url| url.trim_end_matches('/').to_string()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn object_path(&self, key:&str) -> String {
+        format!("/{}/{}", Self::uri_encode(&self.bucket), Self::uri_encode(key))
+    }
+
+    async fn send(&self, method:reqwest::Method, key:&str, file:Option<(tokio::fs::File, u64)>, content_type:Option<&str>) -> Result<reqwest::Response, AppError> {
+        let key = key_of(key).ok_or_else(|| AppError::CustomError(format!("Invalid storage key: {}", key)))?;
+        self.request(method, &self.object_path(key), "", file, content_type).await
+    }
+
+    // `query` is the canonical query string, sorted and encoded. A file body is
+    // streamed with its length and left out of the signature, so an upload is
+    // never held in memory.
+    async fn request(&self, method:reqwest::Method, path:&str, query:&str, file:Option<(tokio::fs::File, u64)>, content_type:Option<&str>) -> Result<reqwest::Response, AppError> {
+        let url = if query.is_empty() { format!("{}{}", self.endpoint, path) } else { format!("{}{}?{}", self.endpoint, path, query) };
+        let host = self.host().ok_or_else(|| AppError::CustomError(format!("Invalid S3 endpoint: {}", self.endpoint)))?;
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = match file {
+            Some(_) => UNSIGNED_PAYLOAD.to_string(),
+            None => Crypto::sha256_hex(&[]),
+        };
+        let authorization = self.authorization(method.as_str(), path, query, &host, &amz_date, &payload_hash);
+
+        let mut request = self.client.request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("Authorization", authorization);
+        if let Some(content_type) = content_type {
+            request = request.header("Content-Type", content_type);
+        }
+        if let Some((file, length)) = file {
+            // S3 refuses chunked uploads, the length goes in front
+            request = request.header("Content-Length", length).body(reqwest::Body::from(file));
+        }
+
+        request.send().await.map_err(|e| AppError::CustomError(e.to_string()))
+    }
+
+    fn authorization(&self, method:&str, path:&str, query:&str, host:&str, amz_date:&str, payload_hash:&str) -> String {
+        let scope = self.scope(amz_date);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, self.signature(amz_date, &canonical_request)
+        )
+    }
+
+    fn host(&self) -> Option<String> {
+        let url = reqwest::Url::parse(&self.endpoint).ok()?;
+        let host = url.host_str()?;
+        Some(match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        })
+    }
+
+    fn scope(&self, amz_date:&str) -> String {
+        format!("{}/{}/s3/aws4_request", &amz_date[..8], self.region)
+    }
+
+    fn signature(&self, amz_date:&str, canonical_request:&str) -> String {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, self.scope(amz_date), Crypto::sha256_hex(canonical_request.as_bytes())
+        );
+
+        let signing_key = ["s3", "aws4_request"].iter().fold(
+            Crypto::hmac_sha256(
+                &Crypto::hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &amz_date.as_bytes()[..8]),
+                self.region.as_bytes()
+            ),
+            |key, part| Crypto::hmac_sha256(&key, part.as_bytes())
+        );
+        Crypto::hmac_sha256_hex(&signing_key, string_to_sign.as_bytes())
+    }
+
+    // RFC 3986 unreserved characters and the path separator are kept as they are
+    fn uri_encode(value:&str) -> String {
+        value.bytes().map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        }).collect()
+    }
+
+    // query values encode the separator too
+    fn query_encode(value:&str) -> String {
+        Self::uri_encode(value).replace('/', "%2F")
+    }
+}
+
+impl Storage for S3Storage {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn put<'a>(&'a self, key:&'a str, source:&'a Path, content_type:&'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let file = tokio::fs::File::open(source).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+            let length = file.metadata().await.map_err(|e| AppError::CustomError(e.to_string()))?.len();
+            let response = self.send(reqwest::Method::PUT, key, Some((file, length)), Some(content_type)).await?;
+            if !response.status().is_success() {
+                return Err(AppError::CustomError(format!("storage returned {} for {}", response.status(), key)));
+            }
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, key:&'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let response = self.send(reqwest::Method::DELETE, key, None, None).await?;
+            if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+                return Err(AppError::CustomError(format!("storage returned {} for {}", response.status(), key)));
+            }
+            Ok(())
+        })
+    }
+
+    // ListObjectsV2, a page at a time
+    fn list<'a>(&'a self, prefix:&'a str) -> BoxFuture<'a, Result<Vec<StoredObject>, AppError>> {
+        Box::pin(async move {
+            let path = format!("/{}", Self::uri_encode(&self.bucket));
+            let mut objects:Vec<StoredObject> = Vec::new();
+            let mut continuation:Option<String> = None;
+
+            loop {
+                let mut query = String::new();
+                if let Some(token) = continuation.as_ref() {
+                    query.push_str(&format!("continuation-token={}&", Self::query_encode(token)));
+                }
+                query.push_str(&format!("list-type=2&prefix={}", Self::query_encode(prefix)));
+
+                let response = self.request(reqwest::Method::GET, &path, &query, None, None).await?;
+                if !response.status().is_success() {
+                    return Err(AppError::CustomError(format!("storage returned {} listing {}", response.status(), prefix)));
+                }
+                let body = response.text().await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+                for entry in S3_CONTENTS_REGEX.captures_iter(&body) {
+                    objects.push(StoredObject {
+                        key: entry[1].replace("&amp;", "&"),
+                        size: entry[3].parse().unwrap_or_default(),
+                        modified: DateTime::parse_from_rfc3339(&entry[2]).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now()),
+                    });
+                }
+
+                continuation = S3_CONTINUATION_REGEX.captures(&body).map(|c| c[1].to_string());
+                if continuation.is_none() {
+                    return Ok(objects);
+                }
+            }
+        })
+    }
+
+    fn url(&self, key:&str) -> String {
+        match self.public_url.as_ref() {
+            Some(public_url) => format!("{}/{}", public_url, Self::uri_encode(key)),
+            None => format!("{}{}", self.endpoint, self.object_path(key)),
+        }
+    }
+
+    // query string presigned GET, S3 caps the lifetime at seven days
+    fn signed_url(&self, key:&str, expires:i64) -> String {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let expires_in = (expires - now.timestamp()).clamp(1, 7 * 24 * 60 * 60);
+
+        let query = format!(
+            "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
+            Self::query_encode(&format!("{}/{}", self.access_key, self.scope(&amz_date))), amz_date, expires_in
+        );
+        let canonical_request = format!(
+            "GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+            self.object_path(key), query, self.host().unwrap_or_default()
+        );
+
+        format!(
+            "{}{}?{}&X-Amz-Signature={}",
+            self.endpoint, self.object_path(key), query, self.signature(&amz_date, &canonical_request)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET:&str = "test-file-secret";
+
+    #[test]
+    fn key_of_rejects_links_and_path_traversal() {
+        assert_eq!(key_of("/static/event/a.jpg"), Some("event/a.jpg"));
+        assert_eq!(key_of("student/b.pdf"), Some("student/b.pdf"));
+        assert_eq!(key_of("https://cdn.example.com/a.jpg"), None);
+        assert_eq!(key_of("/static/../secrets.env"), None);
+        assert_eq!(key_of("student/../../etc/passwd"), None);
+        assert_eq!(key_of("./student/b.pdf"), None);
+        assert_eq!(key_of("/etc/passwd"), None);
+        assert_eq!(key_of("/static/"), None);
+    }
+
+    #[test]
+    fn signatures_expire_and_are_bound_to_key_and_secret() {
+        let now = 1_700_000_000;
+        let expires = now + 60;
+        let signature = file_signature(SECRET, "student/b.pdf", expires);
+
+        assert!(signature_valid(SECRET, "student/b.pdf", expires, &signature, now));
+        assert!(signature_valid(SECRET, "student/b.pdf", expires, &signature.to_uppercase(), now));
+        assert!(!signature_valid(SECRET, "student/b.pdf", expires, &signature, expires));
+        assert!(!signature_valid(SECRET, "student/c.pdf", expires, &signature, now));
+        assert!(!signature_valid(SECRET, "student/b.pdf", expires + 60, &signature, now));
+        assert!(!signature_valid("other-secret", "student/b.pdf", expires, &signature, now));
+        assert!(!signature_valid(SECRET, "student/b.pdf", expires, "", now));
+    }
+}
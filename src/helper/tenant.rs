@@ -0,0 +1,118 @@
+use std::{env, future::Future, marker::PhantomData, ops::Deref, pin::Pin, sync::Arc};
+
+use actix_web::{dev::Payload, error::InternalError, web::Data, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+
+use crate::{models::settings::AcademySettings, repo::{app_repo::AppRepo, curriculum_repo::CurriculumRepo, events_repo::EventRepo, finance_repo::FinanceRepo, report_repo::ReportRepo, settings_repo::SettingsRepo, student_repo::StudentRepo, tenant_repo::{TenantRegistry, TenantRepos, DEFAULT_TENANT}, user_repo::UserRepo}};
+
+use super::{app_errors::AppError, response::ResponseBuilder};
+
+// A repository of the academy the request belongs to. Handlers take it in
+// place of Data<Repo> and use it the same way.
+pub struct Tenant<T> {
+    repos:Arc<TenantRepos>,
+    repo:PhantomData<T>
+}
+
+impl<T> Tenant<T> {
+    pub fn slug(&self) -> &str {
+        &self.repos.slug
+    }
+
+    pub fn repos(&self) -> &TenantRepos {
+        &self.repos
+    }
+
+    // the academy's settings, most lookups (belts, fee cycles, currency) need them
+    pub async fn settings(&self) -> Result<AcademySettings, AppError> {
+        self.repos.settings.get_settings().await
+    }
+}
+
+impl<T:TenantRepo> Deref for Tenant<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        T::of(&self.repos)
+    }
+}
+
+// picks a repository out of the tenant's repositories
+pub trait TenantRepo {
+    fn of(repos:&TenantRepos) -> &Self;
+}
+
+impl TenantRepo for UserRepo { fn of(repos:&TenantRepos) -> &Self { &repos.user } }
+impl TenantRepo for StudentRepo { fn of(repos:&TenantRepos) -> &Self { &repos.student } }
+impl TenantRepo for EventRepo { fn of(repos:&TenantRepos) -> &Self { &repos.event } }
+impl TenantRepo for AppRepo { fn of(repos:&TenantRepos) -> &Self { &repos.app } }
+impl TenantRepo for FinanceRepo { fn of(repos:&TenantRepos) -> &Self { &repos.finance } }
+impl TenantRepo for SettingsRepo { fn of(repos:&TenantRepos) -> &Self { &repos.settings } }
+impl TenantRepo for ReportRepo { fn of(repos:&TenantRepos) -> &Self { &repos.report } }
+impl TenantRepo for CurriculumRepo { fn of(repos:&TenantRepos) -> &Self { &repos.curriculum } }
+
+// The tenant claim of the token wins, requests without a token (logins, public
+// pages) are resolved from the subdomain under TENANT_BASE_DOMAIN. A token
+// used on another academy's subdomain is refused.
+pub fn tenant_of(req:&HttpRequest) -> Result<String, AppError> {
+    let claim = req.extensions().get::<serde_json::Value>()
+        .and_then(|claims| claims.get("tenant"))
+        .and_then(|tenant| tenant.as_str())
+        .filter(|tenant| !tenant.is_empty())
+        .map(|tenant| tenant.to_string());
+    let subdomain = subdomain_of(req);
+
+    match (claim, subdomain) {
+        (Some(claim), Some(subdomain)) if claim != subdomain => Err(AppError::CustomError("token was issued for another academy".to_string())),
+        (Some(claim), _) => Ok(claim),
+        (None, Some(subdomain)) => Ok(subdomain),
+        (None, None) => Ok(DEFAULT_TENANT.to_string()),
+    }
+}
+
+fn subdomain_of(req:&HttpRequest) -> Option<String> {
+    let base = env::var("TENANT_BASE_DOMAIN").ok().filter(|base| !base.is_empty())?;
+    let info = req.connection_info();
+    let host = info.host().split(':').next().unwrap_or_default().to_lowercase();
+    let label = host.strip_suffix(&base)?.strip_suffix('.')?;
+
+    // www and nested subdomains are not academies
+    if label.is_empty() || label == "www" || label.contains('.') {
+        return None;
+    }
+    Some(label.to_string())
+}
+
+fn reject(response:HttpResponse, message:String) -> actix_web::Error {
+    InternalError::from_response(message.clone(), response).into()
+}
+
+impl<T:TenantRepo + 'static> FromRequest for Tenant<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req:&HttpRequest, _:&mut Payload) -> Self::Future {
+        let registry = req.app_data::<Data<TenantRegistry>>().cloned();
+        let slug = tenant_of(req);
+
+        Box::pin(async move {
+            let registry = registry.ok_or_else(|| {
+                let message = "tenant registry is not configured".to_string();
+                reject(HttpResponse::InternalServerError().json(ResponseBuilder::<()>::FailedResponse(message.clone())), message)
+            })?;
+
+            let slug = match slug {
+                Ok(slug) => slug,
+                Err(e) => return Err(reject(HttpResponse::Forbidden().json(ResponseBuilder::<()>::FailedResponse(e.to_string())), e.to_string())),
+            };
+
+            match registry.repos(&slug).await {
+                Ok(repos) => Ok(Tenant { repos, repo: PhantomData }),
+                Err(AppError::DataNotFoundError) => {
+                    let message = format!("academy {} not found", slug);
+                    Err(reject(HttpResponse::NotFound().json(ResponseBuilder::<()>::FailedResponse(message.clone())), message))
+                },
+                Err(e) => Err(reject(HttpResponse::NotFound().json(ResponseBuilder::<()>::FailedResponse(e.to_string())), e.to_string())),
+            }
+        })
+    }
+}
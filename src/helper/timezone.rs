@@ -0,0 +1,103 @@
+use std::env;
+
+use chrono::{DateTime, LocalResult, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+
+use super::app_errors::AppError;
+
+// local date-time formats accepted next to RFC 3339, the first one is the
+// format the apps have always sent
+const LOCAL_FORMATS:[&str; 4] = ["%Y%m%d%H%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"];
+
+pub fn parse_timezone(name:&str) -> Result<Tz, AppError> {
+    name.parse::<Tz>()
+        .map_err(|_| AppError::CustomError(format!("Invalid timezone: {}", name)))
+}
+
+// DEFAULT_TIMEZONE, UTC when it is not set
+pub fn default_timezone() -> Tz {
+    env::var("DEFAULT_TIMEZONE").ok()
+        .and_then(|name| name.parse::<Tz>().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+// RFC 3339 with an offset is taken as is, a local date-time is read in `timezone`.
+pub fn parse_datetime(value:&str, timezone:Tz) -> Result<bson::DateTime, AppError> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(bson::DateTime::from_millis(datetime.timestamp_millis()));
+    }
+
+    let local = LOCAL_FORMATS.iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .ok_or_else(|| AppError::CustomError(format!("Invalid date: {}, use RFC 3339 or local YYYYMMDDHHMM", value)))?;
+
+    from_local(local, timezone)
+}
+
+pub fn from_local(local:NaiveDateTime, timezone:Tz) -> Result<bson::DateTime, AppError> {
+    match timezone.from_local_datetime(&local) {
+        LocalResult::Single(datetime) => Ok(bson::DateTime::from_millis(datetime.timestamp_millis())),
+        // the hour repeated when clocks go back, take the first one
+        LocalResult::Ambiguous(earliest, _) => Ok(bson::DateTime::from_millis(earliest.timestamp_millis())),
+        LocalResult::None => Err(AppError::CustomError(format!("{} does not exist in {}", local, timezone))),
+    }
+}
+
+pub fn to_local(datetime:bson::DateTime, timezone:Tz) -> NaiveDateTime {
+    timezone.timestamp_millis_opt(datetime.timestamp_millis())
+        .single()
+        .map(|datetime| datetime.naive_local())
+        .unwrap_or_default()
+}
+
+// Dates stored by update_event before they were kept as BSON dates, written
+// with chrono's Display ("2024-05-01 10:30:00 UTC").
+pub fn parse_legacy_string(value:&str) -> Option<bson::DateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f UTC").ok()
+        .map(|datetime| bson::DateTime::from_millis(datetime.and_utc().timestamp_millis()))
+        .or_else(|| DateTime::parse_from_rfc3339(value).ok().map(|datetime| bson::DateTime::from_millis(datetime.timestamp_millis())))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+    use chrono_tz::Tz;
+
+    use super::{parse_datetime, parse_legacy_string, parse_timezone, to_local};
+
+    fn utc_millis(y:i32, m:u32, d:u32, h:u32, min:u32) -> i64 {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, 0).unwrap().and_utc().timestamp_millis()
+    }
+
+    #[test]
+    fn local_times_are_read_in_the_zone() {
+        let kolkata = parse_timezone("Asia/Kolkata").unwrap();
+        for value in ["202606011830", "2026-06-01T18:30:00", "2026-06-01T18:30", "2026-06-01 18:30"] {
+            assert_eq!(parse_datetime(value, kolkata).unwrap().timestamp_millis(), utc_millis(2026, 6, 1, 13, 0), "{}", value);
+        }
+        // an explicit offset wins over the zone
+        assert_eq!(parse_datetime("2026-06-01T18:30:00+01:00", kolkata).unwrap().timestamp_millis(), utc_millis(2026, 6, 1, 17, 30));
+        assert!(parse_datetime("01/06/2026 18:30", kolkata).is_err());
+        assert!(parse_timezone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn clock_changes_are_handled() {
+        let london:Tz = parse_timezone("Europe/London").unwrap();
+        // 01:30 does not exist when clocks go forward
+        assert!(parse_datetime("202603290130", london).is_err());
+        // 01:30 happens twice when they go back, the first (BST) one is taken
+        assert_eq!(parse_datetime("202610250130", london).unwrap().timestamp_millis(), utc_millis(2026, 10, 25, 0, 30));
+
+        let summer = parse_datetime("202607011800", london).unwrap();
+        assert_eq!(to_local(summer, london), NaiveDateTime::parse_from_str("202607011800", "%Y%m%d%H%M").unwrap());
+    }
+
+    #[test]
+    fn legacy_strings_are_utc() {
+        assert_eq!(parse_legacy_string("2024-05-01 10:30:00 UTC").unwrap().timestamp_millis(), utc_millis(2024, 5, 1, 10, 30));
+        assert_eq!(parse_legacy_string("2024-05-01 10:30:00.250 UTC").unwrap().timestamp_millis(), utc_millis(2024, 5, 1, 10, 30) + 250);
+        assert_eq!(parse_legacy_string("2024-05-01T16:00:00+05:30").unwrap().timestamp_millis(), utc_millis(2024, 5, 1, 10, 30));
+        assert!(parse_legacy_string("202405011030").is_none());
+    }
+}
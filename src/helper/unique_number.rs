@@ -1,5 +1,13 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use lazy_static::lazy_static;
 use rand::RngCore;
 
+lazy_static! {
+    // numbers handed out since startup, shared by every worker thread
+    static ref USED_NUMBERS: Mutex<HashSet<u32>> = Mutex::new(HashSet::new());
+}
+
 pub struct Helper{}
 
 
@@ -17,15 +25,8 @@ impl Helper {
     }
     
     pub fn is_unique(number: u32) -> bool {
-        static mut USED_NUMBERS: Vec<u32> = Vec::new();
-        unsafe {
-            if USED_NUMBERS.contains(&number) {
-                false
-            } else {
-                USED_NUMBERS.push(number);
-                true
-            }
-        }
+        let mut used = USED_NUMBERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        used.insert(number)
     }
 }
 
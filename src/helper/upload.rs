@@ -0,0 +1,284 @@
+use std::{collections::HashMap, path::Path};
+
+use actix_multipart::Multipart;
+use futures::{StreamExt, TryStreamExt};
+use tokio::{fs::File, io::AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::models::media::{ImageVariant, ImageVariants};
+
+use super::{app_errors::AppError, image_pipeline::{self, Rendition}, storage};
+
+const IMAGE_TYPES:&[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+const MEDIA_TYPES:&[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp", "video/mp4", "video/webm"];
+
+// bytes read before the type is sniffed, enough for every signature below
+const SNIFF_LENGTH:usize = 16;
+const MAX_TEXT_FIELD:usize = 4 * 1024;
+
+// Where an endpoint stores its uploads and what it accepts.
+pub struct UploadRule {
+    // storage key prefix
+    pub prefix:&'static str,
+    pub max_bytes:usize,
+    pub max_files:usize,
+    pub allowed:&'static [&'static str],
+    // images go through the pipeline and are stored as variants
+    pub variants:bool
+}
+
+pub const EVENT_MEDIA:UploadRule = UploadRule { prefix: "event", max_bytes: 50 * 1024 * 1024, max_files: 20, allowed: MEDIA_TYPES, variants: true };
+pub const PROFILE_PICTURE:UploadRule = UploadRule { prefix: "student", max_bytes: 5 * 1024 * 1024, max_files: 1, allowed: IMAGE_TYPES, variants: true };
+pub const FACILITY_IMAGE:UploadRule = UploadRule { prefix: "facilities", max_bytes: 10 * 1024 * 1024, max_files: 10, allowed: IMAGE_TYPES, variants: true };
+pub const ACADEMY_LOGO:UploadRule = UploadRule { prefix: "academy", max_bytes: 2 * 1024 * 1024, max_files: 1, allowed: IMAGE_TYPES, variants: true };
+
+pub struct StoredFile {
+    // storage key such as event/<uuid>.jpg, the original JPEG for processed images
+    pub key:String,
+    pub mime:&'static str,
+    pub size:usize,
+    pub original_name:Option<String>,
+    pub variants:Option<ImageVariants>
+}
+
+impl StoredFile {
+    // "image" or "video"
+    pub fn kind(&self) -> &'static str {
+        self.mime.split('/').next().unwrap_or_default()
+    }
+}
+
+pub struct UploadedForm {
+    pub files:Vec<StoredFile>,
+    pub fields:HashMap<String, String>
+}
+
+impl UploadedForm {
+    // removes the stored files when the request fails after the upload
+    pub async fn discard(&self) {
+        for file in self.files.iter() {
+            remove_upload(&file.key, file.variants.as_ref()).await;
+        }
+    }
+}
+
+// Streams every file part of the form to a temporary file and stores it under a
+// random key, text parts are collected as fields. Nothing is kept when it fails.
+pub async fn save_multipart(mut payload:Multipart, rule:&UploadRule) -> Result<UploadedForm, AppError> {
+    let mut form = UploadedForm { files: Vec::new(), fields: HashMap::new() };
+
+    loop {
+        let field = match payload.try_next().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                form.discard().await;
+                return Err(AppError::CustomError(format!("Invalid upload: {}", e)));
+            },
+        };
+
+        let name = field.content_disposition().get_name().unwrap_or_default().to_string();
+        let filename = field.content_disposition().get_filename().map(|f| f.to_string());
+
+        let result = match filename {
+            Some(filename) if form.files.len() >= rule.max_files => {
+                Err(AppError::CustomError(format!("at most {} files can be uploaded, {} is one too many", rule.max_files, filename)))
+            },
+            Some(filename) => save_file(field, rule, filename).await.map(|file| form.files.push(file)),
+            None => read_text(field).await.map(|value| { form.fields.insert(name, value); }),
+        };
+
+        if let Err(e) = result {
+            form.discard().await;
+            return Err(e);
+        }
+    }
+
+    Ok(form)
+}
+
+// removes a stored upload and its variants
+pub async fn remove_upload(value:&str, variants:Option<&ImageVariants>) {
+    remove_file(value).await;
+    for key in variants.map(|v| v.keys()).unwrap_or_default() {
+        remove_file(key).await;
+    }
+}
+
+// removes a stored upload, external links are left alone
+pub async fn remove_file(value:&str) {
+    let key = match storage::key_of(value) {
+        Some(key) => key,
+        None => return,
+    };
+    if let Err(e) = storage::storage().delete(key).await {
+        println!("Could not remove uploaded file {} {}", key, e);
+    }
+}
+
+async fn save_file(mut field:actix_multipart::Field, rule:&UploadRule, filename:String) -> Result<StoredFile, AppError> {
+    // hold the first bytes back until the type is known
+    let mut head:Vec<u8> = Vec::new();
+    while head.len() < SNIFF_LENGTH {
+        match field.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk.map_err(|e| AppError::CustomError(e.to_string()))?),
+            None => break,
+        }
+    }
+
+    let mime = match sniff_mime(&head) {
+        Some(mime) if rule.allowed.contains(&mime) => mime,
+        _ => return Err(AppError::CustomError(format!("{} is not an allowed file type", filename))),
+    };
+
+    let id = Uuid::new_v4();
+    let temp_path = std::env::temp_dir().join(format!("{}.{}", id, extension(mime)));
+    let mut file = File::create(&temp_path).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+    let mut size = 0;
+    let written:Result<(), AppError> = async {
+        write_chunk(&mut file, &head, &mut size, rule, &filename).await?;
+        while let Some(chunk) = field.next().await {
+            let bytes = chunk.map_err(|e| AppError::CustomError(e.to_string()))?;
+            write_chunk(&mut file, &bytes, &mut size, rule, &filename).await?;
+        }
+        file.flush().await.map_err(|e| AppError::CustomError(e.to_string()))
+    }.await;
+
+    drop(file);
+
+    let stored = match written {
+        Ok(()) => store(rule, id, &temp_path, mime).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = tokio::fs::remove_file(&temp_path).await {
+        println!("Could not remove temporary upload {:?} {}", temp_path, e);
+    }
+    let (key, mime, variants) = stored?;
+
+    Ok(StoredFile { key, mime, size, original_name: Some(filename), variants })
+}
+
+// stores the upload as it is, or its renditions when it is an image the pipeline handles
+async fn store(rule:&UploadRule, id:Uuid, temp_path:&Path, mime:&'static str) -> Result<(String, &'static str, Option<ImageVariants>), AppError> {
+    if !rule.variants || !image_pipeline::can_process(mime) {
+        let key = format!("{}/{}.{}", rule.prefix, id, extension(mime));
+        storage::storage().put(&key, temp_path, mime).await?;
+        return Ok((key, mime, None));
+    }
+
+    let source = temp_path.to_path_buf();
+    let renditions = actix_web::web::block(move || image_pipeline::render(&source)).await
+        .map_err(|e| AppError::CustomError(e.to_string()))??;
+    let variants = store_renditions(rule, id, &renditions).await;
+    renditions.iter().for_each(|r| r.remove());
+
+    let variants = variants?;
+    Ok((variants.original.jpeg.clone(), "image/jpeg", Some(variants)))
+}
+
+async fn store_renditions(rule:&UploadRule, id:Uuid, renditions:&[Rendition]) -> Result<ImageVariants, AppError> {
+    let mut stored:Vec<ImageVariant> = Vec::new();
+    for rendition in renditions {
+        // the original keeps the plain name so the key reads like any other upload
+        let name = if rendition.label == "original" { id.to_string() } else { format!("{}_{}", id, rendition.label) };
+        let variant = ImageVariant {
+            jpeg: format!("{}/{}.jpg", rule.prefix, name),
+            webp: None,
+            width: rendition.width,
+            height: rendition.height,
+        };
+
+        if let Err(e) = storage::storage().put(&variant.jpeg, &rendition.jpeg, "image/jpeg").await {
+            for variant in stored.iter() {
+                remove_file(&variant.jpeg).await;
+            }
+            return Err(e);
+        }
+        stored.push(variant);
+    }
+
+    let mut stored = stored.into_iter();
+    match (stored.next(), stored.next(), stored.next()) {
+        (Some(thumbnail), Some(medium), Some(original)) => Ok(ImageVariants { thumbnail, medium, original }),
+        _ => Err(AppError::CustomError("image pipeline did not write every size".to_string())),
+    }
+}
+
+// tokio's file hands the write to its blocking pool, the worker thread keeps serving requests
+async fn write_chunk(file:&mut File, bytes:&[u8], size:&mut usize, rule:&UploadRule, filename:&str) -> Result<(), AppError> {
+    *size += bytes.len();
+    if *size > rule.max_bytes {
+        return Err(AppError::CustomError(format!("{} is larger than {} MB", filename, rule.max_bytes / (1024 * 1024))));
+    }
+    file.write_all(bytes).await.map_err(|e| AppError::CustomError(e.to_string()))
+}
+
+async fn read_text(mut field:actix_multipart::Field) -> Result<String, AppError> {
+    let mut data:Vec<u8> = Vec::new();
+    while let Some(chunk) = field.next().await {
+        data.extend_from_slice(&chunk.map_err(|e| AppError::CustomError(e.to_string()))?);
+        if data.len() > MAX_TEXT_FIELD {
+            return Err(AppError::CustomError("form field is too long".to_string()));
+        }
+    }
+    Ok(String::from_utf8_lossy(&data).trim().to_string())
+}
+
+// the type from the file signature, the client's content type is not trusted
+fn sniff_mime(head:&[u8]) -> Option<&'static str> {
+    match head {
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => Some("video/webm"),
+        // ISO media, HEIC photos share the container with mp4
+        [_, _, _, _, b'f', b't', b'y', b'p', b'h', b'e', b'i', _, ..] | [_, _, _, _, b'f', b't', b'y', b'p', b'm', b'i', b'f', b'1', ..] => Some("image/heic"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("video/mp4"),
+        [b'%', b'P', b'D', b'F', ..] => Some("application/pdf"),
+        _ => None,
+    }
+}
+
+fn extension(mime:&str) -> &'static str {
+    match mime {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/heic" => "heic",
+        "video/webm" => "webm",
+        "video/mp4" => "mp4",
+        "application/pdf" => "pdf",
+        _ => "bin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_mime_reads_the_file_signature() {
+        assert_eq!(sniff_mime(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00]), Some("image/jpeg"));
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n\x00\x00"), Some("image/png"));
+        assert_eq!(sniff_mime(b"GIF89a\x01\x00"), Some("image/gif"));
+        assert_eq!(sniff_mime(b"RIFF\x24\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_mime(&[0x1A, 0x45, 0xDF, 0xA3, 0x01]), Some("video/webm"));
+        assert_eq!(sniff_mime(b"\x00\x00\x00\x18ftypheic\x00\x00"), Some("image/heic"));
+        assert_eq!(sniff_mime(b"\x00\x00\x00\x18ftypmif1\x00\x00"), Some("image/heic"));
+        assert_eq!(sniff_mime(b"\x00\x00\x00\x18ftypisom\x00\x00"), Some("video/mp4"));
+        assert_eq!(sniff_mime(b"%PDF-1.7\n"), Some("application/pdf"));
+    }
+
+    #[test]
+    fn sniff_mime_ignores_the_name_and_rejects_unknown_content() {
+        assert_eq!(sniff_mime(b"<?php echo 1; ?>"), None);
+        assert_eq!(sniff_mime(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(sniff_mime(&[0xFF, 0xD8]), None);
+        assert_eq!(sniff_mime(b"RIFF\x24\x00\x00\x00WAVEfmt "), None);
+        assert_eq!(sniff_mime(b""), None);
+        assert_eq!(extension("image/svg+xml"), "bin");
+    }
+}
@@ -0,0 +1,87 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::models::events::VideoEmbed;
+
+use super::app_errors::AppError;
+
+lazy_static! {
+    // watch, embed, shorts and live urls plus the youtu.be short links
+    static ref YOUTUBE_REGEX: Regex = Regex::new(r"^https?://(?:www\.|m\.)?(?:youtube\.com/(?:watch\?(?:[^#]*&)?v=|embed/|shorts/|live/)|youtu\.be/)([A-Za-z0-9_-]{11})(?:[?&#/].*)?$").unwrap();
+    static ref VIMEO_REGEX: Regex = Regex::new(r"^https?://(?:www\.|player\.)?vimeo\.com/(?:video/)?([0-9]+)(?:[?#/].*)?$").unwrap();
+}
+
+// Embed details of a YouTube or Vimeo link, other links are rejected.
+pub fn parse_video_link(url:&str) -> Result<VideoEmbed, AppError> {
+    let url = url.trim();
+
+    if let Some(captures) = YOUTUBE_REGEX.captures(url) {
+        let video_id = captures[1].to_string();
+        return Ok(VideoEmbed {
+            provider: "youtube".to_string(),
+            embed_url: format!("https://www.youtube.com/embed/{}", video_id),
+            thumbnail_url: Some(format!("https://img.youtube.com/vi/{}/hqdefault.jpg", video_id)),
+            video_id,
+        });
+    }
+
+    if let Some(captures) = VIMEO_REGEX.captures(url) {
+        let video_id = captures[1].to_string();
+        return Ok(VideoEmbed {
+            provider: "vimeo".to_string(),
+            embed_url: format!("https://player.vimeo.com/video/{}", video_id),
+            thumbnail_url: None,
+            video_id,
+        });
+    }
+
+    Err(AppError::CustomError("video link should be a YouTube or Vimeo url".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn youtube_links_resolve_to_the_embed_url() {
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=42",
+            "https://m.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ?start=10",
+            "https://www.youtube.com/live/dQw4w9WgXcQ",
+            " https://youtu.be/dQw4w9WgXcQ?si=abc ",
+        ] {
+            let embed = parse_video_link(url).unwrap();
+            assert_eq!(embed.provider, "youtube", "{}", url);
+            assert_eq!(embed.video_id, "dQw4w9WgXcQ", "{}", url);
+            assert_eq!(embed.embed_url, "https://www.youtube.com/embed/dQw4w9WgXcQ");
+            assert_eq!(embed.thumbnail_url.as_deref(), Some("https://img.youtube.com/vi/dQw4w9WgXcQ/hqdefault.jpg"));
+        }
+    }
+
+    #[test]
+    fn vimeo_links_resolve_to_the_player_url() {
+        for url in ["https://vimeo.com/76979871", "https://player.vimeo.com/video/76979871?h=abc"] {
+            let embed = parse_video_link(url).unwrap();
+            assert_eq!(embed.provider, "vimeo");
+            assert_eq!(embed.video_id, "76979871");
+            assert_eq!(embed.embed_url, "https://player.vimeo.com/video/76979871");
+            assert_eq!(embed.thumbnail_url, None);
+        }
+    }
+
+    #[test]
+    fn other_links_are_rejected() {
+        for url in [
+            "",
+            "https://example.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com.evil.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?v=short",
+            "javascript:alert(1)//youtu.be/dQw4w9WgXcQ",
+            "https://vimeo.com/channels/staffpicks",
+        ] {
+            assert!(parse_video_link(url).is_err(), "{}", url);
+        }
+    }
+}
@@ -10,13 +10,13 @@ pub mod config;
 pub mod helper;
 pub mod middleware;
 mod mongoRepo;
-use crate::repo::app_repo::AppRepo;
-use crate::repo::events_repo::EventRepo;
-use crate::repo::user_repo::*;
-use crate::repo::student_repo::*;
+use std::time::Duration;
+use crate::repo::tenant_repo::TenantRegistry;
 use actix_files as fs;
-use crate::router::{event_router::*, user_router::*, app_router::*,};
+use crate::router::{event_router::*, user_router::*, app_router::*, calendar_router::*, file_router::*, curriculum_router::*, finance_router::*, report_router::*, settings_router::*, tenant_router::*};
+use crate::service::{calendar_service, file_service, finance_service, payment_gateway, user_service};
 use crate::router::student_routers::*;
+use crate::helper::storage;
 
 #[allow(non_snake_case)]
 #[actix_web::main]
@@ -26,38 +26,122 @@ async fn main() -> std::io::Result<()> {
     }   
     env_logger::init();
 
-    let db = match  config::db_config::DBConfig::init().await{
+    let client = match config::db_config::DBConfig::client().await {
         Ok(instance) => instance,
         Err(err) => {
             panic!("{}", err)
         },
     };
 
+    // every academy is opened once at startup so its migrations run before serving
+    let tenants = Data::new(TenantRegistry::init(client).await);
+    if let Err(e) = tenants.all().await {
+        panic!("{}", e)
+    }
+    match storage::LocalStorage::init().move_private_files() {
+        Ok(0) => {},
+        Ok(count) => println!("Moved {} private files out of ./static", count),
+        Err(e) => println!("Moving private files failed {}", e),
+    }
+    // `k_admin migrate-storage` copies the files under ./static to S3 and exits
+    if std::env::args().nth(1).as_deref() == Some("migrate-storage") {
+        match storage::migrate_local_files(&storage::S3Storage::init()).await {
+            Ok(count) => println!("Copied {} files to S3, set STORAGE_BACKEND=s3 to serve them", count),
+            Err(e) => println!("Storage migration failed {}", e),
+        }
+        return Ok(());
+    }
+    // `k_admin create-super-admin <email> <password> [name]` seeds the first super admin and exits
+    if std::env::args().nth(1).as_deref() == Some("create-super-admin") {
+        let args:Vec<String> = std::env::args().skip(2).collect();
+        let (email, password) = match (args.first(), args.get(1)) {
+            (Some(email), Some(password)) => (email, password),
+            _ => {
+                println!("usage: k_admin create-super-admin <email> <password> [name]");
+                return Ok(());
+            },
+        };
+        let name = args.get(2).map(String::as_str).unwrap_or("Super Admin");
+        let repos = match tenants.repos(repo::tenant_repo::DEFAULT_TENANT).await {
+            Ok(repos) => repos,
+            Err(e) => panic!("{}", e),
+        };
+        match user_service::create_super_admin(&repos.user, name, email, password).await {
+            Ok(_) => println!("Super admin {} created", email),
+            Err(e) => println!("Creating the super admin failed {}", e),
+        }
+        return Ok(());
+    }
+    if let Err(e) = calendar_service::feed_secret() {
+        panic!("Calendar feeds: {}", e)
+    }
+    if let Err(e) = storage::file_url_secret() {
+        panic!("File urls: {}", e)
+    }
+    let payment_gateway = match payment_gateway::init_gateway() {
+        Ok(gateway) => Data::from(gateway),
+        Err(e) => panic!("Payment gateway: {}", e),
+    };
+
+    // pick up payments whose webhook never reached us
+    let reconcile_tenants = tenants.clone();
+    let reconcile_gateway = payment_gateway.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(15 * 60));
+        loop {
+            interval.tick().await;
+            let all = match reconcile_tenants.all().await {
+                Ok(all) => all,
+                Err(e) => {
+                    println!("Payment reconciliation failed {}", e);
+                    continue;
+                },
+            };
+            for repos in all {
+                if let Err(e) = finance_service::reconcile(&repos.finance, reconcile_gateway.get_ref()).await {
+                    println!("[{}] Payment reconciliation failed {}", repos.slug, e);
+                }
+            }
+        }
+    });
 
-    let db_user = UserRepo::init(db.clone()).await;
-    let studentRepo = StudentRepo::init(db.clone());
-    let eventRepo = EventRepo::init(db.clone());
-    let db_user_db = Data::new(db_user);
-    let db_student = Data::new(StudentRepo::init(db.clone()));
-    let db_event = Data::new(EventRepo::init(db.clone()));
-    let appRepo = AppRepo::init(db, studentRepo, eventRepo).await;
-    let db_app = Data::new(appRepo);
 
+    // delete uploads nothing points to once they are past the grace period
+    let gc_tenants = tenants.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            match file_service::collect_garbage(&gc_tenants, false).await {
+                Ok(report) => {
+                    if report.deleted > 0 || !report.missing.is_empty() {
+                        println!("File cleanup removed {} orphaned files, {} referenced files are missing", report.deleted, report.missing.len());
+                    }
+                },
+                Err(e) => println!("File cleanup failed {}", e),
+            }
+        }
+    });
 
     println!("🚀 Server started successfully!");
 
     HttpServer::new(move || {
         App::new()
             
-            .app_data(db_user_db.clone())
-            .app_data(db_student.clone())
-            .app_data(db_event.clone())
-            .app_data(db_app.clone())
+            .app_data(tenants.clone())
+            .app_data(payment_gateway.clone())
             .service(fs::Files::new("/static", "static"))
             .service(app_router())
             .service(event_router())
             .service(student_router())
             .service(user_router())
+            .service(finance_router())
+            .service(settings_router())
+            .service(report_router())
+            .service(curriculum_router())
+            .service(calendar_router())
+            .service(file_router())
+            .service(tenant_router())
             .wrap(middleware::auth_middeleware::Authentication)
             .wrap(Cors::permissive())
             .wrap(Logger::default())
@@ -1,7 +1,7 @@
 use std::{future::{ready, Future, Ready}, pin::Pin};
 
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, error::ErrorUnauthorized, Error
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, error::ErrorUnauthorized, Error, HttpMessage
 };
 
 use crate::service::jwt_service;
@@ -64,7 +64,10 @@ where
                         // let token_author = jwt_config::TokenAuthentication::init();
     
                         match jwt_service::JwtService::validate_token(&header_val) {
-                            Ok(_) => {},
+                            // keep the claims so handlers can tell who is calling
+                            Ok(token) => {
+                                req.extensions_mut().insert(token.claims);
+                            },
                             Err(e) => {
                                 return Box::pin(async move{
                                     Err(ErrorUnauthorized(e.to_string()))
@@ -98,8 +101,9 @@ where
 #[allow(non_snake_case)]
 fn isStatic(path:String) -> bool {
 
-    if path.contains("add-student") || path.contains("/static") || path.contains("/student/upload-profile") || path == "/api/login" || path.contains("/guest-access") || path.contains("student/login")
-    || path.contains("uploade_facility_image") || path.contains("user") {
+    if path.contains("add-student") || path.contains("/static") || path.contains("/student/upload-profile") || path == "/api/login" || path.contains("/guest-access") || path.starts_with("/api/student/login/")
+    || path.contains("/payment-webhook")
+    || path.starts_with("/api/calendar/feed/") || path.starts_with("/api/files/") || path == "/api/app/branches/nearby" {
         return true
     }
 
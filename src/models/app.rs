@@ -1,6 +1,10 @@
+use core::fmt;
+
 use bson::{oid::ObjectId, Document};
 use serde::{Deserialize, Serialize};
 
+use super::{finance::TaxConfig, media::ImageVariants, money::{Discount, Money}};
+
 #[derive(Serialize, Deserialize)]
 pub struct Branches {
     #[serde(rename="_id", skip_serializing_if="Option::is_none")]
@@ -8,6 +12,25 @@ pub struct Branches {
     pub name:String,
     pub address:String,
     pub is_active:bool,
+    // IANA name such as "Asia/Kolkata", local event times of the branch are read in it
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    // short unique code such as "KRM", used on receipts and ids
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub code:Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub location:Option<GeoPoint>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub postal_address:Option<BranchAddress>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub phone:Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub email:Option<String>,
+    // a weekday without an entry is closed, a day may have more than one slot
+    #[serde(default)]
+    pub hours:Vec<OperatingHours>,
+    #[serde(default)]
+    pub holidays:Vec<HolidayClosure>,
     pub created_at:bson::DateTime,
     pub updated_at:bson::DateTime
 }
@@ -17,15 +40,95 @@ impl Branches {
         bson::to_document(self)
     }
 }
+
+// GeoJSON point, coordinates are [longitude, latitude] as the 2dsphere index expects
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GeoPoint {
+    #[serde(rename="type")]
+    pub kind:String,
+    pub coordinates:[f64; 2]
+}
+
+impl GeoPoint {
+    pub fn new(lat:f64, lng:f64) -> Self {
+        GeoPoint { kind: "Point".to_string(), coordinates: [lng, lat] }
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.coordinates[1]
+    }
+
+    pub fn lng(&self) -> f64 {
+        self.coordinates[0]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BranchAddress {
+    pub line1:String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub line2:Option<String>,
+    pub city:String,
+    pub state:String,
+    pub postal_code:String,
+    pub country:String
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Weekday {
+    MON,
+    TUE,
+    WED,
+    THU,
+    FRI,
+    SAT,
+    SUN
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Weekday::MON => write!(f, "MON"),
+            Weekday::TUE => write!(f, "TUE"),
+            Weekday::WED => write!(f, "WED"),
+            Weekday::THU => write!(f, "THU"),
+            Weekday::FRI => write!(f, "FRI"),
+            Weekday::SAT => write!(f, "SAT"),
+            Weekday::SUN => write!(f, "SUN"),
+        }
+    }
+}
+
+// opening slot in the branch timezone, times are "HH:MM"
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OperatingHours {
+    pub day:Weekday,
+    pub opens:String,
+    pub closes:String
+}
+
+// days the branch is shut, dates are "YYYY-MM-DD" and both ends are included
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HolidayClosure {
+    pub from:String,
+    pub to:String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub reason:Option<String>
+}
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize)]
 pub struct Fees {
     #[serde(rename="_id",skip_serializing_if="Option::is_none")]
     pub id:Option<ObjectId>,
     pub fee_type:String,
-    pub fee_amount:i64,
+    pub fee_amount:Money,
     pub is_discount:bool,
-    pub fee_discount:f64,
+    pub fee_discount:Option<Discount>,
+    // set by the legacy migration when a discount may have been a percentage
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub discount_review:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub tax:Option<TaxConfig>,
     pub created_at:bson::DateTime,
     pub updated_at:bson::DateTime
 }
@@ -34,6 +137,17 @@ impl Fees {
     pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
     }
+
+    // the amount payable once the discount is switched on
+    pub fn net_amount(&self) -> Money {
+        match (&self.fee_discount, self.is_discount) {
+            (Some(discount), true) => Money::new(
+                self.fee_amount.amount_minor - discount.amount_off(&self.fee_amount).amount_minor,
+                &self.fee_amount.currency
+            ),
+            _ => self.fee_amount.clone(),
+        }
+    }
 }
 #[derive(Serialize, Deserialize)]
 pub struct Courses{
@@ -43,6 +157,11 @@ pub struct Courses{
     pub description:String,
     pub is_active:bool,
     pub course_duration:String,
+    // seats per branch, a branch that is not listed has no limit
+    #[serde(default)]
+    pub branch_capacity:Vec<BranchCapacity>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub prerequisites:Option<CoursePrerequisites>,
     pub created_at:bson::DateTime,
     pub updated_at:bson::DateTime
 }
@@ -51,6 +170,78 @@ impl Courses {
     pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
     }
+
+    pub fn capacity_for(&self, branch_id:&ObjectId) -> Option<i64> {
+        self.branch_capacity.iter()
+            .find(|c| &c.branch_id == branch_id)
+            .map(|c| c.capacity)
+    }
+}
+
+// Who may enroll. Every rule that is set has to hold.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CoursePrerequisites {
+    // lowest belt, stored lower cased like the student level
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub min_level:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub min_age:Option<i64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub max_age:Option<i64>,
+    #[serde(default)]
+    pub completed_courses:Vec<ObjectId>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BranchCapacity {
+    pub branch_id:ObjectId,
+    pub capacity:i64,
+    // counted down as students become ACTIVE so seats are taken atomically
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub seats_left:Option<i64>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Enrollments {
+    #[serde(rename="_id",skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub student_id:ObjectId,
+    pub course_id:ObjectId,
+    pub branch_id:ObjectId,
+    pub start_date:bson::DateTime,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub end_date:Option<bson::DateTime>,
+    pub status:String,
+    // admin who enrolled the student without meeting the prerequisites
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub prerequisite_override_by:Option<String>,
+    pub created_at:bson::DateTime,
+    pub updated_at:bson::DateTime
+}
+
+impl Enrollments {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum EnrollmentStatus {
+    ACTIVE,
+    WAITLISTED,
+    COMPLETED,
+    DROPPED
+}
+
+impl fmt::Display for EnrollmentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnrollmentStatus::ACTIVE => write!(f, "ACTIVE"),
+            EnrollmentStatus::WAITLISTED => write!(f, "WAITLISTED"),
+            EnrollmentStatus::COMPLETED => write!(f, "COMPLETED"),
+            EnrollmentStatus::DROPPED => write!(f, "DROPPED"),
+        }
+    }
 }
 #[derive(Serialize, Deserialize)]
 #[allow(non_snake_case)]
@@ -59,12 +250,53 @@ pub struct Facilities {
     pub id:Option<ObjectId>,
     pub title:String,
     pub description:String,
+    // first image of the gallery, kept for apps that show a single picture
     #[serde(skip_serializing_if="Option::is_none")]
     pub imageUrl:Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub image_variants:Option<ImageVariants>,
+    #[serde(default)]
+    pub branches:Vec<ObjectId>,
+    #[serde(default)]
+    pub gallery:Vec<FacilityImage>,
+    #[serde(default)]
+    pub amenities:Vec<Amenity>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub availability_notes:Option<String>,
     pub created_at:bson::DateTime,
     pub updated_at:bson::DateTime
 }
 
+// one picture of a facility, the order of Facilities.gallery is the display order
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FacilityImage {
+    pub id:ObjectId,
+    pub key:String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub variants:Option<ImageVariants>,
+    pub created_at:bson::DateTime
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum Amenity {
+    CHANGING_ROOM,
+    PARKING,
+    MATS,
+    AC
+}
+
+impl fmt::Display for Amenity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Amenity::CHANGING_ROOM => write!(f, "CHANGING_ROOM"),
+            Amenity::PARKING => write!(f, "PARKING"),
+            Amenity::MATS => write!(f, "MATS"),
+            Amenity::AC => write!(f, "AC"),
+        }
+    }
+}
+
 impl Facilities {
     pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
@@ -79,13 +311,108 @@ pub struct Enquiries {
     pub contact:String,
     pub subject:String,
     pub message:String,
+    // branch id the enquiry is for, unassigned enquiries are only seen by admins
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub branch:Option<String>,
+    // enquiries from before the lifecycle are NEW
+    #[serde(default="new_enquiry_status")]
+    pub status:String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub lost_reason:Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub trial_at:Option<bson::DateTime>,
+    // channel the enquiry came through, see EnquirySource
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub source:Option<String>,
+    // id of the staff user following the enquiry up
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub assigned_to:Option<String>,
+    #[serde(default)]
+    pub follow_ups:Vec<EnquiryFollowUp>,
+    // timeline of notes, status changes are recorded here too
+    #[serde(default)]
+    pub notes:Vec<EnquiryNote>,
+    // name of the child the enquiry is for, the enquirer is then the guardian
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub student_name:Option<String>,
+    // student registered from the enquiry, set with the CONVERTED status
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub student_id:Option<ObjectId>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub converted_at:Option<bson::DateTime>,
     pub created_at:bson::DateTime,
     pub updated_at:bson::DateTime
 }
 
+fn new_enquiry_status() -> String {
+    EnquiryStatus::NEW.to_string()
+}
+
 impl Enquiries {
     
     pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
     }
+}
+
+// A call back or visit planned for an enquiry.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnquiryFollowUp {
+    pub id:ObjectId,
+    pub due_at:bson::DateTime,
+    pub note:String,
+    // staff user id the follow-up is due for
+    pub assigned_to:String,
+    pub created_by:String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub done_at:Option<bson::DateTime>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnquiryNote {
+    pub id:ObjectId,
+    pub text:String,
+    // name of the user who wrote it
+    pub author:String,
+    pub created_at:bson::DateTime
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum EnquiryStatus {
+    NEW,
+    CONTACTED,
+    TRIALSCHEDULED,
+    CONVERTED,
+    LOST
+}
+
+impl fmt::Display for EnquiryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnquiryStatus::NEW => write!(f, "NEW"),
+            EnquiryStatus::CONTACTED => write!(f, "CONTACTED"),
+            EnquiryStatus::TRIALSCHEDULED => write!(f, "TRIAL_SCHEDULED"),
+            EnquiryStatus::CONVERTED => write!(f, "CONVERTED"),
+            EnquiryStatus::LOST => write!(f, "LOST"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum EnquirySource {
+    WALKIN,
+    WEBSITE,
+    INSTAGRAM,
+    REFERRAL
+}
+
+impl fmt::Display for EnquirySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnquirySource::WALKIN => write!(f, "WALK_IN"),
+            EnquirySource::WEBSITE => write!(f, "WEBSITE"),
+            EnquirySource::INSTAGRAM => write!(f, "INSTAGRAM"),
+            EnquirySource::REFERRAL => write!(f, "REFERRAL"),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+use core::fmt;
+
+use bson::{oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+// Syllabus of one belt on one course. Every change is saved as a new version and
+// older versions stay untouched, so progress recorded against them keeps meaning.
+#[derive(Serialize, Deserialize)]
+pub struct Curriculums {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub course_id:ObjectId,
+    // belt the student holds while working through this syllabus
+    pub level:String,
+    pub version:i64,
+    pub is_current:bool,
+    pub items:Vec<SyllabusItem>,
+    pub created_by:String,
+    pub created_at:bson::DateTime
+}
+
+impl Curriculums {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
+
+// item_id is kept across versions for the same category and name, so ticks made
+// on an earlier version still count after the syllabus is revised
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SyllabusItem {
+    pub item_id:String,
+    pub category:String,
+    pub name:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub description:Option<String>
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum SyllabusCategory {
+    KATA,
+    KIHON,
+    KUMITE,
+    THEORY
+}
+
+impl fmt::Display for SyllabusCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyllabusCategory::KATA => write!(f, "KATA"),
+            SyllabusCategory::KIHON => write!(f, "KIHON"),
+            SyllabusCategory::KUMITE => write!(f, "KUMITE"),
+            SyllabusCategory::THEORY => write!(f, "THEORY"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SyllabusProgress {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub student_id:ObjectId,
+    pub course_id:ObjectId,
+    pub level:String,
+    // the version the item was ticked on
+    pub curriculum_id:ObjectId,
+    pub item_id:String,
+    pub completed_by:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub notes:Option<String>,
+    pub completed_at:bson::DateTime
+}
+
+impl SyllabusProgress {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
@@ -1,6 +1,10 @@
+use core::fmt;
+
 use bson::{oid::ObjectId, Document};
 use serde::{Deserialize, Serialize};
 
+use super::{media::ImageVariants, money::Money};
+
 #[derive(Serialize,Deserialize, Default)]
 pub struct Events {
     #[serde(rename="_id", skip_serializing_if="Option::is_none")]
@@ -13,6 +17,21 @@ pub struct Events {
     pub file_data:Option<Vec<FileData>>,
     pub start_date:Option<bson::DateTime>,
     pub end_date:Option<bson::DateTime>,
+    // IANA zone the dates were entered in, series repeat at the same local time
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    // branch ids the event is for, empty for academy-wide events
+    #[serde(default)]
+    pub branches:Vec<String>,
+    // None while the event is not open for registration
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub registration:Option<RegistrationSettings>,
+    // students holding a place, taken with a conditional $inc against the capacity
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub registered_count:Option<i64>,
+    // None for one-off events
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub recurrence:Option<Recurrence>,
     pub created_at:Option<bson::DateTime>,
     pub updated_at:Option<bson::DateTime>
 }
@@ -23,15 +42,124 @@ impl Events {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+// One gallery item, an uploaded file or an external video link. The order of
+// Events.file_data is the display order.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct FileData {
+    // stable id, items saved before ids existed get one on startup
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
     pub file_type:String,
     pub file_path:String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub caption:Option<String>,
+    #[serde(default)]
+    pub is_cover:bool,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub embed:Option<VideoEmbed>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub variants:Option<ImageVariants>,
     pub created_at:Option<bson::DateTime>
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VideoEmbed {
+    pub provider:String,
+    pub video_id:String,
+    pub embed_url:String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub thumbnail_url:Option<String>
+}
+
 impl FileData {
     pub fn to_docmunet(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
     }
-}
\ No newline at end of file
+}
+// A series: start_date/end_date of the event are the first occurrence and
+// every occurrence lasts as long. Single occurrences are edited or cancelled
+// through exceptions keyed by their original start.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Recurrence {
+    pub rrule:String,
+    #[serde(default)]
+    pub exceptions:Vec<OccurrenceException>
+}
+
+impl Recurrence {
+    pub fn exception(&self, occurrence_start:bson::DateTime) -> Option<&OccurrenceException> {
+        self.exceptions.iter().find(|e| e.occurrence_start == occurrence_start)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OccurrenceException {
+    pub occurrence_start:bson::DateTime,
+    pub cancelled:bool,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub title:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub location:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub start_date:Option<bson::DateTime>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub end_date:Option<bson::DateTime>,
+    pub updated_at:Option<bson::DateTime>
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct RegistrationSettings {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub capacity:Option<i64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub opens_at:Option<bson::DateTime>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub closes_at:Option<bson::DateTime>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fee:Option<Money>,
+    // empty lists allow everyone
+    #[serde(default)]
+    pub levels:Vec<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub min_age:Option<i64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub max_age:Option<i64>,
+    #[serde(default)]
+    pub branches:Vec<String>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EventRegistrations {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub event_id:ObjectId,
+    pub student_id:ObjectId,
+    pub status:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub fee:Option<Money>,
+    pub fee_paid:bool,
+    pub registered_by:String,
+    pub created_at:bson::DateTime,
+    pub updated_at:bson::DateTime
+}
+
+impl EventRegistrations {
+    pub fn to_docmunet(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum RegistrationStatus {
+    REGISTERED,
+    CANCELLED
+}
+
+impl fmt::Display for RegistrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistrationStatus::REGISTERED => write!(f, "REGISTERED"),
+            RegistrationStatus::CANCELLED => write!(f, "CANCELLED"),
+        }
+    }
+}
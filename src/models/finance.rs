@@ -0,0 +1,277 @@
+use core::fmt;
+
+use bson::{oid::ObjectId, Document};
+use chrono::{Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use super::money::Money;
+
+#[derive(Serialize, Deserialize)]
+pub struct Invoices {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub invoice_number:String,
+    pub student_id:ObjectId,
+    pub fee_id:ObjectId,
+    pub fee_type:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub course_id:Option<ObjectId>,
+    // total payable, taxes included
+    pub amount:Money,
+    // discount the fee plan gave off the list price
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub discount_amount:Option<Money>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub taxable_amount:Option<Money>,
+    #[serde(default)]
+    pub tax_lines:Vec<TaxLine>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub hsn_sac:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gstin:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub place_of_supply:Option<String>,
+    pub status:String,
+    // the latest provider order, checkout uses it
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gateway_order_id:Option<String>,
+    // every order opened for the invoice, a webhook for an earlier one still matches
+    #[serde(default)]
+    pub gateway_order_ids:Vec<String>,
+    pub due_date:bson::DateTime,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub paid_at:Option<bson::DateTime>,
+    pub created_at:bson::DateTime,
+    pub updated_at:bson::DateTime
+}
+
+impl Invoices {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+
+    // invoices from before the list only carry the latest order
+    pub fn order_ids(&self) -> Vec<String> {
+        let mut order_ids = self.gateway_order_ids.clone();
+        if let Some(order_id) = self.gateway_order_id.as_ref() {
+            if !order_ids.contains(order_id) {
+                order_ids.push(order_id.to_string());
+            }
+        }
+        order_ids
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Payments {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub invoice_id:ObjectId,
+    pub student_id:ObjectId,
+    pub amount:Money,
+    pub gateway:String,
+    pub gateway_order_id:String,
+    pub gateway_payment_id:String,
+    // minor units held by requested and approved refunds, reserved atomically
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub refunded_minor:Option<i64>,
+    pub created_at:bson::DateTime
+}
+
+impl Payments {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum InvoiceStatus {
+    PENDING,
+    PAID
+}
+
+impl fmt::Display for InvoiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvoiceStatus::PENDING => write!(f, "PENDING"),
+            InvoiceStatus::PAID => write!(f, "PAID"),
+        }
+    }
+}
+
+// GST rates are kept in basis points, 900 = 9%
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TaxConfig {
+    pub hsn_sac:String,
+    pub cgst_rate:i64,
+    pub sgst_rate:i64,
+    pub igst_rate:i64
+}
+
+impl TaxConfig {
+    // intra-state supplies carry CGST and SGST, inter-state supplies carry IGST
+    pub fn tax_lines(&self, taxable:&Money, intra_state:bool) -> Vec<TaxLine> {
+        let rates = if intra_state {
+            vec![(TaxTypes::CGST, self.cgst_rate), (TaxTypes::SGST, self.sgst_rate)]
+        } else {
+            vec![(TaxTypes::IGST, self.igst_rate)]
+        };
+
+        rates.into_iter()
+            .filter(|(_, rate)| *rate > 0)
+            .map(|(tax_type, rate)| TaxLine {
+                tax_type: tax_type.to_string(),
+                rate,
+                amount: taxable.mul_ratio(rate, 10_000),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TaxLine {
+    pub tax_type:String,
+    pub rate:i64,
+    pub amount:Money
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum TaxTypes {
+    CGST,
+    SGST,
+    IGST
+}
+
+impl fmt::Display for TaxTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaxTypes::CGST => write!(f, "CGST"),
+            TaxTypes::SGST => write!(f, "SGST"),
+            TaxTypes::IGST => write!(f, "IGST"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Refunds {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub payment_id:ObjectId,
+    pub invoice_id:ObjectId,
+    pub student_id:ObjectId,
+    pub amount:Money,
+    pub reason:String,
+    pub status:String,
+    pub requested_by:String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub approved_by:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub approved_at:Option<bson::DateTime>,
+    // the credit note issued when the refund is approved
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub credit_note_id:Option<ObjectId>,
+    pub created_at:bson::DateTime,
+    pub updated_at:bson::DateTime
+}
+
+impl Refunds {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum RefundStatus {
+    REQUESTED,
+    APPROVED,
+    REJECTED
+}
+
+impl fmt::Display for RefundStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefundStatus::REQUESTED => write!(f, "REQUESTED"),
+            RefundStatus::APPROVED => write!(f, "APPROVED"),
+            RefundStatus::REJECTED => write!(f, "REJECTED"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreditNotes {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub credit_note_number:String,
+    pub student_id:ObjectId,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub invoice_id:Option<ObjectId>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub refund_id:Option<ObjectId>,
+    pub amount:Money,
+    pub reason:String,
+    pub issued_by:String,
+    pub created_at:bson::DateTime
+}
+
+impl CreditNotes {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
+
+// One row per money movement on a student account. Amounts are signed from the
+// academy's side: positive is owed by the student, negative is owed to them,
+// so the balance is the plain sum.
+#[derive(Serialize, Deserialize)]
+pub struct LedgerEntries {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub student_id:ObjectId,
+    pub entry_type:String,
+    // id of the invoice, payment, refund or credit note behind the entry
+    pub reference_id:ObjectId,
+    pub amount:Money,
+    pub description:String,
+    pub created_at:bson::DateTime
+}
+
+impl LedgerEntries {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub enum LedgerEntryTypes {
+    INVOICE,
+    PAYMENT,
+    CREDITNOTE,
+    REFUND
+}
+
+impl fmt::Display for LedgerEntryTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerEntryTypes::INVOICE => write!(f, "INVOICE"),
+            LedgerEntryTypes::PAYMENT => write!(f, "PAYMENT"),
+            LedgerEntryTypes::CREDITNOTE => write!(f, "CREDIT_NOTE"),
+            LedgerEntryTypes::REFUND => write!(f, "REFUND"),
+        }
+    }
+}
+
+// Unused share of a fee covering `period_months` from `period_start` when the
+// student leaves on `leave_date`, counted in days and rounded half to even.
+pub fn pro_rata_unused(amount:&Money, period_start:NaiveDate, period_months:u32, leave_date:NaiveDate) -> Money {
+    let period_end = period_start.checked_add_months(Months::new(period_months)).unwrap_or(period_start);
+    let total_days = (period_end - period_start).num_days();
+
+    if total_days <= 0 || leave_date >= period_end {
+        return Money::zero(&amount.currency);
+    }
+    if leave_date <= period_start {
+        return amount.clone();
+    }
+
+    amount.mul_ratio((period_end - leave_date).num_days(), total_days)
+}
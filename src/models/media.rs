@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+// Renditions written by the image pipeline. Stored values are storage keys,
+// the DTOs hand the same shape out with urls.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ImageVariants {
+    pub thumbnail:ImageVariant,
+    pub medium:ImageVariant,
+    pub original:ImageVariant
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ImageVariant {
+    pub jpeg:String,
+    // only images uploaded before the lossless WebP renditions were dropped have one
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub webp:Option<String>,
+    pub width:u32,
+    pub height:u32
+}
+
+impl ImageVariants {
+    pub fn keys(&self) -> Vec<&str> {
+        [&self.thumbnail, &self.medium, &self.original].iter()
+            .flat_map(|variant| [Some(variant.jpeg.as_str()), variant.webp.as_deref()])
+            .flatten()
+            .collect()
+    }
+
+    pub fn map_keys(self, map:impl Fn(&str) -> String) -> Self {
+        let variant = |v:ImageVariant| ImageVariant { jpeg: map(&v.jpeg), webp: v.webp.as_deref().map(&map), ..v };
+        ImageVariants {
+            thumbnail: variant(self.thumbnail),
+            medium: variant(self.medium),
+            original: variant(self.original),
+        }
+    }
+}
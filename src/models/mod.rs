@@ -1,4 +1,10 @@
 pub mod user_models;
 pub mod student_model;
 pub mod events;
-pub mod app;
\ No newline at end of file
+pub mod app;
+pub mod money;
+pub mod finance;
+pub mod settings;
+pub mod curriculum;
+pub mod media;
+pub mod tenant;
\ No newline at end of file
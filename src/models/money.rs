@@ -0,0 +1,266 @@
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::helper::app_errors::AppError;
+
+pub const DEFAULT_CURRENCY: &str = "INR";
+
+// Active ISO 4217 codes, sorted for the binary search
+const ISO_4217:[&str; 158] = [
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT", "BGN", "BHD", "BIF",
+    "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD", "CDF", "CHF", "CLP", "CNY", "COP", "CRC",
+    "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD", "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS",
+    "GIP", "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD",
+    "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR", "LRD", "LSL",
+    "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR", "MWK", "MXN", "MYR", "MZN", "NAD",
+    "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN", "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD",
+    "RUB", "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD", "SHP", "SLE", "SLL", "SOS", "SRD", "SSP", "STN", "SVC",
+    "SYP", "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VED", "VES", "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG", "ZWL",
+];
+
+// Money is always kept in the minor unit of the currency (paise for INR) so
+// totals never go through floating point.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Money {
+    pub amount_minor:i64,
+    pub currency:String
+}
+
+impl Money {
+    pub fn new(amount_minor:i64, currency:&str) -> Self {
+        Money {
+            amount_minor,
+            currency: currency.to_uppercase(),
+        }
+    }
+
+    pub fn zero(currency:&str) -> Self {
+        Self::new(0, currency)
+    }
+
+    // parse a decimal string such as "1500", "1500.5" or "1500.50" into minor units
+    pub fn parse(value:&str, currency:&str) -> Result<Self, AppError> {
+        if !is_iso_currency(currency) {
+            return Err(AppError::CustomError(format!("Invalid currency: {}", currency)));
+        }
+        let amount_minor = parse_scaled(value, 2)
+            .ok_or(AppError::CustomError(format!("Invalid amount: {}", value)))?;
+        Ok(Self::new(amount_minor, currency))
+    }
+
+    pub fn to_decimal_string(&self) -> String {
+        format_scaled(self.amount_minor, 2)
+    }
+
+    pub fn checked_add(&self, other:&Money) -> Result<Money, AppError> {
+        self.ensure_same_currency(other)?;
+        self.amount_minor.checked_add(other.amount_minor)
+            .map(|amount| Money::new(amount, &self.currency))
+            .ok_or(AppError::CustomError("amount overflow".to_string()))
+    }
+
+    pub fn checked_sub(&self, other:&Money) -> Result<Money, AppError> {
+        self.ensure_same_currency(other)?;
+        self.amount_minor.checked_sub(other.amount_minor)
+            .map(|amount| Money::new(amount, &self.currency))
+            .ok_or(AppError::CustomError("amount overflow".to_string()))
+    }
+
+    // multiply by numerator/denominator, rounding half to even
+    pub fn mul_ratio(&self, numerator:i64, denominator:i64) -> Money {
+        let amount = round_half_even(self.amount_minor as i128 * numerator as i128, denominator as i128);
+        Money::new(amount, &self.currency)
+    }
+
+    fn ensure_same_currency(&self, other:&Money) -> Result<(), AppError> {
+        if self.currency != other.currency {
+            return Err(AppError::CustomError(format!("currency mismatch: {} and {}", self.currency, other.currency)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DiscountKind {
+    PERCENT,
+    FIXED
+}
+
+impl fmt::Display for DiscountKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscountKind::PERCENT => write!(f, "PERCENT"),
+            DiscountKind::FIXED => write!(f, "FIXED"),
+        }
+    }
+}
+
+// A PERCENT discount keeps its value in basis points (1250 = 12.50%),
+// a FIXED discount keeps it in the minor unit of the fee currency.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Discount {
+    pub kind:DiscountKind,
+    pub value:i64
+}
+
+impl Discount {
+    pub fn percent(value:&str) -> Result<Self, AppError> {
+        let basis_points = parse_rate(value)
+            .map_err(|_| AppError::CustomError(format!("Invalid discount: {}", value)))?;
+        Ok(Discount { kind: DiscountKind::PERCENT, value: basis_points })
+    }
+
+    pub fn fixed(value:&str) -> Result<Self, AppError> {
+        let amount_minor = parse_scaled(value, 2)
+            .ok_or(AppError::CustomError(format!("Invalid discount: {}", value)))?;
+        Ok(Discount { kind: DiscountKind::FIXED, value: amount_minor })
+    }
+
+    // the discount amount taken off `amount`, never more than `amount` itself
+    pub fn amount_off(&self, amount:&Money) -> Money {
+        let off = match self.kind {
+            DiscountKind::PERCENT => amount.mul_ratio(self.value, 10_000),
+            DiscountKind::FIXED => Money::new(self.value, &amount.currency),
+        };
+
+        Money::new(off.amount_minor.min(amount.amount_minor), &amount.currency)
+    }
+
+    pub fn value_string(&self) -> String {
+        format_scaled(self.value, 2)
+    }
+}
+
+pub fn is_iso_currency(code:&str) -> bool {
+    ISO_4217.binary_search(&code.to_uppercase().as_str()).is_ok()
+}
+
+// The currency a request charges in. Ledgers and reports are kept in the academy
+// currency, so a client may repeat it but not ask for another one.
+pub fn request_currency(requested:Option<&str>, academy:&str) -> Result<String, AppError> {
+    let currency = requested.unwrap_or(academy).trim().to_uppercase();
+    if !is_iso_currency(&currency) {
+        return Err(AppError::CustomError(format!("Invalid currency: {}", currency)));
+    }
+    if currency != academy.to_uppercase() {
+        return Err(AppError::CustomError(format!("currency {} does not match the academy currency {}", currency, academy)));
+    }
+    Ok(currency)
+}
+
+// a percentage such as "9" or "12.5" in basis points, at most 100 percent
+pub fn parse_rate(value:&str) -> Result<i64, AppError> {
+    match parse_scaled(value, 2) {
+        Some(basis_points) if basis_points <= 10_000 => Ok(basis_points),
+        _ => Err(AppError::CustomError(format!("Invalid rate: {}", value))),
+    }
+}
+
+pub fn format_rate(basis_points:i64) -> String {
+    format_scaled(basis_points, 2)
+}
+
+// Round numerator/denominator to the nearest integer, ties go to the even neighbour.
+pub fn round_half_even(numerator:i128, denominator:i128) -> i64 {
+    let (numerator, denominator) = if denominator < 0 { (-numerator, -denominator) } else { (numerator, denominator) };
+    let quotient = numerator.div_euclid(denominator);
+    let remainder = numerator.rem_euclid(denominator);
+
+    let rounded = match (remainder * 2).cmp(&denominator) {
+        std::cmp::Ordering::Greater => quotient + 1,
+        std::cmp::Ordering::Equal => quotient + (quotient & 1),
+        std::cmp::Ordering::Less => quotient,
+    };
+
+    rounded as i64
+}
+
+// "12.5" with scale 2 => 1250. Rejects signs, exponents and extra decimals.
+fn parse_scaled(value:&str, scale:u32) -> Option<i64> {
+    let value = value.trim();
+    let (whole, fraction) = match value.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (value, ""),
+    };
+
+    if whole.is_empty() || fraction.len() > scale as usize
+        || !whole.chars().all(|c| c.is_ascii_digit())
+        || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let whole:i64 = whole.parse().ok()?;
+    let fraction:i64 = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<i64>().ok()? * 10_i64.pow(scale - fraction.len() as u32)
+    };
+
+    whole.checked_mul(10_i64.pow(scale))?.checked_add(fraction)
+}
+
+fn format_scaled(value:i64, scale:u32) -> String {
+    let factor = 10_i64.pow(scale);
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    format!("{}{}.{:0width$}", sign, value / factor as u64, value % factor as u64, width = scale as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_iso_currency, request_currency, round_half_even, Discount, DiscountKind, Money};
+
+    #[test]
+    fn ties_round_to_the_even_neighbour() {
+        assert_eq!(round_half_even(5, 2), 2);
+        assert_eq!(round_half_even(7, 2), 4);
+        assert_eq!(round_half_even(-5, 2), -2);
+        assert_eq!(round_half_even(-7, 2), -4);
+        assert_eq!(round_half_even(5, -2), -2);
+        assert_eq!(round_half_even(11, 4), 3);
+        assert_eq!(round_half_even(9, 4), 2);
+        // 18% of 12.25 is 2.205, the half paisa goes to the even 2.20
+        assert_eq!(Money::new(1225, "INR").mul_ratio(1800, 10_000).amount_minor, 220);
+    }
+
+    #[test]
+    fn amounts_parse_to_minor_units_and_back() {
+        assert_eq!(Money::parse("1500", "inr").unwrap(), Money::new(150_000, "INR"));
+        assert_eq!(Money::parse("1500.5", "INR").unwrap().amount_minor, 150_050);
+        assert_eq!(Money::new(-150_005, "INR").to_decimal_string(), "-1500.05");
+        for invalid in ["", "-1", "1e3", "1.005", "12,50", ".5"] {
+            assert!(Money::parse(invalid, "INR").is_err(), "{} should not parse", invalid);
+        }
+    }
+
+    #[test]
+    fn discounts_never_exceed_the_amount() {
+        let fee = Money::new(100_000, "INR");
+
+        let percent = Discount::percent("12.5").unwrap();
+        assert_eq!(percent, Discount { kind: DiscountKind::PERCENT, value: 1250 });
+        assert_eq!(percent.amount_off(&fee).amount_minor, 12_500);
+
+        let fixed = Discount::fixed("250.75").unwrap();
+        assert_eq!(fixed.amount_off(&fee).amount_minor, 25_075);
+        assert_eq!(Discount::fixed("5000").unwrap().amount_off(&fee), fee);
+
+        assert!(Discount::percent("100.01").is_err());
+        assert!(Discount::fixed("abc").is_err());
+    }
+
+    #[test]
+    fn currencies_are_iso_codes_of_the_academy() {
+        assert!(is_iso_currency("INR"));
+        assert!(is_iso_currency("usd"));
+        assert!(!is_iso_currency("XYZ"));
+        assert!(Money::parse("10", "RUPEES").is_err());
+
+        assert_eq!(request_currency(None, "INR").unwrap(), "INR");
+        assert_eq!(request_currency(Some(" inr "), "INR").unwrap(), "INR");
+        assert!(request_currency(Some("USD"), "INR").is_err());
+        assert!(request_currency(Some("ABC"), "INR").is_err());
+    }
+}
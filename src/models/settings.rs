@@ -0,0 +1,185 @@
+use bson::{oid::ObjectId, Document};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::helper::timezone;
+
+use super::{media::ImageVariants, money::DEFAULT_CURRENCY};
+
+// Academy wide settings, kept as a single document in the settings collection.
+// Lists left empty fall back to the defaults the academy started with.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct AcademySettings {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    // name and contact shown to parents, set when the tenant is created
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub academy_name:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub contact_email:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub contact_phone:Option<String>,
+    // storage key of the logo
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub logo:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub logo_variants:Option<ImageVariants>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub currency:Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub timezone:Option<String>,
+    #[serde(default)]
+    pub belts:Vec<BeltLevel>,
+    #[serde(default)]
+    pub fee_cycles:Vec<FeeCycle>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub registration:Option<RegistrationOptions>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub gstin:Option<String>,
+    // two digit GST state code of the academy, taken from the GSTIN
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub state_code:Option<String>,
+    pub updated_at:Option<bson::DateTime>
+}
+
+// A belt of the grading ladder. Students keep the key lower cased.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BeltLevel {
+    pub key:String,
+    pub name:String,
+    // hex colour such as #FFD700
+    pub color:String,
+    // position on the ladder, lowest first
+    pub order:i32
+}
+
+// A billing cycle fees can be set up for, fees keep the key.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FeeCycle {
+    pub key:String,
+    pub name:String,
+    pub months:u32
+}
+
+// Choices offered on the student registration form.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RegistrationOptions {
+    pub default_nationality:String,
+    pub genders:Vec<String>,
+    // empty accepts any blood group
+    #[serde(default)]
+    pub blood_groups:Vec<String>,
+    #[serde(default)]
+    pub require_aadhaar:bool
+}
+
+impl Default for RegistrationOptions {
+    fn default() -> Self {
+        RegistrationOptions {
+            default_nationality: "INDIAN".to_string(),
+            genders: vec!["male".to_string(), "female".to_string()],
+            blood_groups: Vec::new(),
+            require_aadhaar: false,
+        }
+    }
+}
+
+// the ladder the academy has always graded with
+const DEFAULT_BELTS:[(&str, &str, &str); 10] = [
+    ("OFFWHITE", "Off White", "#FAF9F6"),
+    ("YELLOW", "Yellow", "#FFD700"),
+    ("ORANGE", "Orange", "#FFA500"),
+    ("GREEN", "Green", "#008000"),
+    ("BLUE", "Blue", "#0000FF"),
+    ("PURPLE", "Purple", "#800080"),
+    ("BROWN", "Brown", "#8B4513"),
+    ("BROWNII", "Brown II", "#8B4513"),
+    ("BROWNIII", "Brown III", "#8B4513"),
+    ("BLACK", "Black", "#000000"),
+];
+
+const DEFAULT_FEE_CYCLES:[(&str, &str, u32); 4] = [
+    ("MONTHLY", "Monthly", 1),
+    ("THREEMONTH", "Three months", 3),
+    ("SIXMONTH", "Six months", 6),
+    ("YEARLY", "Yearly", 12),
+];
+
+// keys are matched loosely so "Off_White", "offwhite" and "OFFWHITE" are one belt
+pub fn lookup_key(value:&str) -> String {
+    value.chars().filter(|c| !matches!(c, '_' | '-' | ' ')).collect::<String>().to_lowercase()
+}
+
+impl AcademySettings {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+
+    pub fn currency(&self) -> &str {
+        self.currency.as_deref().unwrap_or(DEFAULT_CURRENCY)
+    }
+
+    // the academy's zone, DEFAULT_TIMEZONE when none is set
+    pub fn timezone(&self) -> Tz {
+        self.timezone.as_deref()
+            .and_then(|name| timezone::parse_timezone(name).ok())
+            .unwrap_or_else(timezone::default_timezone)
+    }
+
+    // the belt ladder, lowest first
+    pub fn belts(&self) -> Vec<BeltLevel> {
+        if self.belts.is_empty() {
+            return DEFAULT_BELTS.iter().enumerate().map(|(order, (key, name, color))| BeltLevel {
+                key: key.to_string(),
+                name: name.to_string(),
+                color: color.to_string(),
+                order: order as i32,
+            }).collect();
+        }
+
+        let mut belts = self.belts.clone();
+        belts.sort_by_key(|belt| belt.order);
+        belts
+    }
+
+    pub fn belt(&self, level:&str) -> Option<BeltLevel> {
+        let key = lookup_key(level);
+        self.belts().into_iter().find(|belt| lookup_key(&belt.key) == key)
+    }
+
+    // position on the ladder, to compare two belts
+    pub fn belt_rank(&self, level:&str) -> Option<usize> {
+        let key = lookup_key(level);
+        self.belts().iter().position(|belt| lookup_key(&belt.key) == key)
+    }
+
+    pub fn next_belt(&self, level:&str) -> Option<BeltLevel> {
+        let rank = self.belt_rank(level)?;
+        self.belts().get(rank + 1).cloned()
+    }
+
+    // the stored form of a level, None when it is not on the ladder
+    pub fn level_key(&self, level:&str) -> Option<String> {
+        self.belt(level).map(|belt| belt.key.to_lowercase())
+    }
+
+    pub fn fee_cycles(&self) -> Vec<FeeCycle> {
+        if self.fee_cycles.is_empty() {
+            return DEFAULT_FEE_CYCLES.iter().map(|(key, name, months)| FeeCycle {
+                key: key.to_string(),
+                name: name.to_string(),
+                months: *months,
+            }).collect();
+        }
+        self.fee_cycles.clone()
+    }
+
+    pub fn fee_cycle(&self, fee_type:&str) -> Option<FeeCycle> {
+        let key = lookup_key(fee_type);
+        self.fee_cycles().into_iter().find(|cycle| lookup_key(&cycle.key) == key)
+    }
+
+    pub fn registration(&self) -> RegistrationOptions {
+        self.registration.clone().unwrap_or_default()
+    }
+}
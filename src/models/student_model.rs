@@ -1,6 +1,9 @@
 use bson::{oid::ObjectId, Document};
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
+use super::media::ImageVariants;
+
 #[derive(Serialize, Deserialize)]
 pub struct Students {
     #[serde(skip_serializing_if="Option::is_none", rename="_id")]
@@ -13,6 +16,8 @@ pub struct Students {
     pub is_active_student:bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile_pic:Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub profile_pic_variants:Option<ImageVariants>,
     #[serde(skip_serializing_if="Option::is_none")]
     pub class_branch:Option<String>,
     #[serde(skip_serializing_if="Option::is_none")]
@@ -25,6 +30,9 @@ pub struct Students {
     pub addhar_number:Option<String>,
     pub geneder:Option<String>,
     pub registration_status:Option<String>,
+    // enquiry the registration was converted from
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub enquiry_id:Option<ObjectId>,
     pub created_at:Option<bson::DateTime>,
     pub updated_at:Option<bson::DateTime>
 }
@@ -33,6 +41,23 @@ impl Students {
     pub fn to_docmunet(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
     }
+
+    // Whole years on `today` counted from the date of birth. The stored age was
+    // entered at registration and goes stale, it is used only without a date of birth.
+    pub fn age_on(&self, today:NaiveDate) -> i64 {
+        match birth_date(&self.date_of_birth) {
+            Some(born) => today.years_since(born).map(i64::from).unwrap_or_default(),
+            None => self.age,
+        }
+    }
+}
+
+// ISO dates, optionally with a time after them, or day first as older forms sent them
+fn birth_date(value:&str) -> Option<NaiveDate> {
+    let value = value.trim();
+    value.get(..10)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .or_else(|| ["%d/%m/%Y", "%d-%m-%Y"].iter().find_map(|format| NaiveDate::parse_from_str(value, format).ok()))
 }
 #[derive(Serialize, Deserialize)]
 pub struct Parents {
@@ -49,4 +74,57 @@ impl Parents {
     pub fn to_docmunet(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::Students;
+
+    fn student(age:i64, date_of_birth:&str) -> Students {
+        Students {
+            id: None,
+            student_id: None,
+            name: "Test".to_string(),
+            age,
+            date_of_birth: date_of_birth.to_string(),
+            address: String::new(),
+            is_active_student: true,
+            profile_pic: None,
+            profile_pic_variants: None,
+            class_branch: None,
+            parent: None,
+            level: None,
+            nationality: None,
+            blood_group: None,
+            weight: None,
+            school_name: None,
+            addhar_number: None,
+            geneder: None,
+            registration_status: None,
+            enquiry_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn age_is_counted_from_the_date_of_birth() {
+        let today = NaiveDate::from_ymd_opt(2026, 6, 15).unwrap();
+
+        assert_eq!(student(8, "2015-06-15").age_on(today), 11);
+        assert_eq!(student(8, "2015-06-16").age_on(today), 10);
+        assert_eq!(student(8, "2015-06-16T00:00:00.000Z").age_on(today), 10);
+        assert_eq!(student(8, "16/06/2015").age_on(today), 10);
+        assert_eq!(student(8, "16-06-2015").age_on(today), 10);
+    }
+
+    #[test]
+    fn stored_age_is_used_without_a_date_of_birth() {
+        let today = NaiveDate::from_ymd_opt(2026, 6, 15).unwrap();
+
+        assert_eq!(student(8, "").age_on(today), 8);
+        assert_eq!(student(8, "not a date").age_on(today), 8);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,23 @@
+use bson::{oid::ObjectId, Document};
+use serde::{Deserialize, Serialize};
+
+// An academy hosted on this deployment. Its data lives in a database of its
+// own, the registry of tenants is kept in the default database.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Tenants {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id:Option<ObjectId>,
+    // subdomain and JWT claim of the academy
+    pub slug:String,
+    pub name:String,
+    pub database:String,
+    pub is_active:bool,
+    pub created_at:Option<bson::DateTime>,
+    pub updated_at:Option<bson::DateTime>
+}
+
+impl Tenants {
+    pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
+        bson::to_document(self)
+    }
+}
\ No newline at end of file
@@ -14,6 +14,9 @@ pub struct Users {
     #[serde(deserialize_with="deserialize_user_type")]
     pub user_type:UserTypes,
     pub is_active:bool,
+    // branch ids a SUBADMIN may work on, ignored for other user types
+    #[serde(default)]
+    pub branches:Vec<String>,
     pub created_at:Option<bson::DateTime>,
     pub updated_at:Option<bson::DateTime>
 }
@@ -32,8 +35,9 @@ where
 {
     let value = String::deserialize(deserializer)?;
     match value.as_str() {
+        "SuperAdminUser" | "SUPERADMIN" => Ok(UserTypes::SUPERADMIN),
         "AdminUser" | "ADMIN" => Ok(UserTypes::ADMIN),
-        "SubAdminUser"  => Ok(UserTypes::SUBADMIN),
+        "SubAdminUser" | "SUBADMIN" => Ok(UserTypes::SUBADMIN),
         "EndUser" | "end_user" => Ok(UserTypes::ENDUSER),
         _ => {
             Err(serde::de::Error::custom(format!("Unknown user type: {}", value)))
@@ -43,6 +47,8 @@ where
 
 #[derive(Serialize, Deserialize)]
 pub enum UserTypes {
+    // runs the deployment, only valid on the default academy
+    SUPERADMIN,
     ADMIN,
     SUBADMIN,
     ENDUSER
@@ -57,6 +63,7 @@ impl Default for UserTypes {
 impl ToString for UserTypes {
     fn to_string(&self) -> String {
         match self {
+            UserTypes::SUPERADMIN => String::from("SuperAdmin"),
             UserTypes::ADMIN => String::from("Admin"),
             UserTypes::SUBADMIN => String::from("SubAdmin"),
             UserTypes::ENDUSER => String::from("EndUser"),
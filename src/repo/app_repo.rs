@@ -5,10 +5,20 @@ use bson::{doc, document, oid::ObjectId, Document};
 use futures::TryStreamExt;
 use mongodb::{options::{self, IndexOptions}, results::{DeleteResult, InsertOneResult, UpdateResult}, Collection, Database, IndexModel, error::ErrorKind};
 
-use crate::{dto::app_dto::{CreateBranchDTO, CreateCourseDTO, CreateFacilities}, helper::app_errors::AppError, models::app::{Branches, Courses, Enquiries, Facilities, Fees}, StudentRepo};
+use crate::{dto::app_dto::{CreateBranchDTO, CreateCourseDTO, CreateFacilities}, helper::{app_errors::AppError, branch_scope::BranchScope}, models::{app::{BranchCapacity, Branches, GeoPoint, CoursePrerequisites, Courses, EnrollmentStatus, Enrollments, Enquiries, EnquiryFollowUp, EnquiryNote, EnquiryStatus, Facilities, FacilityImage, Fees}, media::ImageVariants, money::{Discount, DiscountKind, Money, DEFAULT_CURRENCY}, student_model::Students}, repo::student_repo::StudentRepo};
 
 use super::events_repo::EventRepo;
 
+// legacy numeric amounts were whole rupees (or a float percentage), keep two decimals
+fn legacy_minor_units(value:Option<&bson::Bson>) -> i64 {
+    match value {
+        Some(bson::Bson::Int32(v)) => *v as i64 * 100,
+        Some(bson::Bson::Int64(v)) => v * 100,
+        Some(bson::Bson::Double(v)) => (v * 100.0).round() as i64,
+        _ => 0,
+    }
+}
+
 #[allow(non_snake_case)]
 pub struct AppRepo {
     branch_col:Collection<Document>,
@@ -16,6 +26,7 @@ pub struct AppRepo {
     course_col:Collection<Document>,
     facilities_col:Collection<Document>,
     enquiry_col:Collection<Document>,
+    enrollment_col:Collection<Document>,
     studentRepo:StudentRepo,
     eventRepo:EventRepo,
     
@@ -28,10 +39,15 @@ impl AppRepo {
         let course_col = db.collection("courses");
         let facilities_col = db.collection("facilities");
         let enquiry_col = db.collection("enquiries");
+        let enrollment_col = db.collection("enrollments");
 
         Self::createUniqueIndex(course_col.clone(), "name".to_string(), true).await;
+        Self::createUniqueIndex(enrollment_col.clone(), "course_id".to_string(), false).await;
+        Self::createEnrollmentIndex(enrollment_col.clone()).await;
+        Self::createBranchIndexes(branch_col.clone()).await;
+        Self::migrateFeeDocuments(fees_col.clone()).await;
 
-        AppRepo{ branch_col, fees_col ,studentRepo, eventRepo, course_col, facilities_col, enquiry_col }
+        AppRepo{ branch_col, fees_col ,studentRepo, eventRepo, course_col, facilities_col, enquiry_col, enrollment_col }
     }
 
     pub async fn createUniqueIndex(collection:Collection<Document>, filedName:String,isUnique:bool) {
@@ -48,6 +64,87 @@ impl AppRepo {
         }
     }
 
+    // a student holds at most one ACTIVE or WAITLISTED enrollment per course,
+    // a partial index on $in needs MongoDB 6.0
+    pub async fn createEnrollmentIndex(collection:Collection<Document>) {
+        let index_model = IndexModel::builder()
+            .keys(doc! { "student_id":1, "course_id":1 })
+            .options(IndexOptions::builder().unique(true).partial_filter_expression(doc! {
+                "status": { "$in": [EnrollmentStatus::ACTIVE.to_string(), EnrollmentStatus::WAITLISTED.to_string()] }
+            }).build())
+            .build();
+
+        if let Err(e) = collection.create_index(index_model, None).await {
+            println!("Enrollment index is not created {:?}", e);
+        }
+    }
+
+    // 2dsphere index for the nearby search, codes are unique where they are set
+    pub async fn createBranchIndexes(collection:Collection<Document>) {
+        let indexes = vec![
+            IndexModel::builder().keys(doc! { "location":"2dsphere" }).build(),
+            IndexModel::builder()
+                .keys(doc! { "code":1 })
+                .options(IndexOptions::builder().unique(true).partial_filter_expression(doc! { "code": { "$type":"string" } }).build())
+                .build(),
+        ];
+
+        if let Err(e) = collection.create_indexes(indexes, None).await {
+            println!("Branch indexes are not created {:?}", e);
+        }
+    }
+
+    // Fee documents written before Money existed keep fee_amount as a plain number of
+    // rupees and fee_discount as a float. Convert them in place; already migrated
+    // documents no longer match the filter so this is safe to run on every start.
+    pub async fn migrateFeeDocuments(collection:Collection<Document>) {
+        let filter = doc! { "fee_amount": { "$type": ["int", "long", "double"] } };
+        let mut cursor = match collection.find(filter.clone(), None).await {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                println!("Fee migration failed {:?}", e);
+                return;
+            },
+        };
+
+        let mut migrated = 0;
+        while let Ok(Some(fee)) = cursor.try_next().await {
+            let feeId = match fee.get_object_id("_id") {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+
+            let fee_amount = Money::new(legacy_minor_units(fee.get("fee_amount")), DEFAULT_CURRENCY);
+            // the legacy discount was always taken off as rupees, so it stays a fixed
+            // amount. Small values may have been meant as a percentage, those are
+            // flagged for an admin to check instead of guessed.
+            let discount_value = legacy_minor_units(fee.get("fee_discount"));
+            let fee_discount = (discount_value > 0).then_some(Discount { kind: DiscountKind::FIXED, value: discount_value });
+            let discount_review = (discount_value > 0 && discount_value <= 10_000).then(|| format!(
+                "legacy discount {} was migrated as a fixed amount, check whether it was meant as a percentage",
+                Money::new(discount_value, DEFAULT_CURRENCY).to_decimal_string()
+            ));
+
+            let mut changes = doc! {
+                "fee_amount": bson::to_bson(&fee_amount).unwrap(),
+                "fee_discount": bson::to_bson(&fee_discount).unwrap(),
+            };
+            if let Some(review) = discount_review.as_ref() {
+                println!("Fee {} needs review: {}", feeId, review);
+                changes.insert("discount_review", review);
+            }
+            let update = doc! { "$set":changes };
+
+            let mut selector = filter.clone();
+            selector.insert("_id", feeId);
+            if let Ok(result) = collection.update_one(selector, update, None).await {
+                migrated += result.modified_count;
+            }
+        }
+
+        println!("Fee documents migrated : {}", migrated);
+    }
+
     pub async fn add_branch(&self, branch:Branches) -> Result<InsertOneResult, AppError> {
         let branch_bson = match branch.to_document() {
             Ok(document) => document,
@@ -56,6 +153,7 @@ impl AppRepo {
 
         match self.branch_col.insert_one(branch_bson, None).await {
             Ok(result) => Ok(result),
+            Err(e) if is_duplicate_key(&e) => Err(AppError::CustomError("branch code is already used".to_string())),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
@@ -84,26 +182,86 @@ impl AppRepo {
     }
 
     pub async fn update_branches(&self, branchId:ObjectId, branch:CreateBranchDTO) -> Result<UpdateResult, AppError> {
-        let update = doc! {
-            "$set": {
-                "name":branch.name,
-                "address":branch.address,
-                "is_active":branch.is_active,
-                "updated_at": bson::DateTime::now()
-            }
+        let mut changes = doc! {
+            "name":branch.name,
+            "address":branch.address,
+            "is_active":branch.is_active,
+            "timezone":branch.timezone,
+            "updated_at": bson::DateTime::now()
         };
+        // profile fields are only replaced when sent
+        if let Some(code) = branch.code {
+            changes.insert("code", code);
+        }
+        if let Some(location) = branch.location {
+            changes.insert("location", bson::to_bson(&GeoPoint::new(location.lat, location.lng)).unwrap());
+        }
+        if let Some(address) = branch.postal_address {
+            changes.insert("postal_address", bson::to_bson(&address).unwrap());
+        }
+        if let Some(phone) = branch.phone {
+            changes.insert("phone", phone);
+        }
+        if let Some(email) = branch.email {
+            changes.insert("email", email);
+        }
+        if let Some(hours) = branch.hours {
+            changes.insert("hours", bson::to_bson(&hours).unwrap());
+        }
+        if let Some(holidays) = branch.holidays {
+            changes.insert("holidays", bson::to_bson(&holidays).unwrap());
+        }
 
-        match self.branch_col.update_one(doc! { "_id":branchId }, update, None).await {
+        match self.branch_col.update_one(doc! { "_id":branchId }, doc! { "$set":changes }, None).await {
             Ok(result) => Ok(result),
+            Err(e) if is_duplicate_key(&e) => Err(AppError::CustomError("branch code is already used".to_string())),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     } 
 
+    // Active branches ordered by distance from the point, distance is in metres.
+    pub async fn nearby_branches(&self, lat:f64, lng:f64, max_distance:f64, limit:i64) -> Result<Vec<(Branches, f64)>, AppError> {
+        let pipeline = vec![
+            doc! {
+                "$geoNear": {
+                    "near": bson::to_bson(&GeoPoint::new(lat, lng)).unwrap(),
+                    "distanceField":"distance",
+                    "maxDistance":max_distance,
+                    "spherical":true,
+                    "query": { "is_active":true }
+                }
+            },
+            doc! { "$limit":limit },
+        ];
+
+        let mut cursor = match self.branch_col.aggregate(pipeline, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut branches:Vec<(Branches, f64)> = Vec::new();
+        while let Some(mut branch) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            let distance = branch.remove("distance").and_then(|d| d.as_f64()).unwrap_or_default();
+            match bson::from_document(branch) {
+                Ok(branch) => branches.push((branch, distance)),
+                Err(e) => return Err(AppError::CustomError(e.to_string())),
+            }
+        }
+
+        Ok(branches)
+    }
+
     pub async fn delete_branch(&self, branchId:ObjectId) -> Result<DeleteResult, AppError> {
-        match self.branch_col.delete_one(doc! { "_id":branchId }, None).await {
-            Ok(result) => Ok(result),
-            Err(e) => Err(AppError::CustomError(e.to_string())),
+        let result = match self.branch_col.delete_one(doc! { "_id":branchId }, None).await {
+            Ok(result) => result,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        // facilities of the branch stay, they are only no longer listed under it
+        if let Err(e) = self.facilities_col.update_many(doc! { "branches":branchId }, doc! { "$pull": { "branches":branchId } }, None).await {
+            return Err(AppError::CustomError(e.to_string()));
         }
+        Ok(result)
     }
 
     pub async fn get_branch(&self, branchId:ObjectId) -> Result<Branches, AppError> {
@@ -116,30 +274,34 @@ impl AppRepo {
         bson::from_document(branch).map_err(|e| AppError::CustomError(e.to_string()))
     }
 
-    pub async fn total_branches(&self) -> Result<u64, AppError> {
-        match self.branch_col.count_documents(None, None).await {
+    pub async fn total_branches(&self, scope:&BranchScope) -> Result<u64, AppError> {
+        let filter = match scope.branches() {
+            Some(branches) => doc! { "_id": { "$in": branches.iter().filter_map(|b| ObjectId::parse_str(b).ok()).collect::<Vec<ObjectId>>() } },
+            None => doc! {},
+        };
+        match self.branch_col.count_documents(filter, None).await {
             Ok(count) => Ok(count),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
-    pub async fn total_students(&self) -> Result<u64, AppError> {
-        let result = self.studentRepo.total_students().await;
+    pub async fn total_students(&self, scope:&BranchScope) -> Result<u64, AppError> {
+        let result = self.studentRepo.total_students(scope).await;
         Ok(result)
     }
 
-    pub async fn last_month_admission_count(&self) -> Result<u64, AppError> {
-        let result = self.studentRepo.last_month_admission_count().await;
+    pub async fn last_month_admission_count(&self, scope:&BranchScope) -> Result<u64, AppError> {
+        let result = self.studentRepo.last_month_admission_count(scope).await;
         Ok(result)
     }
 
-    pub async fn total_events(&self) -> Result<u64, AppError> {
-        let result = self.eventRepo.total_event().await;
+    pub async fn total_events(&self, scope:&BranchScope) -> Result<u64, AppError> {
+        let result = self.eventRepo.total_event(scope).await;
         result
     }
 
-    pub async fn upcommint_event_count(&self) -> Result<u64, AppError> {
-        self.eventRepo.upcommint_event_count().await
+    pub async fn upcommint_event_count(&self, scope:&BranchScope) -> Result<u64, AppError> {
+        self.eventRepo.upcommint_event_count(scope).await
     }
 
     // ------------------------------ FEES ------------------------------------ //
@@ -283,11 +445,44 @@ impl AppRepo {
         }
     } 
 
-    pub async fn delete_course(&self, courseId:ObjectId) -> Result<DeleteResult, AppError> {
-        match self.course_col.delete_one( doc! { "_id":courseId, "is_active":false }, None).await {
-            Ok(result) => Ok(result),
-            Err(e) => Err(AppError::CustomError(e.to_string())),
+    // Deletes an inactive course that nobody is studying, its waitlist is dropped with it.
+    // Only an inactive course without active students is deleted. An enrollment
+    // that lands while it is deleted shows up in the second count and the course
+    // is put back, enroll_student checks the other way round.
+    pub async fn delete_course(&self, courseId:ObjectId) -> Result<bool, AppError> {
+        let activeFilter = doc! { "course_id":courseId, "status":EnrollmentStatus::ACTIVE.to_string() };
+        let activeEnrollments = self.count_enrollments(activeFilter.clone()).await?;
+        if activeEnrollments > 0 {
+            return Err(AppError::CustomError(format!("course has {} active enrollments", activeEnrollments)));
+        }
+
+        let course = match self.course_col.find_one_and_delete(doc! { "_id":courseId, "is_active":false }, None).await {
+            Ok(Some(course)) => course,
+            Ok(None) => return Ok(false),
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let activeEnrollments = self.count_enrollments(activeFilter).await?;
+        if activeEnrollments > 0 {
+            if let Err(e) = self.course_col.insert_one(course, None).await {
+                return Err(AppError::CustomError(e.to_string()));
+            }
+            return Err(AppError::CustomError(format!("course has {} active enrollments", activeEnrollments)));
+        }
+
+        let update = doc! {
+            "$set": {
+                "status":EnrollmentStatus::DROPPED.to_string(),
+                "end_date":bson::DateTime::now(),
+                "updated_at":bson::DateTime::now()
+            }
+        };
+
+        if let Err(e) = self.enrollment_col.update_many(doc! { "course_id":courseId, "status":EnrollmentStatus::WAITLISTED.to_string() }, update, None).await {
+            return Err(AppError::CustomError(e.to_string()));
         }
+
+        Ok(true)
     }
 
     pub async fn get_course(&self, courseId:ObjectId) -> Result<Courses, AppError> {
@@ -302,6 +497,267 @@ impl AppRepo {
     }
 
 
+    // Changing the capacity moves the seats left by the same amount, the seats
+    // already taken stay taken.
+    pub async fn set_course_capacity(&self, courseId:ObjectId, branchId:ObjectId, capacity:i64) -> Result<UpdateResult, AppError> {
+        self.seed_seats(courseId, branchId).await?;
+
+        let current = self.get_course(courseId).await?.capacity_for(&branchId);
+        let (filter, update) = match current {
+            Some(current) => (
+                doc! { "_id":courseId, "branch_capacity": { "$elemMatch": { "branch_id":branchId, "capacity":current } } },
+                doc! {
+                    "$set": { "branch_capacity.$.capacity":capacity, "updated_at":bson::DateTime::now() },
+                    "$inc": { "branch_capacity.$.seats_left":capacity - current }
+                }
+            ),
+            None => {
+                let seatsTaken = self.count_enrollments(doc! {
+                    "course_id":courseId,
+                    "branch_id":branchId,
+                    "status":EnrollmentStatus::ACTIVE.to_string()
+                }).await? as i64;
+
+                (
+                    doc! { "_id":courseId, "branch_capacity.branch_id": { "$ne":branchId } },
+                    doc! {
+                        "$push": { "branch_capacity": { "branch_id":branchId, "capacity":capacity, "seats_left":capacity - seatsTaken } },
+                        "$set": { "updated_at":bson::DateTime::now() }
+                    }
+                )
+            },
+        };
+
+        match self.course_col.update_one(filter, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // Branches given a capacity before seats were counted start from their
+    // active enrollments, only the first seed is applied.
+    async fn seed_seats(&self, courseId:ObjectId, branchId:ObjectId) -> Result<(), AppError> {
+        let capacity = match self.get_course(courseId).await?.branch_capacity.into_iter().find(|c| c.branch_id == branchId) {
+            Some(BranchCapacity { capacity, seats_left: None, .. }) => capacity,
+            _ => return Ok(()),
+        };
+
+        let seatsTaken = self.count_enrollments(doc! {
+            "course_id":courseId,
+            "branch_id":branchId,
+            "status":EnrollmentStatus::ACTIVE.to_string()
+        }).await? as i64;
+
+        match self.course_col.update_one(
+            doc! { "_id":courseId, "branch_capacity": { "$elemMatch": { "branch_id":branchId, "seats_left": { "$exists":false } } } },
+            doc! { "$set": { "branch_capacity.$.seats_left":capacity - seatsTaken } },
+            None
+        ).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // Takes a seat on the course with a conditional $inc, false when the branch
+    // is full. A branch without a capacity always has room.
+    async fn take_seat(&self, courseId:ObjectId, branchId:ObjectId) -> Result<bool, AppError> {
+        self.seed_seats(courseId, branchId).await?;
+
+        let result = self.course_col.update_one(
+            doc! { "_id":courseId, "branch_capacity": { "$elemMatch": { "branch_id":branchId, "seats_left": { "$gt":0 } } } },
+            doc! { "$inc": { "branch_capacity.$.seats_left":-1 } },
+            None
+        ).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+        if result.matched_count > 0 {
+            return Ok(true);
+        }
+
+        match self.course_col.count_documents(doc! { "_id":courseId, "branch_capacity.branch_id":branchId }, None).await {
+            Ok(limited) => Ok(limited == 0),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    async fn free_seat(&self, courseId:ObjectId, branchId:ObjectId) -> Result<(), AppError> {
+        match self.course_col.update_one(
+            doc! { "_id":courseId, "branch_capacity": { "$elemMatch": { "branch_id":branchId, "seats_left": { "$exists":true } } } },
+            doc! { "$inc": { "branch_capacity.$.seats_left":1 } },
+            None
+        ).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn set_course_prerequisites(&self, courseId:ObjectId, prerequisites:CoursePrerequisites) -> Result<UpdateResult, AppError> {
+        let prerequisites = bson::to_bson(&prerequisites).map_err(|e| AppError::CustomError(e.to_string()))?;
+        let update = doc! {
+            "$set": {
+                "prerequisites":prerequisites,
+                "updated_at":bson::DateTime::now()
+            }
+        };
+
+        match self.course_col.update_one(doc! { "_id":courseId }, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // ------------------------------- ENROLLMENTS ------------------------------------- //
+    pub async fn get_student(&self, studentId:ObjectId) -> Result<Students, AppError> {
+        self.studentRepo.get_student(studentId, &BranchScope::All).await
+    }
+
+    // courses the student has finished, for prerequisite checks
+    pub async fn completed_courses(&self, studentId:ObjectId) -> Result<Vec<ObjectId>, AppError> {
+        let enrollments = self.list_enrollments(doc! { "student_id":studentId, "status":EnrollmentStatus::COMPLETED.to_string() }).await?;
+        Ok(enrollments.into_iter().map(|e| e.course_id).collect())
+    }
+
+    // Enrolls the student as ACTIVE while the branch has seats, WAITLISTED after that.
+    pub async fn enroll_student(&self, mut enrollment:Enrollments) -> Result<Enrollments, AppError> {
+        let existing = self.count_enrollments(doc! {
+            "student_id":enrollment.student_id,
+            "course_id":enrollment.course_id,
+            "status": { "$in": [EnrollmentStatus::ACTIVE.to_string(), EnrollmentStatus::WAITLISTED.to_string()] }
+        }).await?;
+        if existing > 0 {
+            return Err(AppError::CustomError("student is already enrolled in this course".to_string()));
+        }
+
+        let seated = self.take_seat(enrollment.course_id, enrollment.branch_id).await?;
+        enrollment.status = match seated {
+            true => EnrollmentStatus::ACTIVE.to_string(),
+            false => EnrollmentStatus::WAITLISTED.to_string(),
+        };
+
+        let inserted = match enrollment.to_document() {
+            Ok(document) => self.enrollment_col.insert_one(document, None).await.map_err(|e| match is_duplicate_key(&e) {
+                // a concurrent request enrolled the student first
+                true => AppError::CustomError("student is already enrolled in this course".to_string()),
+                false => AppError::CustomError(e.to_string()),
+            }),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        };
+
+        let result = match inserted {
+            Ok(result) => result,
+            Err(e) => {
+                if seated {
+                    self.free_seat(enrollment.course_id, enrollment.branch_id).await?;
+                }
+                return Err(e);
+            },
+        };
+        enrollment.id = result.inserted_id.as_object_id();
+
+        // the course may have been deleted meanwhile, delete_course checks the other way round
+        match self.course_col.find_one(doc! { "_id":enrollment.course_id }, None).await {
+            Ok(Some(_)) => Ok(enrollment),
+            Ok(None) => {
+                if let Err(e) = self.enrollment_col.delete_one(doc! { "_id":enrollment.id }, None).await {
+                    return Err(AppError::CustomError(e.to_string()));
+                }
+                Err(AppError::DataNotFoundError)
+            },
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_enrollment(&self, enrollmentId:ObjectId) -> Result<Enrollments, AppError> {
+        match self.enrollment_col.find_one(doc! { "_id":enrollmentId }, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn list_enrollments(&self, filter:Document) -> Result<Vec<Enrollments>, AppError> {
+        let opt = options::FindOptions::builder()
+            .sort(doc! { "created_at":1 })
+            .build();
+
+        let mut cursor = match self.enrollment_col.find(filter, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut enrollments:Vec<Enrollments> = Vec::new();
+        while let Some(enrollment) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            enrollments.push(bson::from_document(enrollment).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(enrollments)
+    }
+
+    pub async fn count_enrollments(&self, filter:Document) -> Result<u64, AppError> {
+        match self.enrollment_col.count_documents(filter, None).await {
+            Ok(count) => Ok(count),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // Closes an active or waitlisted enrollment as COMPLETED or DROPPED, an active
+    // one gives its seat back. Returns the enrollment as it was before closing,
+    // None when it was already closed.
+    pub async fn close_enrollment(&self, enrollmentId:ObjectId, status:EnrollmentStatus) -> Result<Option<Enrollments>, AppError> {
+        let update = doc! {
+            "$set": {
+                "status":status.to_string(),
+                "end_date":bson::DateTime::now(),
+                "updated_at":bson::DateTime::now()
+            }
+        };
+
+        let filter = doc! {
+            "_id":enrollmentId,
+            "status": { "$in": [EnrollmentStatus::ACTIVE.to_string(), EnrollmentStatus::WAITLISTED.to_string()] }
+        };
+
+        let closed:Enrollments = match self.enrollment_col.find_one_and_update(filter, update, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string()))?,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        if closed.status == EnrollmentStatus::ACTIVE.to_string() {
+            self.free_seat(closed.course_id, closed.branch_id).await?;
+        }
+
+        Ok(Some(closed))
+    }
+
+    // Moves the longest waiting students to ACTIVE while the branch has seats.
+    // Returns the promoted enrollments.
+    pub async fn promote_waitlist(&self, courseId:ObjectId, branchId:ObjectId) -> Result<Vec<ObjectId>, AppError> {
+        let mut promoted:Vec<ObjectId> = Vec::new();
+
+        while self.take_seat(courseId, branchId).await? {
+            let opt = options::FindOneAndUpdateOptions::builder()
+                .sort(doc! { "created_at":1 })
+                .build();
+
+            let next = self.enrollment_col.find_one_and_update(
+                doc! { "course_id":courseId, "branch_id":branchId, "status":EnrollmentStatus::WAITLISTED.to_string() },
+                doc! { "$set": { "status":EnrollmentStatus::ACTIVE.to_string(), "updated_at":bson::DateTime::now() } },
+                opt
+            ).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+            match next.and_then(|document| document.get_object_id("_id").ok()) {
+                Some(enrollmentId) => promoted.push(enrollmentId),
+                None => {
+                    // nobody is waiting, the seat goes back
+                    self.free_seat(courseId, branchId).await?;
+                    break;
+                },
+            }
+        }
+
+        Ok(promoted)
+    }
+
     // ------------------------------- FACILITIES ------------------------------------- //
     pub async fn add_facilities(&self, facility:Facilities) -> Result<InsertOneResult, AppError> {
         let bson_doc = match facility.to_document() {
@@ -348,28 +804,142 @@ impl AppRepo {
         Ok(facilities)
     }
 
-    pub async fn update_facilities(&self, facilityID:ObjectId, facility:CreateFacilities, isImage:bool) -> Result<UpdateResult, AppError> {
-        let mut update = doc! {};
-        println!("IS Image URL  : {:?} and tag is {:?}", facility.image_url, isImage);
-        if isImage{
-            update = doc! {
-                "$set": {
-                    "imageUrl":facility.image_url,
-                    "updated_at":bson::DateTime::now()
-                }
+    // imageUrl used to hold a /static/ path, it holds the storage key now
+    pub async fn migrate_facility_image_keys(&self) -> Result<u64, AppError> {
+        let update = vec![doc! {
+            "$set": { "imageUrl": { "$substrCP": ["$imageUrl", 8, { "$strLenCP":"$imageUrl" }] } }
+        }];
+        match self.facilities_col.update_many(doc! { "imageUrl": { "$regex":"^/static/" } }, update, None).await {
+            Ok(result) => Ok(result.modified_count),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // fee cycles that have a fee set up
+    pub async fn fee_types_in_use(&self) -> Result<Vec<String>, AppError> {
+        match self.fees_col.distinct("fee_type", None, None).await {
+            Ok(fee_types) => Ok(fee_types.iter().filter_map(|f| f.as_str()).map(String::from).collect()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // stored values of every facility image and its variants
+    pub async fn facility_file_references(&self) -> Result<Vec<String>, AppError> {
+        let opt = options::FindOptions::builder()
+            .projection(doc! { "imageUrl":1, "image_variants":1, "gallery":1 })
+            .build();
+        let mut cursor = match self.facilities_col.find(doc! { "$or": [{ "imageUrl": { "$type":"string" } }, { "gallery.0": { "$exists":true } }] }, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut references:Vec<String> = Vec::new();
+        while let Some(facility) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            if let Ok(imageUrl) = facility.get_str("imageUrl") {
+                references.push(imageUrl.to_string());
             }
-        }else {
-            update = doc! {
-                "$set": {
-                    "title": facility.title,
-                    "description":facility.description,
-                    "updated_at":bson::DateTime::now()
+            if let Some(variants) = facility.get("image_variants").and_then(|v| bson::from_bson::<ImageVariants>(v.clone()).ok()) {
+                references.extend(variants.keys().into_iter().map(String::from));
+            }
+            if let Some(Ok(gallery)) = facility.get("gallery").map(|g| bson::from_bson::<Vec<FacilityImage>>(g.clone())) {
+                for image in gallery {
+                    references.push(image.key);
+                    references.extend(image.variants.iter().flat_map(|v| v.keys()).map(String::from));
                 }
+            }
+        }
+        Ok(references)
+    }
+
+    // Replaces the gallery if it still holds `current`, so two edits of the same
+    // gallery can not overwrite each other. The first image becomes the cover.
+    pub async fn set_facility_gallery(&self, facilityID:ObjectId, current:&[FacilityImage], gallery:Vec<FacilityImage>) -> Result<UpdateResult, AppError> {
+        let current_ids:Vec<ObjectId> = current.iter().map(|image| image.id).collect();
+        let cover = gallery.first().cloned();
+        let gallery = bson::to_bson(&gallery).map_err(|e| AppError::CustomError(e.to_string()))?;
+        let coverVariants = bson::to_bson(&cover.as_ref().and_then(|c| c.variants.clone())).map_err(|e| AppError::CustomError(e.to_string()))?;
+        let update = doc! {
+            "$set": {
+                "gallery":gallery,
+                "imageUrl":cover.map(|c| c.key),
+                "image_variants":coverVariants,
+                "updated_at":bson::DateTime::now()
+            }
+        };
+
+        let filter = doc! { "_id":facilityID, "$expr": { "$eq": [{ "$ifNull": ["$gallery.id", []] }, current_ids] } };
+        match self.facilities_col.update_one(filter, update, None).await {
+            Ok(result) if result.matched_count == 0 => Err(AppError::CustomError("the gallery changed, reload and try again".to_string())),
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // the single image of facilities saved before galleries becomes their first gallery image
+    pub async fn backfill_facility_galleries(&self) -> Result<u64, AppError> {
+        let filter = doc! { "imageUrl": { "$type":"string" }, "gallery.0": { "$exists":false } };
+        let mut cursor = match self.facilities_col.find(filter, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut updated = 0;
+        while let Some(facility) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            let facility:Facilities = bson::from_document(facility).map_err(|e| AppError::CustomError(e.to_string()))?;
+            let (id, key) = match (facility.id, facility.imageUrl) {
+                (Some(id), Some(key)) => (id, key),
+                _ => continue,
             };
+            let image = FacilityImage { id: ObjectId::new(), key, variants: facility.image_variants, created_at: facility.updated_at };
+            self.set_facility_gallery(id, &[], vec![image]).await?;
+            updated += 1;
         }
-        
 
-        match self.facilities_col.update_one(doc! {"_id":facilityID}, update, None).await {
+        Ok(updated)
+    }
+
+    pub async fn count_branches(&self, branchIds:&[ObjectId]) -> Result<u64, AppError> {
+        match self.branch_col.count_documents(doc! { "_id": { "$in":branchIds } }, None).await {
+            Ok(count) => Ok(count),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn list_branch_facilities(&self, branchId:ObjectId) -> Result<Vec<Facilities>, AppError> {
+        let opt = options::FindOptions::builder()
+            .sort(doc! {"title":1})
+            .build();
+        let mut cursor = match self.facilities_col.find(doc! { "branches":branchId }, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut facilities:Vec<Facilities> = Vec::new();
+        while let Some(facility) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            facilities.push(bson::from_document(facility).map_err(|e| AppError::CustomError(e.to_string()))?);
+        }
+        Ok(facilities)
+    }
+
+    pub async fn update_facilities(&self, facilityID:ObjectId, facility:CreateFacilities, branches:Option<Vec<ObjectId>>) -> Result<UpdateResult, AppError> {
+        let mut changes = doc! { "updated_at":bson::DateTime::now() };
+        if let Some(title) = facility.title {
+            changes.insert("title", title);
+        }
+        if let Some(description) = facility.description {
+            changes.insert("description", description);
+        }
+        if let Some(branches) = branches {
+            changes.insert("branches", branches);
+        }
+        if let Some(amenities) = facility.amenities {
+            changes.insert("amenities", amenities.iter().map(|a| a.to_string()).collect::<Vec<String>>());
+        }
+        if let Some(notes) = facility.availability_notes {
+            changes.insert("availability_notes", Some(notes.trim().to_string()).filter(|n| !n.is_empty()));
+        }
+
+        match self.facilities_col.update_one(doc! {"_id":facilityID}, doc! { "$set":changes }, None).await {
             Ok(result) => Ok(result),
             Err(e) => {
                 Err(AppError::CustomError(e.to_string()))
@@ -397,11 +967,11 @@ impl AppRepo {
         }
     }
 
-    pub async fn list_enquires(&self) -> Result<Vec<Enquiries>, AppError> {
+    pub async fn list_enquires(&self, filter:Document, scope:&BranchScope) -> Result<Vec<Enquiries>, AppError> {
         let opt = options::FindOptions::builder()
             .sort(doc! {"created_at":-1})
             .build();
-        let mut cursor = match self.enquiry_col.find(None, opt).await {
+        let mut cursor = match self.enquiry_col.find(scope.restrict(filter, "branch"), opt).await {
             Ok(cursor) => cursor,
             Err(e) => return Err(AppError::CustomError(e.to_string())),
         };
@@ -420,12 +990,144 @@ impl AppRepo {
         Ok(enquires)
     }
 
-    pub async fn delete_enquiries(&self, enquiryID:ObjectId) -> Result<DeleteResult, AppError> {
-        match self.enquiry_col.delete_one(doc! { "_id": enquiryID }, None).await {
+    pub async fn delete_enquiries(&self, enquiryID:ObjectId, scope:&BranchScope) -> Result<DeleteResult, AppError> {
+        match self.enquiry_col.delete_one(scope.restrict(doc! { "_id": enquiryID }, "branch"), None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_enquiry(&self, enquiryID:ObjectId, scope:&BranchScope) -> Result<Enquiries, AppError> {
+        match self.enquiry_col.find_one(scope.restrict(doc! { "_id":enquiryID }, "branch"), None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // sets the status fields and records the change on the timeline
+    pub async fn update_enquiry_status(&self, enquiryID:ObjectId, scope:&BranchScope, fields:Document, note:EnquiryNote) -> Result<UpdateResult, AppError> {
+        self.set_enquiry_with_note(enquiryID, scope, fields, note).await
+    }
+
+    pub async fn assign_enquiry(&self, enquiryID:ObjectId, scope:&BranchScope, userId:&str, note:EnquiryNote) -> Result<UpdateResult, AppError> {
+        self.set_enquiry_with_note(enquiryID, scope, doc! { "assigned_to":userId }, note).await
+    }
+
+    pub async fn add_enquiry_note(&self, enquiryID:ObjectId, scope:&BranchScope, note:EnquiryNote) -> Result<UpdateResult, AppError> {
+        self.set_enquiry_with_note(enquiryID, scope, doc! {}, note).await
+    }
+
+    pub async fn add_enquiry_follow_up(&self, enquiryID:ObjectId, scope:&BranchScope, follow_up:&EnquiryFollowUp) -> Result<UpdateResult, AppError> {
+        let follow_up = bson::to_bson(follow_up).map_err(|e| AppError::CustomError(e.to_string()))?;
+        self.update_enquiry(enquiryID, scope, doc! {
+            "$set": { "updated_at":bson::DateTime::now() },
+            "$push": { "follow_ups":follow_up }
+        }).await
+    }
+
+    // Links the enquiry to the student being registered from it. matched_count is 0
+    // when it was converted already, so two conversions can not both go through.
+    pub async fn convert_enquiry(&self, enquiryID:ObjectId, studentId:ObjectId, scope:&BranchScope, note:EnquiryNote) -> Result<UpdateResult, AppError> {
+        let note = bson::to_bson(&note).map_err(|e| AppError::CustomError(e.to_string()))?;
+        let filter = doc! { "_id":enquiryID, "student_id": { "$exists":false } };
+        let update = doc! {
+            "$set": {
+                "status":EnquiryStatus::CONVERTED.to_string(),
+                "student_id":studentId,
+                "lost_reason":null,
+                "converted_at":bson::DateTime::now(),
+                "updated_at":bson::DateTime::now()
+            },
+            "$push": { "notes":note }
+        };
+
+        match self.enquiry_col.update_one(scope.restrict(filter, "branch"), update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // undoes convert_enquiry when the student could not be saved, the conversion
+    // note is matched by id as other notes may have been added since
+    pub async fn revert_enquiry_conversion(&self, enquiryID:ObjectId, noteId:ObjectId, status:&str, lost_reason:Option<&str>) -> Result<UpdateResult, AppError> {
+        let update = doc! {
+            "$set": { "status":status, "lost_reason":lost_reason, "updated_at":bson::DateTime::now() },
+            "$unset": { "student_id":"", "converted_at":"" },
+            "$pull": { "notes": { "id":noteId } }
+        };
+
+        match self.enquiry_col.update_one(doc! { "_id":enquiryID }, update, None).await {
             Ok(result) => Ok(result),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
+    // marks an open follow-up done, modified_count is 0 when it already was
+    pub async fn complete_follow_up(&self, enquiryID:ObjectId, followUpId:ObjectId, scope:&BranchScope) -> Result<UpdateResult, AppError> {
+        let filter = doc! {
+            "_id":enquiryID,
+            "follow_ups": { "$elemMatch": { "id":followUpId, "done_at": { "$exists":false } } }
+        };
+        let update = doc! {
+            "$set": { "follow_ups.$.done_at":bson::DateTime::now(), "updated_at":bson::DateTime::now() }
+        };
 
-}
\ No newline at end of file
+        match self.enquiry_col.update_one(scope.restrict(filter, "branch"), update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // Open follow-ups of a user due before `until`, oldest first, each with its enquiry.
+    pub async fn due_follow_ups(&self, userId:&str, until:bson::DateTime, scope:&BranchScope) -> Result<Vec<(Enquiries, EnquiryFollowUp)>, AppError> {
+        let due = doc! { "assigned_to":userId, "done_at": { "$exists":false }, "due_at": { "$lt":until } };
+        let pipeline = vec![
+            doc! { "$match": scope.restrict(doc! { "follow_ups": { "$elemMatch":due.clone() } }, "branch") },
+            doc! { "$unwind": "$follow_ups" },
+            doc! { "$match": {
+                "follow_ups.assigned_to":userId,
+                "follow_ups.done_at": { "$exists":false },
+                "follow_ups.due_at": { "$lt":until }
+            } },
+            doc! { "$sort": { "follow_ups.due_at":1 } },
+        ];
+
+        let mut cursor = match self.enquiry_col.aggregate(pipeline, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut due_follow_ups = Vec::new();
+        while let Some(mut document) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            let follow_up = match document.remove("follow_ups") {
+                Some(bson::Bson::Document(follow_up)) => bson::from_document(follow_up).map_err(|e| AppError::CustomError(e.to_string()))?,
+                _ => continue,
+            };
+            let enquiry:Enquiries = bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string()))?;
+            due_follow_ups.push((enquiry, follow_up));
+        }
+
+        Ok(due_follow_ups)
+    }
+
+    async fn set_enquiry_with_note(&self, enquiryID:ObjectId, scope:&BranchScope, mut fields:Document, note:EnquiryNote) -> Result<UpdateResult, AppError> {
+        fields.insert("updated_at", bson::DateTime::now());
+        let note = bson::to_bson(&note).map_err(|e| AppError::CustomError(e.to_string()))?;
+        self.update_enquiry(enquiryID, scope, doc! { "$set":fields, "$push": { "notes":note } }).await
+    }
+
+    async fn update_enquiry(&self, enquiryID:ObjectId, scope:&BranchScope, update:Document) -> Result<UpdateResult, AppError> {
+        match self.enquiry_col.update_one(scope.restrict(doc! { "_id":enquiryID }, "branch"), update, None).await {
+            Ok(result) if result.matched_count == 0 => Err(AppError::DataNotFoundError),
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+
+}
+
+fn is_duplicate_key(err:&mongodb::error::Error) -> bool {
+    matches!(*err.kind, ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref write_error)) if write_error.code == 11000)
+}
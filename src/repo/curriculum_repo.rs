@@ -0,0 +1,165 @@
+use bson::{doc, oid::ObjectId, Document};
+use futures::TryStreamExt;
+use mongodb::{options::{self, IndexOptions}, results::{DeleteResult, InsertOneResult}, Collection, Database, IndexModel};
+
+use crate::{helper::app_errors::AppError, models::curriculum::{Curriculums, SyllabusItem, SyllabusProgress}, repo::student_repo::StudentRepo};
+
+#[allow(non_snake_case)]
+pub struct CurriculumRepo {
+    curriculum_col:Collection<Document>,
+    progress_col:Collection<Document>,
+    course_col:Collection<Document>,
+    pub studentRepo:StudentRepo,
+}
+
+#[allow(non_snake_case)]
+impl CurriculumRepo {
+    pub async fn init(db:Database, studentRepo:StudentRepo) -> Self {
+        let curriculum_col = db.collection("curriculums");
+        let progress_col = db.collection("syllabus_progress");
+        let course_col = db.collection("courses");
+
+        Self::createUniqueIndex(curriculum_col.clone(), doc! { "course_id":1, "level":1, "version":1 }).await;
+        // an item is ticked once per student and course belt
+        Self::createUniqueIndex(progress_col.clone(), doc! { "student_id":1, "course_id":1, "level":1, "item_id":1 }).await;
+
+        CurriculumRepo { curriculum_col, progress_col, course_col, studentRepo }
+    }
+
+    async fn createUniqueIndex(collection:Collection<Document>, keys:Document) {
+        let index_model = IndexModel::builder()
+            .keys(keys)
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+
+        if let Err(e) = collection.create_index(index_model, None).await {
+            println!("Index is not create on collection index filed {:?}", e);
+        }
+    }
+
+    pub async fn course_exists(&self, courseId:ObjectId) -> Result<bool, AppError> {
+        match self.course_col.count_documents(doc! { "_id":courseId }, None).await {
+            Ok(count) => Ok(count > 0),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // ------------------------------- CURRICULUM ------------------------------------- //
+    // Saves the items as the next version and makes it the current one. Items that
+    // match an item of the previous version by category and name keep its item_id.
+    pub async fn add_version(&self, mut curriculum:Curriculums) -> Result<Curriculums, AppError> {
+        let previous = self.current_curriculum(curriculum.course_id, &curriculum.level).await?;
+
+        if let Some(previous) = previous.as_ref() {
+            curriculum.version = previous.version + 1;
+            curriculum.items = curriculum.items.into_iter().map(|item| {
+                match previous.items.iter().find(|p| p.category == item.category && p.name.eq_ignore_ascii_case(&item.name)) {
+                    Some(existing) => SyllabusItem { item_id: existing.item_id.to_string(), ..item },
+                    None => item,
+                }
+            }).collect();
+        } else {
+            curriculum.version = 1;
+        }
+
+        let bson_doc = match curriculum.to_document() {
+            Ok(document) => document,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        // the unique version index turns a concurrent revision into an error instead of a fork
+        let result = match self.curriculum_col.insert_one(bson_doc, None).await {
+            Ok(result) => result,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+        curriculum.id = result.inserted_id.as_object_id();
+
+        let update = doc! { "$set": { "is_current":false } };
+        let filter = doc! { "course_id":curriculum.course_id, "level":&curriculum.level, "_id": { "$ne":curriculum.id } };
+        if let Err(e) = self.curriculum_col.update_many(filter, update, None).await {
+            return Err(AppError::CustomError(e.to_string()));
+        }
+
+        Ok(curriculum)
+    }
+
+    pub async fn current_curriculum(&self, courseId:ObjectId, level:&str) -> Result<Option<Curriculums>, AppError> {
+        let opt = options::FindOneOptions::builder()
+            .sort(doc! { "version":-1 })
+            .build();
+
+        match self.curriculum_col.find_one(doc! { "course_id":courseId, "level":level }, opt).await {
+            Ok(Some(document)) => bson::from_document(document).map(Some).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Ok(None),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_curriculum(&self, curriculumId:ObjectId) -> Result<Curriculums, AppError> {
+        match self.curriculum_col.find_one(doc! { "_id":curriculumId }, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn curriculum_history(&self, courseId:ObjectId, level:&str) -> Result<Vec<Curriculums>, AppError> {
+        let opt = options::FindOptions::builder()
+            .sort(doc! { "version":-1 })
+            .build();
+
+        let mut cursor = match self.curriculum_col.find(doc! { "course_id":courseId, "level":level }, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut curriculums:Vec<Curriculums> = Vec::new();
+        while let Some(curriculum) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            curriculums.push(bson::from_document(curriculum).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(curriculums)
+    }
+
+    // ------------------------------- PROGRESS ------------------------------------- //
+    pub async fn tick_item(&self, progress:SyllabusProgress) -> Result<InsertOneResult, AppError> {
+        let bson_doc = match progress.to_document() {
+            Ok(document) => document,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        match self.progress_col.insert_one(bson_doc, None).await {
+            Ok(result) => Ok(result),
+            Err(err) => match *err.kind {
+                mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref write_error))
+                    if write_error.code == 11000 => Err(AppError::CustomError("item is already ticked".to_string())),
+                _ => Err(AppError::CustomError(err.to_string())),
+            },
+        }
+    }
+
+    pub async fn untick_item(&self, studentId:ObjectId, courseId:ObjectId, level:&str, itemId:&str) -> Result<DeleteResult, AppError> {
+        match self.progress_col.delete_one(doc! { "student_id":studentId, "course_id":courseId, "level":level, "item_id":itemId }, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn student_progress(&self, studentId:ObjectId, courseId:ObjectId, level:&str) -> Result<Vec<SyllabusProgress>, AppError> {
+        let opt = options::FindOptions::builder()
+            .sort(doc! { "completed_at":1 })
+            .build();
+
+        let mut cursor = match self.progress_col.find(doc! { "student_id":studentId, "course_id":courseId, "level":level }, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut progress:Vec<SyllabusProgress> = Vec::new();
+        while let Some(item) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            progress.push(bson::from_document(item).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(progress)
+    }
+}
@@ -1,35 +1,51 @@
-use core::sync;
-
 use bson::{ doc, oid::ObjectId, Document };
 use futures::TryStreamExt;
 use mongodb::{
-    options,
+    error::ErrorKind,
+    options::{ self, IndexOptions },
     results::{ DeleteResult, InsertOneResult, UpdateResult },
     Collection,
     Database,
-    sync::Collection as syncCollection
+    IndexModel,
 };
 
 use crate::{
     dto::event_dto::UpdateEventDTO,
-    helper::app_errors::AppError,
-    models::events::{ Events, FileData }, mongoRepo::mongorepos::MongoRepo,
+    helper::{ app_errors::AppError, branch_scope::BranchScope, timezone },
+    models::events::{ EventRegistrations, Events, FileData, OccurrenceException, Recurrence, RegistrationSettings, RegistrationStatus },
+    repo::student_repo::StudentRepo,
 };
 
+#[allow(non_snake_case)]
 pub struct EventRepo {
     event_col: Collection<Document>,
+    branch_col: Collection<Document>,
+    registration_col: Collection<Document>,
+    pub studentRepo: StudentRepo,
 }
 
 #[allow(non_snake_case)]
 impl EventRepo {
-    pub fn init(db: Database) -> Self {
+    pub async fn init(db: Database) -> Self {
         let event_col = db.collection("events");
-        
+        let branch_col = db.collection("branches");
+        let registration_col: Collection<Document> = db.collection("event_registrations");
+        let studentRepo = StudentRepo::init(db);
 
-        EventRepo { event_col }
+        // a student has one registration per event, however many requests race
+        let index_model = IndexModel::builder()
+            .keys(doc! { "event_id":1, "student_id":1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = registration_col.create_index(index_model, None).await {
+            println!("Index is not create on collection index filed {:?}", e);
+        }
+
+        EventRepo { event_col, branch_col, registration_col, studentRepo }
     }
 
-    pub async fn add_event(&self, event: Events) -> Result<InsertOneResult, AppError> {
+    pub async fn add_event(&self, event: Events, scope: &BranchScope) -> Result<InsertOneResult, AppError> {
+        scope.check(&event.branches)?;
         let event_bson = match event.to_docmunet() {
             Ok(document) => document,
             Err(e) => {
@@ -46,28 +62,125 @@ impl EventRepo {
     pub async fn add_file_data(
         &self,
         eventId: ObjectId,
-        fileData: FileData
+        fileData: Vec<FileData>,
+        scope: &BranchScope
     ) -> Result<UpdateResult, AppError> {
-        let bson_fileData = match fileData.to_docmunet() {
-            Ok(data) => data,
-            Err(e) => {
-                return Err(AppError::CustomError(e.to_string()));
+        let mut bson_fileData: Vec<Document> = Vec::new();
+        for data in fileData.iter() {
+            match data.to_docmunet() {
+                Ok(data) => bson_fileData.push(data),
+                Err(e) => {
+                    return Err(AppError::CustomError(e.to_string()));
+                }
             }
-        };
+        }
         let update =
             doc! {
             "$push":doc! {
-                "file_data":bson_fileData
+                "file_data": { "$each":bson_fileData }
             }
         };
 
-        match self.event_col.update_one(doc! { "_id":eventId }, update, None).await {
+        match self.event_col.update_one(scope.restrict(doc! { "_id":eventId }, "branches"), update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // ------------------------------- MEDIA ------------------------------------- //
+    // Rewrites the whole gallery, used for removing, reordering and the cover.
+    // `current` guards against a concurrent change since the gallery was read.
+    pub async fn set_file_data(&self, eventId: ObjectId, current: &[FileData], fileData: Vec<FileData>, scope: &BranchScope) -> Result<UpdateResult, AppError> {
+        let current_ids: Vec<Option<ObjectId>> = current.iter().map(|f| f.id).collect();
+        let fileData = bson::to_bson(&fileData).map_err(|e| AppError::CustomError(e.to_string()))?;
+        let update = doc! { "$set": { "file_data":fileData, "updated_at":bson::DateTime::now() } };
+
+        let filter = scope.restrict(doc! { "_id":eventId, "$expr": { "$eq": [{ "$ifNull": ["$file_data.id", []] }, current_ids] } }, "branches");
+        match self.event_col.update_one(filter, update, None).await {
+            Ok(result) if result.matched_count == 0 => Err(AppError::CustomError("the gallery changed, reload and try again".to_string())),
             Ok(result) => Ok(result),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
-    pub async fn get_events(&self, skip: i64, limit: i64) -> Result<Vec<Events>, AppError> {
+    pub async fn set_media_caption(&self, eventId: ObjectId, mediaId: ObjectId, caption: Option<String>, scope: &BranchScope) -> Result<UpdateResult, AppError> {
+        let update = doc! { "$set": { "file_data.$.caption":caption, "updated_at":bson::DateTime::now() } };
+
+        match self.event_col.update_one(scope.restrict(doc! { "_id":eventId, "file_data.id":mediaId }, "branches"), update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // gives an id to gallery items saved before items had one
+    pub async fn backfill_media_ids(&self) -> Result<u64, AppError> {
+        let filter = doc! { "file_data": { "$elemMatch": { "id": { "$exists":false } } } };
+        let mut cursor = match self.event_col.find(filter, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut updated = 0;
+        while let Some(event) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            let event: Events = bson::from_document(event).map_err(|e| AppError::CustomError(e.to_string()))?;
+            let fileData: Vec<FileData> = event.file_data.unwrap_or_default().into_iter()
+                .map(|f| FileData { id: f.id.or(Some(ObjectId::new())), ..f })
+                .collect();
+            let fileData = bson::to_bson(&fileData).map_err(|e| AppError::CustomError(e.to_string()))?;
+
+            if let Err(e) = self.event_col.update_one(doc! { "_id":event.id }, doc! { "$set": { "file_data":fileData } }, None).await {
+                return Err(AppError::CustomError(e.to_string()));
+            }
+            updated += 1;
+        }
+
+        Ok(updated)
+    }
+
+    // uploaded gallery items used to hold a /static/ path, links are left as they are
+    pub async fn migrate_media_keys(&self) -> Result<u64, AppError> {
+        let update = vec![doc! {
+            "$set": { "file_data": { "$map": {
+                "input": "$file_data",
+                "as": "file",
+                "in": { "$cond": [
+                    { "$regexMatch": { "input":"$$file.file_path", "regex":"^/static/" } },
+                    { "$mergeObjects": ["$$file", { "file_path": { "$substrCP": ["$$file.file_path", 8, { "$strLenCP":"$$file.file_path" }] } }] },
+                    "$$file"
+                ] }
+            } } }
+        }];
+        match self.event_col.update_many(doc! { "file_data.file_path": { "$regex":"^/static/" } }, update, None).await {
+            Ok(result) => Ok(result.modified_count),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // stored values of every uploaded gallery item and its variants, links excluded
+    pub async fn media_file_references(&self) -> Result<Vec<String>, AppError> {
+        let opt = options::FindOptions::builder()
+            .projection(doc! { "file_data":1 })
+            .build();
+        let mut cursor = match self.event_col.find(doc! { "file_data.0": { "$exists":true } }, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut references:Vec<String> = Vec::new();
+        while let Some(event) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            let fileData: Vec<FileData> = match event.get("file_data").map(|f| bson::from_bson(f.clone())) {
+                Some(Ok(fileData)) => fileData,
+                _ => continue,
+            };
+            for file in fileData.into_iter().filter(|f| f.embed.is_none()) {
+                references.push(file.file_path);
+                references.extend(file.variants.iter().flat_map(|v| v.keys()).map(String::from));
+            }
+        }
+        Ok(references)
+    }
+
+    pub async fn get_events(&self, skip: i64, limit: i64, scope: &BranchScope) -> Result<Vec<Events>, AppError> {
         let opt = options::FindOptions
             ::builder()
             .sort(doc! { "created_at":-1 })
@@ -81,7 +194,7 @@ impl EventRepo {
             "is_active":true
         };
 
-        let mut cursor = match self.event_col.find(filter, opt).await {
+        let mut cursor = match self.event_col.find(scope.restrict_events(filter), opt).await {
             Ok(cursor) => cursor,
             Err(e) => {
                 return Err(AppError::CustomError(e.to_string()));
@@ -94,7 +207,7 @@ impl EventRepo {
 
         while let Some(event) = cursor.try_next().await.ok().expect("Mapping Error") {
             
-            if let Some(end_date) = event.get("end_date").and_then(|bson| bson.as_datetime()) {
+            if event.get("end_date").and_then(|bson| bson.as_datetime()).is_some() {
                 
                 events.push(bson::from_document(event).unwrap());
             }
@@ -103,8 +216,8 @@ impl EventRepo {
         Ok(events)
     }
 
-    pub async fn get_event(&self, eventId: ObjectId) -> Result<Events, AppError> {
-        let event = match self.event_col.find_one(doc! { "_id":eventId }, None).await {
+    pub async fn get_event(&self, eventId: ObjectId, scope: &BranchScope) -> Result<Events, AppError> {
+        let event = match self.event_col.find_one(scope.restrict_events(doc! { "_id":eventId }), None).await {
             Ok(Some(document)) => document,
             Ok(None) => {
                 return Err(AppError::DataNotFoundError);
@@ -117,8 +230,8 @@ impl EventRepo {
         bson::from_document(event).map_err(|e| AppError::CustomError(e.to_string()))
     }
 
-    pub async fn delete_event(&self, eventId: ObjectId) -> Result<DeleteResult, AppError> {
-        match self.event_col.delete_one(doc! { "_id":eventId }, None).await {
+    pub async fn delete_event(&self, eventId: ObjectId, scope: &BranchScope) -> Result<DeleteResult, AppError> {
+        match self.event_col.delete_one(scope.restrict(doc! { "_id":eventId }, "branches"), None).await {
             Ok(result) => { Ok(result) }
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
@@ -127,44 +240,431 @@ impl EventRepo {
     pub async fn update_event(
         &self,
         eventdId: ObjectId,
-        eventDTO: UpdateEventDTO
+        eventDTO: UpdateEventDTO,
+        startDate: bson::DateTime,
+        endDate: bson::DateTime,
+        timezone: String,
+        scope: &BranchScope
     ) -> Result<UpdateResult, AppError> {
-        let update =
+        if let Some(branches) = eventDTO.branches.as_ref() {
+            scope.check(branches)?;
+        }
+        let mut changes =
             doc! {
-            "$set":{
                 "title":eventDTO.title.to_string(),
                 "discription":eventDTO.discription,
                 "location":eventDTO.location,
                 "is_active":eventDTO.is_active,
-                "start_date":eventDTO.start_date,
-                "end_date":eventDTO.end_date,
+                "start_date":startDate,
+                "end_date":endDate,
+                "timezone":timezone,
                 "updated_at":bson::DateTime::now()
-            }
         };
+        if let Some(branches) = eventDTO.branches {
+            changes.insert("branches", branches);
+        }
+        let update = doc! { "$set":changes };
 
-        match self.event_col.update_one(doc! { "_id":eventdId }, update, None).await {
+        match self.event_col.update_one(scope.restrict(doc! { "_id":eventdId }, "branches"), update, None).await {
             Ok(result) => Ok(result),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
-    pub async fn total_event(&self) -> Result<u64, AppError> {
-        match self.event_col.count_documents(None, None).await {
+    pub async fn total_event(&self, scope: &BranchScope) -> Result<u64, AppError> {
+        match self.event_col.count_documents(scope.restrict_events(doc! {}), None).await {
             Ok(result) => Ok(result),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
-    pub async fn upcommint_event_count(&self) -> Result<u64, AppError> {
+    pub async fn upcommint_event_count(&self, scope: &BranchScope) -> Result<u64, AppError> {
         let filter =
             doc! {
             "start_date": {
                 "$gte":bson::DateTime::now(),
             }
         };
-        match self.event_col.count_documents(filter, None).await {
+        match self.event_col.count_documents(scope.restrict_events(filter), None).await {
             Ok(count) => { Ok(count) }
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
+
+    // update_event used to save dates as strings, turn them back into dates
+    pub async fn migrate_legacy_dates(&self) -> Result<u64, AppError> {
+        let filter = doc! {
+            "$or": [
+                { "start_date": { "$type": "string" } },
+                { "end_date": { "$type": "string" } }
+            ]
+        };
+
+        let mut cursor = match self.event_col.find(filter, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut migrated = 0;
+        while let Some(event) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            let mut changes = Document::new();
+            for field in ["start_date", "end_date"] {
+                if let Ok(value) = event.get_str(field) {
+                    match timezone::parse_legacy_string(value) {
+                        Some(date) => { changes.insert(field, date); },
+                        None => println!("Event {:?} has an unreadable {} {}", event.get_object_id("_id"), field, value),
+                    }
+                }
+            }
+
+            if changes.is_empty() {
+                continue;
+            }
+            if let Err(e) = self.event_col.update_one(doc! { "_id":event.get("_id") }, doc! { "$set":changes }, None).await {
+                return Err(AppError::CustomError(e.to_string()));
+            }
+            migrated += 1;
+        }
+
+        Ok(migrated)
+    }
+
+    pub async fn branch_timezone(&self, branchId: &str) -> Result<Option<String>, AppError> {
+        let branchId = match ObjectId::parse_str(branchId) {
+            Ok(objId) => objId,
+            Err(_) => return Ok(None),
+        };
+
+        match self.branch_col.find_one(doc! { "_id":branchId }, None).await {
+            Ok(branch) => Ok(branch.and_then(|b| b.get_str("timezone").ok().map(|t| t.to_string()))),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // ------------------------------- RECURRENCE ------------------------------------- //
+    // Active one-off events overlapping [from, to) and every active series that
+    // started before `to`; the caller expands the series.
+    pub async fn events_between(&self, from: bson::DateTime, to: bson::DateTime, scope: &BranchScope) -> Result<Vec<Events>, AppError> {
+        let filter = doc! {
+            "is_active": true,
+            "start_date": { "$lt": to },
+            "$or": [
+                { "recurrence": { "$exists": true, "$ne": bson::Bson::Null } },
+                { "end_date": { "$gte": from } }
+            ]
+        };
+
+        let mut cursor = match self.event_col.find(scope.restrict_events(filter), None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut events: Vec<Events> = Vec::new();
+        while let Some(event) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            events.push(bson::from_document(event).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(events)
+    }
+
+    pub async fn set_recurrence(&self, eventId: ObjectId, recurrence: Option<Recurrence>, scope: &BranchScope) -> Result<UpdateResult, AppError> {
+        let update = match recurrence {
+            Some(recurrence) => {
+                let recurrence = bson::to_bson(&recurrence).map_err(|e| AppError::CustomError(e.to_string()))?;
+                doc! { "$set": { "recurrence": recurrence, "updated_at": bson::DateTime::now() } }
+            },
+            None => doc! { "$unset": { "recurrence": "" }, "$set": { "updated_at": bson::DateTime::now() } },
+        };
+
+        match self.event_col.update_one(scope.restrict(doc! { "_id":eventId }, "branches"), update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // replaces the exception of the same occurrence, if any
+    // Replaces the exception of one occurrence in place, or adds it when the
+    // occurrence has none. Each write is a single update so concurrent edits
+    // can not lose the exception or store it twice.
+    pub async fn set_occurrence_exception(&self, eventId: ObjectId, exception: OccurrenceException, scope: &BranchScope) -> Result<UpdateResult, AppError> {
+        let occurrenceStart = exception.occurrence_start;
+        let exception = bson::to_bson(&exception).map_err(|e| AppError::CustomError(e.to_string()))?;
+
+        let replaced = self.replace_exception(eventId, occurrenceStart, &exception, scope).await?;
+        if replaced.matched_count > 0 {
+            return Ok(replaced);
+        }
+
+        let push = doc! {
+            "$push": { "recurrence.exceptions": exception.clone() },
+            "$set": { "updated_at": bson::DateTime::now() }
+        };
+        let filter = doc! {
+            "_id":eventId,
+            "recurrence": { "$type": "object" },
+            "recurrence.exceptions.occurrence_start": { "$ne": occurrenceStart }
+        };
+        let pushed = match self.event_col.update_one(scope.restrict(filter, "branches"), push, None).await {
+            Ok(result) => result,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+        if pushed.matched_count > 0 {
+            return Ok(pushed);
+        }
+
+        // someone else added the exception in between
+        self.replace_exception(eventId, occurrenceStart, &exception, scope).await
+    }
+
+    async fn replace_exception(&self, eventId: ObjectId, occurrenceStart: bson::DateTime, exception: &bson::Bson, scope: &BranchScope) -> Result<UpdateResult, AppError> {
+        let opt = options::UpdateOptions::builder()
+            .array_filters(vec![doc! { "exception.occurrence_start": occurrenceStart }])
+            .build();
+        let update = doc! {
+            "$set": { "recurrence.exceptions.$[exception]": exception.clone(), "updated_at": bson::DateTime::now() }
+        };
+
+        match self.event_col.update_one(scope.restrict(doc! { "_id":eventId, "recurrence.exceptions.occurrence_start": occurrenceStart }, "branches"), update, opt).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // ------------------------------- CALENDAR ------------------------------------- //
+    // Active events for a calendar feed. With a branch only academy-wide events,
+    // events of that branch and the extra event ids are returned.
+    pub async fn feed_events(&self, branch: Option<&str>, eventIds: Vec<ObjectId>) -> Result<Vec<Events>, AppError> {
+        let mut filter = doc! {
+            "is_active": true,
+            "start_date": { "$type": "date" },
+            "end_date": { "$type": "date" }
+        };
+        if let Some(branch) = branch {
+            filter.insert("$or", vec![
+                doc! { "branches": { "$exists": false } },
+                doc! { "branches": { "$size": 0 } },
+                doc! { "branches": branch },
+                doc! { "_id": { "$in": eventIds } },
+            ]);
+        }
+
+        let opt = options::FindOptions::builder()
+            .sort(doc! { "start_date":1 })
+            .build();
+
+        let mut cursor = match self.event_col.find(filter, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut events: Vec<Events> = Vec::new();
+        while let Some(event) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            events.push(bson::from_document(event).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(events)
+    }
+
+    // ------------------------------- REGISTRATIONS ------------------------------------- //
+    pub async fn set_registration(&self, eventId: ObjectId, settings: RegistrationSettings, scope: &BranchScope) -> Result<UpdateResult, AppError> {
+        let settings = bson::to_bson(&settings).map_err(|e| AppError::CustomError(e.to_string()))?;
+        let update = doc! {
+            "$set": {
+                "registration": settings,
+                "updated_at": bson::DateTime::now()
+            }
+        };
+
+        match self.event_col.update_one(scope.restrict(doc! { "_id":eventId }, "branches"), update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn count_registrations(&self, eventId: ObjectId) -> Result<u64, AppError> {
+        let filter = doc! { "event_id":eventId, "status":RegistrationStatus::REGISTERED.to_string() };
+        match self.registration_col.count_documents(filter, None).await {
+            Ok(count) => Ok(count),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // Events created before places were counted start from their registrations,
+    // only the first seed is applied.
+    async fn seed_registered(&self, eventId: ObjectId) -> Result<(), AppError> {
+        let registered = self.count_registrations(eventId).await? as i64;
+        match self.event_col.update_one(
+            doc! { "_id":eventId, "registered_count": { "$exists":false } },
+            doc! { "$set": { "registered_count":registered } },
+            None
+        ).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // Takes a place with a conditional $inc, false when the event is full.
+    async fn take_place(&self, eventId: ObjectId, capacity: Option<i64>) -> Result<bool, AppError> {
+        self.seed_registered(eventId).await?;
+
+        let filter = match capacity {
+            Some(capacity) => doc! { "_id":eventId, "registration.capacity":capacity, "registered_count": { "$lt":capacity } },
+            None => doc! { "_id":eventId },
+        };
+
+        match self.event_col.update_one(filter, doc! { "$inc": { "registered_count":1 } }, None).await {
+            Ok(result) => Ok(result.matched_count > 0),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    async fn free_place(&self, eventId: ObjectId) -> Result<(), AppError> {
+        match self.event_col.update_one(doc! { "_id":eventId, "registered_count": { "$gt":0 } }, doc! { "$inc": { "registered_count":-1 } }, None).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // A student has one registration per event. Registering again after a
+    // cancellation reopens the same record. The place is taken first and given
+    // back when the registration can not be made.
+    pub async fn register_student(&self, registration: EventRegistrations, capacity: Option<i64>) -> Result<EventRegistrations, AppError> {
+        let eventId = registration.event_id;
+        if !self.take_place(eventId, capacity).await? {
+            return Err(AppError::CustomError("event is full".to_string()));
+        }
+
+        match self.save_registration(registration).await {
+            Ok(registration) => Ok(registration),
+            Err(e) => {
+                self.free_place(eventId).await?;
+                Err(e)
+            },
+        }
+    }
+
+    async fn save_registration(&self, mut registration: EventRegistrations) -> Result<EventRegistrations, AppError> {
+        let fee = bson::to_bson(&registration.fee).map_err(|e| AppError::CustomError(e.to_string()))?;
+        let update = doc! {
+            "$set": {
+                "status": RegistrationStatus::REGISTERED.to_string(),
+                "fee": fee,
+                "fee_paid": registration.fee_paid,
+                "registered_by": &registration.registered_by,
+                "updated_at": bson::DateTime::now()
+            }
+        };
+
+        // reopen a cancelled registration, only one request can flip it back
+        let filter = doc! {
+            "event_id":registration.event_id,
+            "student_id":registration.student_id,
+            "status": { "$ne":RegistrationStatus::REGISTERED.to_string() }
+        };
+        match self.registration_col.find_one_and_update(filter, update, None).await {
+            Ok(Some(document)) => {
+                registration.id = document.get_object_id("_id").ok();
+                return Ok(registration);
+            },
+            Ok(None) => {},
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        }
+
+        let bson_doc = match registration.to_docmunet() {
+            Ok(document) => document,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        match self.registration_col.insert_one(bson_doc, None).await {
+            Ok(result) => {
+                registration.id = result.inserted_id.as_object_id();
+                Ok(registration)
+            },
+            Err(err) if is_duplicate_key(&err) => Err(AppError::CustomError("student is already registered".to_string())),
+            Err(err) => Err(AppError::CustomError(err.to_string())),
+        }
+    }
+
+    // Cancels a registration and gives its place back. Returns false when it was
+    // not registered.
+    pub async fn cancel_registration(&self, registrationId: ObjectId) -> Result<bool, AppError> {
+        let update = doc! {
+            "$set": {
+                "status": RegistrationStatus::CANCELLED.to_string(),
+                "updated_at": bson::DateTime::now()
+            }
+        };
+
+        let cancelled: EventRegistrations = match self.registration_col.find_one_and_update(
+            doc! { "_id":registrationId, "status":RegistrationStatus::REGISTERED.to_string() },
+            update,
+            None
+        ).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string()))?,
+            Ok(None) => return Ok(false),
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        self.seed_registered(cancelled.event_id).await?;
+        self.free_place(cancelled.event_id).await?;
+        Ok(true)
+    }
+
+    pub async fn get_registration(&self, registrationId: ObjectId) -> Result<EventRegistrations, AppError> {
+        match self.registration_col.find_one(doc! { "_id":registrationId }, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn update_registration(&self, registrationId: ObjectId, changes: Document) -> Result<UpdateResult, AppError> {
+        let mut changes = changes;
+        changes.insert("updated_at", bson::DateTime::now());
+
+        match self.registration_col.update_one(doc! { "_id":registrationId }, doc! { "$set":changes }, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn list_registrations(&self, filter: Document) -> Result<Vec<EventRegistrations>, AppError> {
+        let opt = options::FindOptions::builder()
+            .sort(doc! { "created_at":1 })
+            .build();
+
+        let mut cursor = match self.registration_col.find(filter, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut registrations: Vec<EventRegistrations> = Vec::new();
+        while let Some(registration) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            registrations.push(bson::from_document(registration).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(registrations)
+    }
+
+    pub async fn get_events_by_ids(&self, eventIds: Vec<ObjectId>) -> Result<Vec<Events>, AppError> {
+        let opt = options::FindOptions::builder()
+            .sort(doc! { "start_date":1 })
+            .build();
+
+        let mut cursor = match self.event_col.find(doc! { "_id": { "$in":eventIds } }, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut events: Vec<Events> = Vec::new();
+        while let Some(event) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            events.push(bson::from_document(event).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(events)
+    }
+}
+
+fn is_duplicate_key(err: &mongodb::error::Error) -> bool {
+    matches!(*err.kind, ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref write_error)) if write_error.code == 11000)
 }
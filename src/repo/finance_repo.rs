@@ -0,0 +1,570 @@
+use bson::{doc, oid::ObjectId, Document};
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{error::ErrorKind, options::{self, IndexOptions}, results::{InsertOneResult, UpdateResult}, Collection, Database, IndexModel};
+use serde::de::DeserializeOwned;
+
+use crate::{helper::{app_errors::AppError, branch_scope::BranchScope}, models::{app::{Courses, Fees}, finance::{CreditNotes, InvoiceStatus, Invoices, LedgerEntries, LedgerEntryTypes, Payments, RefundStatus, Refunds}, money::Money}, repo::student_repo::StudentRepo};
+
+#[allow(non_snake_case)]
+pub struct FinanceRepo {
+    invoice_col:Collection<Document>,
+    payment_col:Collection<Document>,
+    refund_col:Collection<Document>,
+    credit_note_col:Collection<Document>,
+    ledger_col:Collection<Document>,
+    fees_col:Collection<Document>,
+    course_col:Collection<Document>,
+    counter_col:Collection<Document>,
+    pub studentRepo:StudentRepo,
+}
+
+// numbers an older document already holds are skipped at most this often
+const NUMBER_ATTEMPTS:usize = 5;
+
+#[allow(non_snake_case)]
+impl FinanceRepo {
+    pub async fn init(db:Database, studentRepo:StudentRepo) -> Self {
+        let invoice_col = db.collection("invoices");
+        let payment_col = db.collection("payments");
+        let refund_col = db.collection("refunds");
+        let credit_note_col = db.collection("credit_notes");
+        let ledger_col = db.collection("ledger");
+        let fees_col = db.collection("fees_col");
+        let course_col = db.collection("courses");
+        let counter_col = db.collection("counters");
+
+        Self::createUniqueIndex(invoice_col.clone(), doc! { "invoice_number":1 }).await;
+        // one document per provider payment is what makes the webhook idempotent
+        Self::createUniqueIndex(payment_col.clone(), doc! { "gateway_payment_id":1 }).await;
+        Self::createUniqueIndex(credit_note_col.clone(), doc! { "credit_note_number":1 }).await;
+        // an approved refund issues one credit note, however often the approval is retried
+        let refund_index = IndexModel::builder()
+            .keys(doc! { "refund_id":1 })
+            .options(IndexOptions::builder().unique(true).partial_filter_expression(doc! { "refund_id": { "$exists":true } }).build())
+            .build();
+        if let Err(e) = credit_note_col.create_index(refund_index, None).await {
+            println!("Index is not create on collection index filed {:?}", e);
+        }
+        // a document is posted to the ledger at most once
+        Self::createUniqueIndex(ledger_col.clone(), doc! { "entry_type":1, "reference_id":1 }).await;
+
+        let repo = FinanceRepo { invoice_col, payment_col, refund_col, credit_note_col, ledger_col, fees_col, course_col, counter_col, studentRepo };
+        repo.backfillLedger().await;
+        repo
+    }
+
+    async fn createUniqueIndex(collection:Collection<Document>, keys:Document) {
+        let index_model = IndexModel::builder()
+            .keys(keys)
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+
+        if let Err(e) = collection.create_index(index_model, None).await {
+            println!("Index is not create on collection index filed {:?}", e);
+        }
+    }
+
+    // Invoices and payments recorded before the ledger existed get their entries
+    // posted once, the unique index keeps reruns from duplicating them.
+    async fn backfillLedger(&self) {
+        match self.ledger_col.count_documents(doc! {}, None).await {
+            Ok(0) => {},
+            Ok(_) => return,
+            Err(e) => {
+                println!("Ledger backfill skipped {:?}", e);
+                return;
+            },
+        }
+
+        let invoices:Vec<Invoices> = self.find_all(&self.invoice_col, doc! {}, doc! { "created_at":1 }).await.unwrap_or_default();
+        for invoice in invoices {
+            if let Err(e) = self.post_invoice(&invoice).await {
+                println!("Ledger backfill failed for invoice {} : {}", invoice.invoice_number, e);
+            }
+        }
+
+        let payments:Vec<Payments> = self.find_all(&self.payment_col, doc! {}, doc! { "created_at":1 }).await.unwrap_or_default();
+        for payment in payments {
+            if let Err(e) = self.post_payment(&payment).await {
+                println!("Ledger backfill failed for payment {} : {}", payment.gateway_payment_id, e);
+            }
+        }
+    }
+
+    async fn find_all<T:DeserializeOwned>(&self, collection:&Collection<Document>, filter:Document, sort:Document) -> Result<Vec<T>, AppError> {
+        let opt = options::FindOptions::builder()
+            .sort(sort)
+            .build();
+
+        let mut cursor = match collection.find(filter, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut items:Vec<T> = Vec::new();
+        while let Some(item) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            items.push(bson::from_document(item).map_err(|e| AppError::CustomError(e.to_string()))?)
+        }
+
+        Ok(items)
+    }
+
+    // narrows a filter on student_id to the students of the scope
+    async fn scoped(&self, filter:Document, scope:&BranchScope) -> Result<Document, AppError> {
+        match self.studentRepo.student_ids(scope).await? {
+            Some(ids) => Ok(doc! { "$and": [filter, { "student_id": { "$in":ids } }] }),
+            None => Ok(filter),
+        }
+    }
+
+    async fn find_scoped<T:DeserializeOwned>(&self, collection:&Collection<Document>, objId:ObjectId, scope:&BranchScope) -> Result<T, AppError> {
+        let filter = self.scoped(doc! { "_id":objId }, scope).await?;
+        match collection.find_one(filter, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    async fn find_by_id<T:DeserializeOwned>(&self, collection:&Collection<Document>, objId:ObjectId) -> Result<T, AppError> {
+        match collection.find_one(doc! { "_id":objId }, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_fee(&self, feeId:ObjectId) -> Result<Fees, AppError> {
+        match self.fees_col.find_one(doc! { "_id":feeId }, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_course(&self, courseId:ObjectId) -> Result<Courses, AppError> {
+        self.find_by_id(&self.course_col, courseId).await
+    }
+
+    // ------------------------------- INVOICES ------------------------------------- //
+    // An invoice without a number gets the next one of the month, a number an
+    // older invoice already holds is skipped.
+    pub async fn add_invoice(&self, invoice:Invoices) -> Result<InsertOneResult, AppError> {
+        let numbered = invoice.invoice_number.is_empty();
+        let mut invoice = invoice;
+        let mut attempts = 0;
+
+        let result = loop {
+            if numbered {
+                invoice.invoice_number = self.next_number("INV").await?;
+            }
+            let bson_doc = match invoice.to_document() {
+                Ok(document) => document,
+                Err(e) => return Err(AppError::CustomError(e.to_string())),
+            };
+
+            match self.invoice_col.insert_one(bson_doc, None).await {
+                Ok(result) => break result,
+                Err(e) if numbered && is_duplicate_key(&e) && attempts < NUMBER_ATTEMPTS => attempts += 1,
+                Err(e) => return Err(AppError::CustomError(e.to_string())),
+            }
+        };
+
+        let invoice = Invoices { id: result.inserted_id.as_object_id(), ..invoice };
+        self.post_invoice(&invoice).await?;
+
+        Ok(result)
+    }
+
+    pub async fn get_invoice(&self, invoiceId:ObjectId, scope:&BranchScope) -> Result<Invoices, AppError> {
+        self.find_scoped(&self.invoice_col, invoiceId, scope).await
+    }
+
+    pub async fn list_invoices(&self, studentId:ObjectId, scope:&BranchScope) -> Result<Vec<Invoices>, AppError> {
+        self.find_invoices(self.scoped(doc! { "student_id":studentId }, scope).await?).await
+    }
+
+    // pending invoices that already have a provider order, candidates for reconciliation
+    pub async fn pending_gateway_invoices(&self) -> Result<Vec<Invoices>, AppError> {
+        self.find_invoices(doc! {
+            "status": InvoiceStatus::PENDING.to_string(),
+            "gateway_order_id": { "$exists": true }
+        }).await
+    }
+
+    // invoices raised in the period that have been paid, pending ones are not yet supplies for GST
+    pub async fn paid_invoices_between(&self, from:bson::DateTime, to:bson::DateTime, scope:&BranchScope) -> Result<Vec<Invoices>, AppError> {
+        self.find_invoices(self.scoped(doc! {
+            "created_at": { "$gte":from, "$lt":to },
+            "status":InvoiceStatus::PAID.to_string()
+        }, scope).await?).await
+    }
+
+    pub async fn invoices_by_ids(&self, invoiceIds:Vec<ObjectId>) -> Result<Vec<Invoices>, AppError> {
+        self.find_invoices(doc! { "_id": { "$in":invoiceIds } }).await
+    }
+
+    async fn find_invoices(&self, filter:Document) -> Result<Vec<Invoices>, AppError> {
+        self.find_all(&self.invoice_col, filter, doc! { "created_at":-1 }).await
+    }
+
+    pub async fn set_gateway_order(&self, invoiceId:ObjectId, orderId:String) -> Result<UpdateResult, AppError> {
+        let update = doc! {
+            "$set": {
+                "gateway_order_id":&orderId,
+                "updated_at":bson::DateTime::now()
+            },
+            "$addToSet": { "gateway_order_ids":&orderId }
+        };
+
+        match self.invoice_col.update_one(doc! { "_id":invoiceId, "status":InvoiceStatus::PENDING.to_string() }, update, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_invoice_by_order(&self, orderId:&str) -> Result<Invoices, AppError> {
+        let filter = doc! { "$or": [{ "gateway_order_ids":orderId }, { "gateway_order_id":orderId }] };
+        match self.invoice_col.find_one(filter, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // ------------------------------- PAYMENTS ------------------------------------- //
+    // Records the payment, posts it to the ledger and marks its invoice paid. Each
+    // step is idempotent, a replay of a payment that stopped half way (webhook
+    // retry or reconciliation) finishes the remaining steps. Returns false when
+    // the payment was already fully applied.
+    pub async fn record_payment(&self, mut payment:Payments) -> Result<bool, AppError> {
+        let bson_doc = match payment.to_document() {
+            Ok(document) => document,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let inserted = match self.payment_col.insert_one(bson_doc, None).await {
+            Ok(result) => {
+                payment.id = result.inserted_id.as_object_id();
+                true
+            },
+            Err(err) if is_duplicate_key(&err) => {
+                payment = self.get_payment_by_gateway_id(&payment.gateway_payment_id).await?;
+                false
+            },
+            Err(err) => return Err(AppError::CustomError(err.to_string())),
+        };
+
+        self.post_payment(&payment).await?;
+
+        let update = doc! {
+            "$set": {
+                "status":InvoiceStatus::PAID.to_string(),
+                "paid_at":payment.created_at,
+                "updated_at":bson::DateTime::now()
+            }
+        };
+
+        match self.invoice_col.update_one(doc! { "_id":payment.invoice_id, "status":InvoiceStatus::PENDING.to_string() }, update, None).await {
+            Ok(result) => Ok(inserted || result.modified_count > 0),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    async fn get_payment_by_gateway_id(&self, gatewayPaymentId:&str) -> Result<Payments, AppError> {
+        match self.payment_col.find_one(doc! { "gateway_payment_id":gatewayPaymentId }, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_payment(&self, paymentId:ObjectId, scope:&BranchScope) -> Result<Payments, AppError> {
+        self.find_scoped(&self.payment_col, paymentId, scope).await
+    }
+
+    // ------------------------------- REFUNDS ------------------------------------- //
+    // Reserves the refund amount on the payment and records the request. The
+    // reservation is a conditional $inc, so concurrent requests can never hold
+    // more than was paid.
+    pub async fn add_refund(&self, payment:&Payments, refund:Refunds) -> Result<InsertOneResult, AppError> {
+        let paymentId = payment.id.unwrap();
+        let amount = refund.amount.amount_minor;
+
+        if refund.amount.currency != payment.amount.currency {
+            return Err(AppError::CustomError("refund currency does not match the payment".to_string()));
+        }
+
+        self.seed_refunded(paymentId).await?;
+
+        let reserved = self.payment_col.update_one(
+            doc! { "_id":paymentId, "refunded_minor": { "$lte":payment.amount.amount_minor - amount } },
+            doc! { "$inc": { "refunded_minor":amount } },
+            None
+        ).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+        if reserved.matched_count == 0 {
+            let refunded = self.find_by_id::<Payments>(&self.payment_col, paymentId).await?.refunded_minor.unwrap_or_default();
+            let left = Money::new(payment.amount.amount_minor - refunded, &payment.amount.currency);
+            return Err(AppError::CustomError(format!("at most {} {} can be refunded on this payment", left.to_decimal_string(), left.currency)));
+        }
+
+        let bson_doc = match refund.to_document() {
+            Ok(document) => document,
+            Err(e) => {
+                self.release_refund(paymentId, amount).await?;
+                return Err(AppError::CustomError(e.to_string()));
+            },
+        };
+
+        match self.refund_col.insert_one(bson_doc, None).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                self.release_refund(paymentId, amount).await?;
+                Err(AppError::CustomError(e.to_string()))
+            },
+        }
+    }
+
+    // Payments recorded before the counter existed start from the refunds
+    // already held against them, only the first seed is applied.
+    async fn seed_refunded(&self, paymentId:ObjectId) -> Result<(), AppError> {
+        let held:Vec<Refunds> = self.find_all(&self.refund_col, doc! {
+            "payment_id":paymentId,
+            "status": { "$in": [RefundStatus::REQUESTED.to_string(), RefundStatus::APPROVED.to_string()] }
+        }, doc! { "created_at":1 }).await?;
+        let refunded:i64 = held.iter().map(|refund| refund.amount.amount_minor).sum();
+
+        match self.payment_col.update_one(
+            doc! { "_id":paymentId, "refunded_minor": { "$exists":false } },
+            doc! { "$set": { "refunded_minor":refunded } },
+            None
+        ).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    async fn release_refund(&self, paymentId:ObjectId, amount:i64) -> Result<(), AppError> {
+        match self.payment_col.update_one(doc! { "_id":paymentId }, doc! { "$inc": { "refunded_minor":-amount } }, None).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_refund(&self, refundId:ObjectId) -> Result<Refunds, AppError> {
+        self.find_by_id(&self.refund_col, refundId).await
+    }
+
+    pub async fn list_refunds(&self, status:Option<String>, scope:&BranchScope) -> Result<Vec<Refunds>, AppError> {
+        let filter = match status {
+            Some(status) => doc! { "status":status },
+            None => doc! {},
+        };
+        self.find_all(&self.refund_col, self.scoped(filter, scope).await?, doc! { "created_at":-1 }).await
+    }
+
+    // Approves a requested refund: the unused portion is credited to the student
+    // with a credit note and the refund pays that credit out, both in the ledger.
+    // Every step is idempotent, approving again finishes an approval that
+    // stopped half way.
+    #[allow(non_snake_case)]
+    pub async fn approve_refund(&self, refund:&Refunds, approvedBy:&str, creditNote:CreditNotes) -> Result<(), AppError> {
+        let refundId = refund.id.unwrap();
+        let unfinished = refund.status == RefundStatus::APPROVED.to_string() && refund.credit_note_id.is_none();
+
+        if !unfinished {
+            // flip the status first so two admins approving at once issue one credit note
+            let result = self.refund_col.update_one(
+                doc! { "_id":refundId, "status":RefundStatus::REQUESTED.to_string() },
+                doc! { "$set": {
+                    "status":RefundStatus::APPROVED.to_string(),
+                    "approved_by":approvedBy,
+                    "approved_at":bson::DateTime::now(),
+                    "updated_at":bson::DateTime::now()
+                }},
+                None
+            ).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+            if result.matched_count == 0 {
+                return Err(AppError::CustomError("refund is not awaiting approval".to_string()));
+            }
+        }
+
+        let creditNoteId = self.add_credit_note(creditNote).await?;
+
+        self.refund_col.update_one(
+            doc! { "_id":refundId },
+            doc! { "$set": { "credit_note_id":creditNoteId } },
+            None
+        ).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+        self.post_ledger(LedgerEntries {
+            id: None,
+            student_id: refund.student_id,
+            entry_type: LedgerEntryTypes::REFUND.to_string(),
+            reference_id: refundId,
+            amount: refund.amount.clone(),
+            description: format!("Refund: {}", refund.reason),
+            created_at: bson::DateTime::now(),
+        }).await
+    }
+
+    // Rejects a requested refund and gives its amount back to the payment.
+    // Returns false when the refund was not awaiting approval.
+    #[allow(non_snake_case)]
+    pub async fn reject_refund(&self, refundId:ObjectId, rejectedBy:&str) -> Result<bool, AppError> {
+        let update = doc! {
+            "$set": {
+                "status":RefundStatus::REJECTED.to_string(),
+                "approved_by":rejectedBy,
+                "updated_at":bson::DateTime::now()
+            }
+        };
+
+        let rejected = match self.refund_col.find_one_and_update(doc! { "_id":refundId, "status":RefundStatus::REQUESTED.to_string() }, update, None).await {
+            Ok(Some(document)) => bson::from_document::<Refunds>(document).map_err(|e| AppError::CustomError(e.to_string()))?,
+            Ok(None) => return Ok(false),
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        self.seed_refunded(rejected.payment_id).await?;
+        self.release_refund(rejected.payment_id, rejected.amount.amount_minor).await?;
+        Ok(true)
+    }
+
+    // ------------------------------- CREDIT NOTES ------------------------------------- //
+    // numbered like invoices when the number is empty
+    pub async fn add_credit_note(&self, creditNote:CreditNotes) -> Result<ObjectId, AppError> {
+        let numbered = creditNote.credit_note_number.is_empty();
+        let mut creditNote = creditNote;
+        let mut attempts = 0;
+
+        let creditNoteId = loop {
+            if numbered {
+                creditNote.credit_note_number = self.next_number("CN").await?;
+            }
+            let bson_doc = match creditNote.to_document() {
+                Ok(document) => document,
+                Err(e) => return Err(AppError::CustomError(e.to_string())),
+            };
+
+            let err = match self.credit_note_col.insert_one(bson_doc, None).await {
+                Ok(result) => break result.inserted_id.as_object_id().unwrap(),
+                Err(err) if is_duplicate_key(&err) => err,
+                Err(e) => return Err(AppError::CustomError(e.to_string())),
+            };
+
+            // a retried refund approval gets the credit note it already issued
+            if creditNote.refund_id.is_some() {
+                match self.credit_note_col.find_one(doc! { "refund_id":creditNote.refund_id }, None).await {
+                    Ok(Some(document)) => {
+                        creditNote = bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string()))?;
+                        break creditNote.id.unwrap();
+                    },
+                    Ok(None) => {},
+                    Err(e) => return Err(AppError::CustomError(e.to_string())),
+                }
+            }
+
+            // otherwise the number is held by an older note
+            if !numbered || attempts >= NUMBER_ATTEMPTS {
+                return Err(AppError::CustomError(err.to_string()));
+            }
+            attempts += 1;
+        };
+
+        self.post_ledger(LedgerEntries {
+            id: None,
+            student_id: creditNote.student_id,
+            entry_type: LedgerEntryTypes::CREDITNOTE.to_string(),
+            reference_id: creditNoteId,
+            amount: negate(&creditNote.amount),
+            description: format!("Credit note {}: {}", creditNote.credit_note_number, creditNote.reason),
+            created_at: creditNote.created_at,
+        }).await?;
+
+        Ok(creditNoteId)
+    }
+
+    pub async fn credit_notes_between(&self, from:bson::DateTime, to:bson::DateTime, scope:&BranchScope) -> Result<Vec<CreditNotes>, AppError> {
+        self.find_all(&self.credit_note_col, self.scoped(doc! { "created_at": { "$gte":from, "$lt":to } }, scope).await?, doc! { "created_at":1 }).await
+    }
+
+    pub async fn list_credit_notes(&self, studentId:ObjectId, scope:&BranchScope) -> Result<Vec<CreditNotes>, AppError> {
+        self.find_all(&self.credit_note_col, self.scoped(doc! { "student_id":studentId }, scope).await?, doc! { "created_at":-1 }).await
+    }
+
+    // ------------------------------- LEDGER ------------------------------------- //
+    pub async fn student_ledger(&self, studentId:ObjectId, scope:&BranchScope) -> Result<Vec<LedgerEntries>, AppError> {
+        self.find_all(&self.ledger_col, self.scoped(doc! { "student_id":studentId }, scope).await?, doc! { "created_at":1 }).await
+    }
+
+    async fn post_invoice(&self, invoice:&Invoices) -> Result<(), AppError> {
+        self.post_ledger(LedgerEntries {
+            id: None,
+            student_id: invoice.student_id,
+            entry_type: LedgerEntryTypes::INVOICE.to_string(),
+            reference_id: invoice.id.unwrap(),
+            amount: invoice.amount.clone(),
+            description: format!("Invoice {} ({})", invoice.invoice_number, invoice.fee_type),
+            created_at: invoice.created_at,
+        }).await
+    }
+
+    async fn post_payment(&self, payment:&Payments) -> Result<(), AppError> {
+        self.post_ledger(LedgerEntries {
+            id: None,
+            student_id: payment.student_id,
+            entry_type: LedgerEntryTypes::PAYMENT.to_string(),
+            reference_id: payment.id.unwrap(),
+            amount: negate(&payment.amount),
+            description: format!("Payment {} via {}", payment.gateway_payment_id, payment.gateway),
+            created_at: payment.created_at,
+        }).await
+    }
+
+    async fn post_ledger(&self, entry:LedgerEntries) -> Result<(), AppError> {
+        let bson_doc = match entry.to_document() {
+            Ok(document) => document,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        match self.ledger_col.insert_one(bson_doc, None).await {
+            Ok(_) => Ok(()),
+            Err(err) if is_duplicate_key(&err) => Ok(()),
+            Err(err) => Err(AppError::CustomError(err.to_string())),
+        }
+    }
+
+    // The next number of a monthly series such as INV-202610-000042. The counter
+    // lives in the academy's database, so numbers stay unique across restarts
+    // and replicas.
+    async fn next_number(&self, prefix:&str) -> Result<String, AppError> {
+        let series = format!("{}-{}", prefix, Utc::now().format("%Y%m"));
+        let opt = options::FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(options::ReturnDocument::After)
+            .build();
+
+        let counter = self.counter_col.find_one_and_update(
+            doc! { "_id":&series },
+            doc! { "$inc": { "seq":1_i64 } },
+            opt
+        ).await.map_err(|e| AppError::CustomError(e.to_string()))?;
+
+        match counter.and_then(|counter| counter.get_i64("seq").ok()) {
+            Some(seq) => Ok(format!("{}-{:06}", series, seq)),
+            None => Err(AppError::CustomError(format!("could not number {}", series))),
+        }
+    }
+}
+
+fn negate(amount:&Money) -> Money {
+    Money::new(-amount.amount_minor, &amount.currency)
+}
+
+fn is_duplicate_key(err:&mongodb::error::Error) -> bool {
+    matches!(*err.kind, ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref write_error)) if write_error.code == 11000)
+}
@@ -1,4 +1,9 @@
 pub mod user_repo;
 pub mod student_repo;
 pub mod events_repo;
-pub mod app_repo;
\ No newline at end of file
+pub mod app_repo;
+pub mod finance_repo;
+pub mod settings_repo;
+pub mod report_repo;
+pub mod curriculum_repo;
+pub mod tenant_repo;
\ No newline at end of file
@@ -0,0 +1,255 @@
+use bson::{doc, Bson, Document};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+
+use crate::{helper::{app_errors::AppError, branch_scope::BranchScope}, models::{app::EnquiryStatus, finance::{InvoiceStatus, RefundStatus}}};
+
+// Filters shared by every report. Dates bound the document date, `to` exclusive.
+pub struct ReportFilter {
+    pub from:Option<bson::DateTime>,
+    pub to:Option<bson::DateTime>,
+    pub branch:Option<String>,
+    pub scope:BranchScope
+}
+
+// Finance reports, each one a single aggregation pipeline. Payments, refunds and
+// invoices do not carry a branch, it is looked up from the student's class branch
+// so documents created before a student changed branch still report somewhere.
+pub struct ReportRepo {
+    invoice_col:Collection<Document>,
+    payment_col:Collection<Document>,
+    enquiry_col:Collection<Document>,
+}
+
+#[allow(non_snake_case)]
+impl ReportRepo {
+    pub fn init(db:Database) -> Self {
+        ReportRepo {
+            invoice_col: db.collection("invoices"),
+            payment_col: db.collection("payments"),
+            enquiry_col: db.collection("enquiries"),
+        }
+    }
+
+    // Payments minus approved refunds grouped by day, month or branch.
+    pub async fn collections(&self, filter:&ReportFilter, groupBy:&str) -> Result<Vec<Document>, AppError> {
+        let group_key = match groupBy {
+            "day" => doc! { "$dateToString": { "format":"%Y-%m-%d", "date":"$date" } },
+            "month" => doc! { "$dateToString": { "format":"%Y-%m", "date":"$date" } },
+            "branch" => doc! { "$ifNull": ["$branch_name", "$branch"] },
+            _ => return Err(AppError::CustomError("group_by should be day, month or branch".to_string())),
+        };
+
+        let mut refunds = vec![
+            doc! { "$match": Self::date_match("approved_at", filter, doc! { "status":RefundStatus::APPROVED.to_string() }) },
+        ];
+        refunds.extend(Self::branch_stages(filter));
+        refunds.push(doc! { "$project": {
+            "date":"$approved_at", "branch":1, "branch_name":1, "currency":"$amount.currency",
+            "collected": { "$literal":0_i64 }, "refunded":"$amount.amount_minor", "payments": { "$literal":0_i64 }
+        }});
+
+        let mut pipeline = vec![
+            doc! { "$match": Self::date_match("created_at", filter, doc! {}) },
+        ];
+        pipeline.extend(Self::branch_stages(filter));
+        pipeline.extend([
+            doc! { "$project": {
+                "date":"$created_at", "branch":1, "branch_name":1, "currency":"$amount.currency",
+                "collected":"$amount.amount_minor", "refunded": { "$literal":0_i64 }, "payments": { "$literal":1_i64 }
+            }},
+            doc! { "$unionWith": { "coll":"refunds", "pipeline":refunds } },
+            doc! { "$group": {
+                "_id": { "key":group_key, "currency":"$currency" },
+                "payment_count": { "$sum":"$payments" },
+                "collected": { "$sum":"$collected" },
+                "refunded": { "$sum":"$refunded" }
+            }},
+            doc! { "$sort": { "_id.key":1 } },
+        ]);
+
+        Self::run(&self.payment_col, pipeline).await
+    }
+
+    // Pending invoices bucketed by how many days past due they are on `asOf`.
+    pub async fn dues_ageing(&self, filter:&ReportFilter, asOf:bson::DateTime) -> Result<Vec<Document>, AppError> {
+        let days_overdue = doc! {
+            "$floor": { "$divide": [{ "$subtract": [asOf, "$due_date"] }, 86_400_000] }
+        };
+
+        let mut pipeline = vec![
+            doc! { "$match": Self::date_match("created_at", filter, doc! { "status":InvoiceStatus::PENDING.to_string() }) },
+        ];
+        pipeline.extend(Self::branch_stages(filter));
+        pipeline.extend([
+            doc! { "$addFields": { "days_overdue":days_overdue } },
+            doc! { "$addFields": { "bucket": { "$switch": {
+                "branches": [
+                    { "case": { "$lt": ["$days_overdue", 0] }, "then":"NOT_DUE" },
+                    { "case": { "$lte": ["$days_overdue", 30] }, "then":"0-30" },
+                    { "case": { "$lte": ["$days_overdue", 60] }, "then":"31-60" }
+                ],
+                "default":"60+"
+            }}}},
+            doc! { "$group": {
+                "_id": { "key":"$bucket", "currency":"$amount.currency" },
+                "invoice_count": { "$sum":1 },
+                "outstanding": { "$sum":"$amount.amount_minor" }
+            }},
+            doc! { "$sort": { "_id.key":1 } },
+        ]);
+
+        Self::run(&self.invoice_col, pipeline).await
+    }
+
+    // Invoiced amount less credit notes raised against the invoices, by course or fee type.
+    pub async fn revenue(&self, filter:&ReportFilter, groupBy:&str) -> Result<Vec<Document>, AppError> {
+        let mut pipeline = vec![
+            doc! { "$match": Self::date_match("created_at", filter, doc! {}) },
+        ];
+        pipeline.extend(Self::branch_stages(filter));
+
+        let group_key = match groupBy {
+            "fee_type" => Bson::String("$fee_type".to_string()),
+            "course" => {
+                pipeline.extend([
+                    doc! { "$lookup": { "from":"courses", "localField":"course_id", "foreignField":"_id", "as":"course" } },
+                    doc! { "$addFields": { "course_name": { "$ifNull": [{ "$arrayElemAt": ["$course.name", 0] }, "UNASSIGNED"] } } },
+                ]);
+                Bson::String("$course_name".to_string())
+            },
+            _ => return Err(AppError::CustomError("group_by should be course or fee_type".to_string())),
+        };
+
+        pipeline.extend([
+            doc! { "$lookup": { "from":"credit_notes", "localField":"_id", "foreignField":"invoice_id", "as":"credit_notes" } },
+            doc! { "$group": {
+                "_id": { "key":group_key, "currency":"$amount.currency" },
+                "invoice_count": { "$sum":1 },
+                "invoiced": { "$sum":"$amount.amount_minor" },
+                "credited": { "$sum": { "$sum":"$credit_notes.amount.amount_minor" } }
+            }},
+            doc! { "$sort": { "_id.key":1 } },
+        ]);
+
+        Self::run(&self.invoice_col, pipeline).await
+    }
+
+    // Discounts given on invoices, by fee type.
+    pub async fn discounts(&self, filter:&ReportFilter) -> Result<Vec<Document>, AppError> {
+        let mut pipeline = vec![
+            doc! { "$match": Self::date_match("created_at", filter, doc! { "discount_amount.amount_minor": { "$gt":0 } }) },
+        ];
+        pipeline.extend(Self::branch_stages(filter));
+        pipeline.extend([
+            doc! { "$group": {
+                "_id": { "key":"$fee_type", "currency":"$discount_amount.currency" },
+                "invoice_count": { "$sum":1 },
+                "discount": { "$sum":"$discount_amount.amount_minor" }
+            }},
+            doc! { "$sort": { "_id.key":1 } },
+        ]);
+
+        Self::run(&self.invoice_col, pipeline).await
+    }
+
+    // Enquiries received in the period and how many were converted to a student,
+    // by source, branch or month. A conversion counts as registered once the
+    // linked student is no longer PENDING.
+    pub async fn conversions(&self, filter:&ReportFilter, groupBy:&str) -> Result<Vec<Document>, AppError> {
+        let group_key = match groupBy {
+            "source" => doc! { "$ifNull": ["$source", "UNKNOWN"] },
+            "branch" => doc! { "$ifNull": ["$branch_name", "$branch"] },
+            "month" => doc! { "$dateToString": { "format":"%Y-%m", "date":"$created_at" } },
+            _ => return Err(AppError::CustomError("group_by should be source, branch or month".to_string())),
+        };
+
+        let mut pipeline = vec![
+            doc! { "$match": Self::date_match("created_at", filter, doc! {}) },
+            doc! { "$addFields": { "branch": { "$ifNull": ["$branch", ""] } } },
+        ];
+        pipeline.extend(Self::branch_name_stages(filter));
+        pipeline.extend([
+            doc! { "$lookup": { "from":"students", "localField":"student_id", "foreignField":"_id", "as":"student" } },
+            doc! { "$addFields": {
+                "converted": { "$cond": [{ "$gt": [{ "$size":"$student" }, 0] }, 1, 0] },
+                "registered": { "$cond": [{ "$and": [
+                    { "$gt": [{ "$size":"$student" }, 0] },
+                    { "$ne": [{ "$arrayElemAt": ["$student.registration_status", 0] }, "PENDING"] }
+                ] }, 1, 0] },
+                "lost": { "$cond": [{ "$eq": ["$status", EnquiryStatus::LOST.to_string()] }, 1, 0] }
+            }},
+            doc! { "$group": {
+                "_id": { "key":group_key },
+                "enquiry_count": { "$sum":1 },
+                "converted": { "$sum":"$converted" },
+                "registered": { "$sum":"$registered" },
+                "lost": { "$sum":"$lost" }
+            }},
+            doc! { "$sort": { "_id.key":1 } },
+        ]);
+
+        Self::run(&self.enquiry_col, pipeline).await
+    }
+
+    fn date_match(field:&str, filter:&ReportFilter, mut matcher:Document) -> Document {
+        let mut range = Document::new();
+        if let Some(from) = filter.from {
+            range.insert("$gte", from);
+        }
+        if let Some(to) = filter.to {
+            range.insert("$lt", to);
+        }
+        if !range.is_empty() {
+            matcher.insert(field, range);
+        }
+        matcher
+    }
+
+    // attaches `branch` (the student's class branch) and `branch_name` when the
+    // class branch is a branch id, then applies the branch filter
+    fn branch_stages(filter:&ReportFilter) -> Vec<Document> {
+        let mut stages = vec![
+            doc! { "$lookup": { "from":"students", "localField":"student_id", "foreignField":"_id", "as":"student" } },
+            doc! { "$addFields": { "branch": { "$ifNull": [{ "$arrayElemAt": ["$student.class_branch", 0] }, ""] } } },
+        ];
+        stages.extend(Self::branch_name_stages(filter));
+        stages
+    }
+
+    // `branch_name` of a `branch` id field, then the branch filter
+    fn branch_name_stages(filter:&ReportFilter) -> Vec<Document> {
+        let mut stages = vec![
+            doc! { "$lookup": {
+                "from":"branches",
+                "let": { "branch_id": { "$convert": { "input":"$branch", "to":"objectId", "onError":Bson::Null, "onNull":Bson::Null } } },
+                "pipeline": [{ "$match": { "$expr": { "$eq": ["$_id", "$$branch_id"] } } }],
+                "as":"branch_doc"
+            }},
+            doc! { "$addFields": { "branch_name": { "$arrayElemAt": ["$branch_doc.name", 0] } } },
+        ];
+
+        if let Some(branch) = filter.branch.as_ref() {
+            stages.push(doc! { "$match": { "$or": [{ "branch":branch }, { "branch_name":branch }] } });
+        }
+        if filter.scope.branches().is_some() {
+            stages.push(doc! { "$match": filter.scope.restrict(doc! {}, "branch") });
+        }
+
+        stages
+    }
+
+    async fn run(collection:&Collection<Document>, pipeline:Vec<Document>) -> Result<Vec<Document>, AppError> {
+        let mut cursor = match collection.aggregate(pipeline, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut rows:Vec<Document> = Vec::new();
+        while let Some(row) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            rows.push(row);
+        }
+
+        Ok(rows)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{sync::RwLock, time::{Duration, Instant}};
+
+use bson::{doc, Document};
+use mongodb::{options::UpdateOptions, results::UpdateResult, Collection, Database};
+
+use crate::{helper::app_errors::AppError, models::{media::ImageVariants, settings::{AcademySettings, BeltLevel, FeeCycle, RegistrationOptions}}};
+
+// settings are read on most writes, other instances see a change within this time
+const CACHE_TTL:Duration = Duration::from_secs(60);
+
+pub struct SettingsRepo {
+    settings_col:Collection<Document>,
+    cache:RwLock<Option<(Instant, AcademySettings)>>
+}
+
+impl SettingsRepo {
+    pub fn init(db:Database) -> Self {
+        let settings_col = db.collection("settings");
+        SettingsRepo { settings_col, cache: RwLock::new(None) }
+    }
+
+    // settings always exist, a fresh install gets the defaults
+    pub async fn get_settings(&self) -> Result<AcademySettings, AppError> {
+        if let Some((loaded_at, settings)) = self.cache.read().unwrap().as_ref() {
+            if loaded_at.elapsed() < CACHE_TTL {
+                return Ok(settings.clone());
+            }
+        }
+
+        let settings = match self.settings_col.find_one(None, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string()))?,
+            Ok(None) => AcademySettings::default(),
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        *self.cache.write().unwrap() = Some((Instant::now(), settings.clone()));
+        Ok(settings)
+    }
+
+    pub async fn update_tax_settings(&self, gstin:String, state_code:String) -> Result<UpdateResult, AppError> {
+        self.set(doc! { "gstin":gstin, "state_code":state_code }).await
+    }
+
+    pub async fn update_academy_profile(&self, academy_name:String, contact_email:Option<String>, contact_phone:Option<String>) -> Result<UpdateResult, AppError> {
+        self.set(doc! { "academy_name":academy_name, "contact_email":contact_email, "contact_phone":contact_phone }).await
+    }
+
+    pub async fn update_locale(&self, currency:String, timezone:String) -> Result<UpdateResult, AppError> {
+        self.set(doc! { "currency":currency, "timezone":timezone }).await
+    }
+
+    pub async fn set_logo(&self, logo:&str, variants:Option<&ImageVariants>) -> Result<UpdateResult, AppError> {
+        let variants = bson::to_bson(&variants).map_err(|e| AppError::CustomError(e.to_string()))?;
+        self.set(doc! { "logo":logo, "logo_variants":variants }).await
+    }
+
+    pub async fn update_belts(&self, belts:&[BeltLevel]) -> Result<UpdateResult, AppError> {
+        let belts = bson::to_bson(belts).map_err(|e| AppError::CustomError(e.to_string()))?;
+        self.set(doc! { "belts":belts }).await
+    }
+
+    pub async fn update_fee_cycles(&self, fee_cycles:&[FeeCycle]) -> Result<UpdateResult, AppError> {
+        let fee_cycles = bson::to_bson(fee_cycles).map_err(|e| AppError::CustomError(e.to_string()))?;
+        self.set(doc! { "fee_cycles":fee_cycles }).await
+    }
+
+    pub async fn update_registration_options(&self, registration:&RegistrationOptions) -> Result<UpdateResult, AppError> {
+        let registration = bson::to_bson(registration).map_err(|e| AppError::CustomError(e.to_string()))?;
+        self.set(doc! { "registration":registration }).await
+    }
+
+    // the logo and its renditions, for the file cleanup
+    pub async fn file_references(&self) -> Result<Vec<String>, AppError> {
+        let settings = self.get_settings().await?;
+        let mut references:Vec<String> = settings.logo.into_iter().collect();
+        references.extend(settings.logo_variants.iter().flat_map(|v| v.keys()).map(String::from));
+        Ok(references)
+    }
+
+    // upserts the single settings document and drops the cached copy
+    async fn set(&self, mut fields:Document) -> Result<UpdateResult, AppError> {
+        fields.insert("updated_at", bson::DateTime::now());
+
+        let opt = UpdateOptions::builder().upsert(true).build();
+        let result = match self.settings_col.update_one(doc! {}, doc! { "$set":fields }, opt).await {
+            Ok(result) => result,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        *self.cache.write().unwrap() = None;
+        Ok(result)
+    }
+}
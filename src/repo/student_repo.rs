@@ -1,7 +1,7 @@
 use actix_web::App;
 use bson::{doc, oid::ObjectId, DateTime as MongoDateTime, Document};
 use mongodb::{ options, results::{DeleteResult, InsertOneResult, UpdateResult}, Collection, Database};
-use crate::{dto::student_dto::CreateStudentDTO, helper::app_errors::AppError, models::student_model::{Parents, Students}};
+use crate::{dto::student_dto::CreateStudentDTO, helper::{app_errors::AppError, branch_scope::BranchScope}, models::{media::ImageVariants, student_model::{Parents, Students}}};
 use futures::stream::TryStreamExt; 
 use chrono::{Datelike, Utc};
 
@@ -29,8 +29,8 @@ impl StudentRepo {
         }
     }
 
-    pub async fn get_student(&self, studentId:ObjectId) -> Result<Students, AppError> {
-        let result = match self.student_col.find_one(doc! { "_id":studentId}, None).await {
+    pub async fn get_student(&self, studentId:ObjectId, scope:&BranchScope) -> Result<Students, AppError> {
+        let result = match self.student_col.find_one(scope.restrict(doc! { "_id":studentId}, "class_branch"), None).await {
             Ok(Some(docResult)) => docResult,
             Ok(None) => return Err(AppError::DataNotFoundError),
             Err(e) => return Err(AppError::CustomError(e.to_string())),
@@ -39,20 +39,63 @@ impl StudentRepo {
         bson::from_document(result).map_err(|e| AppError::CustomError(e.to_string()))
     }
 
-    pub async fn update_profile_pic(&self, filePath:String, userId:ObjectId) -> Result<UpdateResult, AppError> {
+    pub async fn update_profile_pic(&self, filePath:String, variants:Option<ImageVariants>, userId:ObjectId, scope:&BranchScope) -> Result<UpdateResult, AppError> {
+        let variants = bson::to_bson(&variants).map_err(|e| AppError::CustomError(e.to_string()))?;
         let update = doc! {
             "$set" : {
                 "profile_pic":filePath,
+                "profile_pic_variants":variants,
                 "created_at":bson::DateTime::now()
             }
         };
-        match self.student_col.update_one(doc! { "_id": userId }, update, None).await {
+        match self.student_col.update_one(scope.restrict(doc! { "_id": userId }, "class_branch"), update, None).await {
             Ok(result) => Ok(result),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
-    pub async fn get_students(&self, skip:i64, limit:i64, tag:String) -> Result<Vec<Students>, AppError> {
+    // profile_pic used to hold a /static/ path, it holds the storage key now
+    pub async fn migrate_profile_pic_keys(&self) -> Result<u64, AppError> {
+        let update = vec![doc! {
+            "$set": { "profile_pic": { "$substrCP": ["$profile_pic", 8, { "$strLenCP":"$profile_pic" }] } }
+        }];
+        match self.student_col.update_many(doc! { "profile_pic": { "$regex":"^/static/" } }, update, None).await {
+            Ok(result) => Ok(result.modified_count),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // belts students hold, a belt can not leave the ladder while it is in use
+    pub async fn levels_in_use(&self) -> Result<Vec<String>, AppError> {
+        match self.student_col.distinct("level", doc! { "level": { "$type":"string" } }, None).await {
+            Ok(levels) => Ok(levels.iter().filter_map(|l| l.as_str()).map(String::from).collect()),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    // stored values of every profile picture and its variants
+    pub async fn file_references(&self) -> Result<Vec<String>, AppError> {
+        let opt = options::FindOptions::builder()
+            .projection(doc! { "profile_pic":1, "profile_pic_variants":1 })
+            .build();
+        let mut cursor = match self.student_col.find(doc! { "profile_pic": { "$type":"string" } }, opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut references:Vec<String> = Vec::new();
+        while let Some(student) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            if let Ok(profilePic) = student.get_str("profile_pic") {
+                references.push(profilePic.to_string());
+            }
+            if let Some(variants) = student.get("profile_pic_variants").and_then(|v| bson::from_bson::<ImageVariants>(v.clone()).ok()) {
+                references.extend(variants.keys().into_iter().map(String::from));
+            }
+        }
+        Ok(references)
+    }
+
+    pub async fn get_students(&self, skip:i64, limit:i64, tag:String, scope:&BranchScope) -> Result<Vec<Students>, AppError> {
         let opt = options::FindOptions::builder()
             .sort(doc!{"created_at":-1})
             .skip(skip as u64)
@@ -65,7 +108,7 @@ impl StudentRepo {
             filter.insert("level",  tag);
         } 
 
-        let mut cursor = match self.student_col.find(filter, opt).await{
+        let mut cursor = match self.student_col.find(scope.restrict(filter, "class_branch"), opt).await{
             Ok(document) => document,
             Err(e) => return Err(AppError::CustomError(e.to_string())),
         };
@@ -84,14 +127,14 @@ impl StudentRepo {
         Ok(students)
     }
 
-    pub async fn delete_student(&self, studentId:ObjectId) -> Result<DeleteResult, AppError> {
-        match self.student_col.delete_one(doc! { "_id":studentId }, None).await {
+    pub async fn delete_student(&self, studentId:ObjectId, scope:&BranchScope) -> Result<DeleteResult, AppError> {
+        match self.student_col.delete_one(scope.restrict(doc! { "_id":studentId }, "class_branch"), None).await {
             Ok(result) => Ok(result),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
-    pub async fn add_parent(&self, parent:Parents) -> Result<UpdateResult, AppError> {
+    pub async fn add_parent(&self, parent:Parents, scope:&BranchScope) -> Result<UpdateResult, AppError> {
         let update = doc! {
             "$set": {
                 "parent":parent.to_docmunet().unwrap(),
@@ -99,22 +142,20 @@ impl StudentRepo {
             }
         };
 
-        match self.student_col.update_one(doc! {"_id":parent.student_id.unwrap()}, update, None).await {
+        match self.student_col.update_one(scope.restrict(doc! {"_id":parent.student_id.unwrap()}, "class_branch"), update, None).await {
             Ok(result) => Ok(result),
             Err(e) => Err(AppError::CustomError(e.to_string())),
         }
     }
 
-    pub async fn total_students(&self) -> u64 {
-        let result = match self.student_col.count_documents(None, None).await{
-            Ok(count) => count,
-            Err(_) => 0,
-        };
-
-        result
+    pub async fn total_students(&self, scope:&BranchScope) -> u64 {
+        self.student_col.count_documents(scope.restrict(doc! {}, "class_branch"), None).await.unwrap_or_default()
     }
 
-    pub async fn update_student(&self, studentId:ObjectId, student:CreateStudentDTO) -> Result<UpdateResult, AppError> {
+    pub async fn update_student(&self, studentId:ObjectId, student:CreateStudentDTO, scope:&BranchScope) -> Result<UpdateResult, AppError> {
+        // a limited user can not move the student out of their branches
+        scope.check(&student.class_branch.iter().cloned().collect::<Vec<String>>())?;
+
         let update = doc! {
             "$set":{
                 "name":student.name,
@@ -126,7 +167,7 @@ impl StudentRepo {
             }
         };
 
-        match self.student_col.update_one(doc! { "_id":studentId }, update, None).await {
+        match self.student_col.update_one(scope.restrict(doc! { "_id":studentId }, "class_branch"), update, None).await {
             Ok(result) => Ok(result),
             Err(e) => {
                 Err(AppError::CustomError(e.to_string()))
@@ -134,7 +175,7 @@ impl StudentRepo {
         }
     } 
     
-    pub async fn last_month_admission_count(&self) -> u64 {
+    pub async fn last_month_admission_count(&self, scope:&BranchScope) -> u64 {
 
         let now = Utc::now();
         let month = now.month()-1;
@@ -147,7 +188,7 @@ impl StudentRepo {
                 "$lt":MongoDateTime::now()
             }
         };
-        match self.student_col.count_documents(filter, None).await {
+        match self.student_col.count_documents(scope.restrict(filter, "class_branch"), None).await {
             Ok(count) => {
                 println!("COUNT : {:?}", count);
                 count
@@ -157,8 +198,8 @@ impl StudentRepo {
         
     }
 
-    pub async fn pending_registration(&self) -> Result<Vec<Students>, AppError> {
-        let mut cursor = match self.student_col.find(doc! { "registration_status": "PENDING" }, None).await {
+    pub async fn pending_registration(&self, scope:&BranchScope) -> Result<Vec<Students>, AppError> {
+        let mut cursor = match self.student_col.find(scope.restrict(doc! { "registration_status": "PENDING" }, "class_branch"), None).await {
             Ok(cursor) => cursor,
             Err(e) => return  Err(AppError::CustomError(e.to_string())),
         };
@@ -177,6 +218,27 @@ impl StudentRepo {
         Ok(students)
     }
 
+    // ids of the students in scope, None when the scope is not limited
+    pub async fn student_ids(&self, scope:&BranchScope) -> Result<Option<Vec<ObjectId>>, AppError> {
+        if scope.branches().is_none() {
+            return Ok(None);
+        }
+
+        let opt = options::FindOptions::builder().projection(doc! { "_id":1 }).build();
+        let mut cursor = match self.student_col.find(scope.restrict(doc! {}, "class_branch"), opt).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let mut ids:Vec<ObjectId> = Vec::new();
+        while let Some(student) = cursor.try_next().await.map_err(|e| AppError::CustomError(e.to_string()))? {
+            if let Ok(id) = student.get_object_id("_id") {
+                ids.push(id);
+            }
+        }
+        Ok(Some(ids))
+    }
+
     pub async fn student_login(&self, studentId:String) -> Result<Students, AppError> {
         match self.student_col.find_one(doc! { "student_id": studentId }, None).await {
             Ok(Some(student)) =>{
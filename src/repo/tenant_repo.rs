@@ -0,0 +1,218 @@
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+
+use bson::{doc, Document};
+use futures::TryStreamExt;
+use mongodb::{error::ErrorKind, options::IndexOptions, results::{InsertOneResult, UpdateResult}, Client, Collection, Database, IndexModel};
+
+use crate::{config::db_config::DEFAULT_DATABASE, helper::app_errors::AppError, models::tenant::Tenants};
+
+use super::{app_repo::AppRepo, curriculum_repo::CurriculumRepo, events_repo::EventRepo, finance_repo::FinanceRepo, report_repo::ReportRepo, settings_repo::SettingsRepo, student_repo::StudentRepo, user_repo::UserRepo};
+
+// the academy that was here before tenants, it keeps the k_admin database
+pub const DEFAULT_TENANT:&str = "default";
+
+// The repositories of one academy, all opened on the academy's database.
+pub struct TenantRepos {
+    pub slug:String,
+    pub user:UserRepo,
+    pub student:StudentRepo,
+    pub event:EventRepo,
+    pub app:AppRepo,
+    pub finance:FinanceRepo,
+    pub settings:SettingsRepo,
+    pub report:ReportRepo,
+    pub curriculum:CurriculumRepo,
+}
+
+impl TenantRepos {
+    pub async fn init(slug:&str, db:Database) -> Self {
+        let repos = TenantRepos {
+            slug: slug.to_string(),
+            user: UserRepo::init(db.clone()).await,
+            student: StudentRepo::init(db.clone()),
+            event: EventRepo::init(db.clone()).await,
+            finance: FinanceRepo::init(db.clone(), StudentRepo::init(db.clone())).await,
+            settings: SettingsRepo::init(db.clone()),
+            report: ReportRepo::init(db.clone()),
+            curriculum: CurriculumRepo::init(db.clone(), StudentRepo::init(db.clone())).await,
+            app: AppRepo::init(db.clone(), StudentRepo::init(db.clone()), EventRepo::init(db).await).await,
+        };
+        repos.migrate().await;
+        repos
+    }
+
+    // conversions of older documents, a no-op once a database is converted
+    async fn migrate(&self) {
+        match self.event.migrate_legacy_dates().await {
+            Ok(0) => {},
+            Ok(count) => println!("[{}] Converted string dates of {} events", self.slug, count),
+            Err(e) => println!("[{}] Event date migration failed {}", self.slug, e),
+        }
+        if let Err(e) = self.event.backfill_media_ids().await {
+            println!("[{}] Event media id backfill failed {}", self.slug, e);
+        }
+
+        // stored /static/ paths become storage keys
+        let results = [
+            ("student profile pictures", self.student.migrate_profile_pic_keys().await),
+            ("event galleries", self.event.migrate_media_keys().await),
+            ("facility images", self.app.migrate_facility_image_keys().await),
+        ];
+        for (name, result) in results {
+            match result {
+                Ok(0) => {},
+                Ok(count) => println!("[{}] Converted {} {} to storage keys", self.slug, count, name),
+                Err(e) => println!("[{}] Storage key migration of {} failed {}", self.slug, name, e),
+            }
+        }
+
+        match self.app.backfill_facility_galleries().await {
+            Ok(0) => {},
+            Ok(count) => println!("[{}] Moved the image of {} facilities into their gallery", self.slug, count),
+            Err(e) => println!("[{}] Facility gallery backfill failed {}", self.slug, e),
+        }
+    }
+}
+
+// Tenants and their opened repositories. A tenant is opened on its first
+// request and kept until it is changed.
+pub struct TenantRegistry {
+    client:Client,
+    tenant_col:Collection<Document>,
+    opened:RwLock<HashMap<String, Arc<TenantRepos>>>,
+}
+
+impl TenantRegistry {
+    pub async fn init(client:Client) -> Self {
+        let tenant_col:Collection<Document> = client.database(DEFAULT_DATABASE).collection("tenants");
+
+        let index_model = IndexModel::builder()
+            .keys(doc! { "slug":1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = tenant_col.create_index(index_model, None).await {
+            println!("Tenant index creation failed {}", e);
+        }
+
+        TenantRegistry { client, tenant_col, opened: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn database_name(slug:&str) -> String {
+        format!("{}_{}", DEFAULT_DATABASE, slug.replace('-', "_"))
+    }
+
+    // the repositories of an active tenant, opening its database if needed
+    pub async fn repos(&self, slug:&str) -> Result<Arc<TenantRepos>, AppError> {
+        if let Some(repos) = self.opened.read().unwrap().get(slug) {
+            return Ok(repos.clone());
+        }
+
+        let database = if slug == DEFAULT_TENANT {
+            DEFAULT_DATABASE.to_string()
+        } else {
+            match self.get_tenant(slug).await {
+                Ok(tenant) if tenant.is_active => tenant.database,
+                Ok(_) => return Err(AppError::CustomError(format!("academy {} is not active", slug))),
+                Err(e) => return Err(e),
+            }
+        };
+
+        // two first requests may both open the tenant, the migrations are idempotent
+        let repos = Arc::new(TenantRepos::init(slug, self.client.database(&database)).await);
+        let mut opened = self.opened.write().unwrap();
+        Ok(opened.entry(slug.to_string()).or_insert(repos).clone())
+    }
+
+    // opens an academy on the given database, tests point it at a throwaway one
+    #[cfg(test)]
+    pub async fn open(&self, slug:&str, db:Database) -> Arc<TenantRepos> {
+        let repos = Arc::new(TenantRepos::init(slug, db).await);
+        self.opened.write().unwrap().insert(slug.to_string(), repos.clone());
+        repos
+    }
+
+    // the default academy and every active tenant, for the background jobs
+    pub async fn all(&self) -> Result<Vec<Arc<TenantRepos>>, AppError> {
+        let mut all = vec![self.repos(DEFAULT_TENANT).await?];
+        for tenant in self.get_tenants().await?.into_iter().filter(|t| t.is_active) {
+            match self.repos(&tenant.slug).await {
+                Ok(repos) => all.push(repos),
+                Err(e) => println!("Could not open academy {} {}", tenant.slug, e),
+            }
+        }
+        Ok(all)
+    }
+
+    // Every academy including inactive ones, for jobs such as the file cleanup that
+    // must see all data. Fails if any academy can not be opened.
+    pub async fn every(&self) -> Result<Vec<Arc<TenantRepos>>, AppError> {
+        let mut every = vec![self.repos(DEFAULT_TENANT).await?];
+        for tenant in self.get_tenants().await? {
+            let repos = if tenant.is_active {
+                self.repos(&tenant.slug).await
+            } else {
+                // not cached, an inactive academy is not served
+                Ok(Arc::new(TenantRepos::init(&tenant.slug, self.client.database(&tenant.database)).await))
+            };
+            every.push(repos.map_err(|e| AppError::CustomError(format!("could not open academy {} {}", tenant.slug, e)))?);
+        }
+        Ok(every)
+    }
+
+    pub async fn add_tenant(&self, tenant:Tenants) -> Result<InsertOneResult, AppError> {
+        let bson_doc = match tenant.to_document() {
+            Ok(document) => document,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        match self.tenant_col.insert_one(bson_doc, None).await {
+            Ok(result) => Ok(result),
+            Err(e) if is_duplicate_key(&e) => Err(AppError::CustomError("tenant slug is already used".to_string())),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_tenant(&self, slug:&str) -> Result<Tenants, AppError> {
+        match self.tenant_col.find_one(doc! { "slug":slug }, None).await {
+            Ok(Some(document)) => bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())),
+            Ok(None) => Err(AppError::DataNotFoundError),
+            Err(e) => Err(AppError::CustomError(e.to_string())),
+        }
+    }
+
+    pub async fn get_tenants(&self) -> Result<Vec<Tenants>, AppError> {
+        let cursor = match self.tenant_col.find(None, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        let documents:Vec<Document> = cursor.try_collect().await.map_err(|e| AppError::CustomError(e.to_string()))?;
+        documents.into_iter()
+            .map(|document| bson::from_document(document).map_err(|e| AppError::CustomError(e.to_string())))
+            .collect()
+    }
+
+    pub async fn update_tenant(&self, slug:&str, name:Option<String>, is_active:Option<bool>) -> Result<UpdateResult, AppError> {
+        let mut set = doc! { "updated_at":bson::DateTime::now() };
+        if let Some(name) = name {
+            set.insert("name", name);
+        }
+        if let Some(is_active) = is_active {
+            set.insert("is_active", is_active);
+        }
+
+        let result = match self.tenant_col.update_one(doc! { "slug":slug }, doc! { "$set":set }, None).await {
+            Ok(result) if result.matched_count == 0 => return Err(AppError::DataNotFoundError),
+            Ok(result) => result,
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        // a deactivated tenant must not be served from the cache
+        self.opened.write().unwrap().remove(slug);
+        Ok(result)
+    }
+}
+
+fn is_duplicate_key(err:&mongodb::error::Error) -> bool {
+    matches!(*err.kind, ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref write_error)) if write_error.code == 11000)
+}
@@ -46,6 +46,16 @@ impl UserRepo {
     
     }
 
+    pub async fn get_user(&self, user_id:ObjectId) -> Result<Users, AppError> {
+        let user = match self.user_col.find_one(doc! { "_id":user_id }, None).await {
+            Ok(Some(document)) => document,
+            Ok(None) => return Err(AppError::DataNotFoundError),
+            Err(e) => return Err(AppError::CustomError(e.to_string())),
+        };
+
+        bson::from_document(user).map_err(|e| AppError::CustomError(e.to_string()))
+    }
+
     pub async fn get_users(&self) -> Result<Vec<Users>, AppError> {
         let mut result = match self.user_col.find(None, None).await {
             Ok(documets) => documets,
@@ -67,17 +77,18 @@ impl UserRepo {
     }
 
     pub async fn update_user(&self, user_id:ObjectId, user_data:UpdateUserRequestDTO) -> Result<UpdateResult, AppError> {
-        let update = doc! {
-            "$set": {
-                "name":user_data.name,
-                "email":user_data.email,
-                "mobile_number":user_data.mobile_number,
-                "is_active":user_data.is_active,
-                "updated_at":bson::DateTime::now()
-            }
+        let mut changes = doc! {
+            "name":user_data.name,
+            "email":user_data.email,
+            "mobile_number":user_data.mobile_number,
+            "is_active":user_data.is_active,
+            "updated_at":bson::DateTime::now()
         };
+        if let Some(branches) = user_data.branches {
+            changes.insert("branches", branches);
+        }
 
-        match self.user_col.update_one(doc! {"_id":user_id}, update, None).await {
+        match self.user_col.update_one(doc! {"_id":user_id}, doc! { "$set":changes }, None).await {
             Ok(result) => Ok(result),
             Err(e) => {
                 return Err(AppError::CustomError(e.to_string()))
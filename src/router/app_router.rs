@@ -1,6 +1,6 @@
 use actix_web::web;
 
-use crate::service::app_service::*;
+use crate::service::{app_service::*, file_service::gc_files};
 
 pub fn app_router() -> actix_web::Scope {
     web::scope("api/app")
@@ -9,6 +9,7 @@ pub fn app_router() -> actix_web::Scope {
         .route("/update-branch/{path}", web::put().to(update_branch))
         .route("/delete-branch/{path}", web::delete().to(delete_branch))
         .route("/get-branch/{path}", web::get().to(get_branch))
+        .route("/branches/nearby", web::get().to(nearby_branches))
         .route("/app-counts", web::get().to(app_counts))
 
         .route("/guest-access", web::get().to(guest_access_token))
@@ -26,6 +27,14 @@ pub fn app_router() -> actix_web::Scope {
         .route("/update-course", web::put().to(update_course))
         .route("/delete-course/{path}", web::delete().to(delete_course))
         .route("/get-course/{path}", web::get().to(get_course))
+        .route("/set-course-capacity/{path}", web::put().to(set_course_capacity))
+        .route("/set-course-prerequisites/{path}", web::put().to(set_course_prerequisites))
+        .route("/eligible-courses/{path}", web::get().to(eligible_courses))
+
+        // enrollments
+        .route("/enroll-student", web::post().to(enroll_student))
+        .route("/list-enrollments", web::get().to(list_enrollments))
+        .route("/close-enrollment/{path}", web::put().to(close_enrollment))
 
         // facilities router
         .route("/add_facilities", web::post().to(add_facilities))
@@ -33,7 +42,13 @@ pub fn app_router() -> actix_web::Scope {
         .route("/list_facilities", web::get().to(list_facilities))
         .route("/update_facilities/{path}", web::put().to(update_facilities))
         .route("/uploade_facility_image/{path}", web::post().to(upload_facility_image))
+        .route("/delete-facility-image/{facility_id}/{image_id}", web::delete().to(delete_facility_image))
+        .route("/reorder-facility-images/{path}", web::put().to(reorder_facility_images))
         .route("/delete_facilities/{path}", web::delete().to(delete_facility))
+        .route("/branch-facilities/{path}", web::get().to(branch_facilities))
+
+        // uploaded files no document points to
+        .route("/gc-files", web::post().to(gc_files))
     
         
 
@@ -41,6 +56,14 @@ pub fn app_router() -> actix_web::Scope {
         .route("/add_enquiry", web::post().to(add_enquiry))
         .route("/list_enquiry", web::get().to(list_enquires))
         .route("/delete_enquiry/{path}", web::delete().to(delete_enquiry))
+        .route("/enquiry/my-follow-ups", web::get().to(my_follow_ups))
+        .route("/enquiry/{path}", web::get().to(get_enquiry))
+        .route("/enquiry/{path}/status", web::put().to(update_enquiry_status))
+        .route("/enquiry/{path}/assign", web::put().to(assign_enquiry))
+        .route("/enquiry/{path}/follow-ups", web::post().to(add_enquiry_follow_up))
+        .route("/enquiry/{enquiry_id}/follow-ups/{follow_up_id}/done", web::put().to(complete_enquiry_follow_up))
+        .route("/enquiry/{path}/notes", web::post().to(add_enquiry_note))
+        .route("/enquiry/{path}/convert", web::post().to(convert_enquiry))
         
         
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+use actix_web::web;
+
+use crate::service::calendar_service::*;
+
+pub fn calendar_router() -> actix_web::Scope {
+    web::scope("api/calendar")
+        // subscribable feeds, no token needed
+        .route("/feed/events.ics", web::get().to(events_feed))
+        .route("/feed/branch/{branch}.ics", web::get().to(branch_feed))
+        .route("/feed/student/{student_id}/{token}.ics", web::get().to(student_feed))
+
+        .route("/student-feed-url/{student_id}", web::get().to(student_feed_url))
+}
@@ -0,0 +1,17 @@
+use actix_web::web;
+
+use crate::service::curriculum_service::*;
+
+pub fn curriculum_router() -> actix_web::Scope {
+    web::scope("api/curriculum")
+        // syllabus
+        .route("/add-curriculum", web::post().to(add_curriculum))
+        .route("/get-curriculum/{course_id}/{level}", web::get().to(get_curriculum))
+        .route("/curriculum-history/{course_id}/{level}", web::get().to(curriculum_history))
+
+        // progress
+        .route("/tick-progress", web::post().to(tick_progress))
+        .route("/untick-progress", web::post().to(untick_progress))
+        .route("/student-progress/{student_id}/{course_id}", web::get().to(student_progress))
+        .route("/grading-eligibility/{student_id}/{course_id}", web::get().to(grading_eligibility))
+}
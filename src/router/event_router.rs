@@ -7,9 +7,24 @@ pub fn event_router() -> actix_web::Scope {
     web::scope("api/event")
         .route("/add-event", web::post().to(add_event))
         .route("/add-file-data/{path}", web::post().to(add_file_data))
+        .route("/add-video-link/{path}", web::post().to(add_video_link))
+        .route("/delete-media/{event_id}/{media_id}", web::delete().to(delete_media))
+        .route("/reorder-media/{path}", web::put().to(reorder_media))
+        .route("/caption-media/{event_id}/{media_id}", web::put().to(caption_media))
+        .route("/set-cover-media/{event_id}/{media_id}", web::put().to(set_cover_media))
         .route("/get-events/{skip}/{limit}", web::get().to(get_events))
         .route("/get-event/{path}", web::get().to(get_event))
         .route("/delete-event/{path}", web::delete().to(delete_event))
         .route("/update-event/{path}", web::put().to(update_event))
         .route("/total-event", web::get().to(total_event))
+        .route("/set-registration/{path}", web::put().to(set_event_registration))
+        .route("/register/{path}", web::post().to(register_for_event))
+        .route("/cancel-registration/{path}", web::put().to(cancel_event_registration))
+        .route("/mark-registration-paid/{path}", web::put().to(mark_registration_paid))
+        .route("/participants/{path}", web::get().to(event_participants))
+        .route("/student-events/{path}", web::get().to(student_events))
+        .route("/set-recurrence/{path}", web::put().to(set_event_recurrence))
+        .route("/occurrences", web::get().to(event_occurrences))
+        .route("/update-occurrence/{path}", web::put().to(update_occurrence))
+        .route("/cancel-occurrence/{path}", web::put().to(cancel_occurrence))
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+use actix_web::web;
+
+use crate::service::file_service::*;
+
+pub fn file_router() -> actix_web::Scope {
+    web::scope("api/files")
+        // signed private file urls, no token needed
+        .route("/{key:.*}", web::get().to(download_file))
+}
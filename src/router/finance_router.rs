@@ -0,0 +1,28 @@
+use actix_web::web;
+
+use crate::service::finance_service::*;
+
+pub fn finance_router() -> actix_web::Scope {
+    web::scope("api/finance")
+        // invoices
+        .route("/add-invoice", web::post().to(add_invoice))
+        .route("/list-invoices/{path}", web::get().to(list_invoices))
+        .route("/gst-summary/{path}", web::get().to(gst_summary))
+
+        // payments
+        .route("/create-payment-order/{path}", web::post().to(create_payment_order))
+        .route("/payment-webhook", web::post().to(payment_webhook))
+        .route("/reconcile-payments", web::post().to(reconcile_payments))
+
+        // refunds and credit notes
+        .route("/pro-rata-refund/{path}", web::get().to(pro_rata_refund))
+        .route("/request-refund", web::post().to(request_refund))
+        .route("/list-refunds", web::get().to(list_refunds))
+        .route("/approve-refund/{path}", web::put().to(approve_refund))
+        .route("/reject-refund/{path}", web::put().to(reject_refund))
+        .route("/add-credit-note", web::post().to(add_credit_note))
+        .route("/list-credit-notes/{path}", web::get().to(list_credit_notes))
+
+        // ledger
+        .route("/student-ledger/{path}", web::get().to(student_ledger))
+}
@@ -1,4 +1,11 @@
 pub mod user_router;
 pub mod student_routers;
 pub mod event_router;
-pub mod app_router;
\ No newline at end of file
+pub mod app_router;
+pub mod finance_router;
+pub mod settings_router;
+pub mod report_router;
+pub mod curriculum_router;
+pub mod calendar_router;
+pub mod file_router;
+pub mod tenant_router;
\ No newline at end of file
@@ -0,0 +1,12 @@
+use actix_web::web;
+
+use crate::service::report_service::*;
+
+pub fn report_router() -> actix_web::Scope {
+    web::scope("api/reports")
+        .route("/collections", web::get().to(collections_report))
+        .route("/dues-ageing", web::get().to(dues_ageing_report))
+        .route("/revenue", web::get().to(revenue_report))
+        .route("/discounts", web::get().to(discount_report))
+        .route("/enquiry-conversions", web::get().to(conversion_report))
+}
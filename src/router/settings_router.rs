@@ -0,0 +1,15 @@
+use actix_web::web;
+
+use crate::service::settings_service::*;
+
+pub fn settings_router() -> actix_web::Scope {
+    web::scope("api/settings")
+        .route("/get-settings", web::get().to(get_settings))
+        .route("/update-tax-settings", web::put().to(update_tax_settings))
+        .route("/update-academy-profile", web::put().to(update_academy_profile))
+        .route("/update-locale", web::put().to(update_locale))
+        .route("/upload-logo", web::post().to(upload_logo))
+        .route("/update-belts", web::put().to(update_belts))
+        .route("/update-fee-cycles", web::put().to(update_fee_cycles))
+        .route("/update-registration-options", web::put().to(update_registration_options))
+}
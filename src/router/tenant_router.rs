@@ -0,0 +1,11 @@
+use actix_web::web;
+
+use crate::service::tenant_service::*;
+
+// academies of the deployment, super admin only
+pub fn tenant_router() -> actix_web::Scope {
+    web::scope("api/tenants")
+        .route("/add-tenant", web::post().to(add_tenant))
+        .route("/list-tenants", web::get().to(list_tenants))
+        .route("/update-tenant/{slug}", web::put().to(update_tenant))
+}
\ No newline at end of file
@@ -1,28 +1,45 @@
-use std::{path::PathBuf};
-use actix_multipart::form::MultipartForm;
+use actix_multipart::Multipart;
 
-use actix_web::{ web::{Data,Path, Json}, HttpResponse, Responder};
+use actix_web::{ web::{Path, Json, Query}, HttpRequest, HttpResponse, Responder};
+use bson::doc;
+use chrono::{NaiveDate, Utc};
 use bson::oid::ObjectId;
 use serde::{ser::SerializeStruct, Serialize};
 use validator::Validate;
 
-use crate::{dto::{app_dto::{ActiveCourseRequestDTO, AppCountDTO, CoursesDTO, CreateBranchDTO, CreateCourseDTO, CreateEnquiryDTO, CreateFacilities, CreateFeesDTO, EnquiriesDTO, FacilitiesDTO, FeesDTO, GetBranchDTO}, student_dto::UploadProfileDTO}, helper::{app_errors::{AppError, Messages}, response::ResponseBuilder}, models::app::{Branches, Courses, Enquiries, Facilities, Fees}, repo::app_repo::AppRepo};
+use crate::{dto::{app_dto::{ActiveCourseRequestDTO, AppCountDTO, BranchCapacityDTO, CloseEnrollmentDTO, CoursePrerequisitesDTO, CoursesDTO, EligibleCourseDTO, CreateEnrollmentDTO, EnrollmentDTO, EnrollmentQueryDTO, CreateBranchDTO, CreateCourseDTO, CreateEnquiryDTO, CreateFacilities, CreateFeesDTO, EnquiriesDTO, AddEnquiryNoteDTO, AssignEnquiryDTO, ConvertEnquiryDTO, CreateFollowUpDTO, DueFollowUpDTO, EnquiryFollowUpDTO, EnquiryNoteDTO, EnquiryQueryDTO, UpdateEnquiryStatusDTO, FacilitiesDTO, FeesDTO, GetBranchDTO, NearbyBranchDTO, NearbyBranchQueryDTO}, event_dto::ReorderMediaDTO}, helper::{app_errors::{AppError, Messages}, branch_scope::BranchScope, unique_number::Helper, response::ResponseBuilder, timezone, upload}, models::{app::{Branches, GeoPoint, CoursePrerequisites, Courses, EnrollmentStatus, Enrollments, Enquiries, EnquiryFollowUp, EnquiryNote, EnquiryStatus, Facilities, FacilityImage, Fees}, money::{request_currency, DiscountKind, Money}, settings::AcademySettings, student_model::{Parents, Students}, user_models::{UserTypes, Users}}, repo::app_repo::AppRepo};
+use crate::{helper::tenant::Tenant, repo::settings_repo::SettingsRepo};
 
 use super::jwt_service;
 
 
-pub async fn add_branch(db:Data<AppRepo>, request:Json<CreateBranchDTO>) -> impl Responder {
+pub async fn add_branch(db:Tenant<AppRepo>, request:Json<CreateBranchDTO>) -> impl Responder {
     if request.name.is_empty() || request.address.is_empty() {
         return HttpResponse::BadRequest().json(
             ResponseBuilder::<()>::FailedResponse("Invalid request params".to_string())
         );
     };
 
+    if let Err(e) = validate_branch(&request) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let request = request.into_inner();
     let branch = Branches {
         id: None,
-        name: request.name.to_string(),
-        address: request.address.to_string(),
+        name: request.name,
+        address: request.address,
         is_active: request.is_active,
+        timezone: request.timezone,
+        code: request.code,
+        location: request.location.map(|l| GeoPoint::new(l.lat, l.lng)),
+        postal_address: request.postal_address,
+        phone: request.phone,
+        email: request.email,
+        hours: request.hours.unwrap_or_default(),
+        holidays: request.holidays.unwrap_or_default(),
         created_at: bson::DateTime::now(),
         updated_at: bson::DateTime::now(),
     };
@@ -44,7 +61,54 @@ pub async fn add_branch(db:Data<AppRepo>, request:Json<CreateBranchDTO>) -> impl
     }
 }
 
-pub async fn get_branches(db:Data<AppRepo>) -> impl Responder {
+fn validate_branch(request:&CreateBranchDTO) -> Result<(), AppError> {
+    if let Some(Err(e)) = request.timezone.as_deref().map(timezone::parse_timezone) {
+        return Err(e);
+    }
+    if let Err(e) = request.validate() {
+        return Err(AppError::CustomError(e.to_string()));
+    }
+    request.validate_schedule()
+}
+
+const NEARBY_DEFAULT_KM:f64 = 50.0;
+const NEARBY_MAX_KM:f64 = 500.0;
+const NEARBY_MAX_RESULTS:i64 = 20;
+
+// public, closest active branches to the given point
+pub async fn nearby_branches(db:Tenant<AppRepo>, query:Query<NearbyBranchQueryDTO>) -> impl Responder {
+    if !(-90.0..=90.0).contains(&query.lat) || !(-180.0..=180.0).contains(&query.lng) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("lat or lng is out of range".to_string())
+        );
+    }
+
+    let max_km = query.max_km.unwrap_or(NEARBY_DEFAULT_KM).clamp(0.0, NEARBY_MAX_KM);
+    let limit = query.limit.unwrap_or(5).clamp(1, NEARBY_MAX_RESULTS);
+
+    match db.nearby_branches(query.lat, query.lng, max_km * 1000.0, limit).await {
+        Ok(branches) => {
+            let branches:Vec<NearbyBranchDTO> = branches.into_iter().map(|(branch, distance)| NearbyBranchDTO {
+                branch: GetBranchDTO::init(branch),
+                distance_km: (distance / 10.0).round() / 100.0,
+            }).collect();
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(branches)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+pub async fn get_branches(db:Tenant<AppRepo>) -> impl Responder {
     match db.get_branches().await {
         Ok(branches) => {
             if branches.len() == 0 {
@@ -75,7 +139,13 @@ pub async fn get_branches(db:Data<AppRepo>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn update_branch(db:Data<AppRepo>, path:Path<String>, request:Json<CreateBranchDTO>) -> impl Responder {
+pub async fn update_branch(db:Tenant<AppRepo>, path:Path<String>, request:Json<CreateBranchDTO>) -> impl Responder {
+    if let Err(e) = validate_branch(&request) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
             match db.update_branches(objId, request.into_inner()).await {
@@ -111,7 +181,7 @@ pub async fn update_branch(db:Data<AppRepo>, path:Path<String>, request:Json<Cre
 }
 
 #[allow(non_snake_case)]
-pub async fn delete_branch(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
+pub async fn delete_branch(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
             match db.delete_branch(objId).await {
@@ -145,32 +215,17 @@ pub async fn delete_branch(db:Data<AppRepo>, path:Path<String>) -> impl Responde
 }
 
 #[allow(non_snake_case)]
-pub async fn app_counts(db:Data<AppRepo>) -> impl Responder {
+pub async fn app_counts(db:Tenant<AppRepo>, scope:BranchScope) -> impl Responder {
     // student counts
-    let totalStudent =  match db.total_students().await   {
-        Ok(count) => count,
-        Err(_) => 0,
-    };
+    let totalStudent = db.total_students(&scope).await.unwrap_or_default();
 
-    let lastMonthAdmission = match db.last_month_admission_count().await {
-        Ok(count) => count,
-        Err(_) => 0,
-    };
+    let lastMonthAdmission = db.last_month_admission_count(&scope).await.unwrap_or_default();
 
-    let totalBranches = match db.total_branches().await {
-        Ok(count) => count,
-        Err(_) => 0,
-    };
+    let totalBranches = db.total_branches(&scope).await.unwrap_or_default();
 
-    let totalEvents = match db.total_events().await {
-        Ok(count) => count,
-        Err(_) => 0,
-    };
+    let totalEvents = db.total_events(&scope).await.unwrap_or_default();
 
-    let totalUpCommingEvents = match db.upcommint_event_count().await {
-        Ok(count) => count,
-        Err(_) => 0,
-    };
+    let totalUpCommingEvents = db.upcommint_event_count(&scope).await.unwrap_or_default();
 
     let result = AppCountDTO {
         totalStudent,
@@ -189,7 +244,7 @@ pub async fn app_counts(db:Data<AppRepo>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn get_branch(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
+pub async fn get_branch(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
             match db.get_branch(objId).await {
@@ -217,7 +272,7 @@ pub async fn get_branch(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn guest_access_token() -> impl Responder {
+pub async fn guest_access_token(tenant:Tenant<SettingsRepo>) -> impl Responder {
     #[derive(Serialize)]
     struct GeustUser {
         pub name:String,
@@ -229,7 +284,7 @@ pub async fn guest_access_token() -> impl Responder {
         userType: String::from("GUEST"),
     };
 
-    let accessToken = jwt_service::JwtService::GenerateToken(&guestUser);
+    let accessToken = jwt_service::JwtService::GenerateToken(&guestUser, tenant.slug());
     HttpResponse::Ok().json(
         ResponseBuilder::SuccessResponse(
             String::from("Access token has been generate for Guest User"),
@@ -239,19 +294,77 @@ pub async fn guest_access_token() -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn add_fee(db:Data<AppRepo>, fee:Json<CreateFeesDTO>) -> impl Responder {
-    if fee.fee_amount == 0 {
-        return HttpResponse::BadRequest().json(
-            ResponseBuilder::<()>::FailedResponse("invalid fee amount".to_string())
-        );
-    }
+pub async fn add_fee(db:Tenant<AppRepo>, fee:Json<CreateFeesDTO>) -> impl Responder {
+    let settings = match db.settings().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let fee_cycle = match settings.fee_cycle(&fee.fee_type) {
+        Some(fee_cycle) => fee_cycle,
+        None => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Invalid fee type: {}", fee.fee_type))
+            );
+        },
+    };
+
+    let currency = match request_currency(fee.currency.as_deref(), settings.currency()) {
+        Ok(currency) => currency,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+    let fee_amount = match Money::parse(&fee.fee_amount, &currency) {
+        Ok(amount) if amount.amount_minor > 0 => amount,
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("invalid fee amount".to_string())
+            );
+        },
+    };
+
+    let fee_discount = match fee.fee_discount.as_ref().map(|d| d.to_discount()) {
+        Some(Ok(discount)) => {
+            if discount.kind == DiscountKind::FIXED && discount.value > fee_amount.amount_minor {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse("discount can not be more than fee amount".to_string())
+                );
+            }
+            Some(discount)
+        },
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+        None => None,
+    };
+
+    let tax = match fee.tax.as_ref().map(|t| t.to_tax_config()) {
+        Some(Ok(tax)) => Some(tax),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+        None => None,
+    };
 
     let feeModel = Fees {
         id: None,
-        fee_type: fee.fee_type.to_string(),
-        fee_amount: fee.fee_amount.into(),
+        fee_type: fee_cycle.key,
+        fee_amount,
         is_discount: false,
-        fee_discount: fee.fee_discount.into(),
+        fee_discount,
+        discount_review: None,
+        tax,
         created_at: bson::DateTime::now(),
         updated_at: bson::DateTime::now(),
     };
@@ -274,7 +387,7 @@ pub async fn add_fee(db:Data<AppRepo>, fee:Json<CreateFeesDTO>) -> impl Responde
 }
 
 #[allow(non_snake_case)]
-pub async fn get_fee(db:Data<AppRepo>) -> impl Responder {
+pub async fn get_fee(db:Tenant<AppRepo>) -> impl Responder {
     match db.get_fee().await {
         Ok(fees) => {
             if fees.len() == 0 {
@@ -305,7 +418,7 @@ pub async fn get_fee(db:Data<AppRepo>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn make_discount_Active(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
+pub async fn make_discount_Active(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objID) => {
             match db.make_discount_Active(objID).await {
@@ -339,7 +452,7 @@ pub async fn make_discount_Active(db:Data<AppRepo>, path:Path<String>) -> impl R
 }
 
 #[allow(non_snake_case)]
-pub async fn delete_fee(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
+pub async fn delete_fee(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
             match db.delete_fee(objId).await {
@@ -378,7 +491,7 @@ pub async fn delete_fee(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
 
 
 // ------------------------------ COURSES ------------------------------------- //
-pub async fn add_course(db:Data<AppRepo>, course:Json<CreateCourseDTO>) -> impl Responder {
+pub async fn add_course(db:Tenant<AppRepo>, course:Json<CreateCourseDTO>) -> impl Responder {
     match course.validate() {
         Ok(_) => {
 
@@ -388,6 +501,8 @@ pub async fn add_course(db:Data<AppRepo>, course:Json<CreateCourseDTO>) -> impl
                 description: course.description.clone().unwrap().to_string(),
                 is_active: false,
                 course_duration: course.course_duration.to_owned(),
+                branch_capacity: Vec::new(),
+                prerequisites: None,
                 created_at: bson::DateTime::now(),
                 updated_at: bson::DateTime::now(),
             };
@@ -416,7 +531,7 @@ pub async fn add_course(db:Data<AppRepo>, course:Json<CreateCourseDTO>) -> impl
     }
 }
 
-pub async fn list_course(db:Data<AppRepo>) -> impl Responder {
+pub async fn list_course(db:Tenant<AppRepo>) -> impl Responder {
     match db.list_course().await {
         Ok(courses) => {
             if courses.len() == 0 {
@@ -447,7 +562,7 @@ pub async fn list_course(db:Data<AppRepo>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn active_course(db:Data<AppRepo>, args:Json<ActiveCourseRequestDTO>) -> impl Responder {
+pub async fn active_course(db:Tenant<AppRepo>, args:Json<ActiveCourseRequestDTO>) -> impl Responder {
     match args.validate() {
         Ok(_) => {
             match ObjectId::parse_str(args.id.as_ref().unwrap().to_string()) {
@@ -488,7 +603,7 @@ pub async fn active_course(db:Data<AppRepo>, args:Json<ActiveCourseRequestDTO>)
     }
 }
 #[allow(non_snake_case)]
-pub async fn update_course(db:Data<AppRepo>, args:Json<CreateCourseDTO>) -> impl Responder {
+pub async fn update_course(db:Tenant<AppRepo>, args:Json<CreateCourseDTO>) -> impl Responder {
     match args.validate() {
         Ok(_) => {
             match ObjectId::parse_str(args.id.clone()) {
@@ -531,17 +646,16 @@ pub async fn update_course(db:Data<AppRepo>, args:Json<CreateCourseDTO>) -> impl
 }
 
 #[allow(non_snake_case)]
-pub async fn delete_course(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
+pub async fn delete_course(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
             match db.delete_course(objId).await {
-                Ok(result) =>{
-                    if result.deleted_count == 0 {
-                        return HttpResponse::NotFound().json(
-                            ResponseBuilder::<()>::FailedResponse(Messages::DataDeleteFailed.to_string())
-                        );
-                    }
-
+                Ok(false) => {
+                    HttpResponse::NotFound().json(
+                        ResponseBuilder::<()>::FailedResponse(Messages::DataDeleteFailed.to_string())
+                    )
+                },
+                Ok(true) => {
                     HttpResponse::Ok().json(
                         ResponseBuilder::<()>::SuccessResponse(
                             Messages::DataDeleteSucess.to_string(),
@@ -565,7 +679,7 @@ pub async fn delete_course(db:Data<AppRepo>, path:Path<String>) -> impl Responde
 }
 
 #[allow(non_snake_case)]
-pub async fn get_course(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
+pub async fn get_course(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
             match db.get_course(objId).await {
@@ -592,30 +706,49 @@ pub async fn get_course(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
     }
 }
 
-// ------------------------------ FACILITIES ------------------------------------- //
+// Sets the seats a branch has on a course. Raising it promotes waitlisted students.
 #[allow(non_snake_case)]
-pub async fn add_facilities(db:Data<AppRepo>, request:Json<CreateFacilities>) -> impl Responder {
-    match request.validate() {
-        Ok(_) => {
-            let faciliti = Facilities {
-                id: None,
-                title: request.title.to_owned().unwrap(),
-                description: request.description.to_owned().unwrap(),
-                imageUrl: None,
-                created_at:bson::DateTime::now(),
-                updated_at:bson::DateTime::now(),
-            };
-            match db.add_facilities(faciliti).await {
-                Ok(result) => {
+pub async fn set_course_capacity(db:Tenant<AppRepo>, path:Path<String>, request:Json<BranchCapacityDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let (courseId, branchId) = match (ObjectId::parse_str(path.into_inner()), ObjectId::parse_str(&request.branch_id)) {
+        (Ok(courseId), Ok(branchId)) => (courseId, branchId),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    if let Err(e) = db.get_branch(branchId).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Branch {}", e))
+        );
+    }
+
+    match db.set_course_capacity(courseId, branchId, request.capacity).await {
+        Ok(result) => {
+            if result.matched_count == 0 {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(Messages::DataUpdateFailed.to_string())
+                );
+            }
+
+            match db.promote_waitlist(courseId, branchId).await {
+                Ok(promoted) => {
                     HttpResponse::Ok().json(
                         ResponseBuilder::SuccessResponse(
-                            Messages::DataAddedSuccess.to_string(),
-                            Some(result)
+                            Messages::DataUpdateSuccess.to_string(),
+                            Some(promoted.iter().map(|id| id.to_hex()).collect::<Vec<String>>())
                         )
                     )
                 },
                 Err(e) => {
-                    HttpResponse::BadRequest().json(
+                    HttpResponse::InternalServerError().json(
                         ResponseBuilder::<()>::FailedResponse(e.to_string())
                     )
                 },
@@ -628,52 +761,80 @@ pub async fn add_facilities(db:Data<AppRepo>, request:Json<CreateFacilities>) ->
         },
     }
 }
+
 #[allow(non_snake_case)]
-pub async fn get_facilities(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
-    match ObjectId::parse_str(path.into_inner()) {
-        Ok(objId) => {
-            match db.get_facilities(objId).await {
-                Ok(document) => {
-                    HttpResponse::Ok().json(
-                        ResponseBuilder::SuccessResponse(
-                            Messages::DataFetchSuccess.to_string(),
-                            Some(FacilitiesDTO::init(document))
-                        )
-                    )
-                },
-                Err(e) => {
-                    HttpResponse::BadRequest().json(
-                        ResponseBuilder::<()>::FailedResponse(e.to_string())
-                    )
-                },
-            }
-        },
+pub async fn set_course_prerequisites(db:Tenant<AppRepo>, path:Path<String>, request:Json<CoursePrerequisitesDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let courseId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
         Err(_) => {
-            HttpResponse::BadRequest().json(
+            return HttpResponse::BadRequest().json(
                 ResponseBuilder::<()>::InValidIdResponse()
-            )
+            );
+        },
+    };
+
+    let settings = match db.settings().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let min_level = match request.min_level.as_deref().map(|level| settings.level_key(level)) {
+        Some(Some(level)) => Some(level),
+        Some(None) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Invalid Student Level: {}", request.min_level.as_ref().unwrap()))
+            );
         },
+        None => None,
+    };
+
+    if let (Some(min_age), Some(max_age)) = (request.min_age, request.max_age) {
+        if min_age > max_age {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("min_age can not be above max_age".to_string())
+            );
+        }
     }
-}
-#[allow(non_snake_case)]
-pub async fn list_facilities(db:Data<AppRepo>) -> impl Responder {
-    match db.list_facilities().await {
-        Ok(facilities) => {
-            if facilities.len() == 0 {
-                return  HttpResponse::NotFound().json(
-                    ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+
+    let mut completed_courses:Vec<ObjectId> = Vec::new();
+    for id in request.completed_courses.iter() {
+        match ObjectId::parse_str(id) {
+            Ok(objId) if objId != courseId => completed_courses.push(objId),
+            _ => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::InValidIdResponse()
+                );
+            },
+        }
+    }
+
+    let prerequisites = CoursePrerequisites {
+        min_level,
+        min_age: request.min_age,
+        max_age: request.max_age,
+        completed_courses,
+    };
+
+    match db.set_course_prerequisites(courseId, prerequisites).await {
+        Ok(result) => {
+            if result.matched_count == 0 {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(Messages::DataUpdateFailed.to_string())
                 );
-            }
-            let mut facilitiesDTO:Vec<FacilitiesDTO> = Vec::new();
-            for i in facilities {
-                facilitiesDTO.push(FacilitiesDTO::init(i))
             }
 
             HttpResponse::Ok().json(
-                ResponseBuilder::SuccessResponse(
-                    Messages::DataFetchSuccess.to_string(),
-                    Some(facilitiesDTO)
-                )
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
             )
         },
         Err(e) => {
@@ -684,182 +845,235 @@ pub async fn list_facilities(db:Data<AppRepo>) -> impl Responder {
     }
 }
 
+// Active courses with whether the student meets each one's prerequisites.
 #[allow(non_snake_case)]
-pub async fn update_facilities(db:Data<AppRepo>, path:Path<String>, facility:Json<CreateFacilities>) -> impl Responder {
-    match ObjectId::parse_str(path.into_inner()) {
-        Ok(objID) => {
-            let isImage = !facility.image_url.is_none();
-            match db.update_facilities(objID, facility.into_inner(), isImage).await {
-                Ok(result) => {
-                    if result.matched_count == 0 {
-                        return HttpResponse::BadRequest().json(
-                            ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
-                        );
-                    }
-
-                    HttpResponse::Ok().json(
-                        ResponseBuilder::<()>::SuccessResponse(
-                            Messages::DataUpdateSuccess.to_string(),
-                            None
-                        )
-                    )
-                },
-                Err(e) => {
-                    HttpResponse::BadRequest().json(
-                        ResponseBuilder::<()>::FailedResponse(e.to_string())
-                    )
-                },
-            }
-        },
+pub async fn eligible_courses(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
+    let studentId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
         Err(_) => {
-            HttpResponse::BadRequest().json(
+            return HttpResponse::BadRequest().json(
                 ResponseBuilder::<()>::InValidIdResponse()
-            )
+            );
         },
-    }
+    };
+
+    let (student, completed, courses, settings) = match (db.get_student(studentId).await, db.completed_courses(studentId).await, db.list_course().await, db.settings().await) {
+        (Ok(student), Ok(completed), Ok(courses), Ok(settings)) => (student, completed, courses, settings),
+        (Err(e), _, _, _) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Student {}", e))
+            );
+        },
+        (_, Err(e), _, _) | (_, _, Err(e), _) | (_, _, _, Err(e)) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let result:Vec<EligibleCourseDTO> = courses.into_iter()
+        .filter(|course| course.is_active)
+        .map(|course| {
+            let unmet = unmet_prerequisites(&course, &student, &completed, &settings);
+            EligibleCourseDTO {
+                id: course.id.unwrap().to_hex(),
+                name: course.name,
+                eligible: unmet.is_empty(),
+                unmet,
+            }
+        })
+        .collect();
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(result)
+        )
+    )
 }
 
 #[allow(non_snake_case)]
-pub async fn upload_facility_image(db:Data<AppRepo>,path:Path<String> , payload:MultipartForm<UploadProfileDTO>) -> impl Responder {
-    match ObjectId::parse_str(path.into_inner()) {
-        Ok(objId) => {
-                    
-            let f_file_path = format!("/static/facilities/");
-        
-            let temp_file_path = payload.file.file.path();
-            let file_name: &str = payload
-                .file
-                .file_name
-                .as_ref()
-                .map(|m| m.as_ref())
-                .unwrap_or("null");
-        
-            let mut file_path = PathBuf::from(format!(".{}",f_file_path));
-            file_path.push(&sanitize_filename::sanitize(&file_name));
-            match std::fs::rename(temp_file_path, file_path.clone()) {
-                Ok(_) => {
+async fn prerequisite_check(db:&Tenant<AppRepo>, course:&Courses, studentId:ObjectId) -> Result<Vec<String>, AppError> {
+    let student = db.get_student(studentId).await
+        .map_err(|e| AppError::CustomError(format!("Student {}", e)))?;
 
-                    // save the old profile pic path if the user have
-                    let student = match delete_old_facility_pic(db.clone(), objId).await {
-                        Ok(s) => s,
-                        Err(e) =>{
-                            return HttpResponse::BadRequest().json(
-                                ResponseBuilder::<()>::FailedResponse(e.to_string())
-                            )
-                        },
-                    };
-                    let facility = CreateFacilities {
-                        title: None,
-                        description: None,
-                        image_url: Some(format!("{}{}",f_file_path, file_name)),
-                    };
-                    match db.update_facilities(objId, facility, true).await {
-                        Ok(result) => {
-                            if result.matched_count == 0 {
-                                let _ = std::fs::remove_file(file_path);
-                                return HttpResponse::BadRequest().json(
-                                    ResponseBuilder::<()>::FailedResponse(Messages::DataUpdateFailed.to_string())
-                                )
-                            }
+    if course.prerequisites.is_none() {
+        return Ok(Vec::new());
+    }
 
-                            // check if old profile pic there then remove old file
-                            if !student.imageUrl.is_none() {
-                                let res = std::fs::remove_file(format!(".{}",student.imageUrl.unwrap().to_string()));
-                                if res.is_err() {
-                                    println!("Error while deleting a old")
-                                }
-                            }
+    let completed = db.completed_courses(studentId).await?;
+    let settings = db.settings().await?;
+    Ok(unmet_prerequisites(course, &student, &completed, &settings))
+}
 
-                            HttpResponse::Ok().json(
-                                ResponseBuilder::<()>::SuccessResponse(
-                                    Messages::DataUpdateSuccess.to_string(),
-                                    None
-                                )
-                            )
-                        },
-                        Err(e) => {
-                            let _ = std::fs::remove_file(file_path);
-                            HttpResponse::InternalServerError().json(
-                                ResponseBuilder::<()>::FailedResponse(e.to_string())
-                            )
-                        },
-                    }
-                },
-                Err(e) => return HttpResponse::InternalServerError().json(e.to_string()),
-            }
-        
-        },
-        Err(_) => {
-            HttpResponse::BadRequest().json(
-                ResponseBuilder::<()>::InValidIdResponse()
-            )
-        },
+// human readable list of the rules the student fails, empty when eligible
+fn unmet_prerequisites(course:&Courses, student:&Students, completed:&[ObjectId], settings:&AcademySettings) -> Vec<String> {
+    let prerequisites = match course.prerequisites.as_ref() {
+        Some(prerequisites) => prerequisites,
+        None => return Vec::new(),
+    };
+
+    let mut unmet:Vec<String> = Vec::new();
+    let age = student.age_on(Utc::now().with_timezone(&settings.timezone()).date_naive());
+
+    if let Some(min_level) = prerequisites.min_level.as_deref().filter(|level| settings.belt_rank(level).is_some()) {
+        let student_rank = student.level.as_deref().and_then(|level| settings.belt_rank(level));
+        if student_rank < settings.belt_rank(min_level) {
+            unmet.push(format!("belt {} or above", min_level));
+        }
+    }
+    if let Some(min_age) = prerequisites.min_age {
+        if age < min_age {
+            unmet.push(format!("at least {} years old", min_age));
+        }
+    }
+    if let Some(max_age) = prerequisites.max_age {
+        if age > max_age {
+            unmet.push(format!("at most {} years old", max_age));
+        }
+    }
+    for course_id in prerequisites.completed_courses.iter() {
+        if !completed.contains(course_id) {
+            unmet.push(format!("completed course {}", course_id.to_hex()));
+        }
     }
 
+    unmet
 }
-#[allow(non_snake_case)]
-pub async fn delete_facility(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
-    match ObjectId::parse_str(path.into_inner()) {
-        Ok(objId) => {
-            match db.delete_facility(objId).await {
-                Ok(result) => {
-                    if result.deleted_count  == 0 {
-                        return HttpResponse::BadRequest().json(
-                            ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
-                        );
-                    }
 
-                    HttpResponse::Ok().json(
-                        ResponseBuilder::<()>::SuccessResponse(
-                            Messages::DataUpdateSuccess.to_string(),
-                            None
-                        )
-                    )
-                },
-                Err(e) => {
-                    HttpResponse::BadRequest().json(
-                        ResponseBuilder::<()>::FailedResponse(e.to_string())
-                    )
-                },
-            }
-        },
-        Err(_) => {
-            HttpResponse::BadRequest().json(
+// ------------------------------ ENROLLMENTS ------------------------------------- //
+#[allow(non_snake_case)]
+pub async fn enroll_student(db:Tenant<AppRepo>, req:HttpRequest, request:Json<CreateEnrollmentDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let (studentId, courseId, branchId) = match (
+        ObjectId::parse_str(request.student_id.as_ref().unwrap()),
+        ObjectId::parse_str(request.course_id.as_ref().unwrap()),
+        ObjectId::parse_str(request.branch_id.as_ref().unwrap())
+    ) {
+        (Ok(studentId), Ok(courseId), Ok(branchId)) => (studentId, courseId, branchId),
+        _ => {
+            return HttpResponse::BadRequest().json(
                 ResponseBuilder::<()>::InValidIdResponse()
-            )
+            );
         },
-    }
-}
+    };
 
-#[allow(non_snake_case)]
-pub async fn delete_old_facility_pic(db:Data<AppRepo>,facilityID:ObjectId) -> Result<Facilities, AppError> {
-    match db.get_facilities(facilityID).await {
-        Ok(facilities) => {
-            Ok(facilities)
+    let parse_date = |date:&str| NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| bson::DateTime::from_millis(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis()));
+
+    let (start_date, end_date) = match (parse_date(request.start_date.as_ref().unwrap()), request.end_date.as_deref().map(parse_date).transpose()) {
+        (Ok(start_date), Ok(end_date)) if end_date.is_none_or(|end_date| end_date > start_date) => (start_date, end_date),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("Invalid start or end date".to_string())
+            );
+        },
+    };
+
+    let course = match db.get_course(courseId).await {
+        Ok(course) => course,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Course {}", e))
+            );
         },
-        Err(e) =>  Err(e),
+    };
+
+    if !course.is_active {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("course is not active".to_string())
+        );
     }
-}
 
-// ------------------------------ ENQUIRES ------------------------------------- //
+    if let Err(e) = db.get_branch(branchId).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Branch {}", e))
+        );
+    }
 
-pub async fn add_enquiry(db:Data<AppRepo>, enquire:Json<CreateEnquiryDTO>) -> impl Responder {
-    let enquire_m = Enquiries {
+    let unmet = match prerequisite_check(&db, &course, studentId).await {
+        Ok(unmet) => unmet,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let mut prerequisite_override_by = None;
+    if !unmet.is_empty() {
+        match jwt_service::JwtService::current_user(&req) {
+            Some(user) if request.override_prerequisites && user.is_admin() => prerequisite_override_by = Some(user.name),
+            _ => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse(format!("prerequisites not met: {}", unmet.join(", ")))
+                );
+            },
+        }
+    }
+
+    let enrollment = Enrollments {
         id: None,
-        name: enquire.name.to_string(),
-        email: enquire.email.to_string(),
-        contact: enquire.contact.to_string(),
-        subject: enquire.subject.to_string(),
-        message: enquire.message.to_string(),
+        student_id: studentId,
+        course_id: courseId,
+        branch_id: branchId,
+        start_date,
+        end_date,
+        status: EnrollmentStatus::ACTIVE.to_string(),
+        prerequisite_override_by,
         created_at: bson::DateTime::now(),
         updated_at: bson::DateTime::now(),
     };
-    match db.add_enquiry(enquire_m).await {
-        Ok(result) => {
+
+    match db.enroll_student(enrollment).await {
+        Ok(enrollment) => {
             HttpResponse::Ok().json(
                 ResponseBuilder::SuccessResponse(
                     Messages::DataAddedSuccess.to_string(),
-                    Some(result)
+                    Some(EnrollmentDTO::init(enrollment))
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn list_enrollments(db:Tenant<AppRepo>, query:Query<EnrollmentQueryDTO>) -> impl Responder {
+    let mut filter = doc! {};
+    for (field, value) in [("student_id", &query.student_id), ("course_id", &query.course_id), ("branch_id", &query.branch_id)] {
+        if let Some(value) = value {
+            match ObjectId::parse_str(value) {
+                Ok(objId) => { filter.insert(field, objId); },
+                Err(_) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::InValidIdResponse()
+                    );
+                },
+            }
+        }
+    }
+    if let Some(status) = query.status.as_ref() {
+        filter.insert("status", status.to_uppercase());
+    }
+
+    match db.list_enrollments(filter).await {
+        Ok(enrollments) => {
+            let enrollment_dto:Vec<EnrollmentDTO> = enrollments.into_iter().map(EnrollmentDTO::init).collect();
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(enrollment_dto)
                 )
             )
         },
@@ -871,25 +1085,151 @@ pub async fn add_enquiry(db:Data<AppRepo>, enquire:Json<CreateEnquiryDTO>) -> im
     }
 }
 
+// Completes or drops an enrollment, a freed seat goes to the next waitlisted student.
+#[allow(non_snake_case)]
+pub async fn close_enrollment(db:Tenant<AppRepo>, path:Path<String>, request:Json<CloseEnrollmentDTO>) -> impl Responder {
+    let enrollment = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => match db.get_enrollment(objId).await {
+            Ok(enrollment) => enrollment,
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        },
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    // the status it had when closing decides whether a seat was freed
+    let closed = match db.close_enrollment(enrollment.id.unwrap(), request.into_inner().status).await {
+        Ok(Some(closed)) => closed,
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("enrollment is already closed".to_string())
+            );
+        },
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let mut promoted:Vec<ObjectId> = Vec::new();
+    if closed.status == EnrollmentStatus::ACTIVE.to_string() {
+        match db.promote_waitlist(closed.course_id, closed.branch_id).await {
+            Ok(ids) => promoted = ids,
+            Err(e) => println!("Waitlist promotion failed for course {} : {}", closed.course_id, e),
+        }
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataUpdateSuccess.to_string(),
+            Some(promoted.iter().map(|id| id.to_hex()).collect::<Vec<String>>())
+        )
+    )
+}
 
+// ------------------------------ FACILITIES ------------------------------------- //
 #[allow(non_snake_case)]
-pub async fn list_enquires(db:Data<AppRepo>) -> impl Responder {
-    match db.list_enquires().await {
-        Ok(enquires) => {
-            if enquires.len() == 0 {
+pub async fn add_facilities(db:Tenant<AppRepo>, request:Json<CreateFacilities>) -> impl Responder {
+    match request.validate() {
+        Ok(_) => {
+            let branches = match facility_branches(&db, request.branches.as_ref()).await {
+                Ok(branches) => branches.unwrap_or_default(),
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    );
+                },
+            };
+
+            let faciliti = Facilities {
+                id: None,
+                title: request.title.to_owned().unwrap(),
+                description: request.description.to_owned().unwrap(),
+                imageUrl: None,
+                image_variants: None,
+                branches,
+                gallery: Vec::new(),
+                amenities: request.amenities.to_owned().unwrap_or_default(),
+                availability_notes: request.availability_notes.as_ref().map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+                created_at:bson::DateTime::now(),
+                updated_at:bson::DateTime::now(),
+            };
+            match db.add_facilities(faciliti).await {
+                Ok(result) => {
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::SuccessResponse(
+                            Messages::DataAddedSuccess.to_string(),
+                            Some(result)
+                        )
+                    )
+                },
+                Err(e) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+#[allow(non_snake_case)]
+pub async fn get_facilities(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            match db.get_facilities(objId).await {
+                Ok(document) => {
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::SuccessResponse(
+                            Messages::DataFetchSuccess.to_string(),
+                            Some(FacilitiesDTO::init(document))
+                        )
+                    )
+                },
+                Err(e) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+#[allow(non_snake_case)]
+pub async fn list_facilities(db:Tenant<AppRepo>) -> impl Responder {
+    match db.list_facilities().await {
+        Ok(facilities) => {
+            if facilities.len() == 0 {
                 return  HttpResponse::NotFound().json(
                     ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
                 );
             }
-            let mut enquiriesDTO:Vec<EnquiriesDTO> = Vec::new();
-            for i in enquires {
-                enquiriesDTO.push(EnquiriesDTO::init(i))
+            let mut facilitiesDTO:Vec<FacilitiesDTO> = Vec::new();
+            for i in facilities {
+                facilitiesDTO.push(FacilitiesDTO::init(i))
             }
 
             HttpResponse::Ok().json(
                 ResponseBuilder::SuccessResponse(
                     Messages::DataFetchSuccess.to_string(),
-                    Some(enquiriesDTO)
+                    Some(facilitiesDTO)
                 )
             )
         },
@@ -900,38 +1240,1179 @@ pub async fn list_enquires(db:Data<AppRepo>) -> impl Responder {
         },
     }
 }
+
 #[allow(non_snake_case)]
-pub async fn delete_enquiry(db:Data<AppRepo>, path:Path<String>) -> impl Responder {
+pub async fn update_facilities(db:Tenant<AppRepo>, path:Path<String>, facility:Json<CreateFacilities>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
-        Ok(objId) => {
-            match db.delete_enquiries(objId).await {
+        Ok(objID) => {
+            let branches = match facility_branches(&db, facility.branches.as_ref()).await {
+                Ok(branches) => branches,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    );
+                },
+            };
+
+            match db.update_facilities(objID, facility.into_inner(), branches).await {
                 Ok(result) => {
-                    if result.deleted_count == 0 {
-                        return HttpResponse::NotFound().json(
+                    if result.matched_count == 0 {
+                        return HttpResponse::BadRequest().json(
                             ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
                         );
                     }
 
                     HttpResponse::Ok().json(
                         ResponseBuilder::<()>::SuccessResponse(
-                            Messages::DataDeleteSucess.to_string(),
+                            Messages::DataUpdateSuccess.to_string(),
                             None
                         )
                     )
                 },
-                Err(err) => {
+                Err(e) => {
                     HttpResponse::BadRequest().json(
-                        ResponseBuilder::<()>::FailedResponse(err.to_string())
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
                     )
                 },
             }
         },
-        Err(_) =>{
+        Err(_) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+
+// adds the uploaded images to the end of the gallery
+#[allow(non_snake_case)]
+pub async fn upload_facility_image(db:Tenant<AppRepo>, req:HttpRequest, scope:BranchScope, path:Path<String> , payload:Multipart) -> impl Responder {
+    if !jwt_service::JwtService::current_user(&req).is_some_and(|user| user.is_staff()) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse("only academy staff can upload facility images".to_string())
+        );
+    }
+
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            let facility = match db.get_facilities(objId).await {
+                Ok(s) => s,
+                Err(e) =>{
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            };
+
+            // a branch user uploads to facilities of their branches only
+            let branches:Vec<String> = facility.branches.iter().map(|branch| branch.to_hex()).collect();
+            if let Err(e) = scope.check(&branches) {
+                return HttpResponse::Forbidden().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            }
+
+            let form = match upload::save_multipart(payload, &upload::FACILITY_IMAGE).await {
+                Ok(form) => form,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            };
+            if form.files.is_empty() {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse("file is required".to_string())
+                )
+            }
+
+            let mut gallery = facility.gallery.clone();
+            gallery.extend(form.files.iter().map(|file| FacilityImage {
+                id: ObjectId::new(),
+                key: file.key.to_string(),
+                variants: file.variants.clone(),
+                created_at: bson::DateTime::now(),
+            }));
+
+            match db.set_facility_gallery(objId, &facility.gallery, gallery).await {
+                Ok(_) => {
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::<()>::SuccessResponse(
+                            Messages::DataUpdateSuccess.to_string(),
+                            None
+                        )
+                    )
+                },
+                Err(e) => {
+                    form.discard().await;
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) => {
             HttpResponse::BadRequest().json(
                 ResponseBuilder::<()>::InValidIdResponse()
             )
         },
     }
+
 }
 
+#[allow(non_snake_case)]
+pub async fn delete_facility_image(db:Tenant<AppRepo>, path:Path<(String, String)>) -> impl Responder {
+    let (facilityId, imageId) = path.into_inner();
+    let (facilityId, imageId) = match (ObjectId::parse_str(facilityId), ObjectId::parse_str(imageId)) {
+        (Ok(facilityId), Ok(imageId)) => (facilityId, imageId),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
 
+    let gallery = match db.get_facilities(facilityId).await {
+        Ok(facility) => facility.gallery,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Facility {}", e))
+            );
+        },
+    };
+
+    let (removed, rest):(Vec<FacilityImage>, Vec<FacilityImage>) = gallery.iter().cloned().partition(|image| image.id == imageId);
+    let image = match removed.first() {
+        Some(image) => image,
+        None => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Image {}", AppError::DataNotFoundError))
+            );
+        },
+    };
+
+    match db.set_facility_gallery(facilityId, &gallery, rest).await {
+        Ok(_) => {
+            upload::remove_upload(&image.key, image.variants.as_ref()).await;
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn reorder_facility_images(db:Tenant<AppRepo>, path:Path<String>, request:Json<ReorderMediaDTO>) -> impl Responder {
+    let facilityId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let gallery = match db.get_facilities(facilityId).await {
+        Ok(facility) => facility.gallery,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Facility {}", e))
+            );
+        },
+    };
+
+    let mut ordered:Vec<FacilityImage> = Vec::new();
+    for id in request.media_ids.iter() {
+        match gallery.iter().find(|image| image.id.to_hex() == *id) {
+            Some(image) if !ordered.iter().any(|o| o.id == image.id) => ordered.push(image.clone()),
+            _ => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse(format!("Invalid or repeated image id: {}", id))
+                );
+            },
+        }
+    }
+    if ordered.len() != gallery.len() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("media_ids should list every image of the gallery".to_string())
+        );
+    }
+
+    match db.set_facility_gallery(facilityId, &gallery, ordered).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// what a dojo has, for the branch page of the public site
+#[allow(non_snake_case)]
+pub async fn branch_facilities(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
+    let branchId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    if let Err(e) = db.get_branch(branchId).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Branch {}", e))
+        );
+    }
+
+    match db.list_branch_facilities(branchId).await {
+        Ok(facilities) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(facilities.into_iter().map(FacilitiesDTO::init).collect::<Vec<FacilitiesDTO>>())
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn delete_facility(db:Tenant<AppRepo>, path:Path<String>) -> impl Responder {
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            match db.delete_facility(objId).await {
+                Ok(result) => {
+                    if result.deleted_count  == 0 {
+                        return HttpResponse::BadRequest().json(
+                            ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+                        );
+                    }
+
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::<()>::SuccessResponse(
+                            Messages::DataUpdateSuccess.to_string(),
+                            None
+                        )
+                    )
+                },
+                Err(e) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+
+// Parsed ids of the branches a facility is part of, None when the request
+// leaves them as they are. Every branch has to exist.
+async fn facility_branches(db:&AppRepo, branches:Option<&Vec<String>>) -> Result<Option<Vec<ObjectId>>, AppError> {
+    let branches = match branches {
+        Some(branches) => branches,
+        None => return Ok(None),
+    };
+
+    let mut ids:Vec<ObjectId> = Vec::new();
+    for branch in branches {
+        let id = ObjectId::parse_str(branch).map_err(|_| AppError::CustomError(format!("Invalid branch id: {}", branch)))?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    if db.count_branches(&ids).await? != ids.len() as u64 {
+        return Err(AppError::CustomError(format!("Branch {}", AppError::DataNotFoundError)));
+    }
+    Ok(Some(ids))
+}
+
+// ------------------------------ ENQUIRES ------------------------------------- //
+
+pub async fn add_enquiry(db:Tenant<AppRepo>, enquire:Json<CreateEnquiryDTO>) -> impl Responder {
+    if enquire.branch.as_deref().is_some_and(|b| ObjectId::parse_str(b).is_err()) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::InValidIdResponse()
+        );
+    }
+
+    let enquire_m = Enquiries {
+        id: None,
+        name: enquire.name.to_string(),
+        email: enquire.email.to_string(),
+        contact: enquire.contact.to_string(),
+        subject: enquire.subject.to_string(),
+        message: enquire.message.to_string(),
+        branch: enquire.branch.clone(),
+        status: EnquiryStatus::NEW.to_string(),
+        lost_reason: None,
+        trial_at: None,
+        source: enquire.source.as_ref().map(|s| s.to_string()),
+        assigned_to: None,
+        follow_ups: Vec::new(),
+        notes: Vec::new(),
+        student_name: enquire.student_name.clone().filter(|n| !n.trim().is_empty()),
+        student_id: None,
+        converted_at: None,
+        created_at: bson::DateTime::now(),
+        updated_at: bson::DateTime::now(),
+    };
+    match db.add_enquiry(enquire_m).await {
+        Ok(result) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(result)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+
+#[allow(non_snake_case)]
+pub async fn list_enquires(db:Tenant<AppRepo>, scope:BranchScope, query:Query<EnquiryQueryDTO>) -> impl Responder {
+    let mut filter = doc! {};
+    // "trial scheduled" and "walk-in" are accepted for TRIAL_SCHEDULED and WALK_IN
+    for (field, value) in [("status", &query.status), ("source", &query.source)] {
+        if let Some(value) = value {
+            filter.insert(field, value.trim().to_uppercase().replace([' ', '-'], "_"));
+        }
+    }
+    if let Some(assigned_to) = query.assigned_to.as_ref() {
+        filter.insert("assigned_to", assigned_to);
+    }
+
+    match db.list_enquires(filter, &scope).await {
+        Ok(enquires) => {
+            if enquires.len() == 0 {
+                return  HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+                );
+            }
+            let mut enquiriesDTO:Vec<EnquiriesDTO> = Vec::new();
+            for i in enquires {
+                enquiriesDTO.push(EnquiriesDTO::init(i))
+            }
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(enquiriesDTO)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+#[allow(non_snake_case)]
+pub async fn delete_enquiry(db:Tenant<AppRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            match db.delete_enquiries(objId, &scope).await {
+                Ok(result) => {
+                    if result.deleted_count == 0 {
+                        return HttpResponse::NotFound().json(
+                            ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+                        );
+                    }
+
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::<()>::SuccessResponse(
+                            Messages::DataDeleteSucess.to_string(),
+                            None
+                        )
+                    )
+                },
+                Err(err) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(err.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) =>{
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+
+
+
+
+#[allow(non_snake_case)]
+pub async fn get_enquiry(db:Tenant<AppRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
+    let objId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    match db.get_enquiry(objId, &scope).await {
+        Ok(enquiry) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(EnquiriesDTO::init(enquiry))
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Moves an enquiry along the lifecycle, LOST needs a reason and TRIAL_SCHEDULED a trial date.
+#[allow(non_snake_case)]
+pub async fn update_enquiry_status(req:HttpRequest, db:Tenant<AppRepo>, scope:BranchScope, path:Path<String>, request:Json<UpdateEnquiryStatusDTO>) -> impl Responder {
+    let user = match enquiry_staff(&req) {
+        Ok(user) => user,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let enquiry = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => match db.get_enquiry(objId, &scope).await {
+            Ok(enquiry) => enquiry,
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        },
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    // the student link is only made by convert_enquiry
+    if enquiry.student_id.is_some() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("the enquiry is converted, its status can not change".to_string())
+        );
+    }
+    if request.status == EnquiryStatus::CONVERTED {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("an enquiry is converted by registering the student from it".to_string())
+        );
+    }
+
+    let request = request.into_inner();
+    let status = request.status.to_string();
+    let mut fields = doc! { "status":&status, "lost_reason":null };
+    let mut text = format!("Status changed from {} to {}", enquiry.status, status);
+
+    match request.status {
+        EnquiryStatus::LOST => {
+            let reason = request.reason.as_deref().map(str::trim).unwrap_or_default();
+            if reason.is_empty() {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse("a reason is required for a lost enquiry".to_string())
+                );
+            }
+            fields.insert("lost_reason", reason);
+            text = format!("{}: {}", text, reason);
+        },
+        EnquiryStatus::TRIALSCHEDULED => {
+            let trial_at = match request.trial_at.as_deref() {
+                Some(trial_at) => trial_at,
+                None => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse("trial_at is required to schedule a trial".to_string())
+                    );
+                },
+            };
+            let zone = match db.settings().await {
+                Ok(settings) => settings.timezone(),
+                Err(e) => {
+                    return HttpResponse::InternalServerError().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    );
+                },
+            };
+            match timezone::parse_datetime(trial_at, zone) {
+                Ok(trial_at) => {
+                    fields.insert("trial_at", trial_at);
+                    text = format!("{} on {}", text, timezone::to_local(trial_at, zone).format("%Y-%m-%d %H:%M"));
+                },
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    );
+                },
+            }
+        },
+        _ => {},
+    }
+
+    match db.update_enquiry_status(enquiry.id.unwrap(), &scope, fields, enquiry_note(text, &user)).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Hands the enquiry to a staff user, a sub admin must work on the enquiry's branch.
+#[allow(non_snake_case)]
+pub async fn assign_enquiry(req:HttpRequest, db:Tenant<AppRepo>, scope:BranchScope, path:Path<String>, request:Json<AssignEnquiryDTO>) -> impl Responder {
+    let user = match enquiry_staff(&req) {
+        Ok(user) => user,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let enquiry = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => match db.get_enquiry(objId, &scope).await {
+            Ok(enquiry) => enquiry,
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        },
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let assignee = match assignable_user(&db, &enquiry, &request.user_id).await {
+        Ok(assignee) => assignee,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let note = enquiry_note(format!("Assigned to {}", assignee.name), &user);
+    match db.assign_enquiry(enquiry.id.unwrap(), &scope, &request.user_id, note).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Plans a follow-up, it is due for the given user, else the enquiry's assignee, else the caller.
+#[allow(non_snake_case)]
+pub async fn add_enquiry_follow_up(req:HttpRequest, db:Tenant<AppRepo>, scope:BranchScope, path:Path<String>, request:Json<CreateFollowUpDTO>) -> impl Responder {
+    let user = match enquiry_staff(&req) {
+        Ok(user) => user,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let enquiry = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => match db.get_enquiry(objId, &scope).await {
+            Ok(enquiry) => enquiry,
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        },
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let assigned_to = match request.assigned_to.as_ref() {
+        Some(userId) => match assignable_user(&db, &enquiry, userId).await {
+            Ok(_) => userId.clone(),
+            Err(e) => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        },
+        None => enquiry.assigned_to.clone().or(user.id.clone()).unwrap_or_default(),
+    };
+
+    let zone = match db.settings().await {
+        Ok(settings) => settings.timezone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+    let due_at = match timezone::parse_datetime(&request.due_at, zone) {
+        Ok(due_at) => due_at,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let follow_up = EnquiryFollowUp {
+        id: ObjectId::new(),
+        due_at,
+        note: request.note.trim().to_string(),
+        assigned_to,
+        created_by: user.name,
+        done_at: None,
+    };
+
+    match db.add_enquiry_follow_up(enquiry.id.unwrap(), &scope, &follow_up).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(EnquiryFollowUpDTO::init(follow_up))
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn complete_enquiry_follow_up(req:HttpRequest, db:Tenant<AppRepo>, scope:BranchScope, path:Path<(String, String)>) -> impl Responder {
+    if let Err(e) = enquiry_staff(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let (enquiryId, followUpId) = path.into_inner();
+    let (enquiryId, followUpId) = match (ObjectId::parse_str(enquiryId), ObjectId::parse_str(followUpId)) {
+        (Ok(enquiryId), Ok(followUpId)) => (enquiryId, followUpId),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    match db.complete_follow_up(enquiryId, followUpId, &scope).await {
+        Ok(result) if result.matched_count == 0 => {
+            HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse("no open follow-up found".to_string())
+            )
+        },
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn add_enquiry_note(req:HttpRequest, db:Tenant<AppRepo>, scope:BranchScope, path:Path<String>, request:Json<AddEnquiryNoteDTO>) -> impl Responder {
+    let user = match enquiry_staff(&req) {
+        Ok(user) => user,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let objId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let note = enquiry_note(request.text.trim().to_string(), &user);
+    match db.add_enquiry_note(objId, &scope, note.clone()).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(EnquiryNoteDTO::init(note))
+                )
+            )
+        },
+        Err(AppError::DataNotFoundError) => {
+            HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// The caller's open follow-ups due by the end of today in the academy's zone,
+// earlier ones are flagged overdue.
+pub async fn my_follow_ups(req:HttpRequest, db:Tenant<AppRepo>, scope:BranchScope) -> impl Responder {
+    let user_id = match enquiry_staff(&req).map(|user| user.id) {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse("the token does not carry a user id".to_string())
+            );
+        },
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let zone = match db.settings().await {
+        Ok(settings) => settings.timezone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+    let today = Utc::now().with_timezone(&zone).date_naive();
+    let tomorrow = today.succ_opt().unwrap_or(today);
+    let (start_of_day, end_of_day) = match (timezone::from_local(today.and_hms_opt(0, 0, 0).unwrap(), zone), timezone::from_local(tomorrow.and_hms_opt(0, 0, 0).unwrap(), zone)) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(e), _) | (_, Err(e)) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    match db.due_follow_ups(&user_id, end_of_day, &scope).await {
+        Ok(due) => {
+            let due:Vec<DueFollowUpDTO> = due.into_iter().map(|(enquiry, follow_up)| DueFollowUpDTO {
+                enquiry_id: enquiry.id.map(|id| id.to_hex()).unwrap_or_default(),
+                name: enquiry.name,
+                contact: enquiry.contact,
+                status: enquiry.status,
+                overdue: follow_up.due_at < start_of_day,
+                follow_up: EnquiryFollowUpDTO::init(follow_up),
+            }).collect();
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(due)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Starts a PENDING registration from the enquiry. The enquirer becomes the guardian
+// when the enquiry names a child, both records are linked and the enquiry is CONVERTED.
+#[allow(non_snake_case)]
+pub async fn convert_enquiry(req:HttpRequest, db:Tenant<AppRepo>, scope:BranchScope, path:Path<String>, request:Json<ConvertEnquiryDTO>) -> impl Responder {
+    let user = match enquiry_staff(&req) {
+        Ok(user) => user,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let enquiry = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => match db.get_enquiry(objId, &scope).await {
+            Ok(enquiry) => enquiry,
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        },
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    if let Some(studentId) = enquiry.student_id {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(format!("the enquiry is already converted to student {}", studentId.to_hex()))
+        );
+    }
+
+    let request = request.into_inner();
+    let class_branch = request.class_branch.clone().or(enquiry.branch.clone());
+    if let Some(branch) = class_branch.as_ref() {
+        let branchId = match ObjectId::parse_str(branch) {
+            Ok(branchId) => branchId,
+            Err(_) => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse(format!("Invalid branch id: {}", branch))
+                );
+            },
+        };
+        if let Err(e) = scope.check(std::slice::from_ref(branch)) {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        }
+        if let Err(e) = db.get_branch(branchId).await {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Branch {}", e))
+            );
+        }
+    }
+
+    let settings = match db.settings().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let studentId = ObjectId::new();
+    let student_name = request.student_name.clone().or(enquiry.student_name.clone());
+    let address = request.address.clone().unwrap_or_default();
+
+    // an adult enquiring for themselves has no guardian
+    let parent = match student_name {
+        Some(_) => {
+            let contact:String = enquiry.contact.chars().filter(|c| c.is_ascii_digit()).collect();
+            let mobile_number = match request.guardian_mobile.or(contact.parse::<i64>().ok()) {
+                Some(mobile_number) => mobile_number,
+                None => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(format!("contact {} is not a phone number, send guardian_mobile", enquiry.contact))
+                    );
+                },
+            };
+            Some(Parents {
+                student_id: Some(studentId),
+                name: enquiry.name.clone(),
+                address: address.clone(),
+                mobile_number,
+                email: enquiry.email.clone(),
+                created_at: Some(bson::DateTime::now()),
+                updated_at: Some(bson::DateTime::now()),
+            })
+        },
+        None => None,
+    };
+
+    let student = Students {
+        id: Some(studentId),
+        student_id: Some(Helper::generate_unique_number().to_string()),
+        name: student_name.unwrap_or(enquiry.name.clone()),
+        age: request.age.unwrap_or_default(),
+        date_of_birth: request.date_of_birth.unwrap_or_default(),
+        address,
+        is_active_student: true,
+        profile_pic: None,
+        profile_pic_variants: None,
+        class_branch,
+        parent,
+        // new students start on the lowest belt
+        level: settings.belts().first().map(|belt| belt.key.to_lowercase()),
+        nationality: Some(settings.registration().default_nationality),
+        blood_group: None,
+        weight: None,
+        school_name: None,
+        addhar_number: None,
+        geneder: None,
+        registration_status: Some("PENDING".to_string()),
+        enquiry_id: enquiry.id,
+        created_at: Some(bson::DateTime::now()),
+        updated_at: Some(bson::DateTime::now()),
+    };
+
+    // the enquiry is claimed first, a second conversion of it stops here
+    let enquiryId = enquiry.id.unwrap();
+    let note = enquiry_note(format!("Converted to student {}", student.name), &user);
+    let noteId = note.id;
+    match db.convert_enquiry(enquiryId, studentId, &scope, note).await {
+        Ok(result) if result.matched_count == 0 => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("the enquiry is already converted".to_string())
+            );
+        },
+        Ok(_) => {},
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    }
+
+    if let Err(e) = db.repos().student.add_student(student).await {
+        if let Err(revert) = db.revert_enquiry_conversion(enquiryId, noteId, &enquiry.status, enquiry.lost_reason.as_deref()).await {
+            println!("Could not revert the conversion of enquiry {} : {}", enquiryId, revert);
+        }
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataAddedSuccess.to_string(),
+            Some(studentId.to_hex())
+        )
+    )
+}
+
+// enquiries are worked by academy users, not by student or guest tokens
+fn enquiry_staff(req:&HttpRequest) -> Result<jwt_service::AuthUser, AppError> {
+    match jwt_service::JwtService::current_user(req) {
+        Some(user) if user.is_staff() => Ok(user),
+        _ => Err(AppError::CustomError("only academy staff can work on enquiries".to_string())),
+    }
+}
+
+fn enquiry_note(text:String, author:&jwt_service::AuthUser) -> EnquiryNote {
+    EnquiryNote {
+        id: ObjectId::new(),
+        text,
+        author: author.name.clone(),
+        created_at: bson::DateTime::now(),
+    }
+}
+
+// an active user who may work on the enquiry's branch
+#[allow(non_snake_case)]
+async fn assignable_user(db:&Tenant<AppRepo>, enquiry:&Enquiries, userId:&str) -> Result<Users, AppError> {
+    let objId = ObjectId::parse_str(userId).map_err(|_| AppError::CustomError(format!("Invalid user id: {}", userId)))?;
+    let user = match db.repos().user.get_user(objId).await {
+        Ok(user) => user,
+        Err(AppError::DataNotFoundError) => return Err(AppError::CustomError(format!("User {}", AppError::DataNotFoundError))),
+        Err(e) => return Err(e),
+    };
+
+    if !user.is_active {
+        return Err(AppError::CustomError(format!("{} is not an active user", user.name)));
+    }
+    if let (UserTypes::SUBADMIN, Some(branch)) = (&user.user_type, enquiry.branch.as_ref()) {
+        if !user.branches.contains(branch) {
+            return Err(AppError::CustomError(format!("{} does not work on the enquiry's branch", user.name)));
+        }
+    }
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::oid::ObjectId;
+
+    use crate::{config::db_config::DBConfig, helper::branch_scope::BranchScope, models::app::{BranchCapacity, Courses, EnquiryNote, EnquiryStatus, Enquiries, EnrollmentStatus, Enrollments}, repo::{app_repo::AppRepo, events_repo::EventRepo, student_repo::StudentRepo}};
+
+    fn note(text:&str) -> EnquiryNote {
+        EnquiryNote { id: ObjectId::new(), text: text.to_string(), author: "test".to_string(), created_at: bson::DateTime::now() }
+    }
+
+    // Runs against the database at MONGOURI in a throwaway database:
+    // cargo test -- --ignored
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGOURI"]
+    async fn concurrent_enrollments_take_one_seat_each() {
+        let client = DBConfig::client().await.unwrap();
+        let db = client.database(&format!("k_admin_test_{}", ObjectId::new().to_hex()));
+        let repo = AppRepo::init(db.clone(), StudentRepo::init(db.clone()), EventRepo::init(db.clone()).await).await;
+
+        let branch_id = ObjectId::new();
+        let course_id = repo.add_course(Courses {
+            id: None,
+            name: format!("Course {}", ObjectId::new().to_hex()),
+            description: "test".to_string(),
+            is_active: true,
+            course_duration: "3 months".to_string(),
+            // a capacity set before seats were counted, seeded on first use
+            branch_capacity: vec![BranchCapacity { branch_id, capacity: 1, seats_left: None }],
+            prerequisites: None,
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        }).await.unwrap().inserted_id.as_object_id().unwrap();
+
+        let make_enrollment = || Enrollments {
+            id: None,
+            student_id: ObjectId::new(),
+            course_id,
+            branch_id,
+            start_date: bson::DateTime::now(),
+            end_date: None,
+            status: EnrollmentStatus::ACTIVE.to_string(),
+            prerequisite_override_by: None,
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        };
+
+        let (first, second) = futures::join!(repo.enroll_student(make_enrollment()), repo.enroll_student(make_enrollment()));
+        let (first, second) = (first.unwrap(), second.unwrap());
+        let active = [&first, &second].into_iter().filter(|e| e.status == EnrollmentStatus::ACTIVE.to_string()).count();
+        assert_eq!(active, 1);
+
+        // closing the active one hands its seat to the waitlisted one
+        let (seated, waiting) = if first.status == EnrollmentStatus::ACTIVE.to_string() { (first, second) } else { (second, first) };
+        assert!(repo.close_enrollment(seated.id.unwrap(), EnrollmentStatus::DROPPED).await.unwrap().is_some());
+        assert!(repo.close_enrollment(seated.id.unwrap(), EnrollmentStatus::DROPPED).await.unwrap().is_none());
+        assert_eq!(repo.promote_waitlist(course_id, branch_id).await.unwrap(), vec![waiting.id.unwrap()]);
+        assert!(repo.promote_waitlist(course_id, branch_id).await.unwrap().is_empty());
+
+        // the same student twice at once is enrolled once and keeps the other seat free
+        repo.set_course_capacity(course_id, branch_id, 3).await.unwrap();
+        let student_id = ObjectId::new();
+        let (first, second) = futures::join!(
+            repo.enroll_student(Enrollments { student_id, ..make_enrollment() }),
+            repo.enroll_student(Enrollments { student_id, ..make_enrollment() })
+        );
+        assert!(first.is_ok() != second.is_ok());
+        let third = repo.enroll_student(make_enrollment()).await.unwrap();
+        assert_eq!(third.status, EnrollmentStatus::ACTIVE.to_string());
+
+        // an active enrollment keeps an inactive course
+        repo.active_course(false, course_id).await.unwrap();
+        assert!(repo.delete_course(course_id).await.is_err());
+
+        db.drop(None).await.unwrap();
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGOURI"]
+    async fn reverting_a_conversion_keeps_later_notes() {
+        let client = DBConfig::client().await.unwrap();
+        let db = client.database(&format!("k_admin_test_{}", ObjectId::new().to_hex()));
+        let repo = AppRepo::init(db.clone(), StudentRepo::init(db.clone()), EventRepo::init(db.clone()).await).await;
+
+        let enquiry_id = repo.add_enquiry(Enquiries {
+            id: None,
+            name: "Test".to_string(),
+            email: "test@example.com".to_string(),
+            contact: "9999999999".to_string(),
+            subject: "test".to_string(),
+            message: "test".to_string(),
+            branch: None,
+            status: EnquiryStatus::NEW.to_string(),
+            lost_reason: None,
+            trial_at: None,
+            source: None,
+            assigned_to: None,
+            follow_ups: Vec::new(),
+            notes: Vec::new(),
+            student_name: None,
+            student_id: None,
+            converted_at: None,
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        }).await.unwrap().inserted_id.as_object_id().unwrap();
+
+        let conversion = note("converted");
+        let conversion_id = conversion.id;
+        repo.convert_enquiry(enquiry_id, ObjectId::new(), &BranchScope::All, conversion).await.unwrap();
+        // written while the student was being saved
+        repo.add_enquiry_note(enquiry_id, &BranchScope::All, note("called back")).await.unwrap();
+        repo.revert_enquiry_conversion(enquiry_id, conversion_id, &EnquiryStatus::NEW.to_string(), None).await.unwrap();
+
+        let enquiry = repo.get_enquiry(enquiry_id, &BranchScope::All).await.unwrap();
+        assert_eq!(enquiry.notes.iter().map(|n| n.text.as_str()).collect::<Vec<_>>(), ["called back"]);
+        assert!(enquiry.student_id.is_none());
+
+        db.drop(None).await.unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+use std::env;
+
+use actix_web::{web::Path, HttpRequest, HttpResponse, Responder};
+use bson::oid::ObjectId;
+use chrono_tz::Tz;
+
+use crate::{dto::event_dto::CalendarFeedDTO, helper::{app_errors::{AppError, Messages}, branch_scope::BranchScope, crypto::Crypto, ical::{ICalEvent, ICalendar}, response::ResponseBuilder, timezone}, models::events::{Events, RegistrationStatus}, repo::events_repo::EventRepo, service::{event_service::{event_zone, to_naive}, jwt_service::JwtService}};
+use crate::helper::tenant::Tenant;
+
+// ------------------------------ FEEDS ------------------------------------- //
+// The feeds are read by calendar apps that can not send a token, so the
+// /feed routes skip authentication. The student feed is guarded by a secret
+// in its url instead. Feeds are built on every request, edits and deletions
+// show up on the next poll.
+pub async fn events_feed(db:Tenant<EventRepo>) -> impl Responder {
+    match db.feed_events(None, Vec::new()).await {
+        Ok(events) => calendar_response("Academy Events", &events, "events.ics"),
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+pub async fn branch_feed(db:Tenant<EventRepo>, path:Path<String>) -> impl Responder {
+    let branch = path.into_inner();
+    match db.feed_events(Some(&branch), Vec::new()).await {
+        Ok(events) => calendar_response("Branch Events", &events, "branch.ics"),
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Events of the student's branch plus the events the student registered for.
+#[allow(non_snake_case)]
+pub async fn student_feed(db:Tenant<EventRepo>, path:Path<(String, String)>) -> impl Responder {
+    let (student, token) = path.into_inner();
+    if !feed_token(&student).is_ok_and(|expected| Crypto::constant_time_eq(expected.as_bytes(), token.as_bytes())) {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+        );
+    }
+
+    let studentId = match ObjectId::parse_str(&student) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let student = match db.studentRepo.get_student(studentId, &BranchScope::All).await {
+        Ok(student) => student,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Student {}", e))
+            );
+        },
+    };
+
+    let filter = bson::doc! { "student_id":studentId, "status":RegistrationStatus::REGISTERED.to_string() };
+    let eventIds = match db.list_registrations(filter).await {
+        Ok(registrations) => registrations.iter().map(|r| r.event_id).collect(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let branch = student.class_branch.unwrap_or_default();
+    match db.feed_events(Some(&branch), eventIds).await {
+        Ok(events) => calendar_response(&format!("{} - Academy", student.name), &events, "student.ics"),
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Personal feed url for a student, only for staff or the student.
+pub async fn student_feed_url(req:HttpRequest, path:Path<String>) -> impl Responder {
+    let student = path.into_inner();
+    if ObjectId::parse_str(&student).is_err() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::InValidIdResponse()
+        );
+    }
+
+    if !JwtService::current_user(&req).is_some_and(|user| user.is_staff() || user.id.as_deref() == Some(student.as_str())) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse("students can only see their own calendar".to_string())
+        );
+    }
+
+    let token = match feed_token(&student) {
+        Ok(token) => token,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let feed = CalendarFeedDTO {
+        url: format!("/api/calendar/feed/student/{}/{}.ics", student, token),
+        student_id: student,
+    };
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(feed)
+        )
+    )
+}
+
+// CALENDAR_FEED_SECRET, or the JWT secret when it is not set, main refuses to
+// start without one so feed urls are never signed with an empty key
+pub fn feed_secret() -> Result<String, AppError> {
+    env::var("CALENDAR_FEED_SECRET").ok()
+        .or_else(|| env::var("Jwt_Secrete_Key").ok())
+        .filter(|secret| !secret.trim().is_empty())
+        .ok_or_else(|| AppError::CustomError("CALENDAR_FEED_SECRET is not set".to_string()))
+}
+
+// HMAC of the student id, rotating CALENDAR_FEED_SECRET revokes every feed url
+fn feed_token(student_id:&str) -> Result<String, AppError> {
+    let secret = feed_secret()?;
+    Ok(Crypto::hmac_sha256_hex(secret.as_bytes(), format!("calendar:{}", student_id).as_bytes())[..32].to_string())
+}
+
+fn calendar_response(name:&str, events:&[Events], file_name:&str) -> HttpResponse {
+    let mut calendar = ICalendar::new(name);
+    for event in events {
+        for entry in calendar_entries(event) {
+            calendar.add_event(&entry);
+        }
+    }
+    calendar.into_response(file_name)
+}
+
+// A one-off event is one VEVENT. A series is the master VEVENT with its rule,
+// cancelled and moved occurrences as EXDATEs, and one overriding VEVENT per
+// edited occurrence. Events entered in a local zone keep it as TZID.
+fn calendar_entries(event:&Events) -> Vec<ICalEvent> {
+    let zone = event_zone(event);
+    let zone_name = (zone != Tz::UTC).then(|| zone.name().to_string());
+    let time = |date:bson::DateTime| if zone_name.is_some() { timezone::to_local(date, zone) } else { to_naive(date) };
+
+    let (id, start, end) = match (event.id, event.start_date, event.end_date) {
+        (Some(id), Some(start), Some(end)) => (id, time(start), time(end)),
+        _ => return Vec::new(),
+    };
+    let uid = format!("{}@k-admin", id.to_hex());
+    let dtstamp = to_naive(event.updated_at.or(event.created_at).unwrap_or(bson::DateTime::now()));
+
+    let master = ICalEvent {
+        uid: uid.to_string(),
+        timezone: zone_name.clone(),
+        dtstamp,
+        start,
+        end,
+        summary: event.title.to_string(),
+        description: event.discription.to_string(),
+        location: event.location.to_string(),
+        rrule: event.recurrence.as_ref().map(|r| r.rrule.to_string()),
+        exdates: event.recurrence.iter()
+            .flat_map(|r| r.exceptions.iter().filter(|e| e.cancelled))
+            .map(|e| time(e.occurrence_start))
+            .collect(),
+        recurrence_id: None,
+    };
+
+    let mut entries = vec![master];
+    for exception in event.recurrence.iter().flat_map(|r| r.exceptions.iter().filter(|e| !e.cancelled)) {
+        let occurrence = time(exception.occurrence_start);
+        let occurrence_start = exception.start_date.map(time).unwrap_or(occurrence);
+        entries.push(ICalEvent {
+            uid: uid.to_string(),
+            timezone: zone_name.clone(),
+            dtstamp: exception.updated_at.map(to_naive).unwrap_or(dtstamp),
+            start: occurrence_start,
+            end: exception.end_date.map(time).unwrap_or(occurrence_start + (end - start)),
+            summary: exception.title.clone().unwrap_or(event.title.to_string()),
+            description: event.discription.to_string(),
+            location: exception.location.clone().unwrap_or(event.location.to_string()),
+            rrule: None,
+            exdates: Vec::new(),
+            recurrence_id: Some(occurrence),
+        });
+    }
+
+    entries
+}
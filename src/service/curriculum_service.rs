@@ -0,0 +1,370 @@
+use actix_web::{web::{Json, Path}, HttpRequest, HttpResponse, Responder};
+use bson::oid::ObjectId;
+use validator::Validate;
+
+use crate::{dto::{curriculum_dto::{CreateCurriculumDTO, CurriculumDTO, GradingEligibilityDTO, StudentProgressDTO, TickProgressDTO}}, helper::{app_errors::{AppError, Messages}, branch_scope::BranchScope, response::ResponseBuilder}, models::{curriculum::{Curriculums, SyllabusItem, SyllabusProgress}, settings::AcademySettings}, repo::curriculum_repo::CurriculumRepo};
+use crate::helper::tenant::Tenant;
+
+use super::jwt_service::JwtService;
+
+// ------------------------------ CURRICULUM ------------------------------------- //
+// Saves a new version of the syllabus for a course belt.
+#[allow(non_snake_case)]
+pub async fn add_curriculum(db:Tenant<CurriculumRepo>, req:HttpRequest, request:Json<CreateCurriculumDTO>) -> impl Responder {
+    let user = match JwtService::current_user(&req).filter(|user| user.is_staff()) {
+        Some(user) => user,
+        None => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse("only academy staff can change the syllabus".to_string())
+            );
+        },
+    };
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let courseId = match ObjectId::parse_str(&request.course_id) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    match db.course_exists(courseId).await {
+        Ok(true) => {},
+        Ok(false) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Course {}", AppError::DataNotFoundError))
+            );
+        },
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    }
+
+    let level = match academy_settings(&db).await.and_then(|settings| belt_key(&settings, &request.level)) {
+        Ok(level) => level,
+        Err(response) => return response,
+    };
+
+    let request = request.into_inner();
+    let items:Vec<SyllabusItem> = request.items.into_iter().map(|item| SyllabusItem {
+        item_id: ObjectId::new().to_hex(),
+        category: item.category.to_string(),
+        name: item.name.trim().to_string(),
+        description: item.description,
+    }).collect();
+
+    let curriculum = Curriculums {
+        id: None,
+        course_id: courseId,
+        level,
+        version: 0,
+        is_current: true,
+        items,
+        created_by: user.name,
+        created_at: bson::DateTime::now(),
+    };
+
+    match db.add_version(curriculum).await {
+        Ok(curriculum) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(CurriculumDTO::init(curriculum))
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// path is {course_id}/{level}
+#[allow(non_snake_case)]
+pub async fn get_curriculum(db:Tenant<CurriculumRepo>, path:Path<(String, String)>) -> impl Responder {
+    let (courseId, level) = match parse_course_level(&db, path.into_inner()).await {
+        Ok(value) => value,
+        Err(response) => return response,
+    };
+
+    match db.current_curriculum(courseId, &level).await {
+        Ok(Some(curriculum)) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(CurriculumDTO::init(curriculum))
+                )
+            )
+        },
+        Ok(None) => {
+            HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn curriculum_history(db:Tenant<CurriculumRepo>, path:Path<(String, String)>) -> impl Responder {
+    let (courseId, level) = match parse_course_level(&db, path.into_inner()).await {
+        Ok(value) => value,
+        Err(response) => return response,
+    };
+
+    match db.curriculum_history(courseId, &level).await {
+        Ok(curriculums) => {
+            let curriculum_dto:Vec<CurriculumDTO> = curriculums.into_iter().map(CurriculumDTO::init).collect();
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(curriculum_dto)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// ------------------------------ PROGRESS ------------------------------------- //
+// Ticks an item of the current syllabus of the student's belt.
+#[allow(non_snake_case)]
+pub async fn tick_progress(db:Tenant<CurriculumRepo>, req:HttpRequest, request:Json<TickProgressDTO>) -> impl Responder {
+    let user = match JwtService::current_user(&req).filter(|user| user.is_staff()) {
+        Some(user) => user,
+        None => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse("only instructors can record progress".to_string())
+            );
+        },
+    };
+
+    let (studentId, courseId, level, curriculum) = match progress_target(&db, &request).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let itemId = request.item_id.clone().unwrap();
+    if !curriculum.items.iter().any(|item| item.item_id == itemId) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("item is not part of the current syllabus".to_string())
+        );
+    }
+
+    let progress = SyllabusProgress {
+        id: None,
+        student_id: studentId,
+        course_id: courseId,
+        level,
+        curriculum_id: curriculum.id.unwrap(),
+        item_id: itemId,
+        completed_by: user.name,
+        notes: request.notes.clone(),
+        completed_at: bson::DateTime::now(),
+    };
+
+    match db.tick_item(progress).await {
+        Ok(result) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(result)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn untick_progress(db:Tenant<CurriculumRepo>, req:HttpRequest, request:Json<TickProgressDTO>) -> impl Responder {
+    if !JwtService::current_user(&req).is_some_and(|user| user.is_staff()) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse("only instructors can record progress".to_string())
+        );
+    }
+
+    let (studentId, courseId, level, _) = match progress_target(&db, &request).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    match db.untick_item(studentId, courseId, &level, request.item_id.as_ref().unwrap()).await {
+        Ok(result) => {
+            if result.deleted_count == 0 {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(Messages::DataDeleteFailed.to_string())
+                );
+            }
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataDeleteSucess.to_string(), None)
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// path is {student_id}/{course_id}
+#[allow(non_snake_case)]
+pub async fn student_progress(db:Tenant<CurriculumRepo>, path:Path<(String, String)>) -> impl Responder {
+    match syllabus_standing(&db, path.into_inner()).await {
+        Ok(standing) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(standing)
+                )
+            )
+        },
+        Err(response) => response,
+    }
+}
+
+// A student may grade to the next belt once every item of the current syllabus
+// of their belt is ticked. path is {student_id}/{course_id}
+pub async fn grading_eligibility(db:Tenant<CurriculumRepo>, path:Path<(String, String)>) -> impl Responder {
+    let standing = match syllabus_standing(&db, path.into_inner()).await {
+        Ok(standing) => standing,
+        Err(response) => return response,
+    };
+
+    let settings = match academy_settings(&db).await {
+        Ok(settings) => settings,
+        Err(response) => return response,
+    };
+    let next_level = settings.next_belt(&standing.level).map(|belt| belt.key.to_lowercase());
+
+    let eligibility = GradingEligibilityDTO {
+        eligible: next_level.is_some() && standing.completed == standing.total,
+        missing: standing.items.iter().filter(|i| !i.completed).map(|i| format!("{}: {}", i.category, i.name)).collect(),
+        student_id: standing.student_id,
+        course_id: standing.course_id,
+        current_level: standing.level,
+        next_level,
+        curriculum_version: standing.curriculum_version,
+        completed: standing.completed,
+        total: standing.total,
+    };
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(eligibility)
+        )
+    )
+}
+
+async fn academy_settings(db:&Tenant<CurriculumRepo>) -> Result<AcademySettings, HttpResponse> {
+    db.settings().await.map_err(|e| {
+        HttpResponse::InternalServerError().json(ResponseBuilder::<()>::FailedResponse(e.to_string()))
+    })
+}
+
+// levels are stored lower cased, the same way students keep theirs
+fn belt_key(settings:&AcademySettings, level:&str) -> Result<String, HttpResponse> {
+    settings.level_key(level).ok_or_else(|| {
+        HttpResponse::BadRequest().json(ResponseBuilder::<()>::FailedResponse(format!("Invalid Student Level: {}", level)))
+    })
+}
+
+#[allow(non_snake_case)]
+async fn parse_course_level(db:&Tenant<CurriculumRepo>, (courseId, level):(String, String)) -> Result<(ObjectId, String), HttpResponse> {
+    let courseId = ObjectId::parse_str(courseId).map_err(|_| {
+        HttpResponse::BadRequest().json(ResponseBuilder::<()>::InValidIdResponse())
+    })?;
+
+    let settings = academy_settings(db).await?;
+    Ok((courseId, belt_key(&settings, &level)?))
+}
+
+// the student, course, belt and current syllabus a progress request refers to
+#[allow(non_snake_case)]
+async fn progress_target(db:&Tenant<CurriculumRepo>, request:&TickProgressDTO) -> Result<(ObjectId, ObjectId, String, Curriculums), HttpResponse> {
+    if let Err(e) = request.validate() {
+        return Err(HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        ));
+    }
+
+    let (studentId, courseId) = match (
+        ObjectId::parse_str(request.student_id.as_ref().unwrap()),
+        ObjectId::parse_str(request.course_id.as_ref().unwrap())
+    ) {
+        (Ok(studentId), Ok(courseId)) => (studentId, courseId),
+        _ => return Err(HttpResponse::BadRequest().json(ResponseBuilder::<()>::InValidIdResponse())),
+    };
+
+    let (level, curriculum) = student_curriculum(db, studentId, courseId).await?;
+    Ok((studentId, courseId, level, curriculum))
+}
+
+#[allow(non_snake_case)]
+async fn student_curriculum(db:&Tenant<CurriculumRepo>, studentId:ObjectId, courseId:ObjectId) -> Result<(String, Curriculums), HttpResponse> {
+    let student = db.studentRepo.get_student(studentId, &BranchScope::All).await.map_err(|e| {
+        HttpResponse::NotFound().json(ResponseBuilder::<()>::FailedResponse(format!("Student {}", e)))
+    })?;
+
+    let settings = academy_settings(db).await?;
+    let level = match student.level.as_deref().and_then(|level| settings.level_key(level)) {
+        Some(level) => level,
+        None => return Err(HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("student has no belt level".to_string())
+        )),
+    };
+
+    match db.current_curriculum(courseId, &level).await {
+        Ok(Some(curriculum)) => Ok((level, curriculum)),
+        Ok(None) => Err(HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("no syllabus defined for {} belt on this course", level))
+        )),
+        Err(e) => Err(HttpResponse::InternalServerError().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        )),
+    }
+}
+
+#[allow(non_snake_case)]
+async fn syllabus_standing(db:&Tenant<CurriculumRepo>, (studentId, courseId):(String, String)) -> Result<StudentProgressDTO, HttpResponse> {
+    let (studentId, courseId) = match (ObjectId::parse_str(studentId), ObjectId::parse_str(courseId)) {
+        (Ok(studentId), Ok(courseId)) => (studentId, courseId),
+        _ => return Err(HttpResponse::BadRequest().json(ResponseBuilder::<()>::InValidIdResponse())),
+    };
+
+    let (level, curriculum) = student_curriculum(db, studentId, courseId).await?;
+
+    match db.student_progress(studentId, courseId, &level).await {
+        Ok(progress) => Ok(StudentProgressDTO::init(studentId.to_hex(), &curriculum, &progress)),
+        Err(e) => Err(HttpResponse::InternalServerError().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        )),
+    }
+}
@@ -1,45 +1,41 @@
-use std:: io::Write;
 use actix_multipart::Multipart;
-use actix_web::{web::{Data, Json, Path}, Handler, HttpResponse, Responder};
-use bson::oid::ObjectId;
-use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
-use futures::{StreamExt, TryStreamExt};
-use crate::{dto::event_dto::{CreateEventDTO, CreateFileDataDTO, GetEventsDTO, UpdateEventDTO}, helper::{app_errors::{AppError, Messages}, response::ResponseBuilder}, models::events::{Events, FileData}, repo::events_repo::EventRepo};
+use actix_web::{web::{Json, Path, Query}, HttpRequest, HttpResponse, Responder};
+use bson::{doc, oid::ObjectId};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use validator::Validate;
+use crate::{dto::{event_dto::{CancelOccurrenceDTO, CaptionMediaDTO, CreateEventDTO, CreateFileDataDTO, EventRegistrationDTO, EventRegistrationSettingsDTO, GetEventsDTO, GetFileData, ReorderMediaDTO, OccurrenceDTO, OccurrenceQueryDTO, ParticipantDTO, ParticipantQueryDTO, RecurrenceDTO, RegisterEventDTO, StudentEventDTO, UpdateEventDTO, UpdateOccurrenceDTO}}, helper::{app_errors::{AppError, Messages}, branch_scope::BranchScope, csv::CsvBuilder, response::ResponseBuilder, rrule::RRule, timezone, upload, video_link}, models::{events::{EventRegistrations, Events, FileData, OccurrenceException, Recurrence, RegistrationSettings, RegistrationStatus}, money::{request_currency, Money}, settings::{lookup_key, AcademySettings}, student_model::Students}, repo::events_repo::EventRepo, service::jwt_service::JwtService};
+use crate::helper::tenant::Tenant;
 
 
-pub async fn add_event(db:Data<EventRepo>, request:Json<CreateEventDTO>) -> impl Responder {
-    let start_date =  match NaiveDateTime::parse_from_str(&request.start_date, "%Y%m%d%H%M") {
-        Ok(datetime) => {
-            let datetime_utc: DateTime<Utc> = chrono::TimeZone::from_utc_datetime(&Utc, &datetime);
-            let bd = mongodb::bson::DateTime::builder().year(datetime_utc.year()).month(datetime_utc.month().try_into().unwrap()).day(datetime_utc.day().try_into().unwrap()).build().unwrap();
-            bd
-        },
-        Err(_) => {
-           return  HttpResponse::BadRequest().json(
-                ResponseBuilder::<()>::FailedResponse("Invalid event date".to_string())
+pub async fn add_event(db:Tenant<EventRepo>, scope:BranchScope, request:Json<CreateEventDTO>) -> impl Responder {
+    let zone = match event_timezone(&db, request.timezone.as_deref(), None, &request.branches).await {
+        Ok(zone) => zone,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
             );
         },
     };
 
-    let end_date = match NaiveDateTime::parse_from_str(&request.end_date, "%Y%m%d%H%M") {
-        Ok(datetime) => {
-            let datetime_utc: DateTime<Utc> = chrono::TimeZone::from_utc_datetime(&Utc, &datetime);
-            let bd = mongodb::bson::DateTime::builder().year(datetime_utc.year()).month(datetime_utc.month().try_into().unwrap()).day(datetime_utc.day().try_into().unwrap()).build().unwrap();
-            bd
-        },
-        Err(_) => {
-            return  HttpResponse::BadRequest().json(
-                ResponseBuilder::<()>::FailedResponse("Invalid event date".to_string())
+    let (start_date, end_date) = match event_dates(&request.start_date, &request.end_date, zone) {
+        Ok(dates) => dates,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
             );
         },
     };
 
-    if end_date < start_date {
-        return HttpResponse::BadRequest().json(
-            ResponseBuilder::<()>::FailedResponse("end date should be next date of start date".to_string())
-        );
-    }
-
+    let recurrence = match request.rrule.as_deref().map(|rule| RRule::parse(rule).and_then(|rule| stored_rule(rule, zone))) {
+        Some(Ok(rule)) => Some(Recurrence { rrule: rule.to_string(), exceptions: Vec::new() }),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+        None => None,
+    };
     
     let event = Events {
         id: None,
@@ -49,12 +45,17 @@ pub async fn add_event(db:Data<EventRepo>, request:Json<CreateEventDTO>) -> impl
         file_data: None,
         start_date: Some(start_date),
         end_date: Some(end_date),
+        timezone: Some(zone.name().to_string()),
         created_at: Some(bson::DateTime::now()),
         updated_at: Some(bson::DateTime::now()),
         is_active: Some(true),
+        branches: request.branches.clone(),
+        registration: None,
+        registered_count: Some(0),
+        recurrence,
     };
 
-    match db.add_event(event).await {
+    match db.add_event(event, &scope).await {
         Ok(_) => {
            HttpResponse::Ok().json(
             ResponseBuilder::<()>::SuccessResponse(
@@ -71,80 +72,62 @@ pub async fn add_event(db:Data<EventRepo>, request:Json<CreateEventDTO>) -> impl
     }
 }
 
+// Uploads one or more files to the gallery. `file_type` and `caption` text
+// parts apply to all of them, the type defaults to image or video.
 #[allow(non_snake_case)]
-pub async fn add_file_data(db:Data<EventRepo>, path:Path<String>, mut payload:Multipart) -> impl Responder {
+pub async fn add_file_data(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>, payload:Multipart) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
-            let mut file_type: Option<String> = None;
-            let mut file_data: Option<Vec<u8>> = None;
-            let mut filename: Option<String> = None;
-
-            while let Ok(Some(mut field)) = payload.try_next().await {
-                let cd = field.content_disposition();
-                let name = cd.get_name().unwrap();
-
-                if name == "file_type" {
-                    let mut data = Vec::new();
-                    while let Some(chunk) = field.next().await {
-                        data.extend_from_slice(&chunk.unwrap())
-                    }
-                    file_type = Some(String::from_utf8_lossy(&data).to_string())
-                }else {
-                    filename = cd.get_filename().map(|f| f.to_string());
-                    let mut data = Vec::new();
-                    while let Some(chunk) = field.next().await {
-                        data.extend_from_slice(&chunk.unwrap());
-                    }
-                    file_data = Some(data);
-                }
+            let form = match upload::save_multipart(payload, &upload::EVENT_MEDIA).await {
+                Ok(form) => form,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    );
+                },
+            };
+
+            if form.files.is_empty() {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse("at least one file is required".to_string())
+                );
             }
 
-            // prepare for the request
-            let file_path = format!("/static/event/{}", filename.unwrap()); 
+            let caption = form.fields.get("caption").filter(|c| !c.is_empty()).cloned();
+            let fileData: Vec<FileData> = form.files.iter().map(|file| FileData {
+                id: Some(ObjectId::new()),
+                file_type: form.fields.get("file_type").filter(|t| !t.is_empty()).cloned().unwrap_or(file.kind().to_string()),
+                file_path: file.key.to_string(),
+                caption: caption.clone(),
+                is_cover: false,
+                embed: None,
+                variants: file.variants.clone(),
+                created_at: Some(bson::DateTime::now()),
+            }).collect();
 
-            match std::fs::File::create(format!(".{}", file_path)){
-                Ok(mut file) => {
-                    let err = file.write_all(&file_data.unwrap());
+            match db.add_file_data(objId, fileData.clone(), &scope).await {
+                Ok(result) => {
+                    if result.matched_count == 0 {
+                        form.discard().await;
+                        return HttpResponse::NotFound().json(
+                            ResponseBuilder::<()>::FailedResponse(format!("Event {}", AppError::DataNotFoundError))
+                        );
+                    }
 
-                    if err.is_err() {
-                        return HttpResponse::BadRequest().json(
-                            ResponseBuilder::<()>::FailedResponse(
-                                Messages::DataUpdateFailed.to_string()
-                            )
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::SuccessResponse(
+                            Messages::DataUpdateSuccess.to_string(),
+                            Some(fileData.into_iter().map(GetFileData::init).collect::<Vec<_>>())
                         )
-                    };
-                    
-
-                    let fileData = FileData {
-                        file_type: file_type.unwrap(),
-                        file_path:file_path.clone(),
-                        created_at: Some(bson::DateTime::now()),
-                    };
-        
-                    match db.add_file_data(objId, fileData).await {
-                        Ok(_) => {
-                            HttpResponse::Ok().json(
-                                ResponseBuilder::<()>::SuccessResponse(
-                                    Messages::DataUpdateSuccess.to_string(),
-                                    None
-                                )
-                            )
-                        },
-                        Err(e) => {
-                            _ = std::fs::remove_file(format!(".{}", file_path));
-                            HttpResponse::BadRequest().json(
-                                ResponseBuilder::<()>::FailedResponse(e.to_string())
-                            )
-                        },
-                    }
+                    )
                 },
                 Err(e) => {
+                    form.discard().await;
                     HttpResponse::BadRequest().json(
                         ResponseBuilder::<()>::FailedResponse(e.to_string())
                     )
                 },
-            } 
-
+            }
         },
         Err(_) => {
             HttpResponse::BadRequest().json(
@@ -154,9 +137,9 @@ pub async fn add_file_data(db:Data<EventRepo>, path:Path<String>, mut payload:Mu
     }
 }
 
-pub async fn get_events(db:Data<EventRepo>, path:Path<(i64, i64)>) -> impl Responder {
+pub async fn get_events(db:Tenant<EventRepo>, scope:BranchScope, path:Path<(i64, i64)>) -> impl Responder {
     let (skip,limit) = path.into_inner();
-    match db.get_events(skip, limit).await {
+    match db.get_events(skip, limit, &scope).await {
         Ok(events) => {
             if events.len() == 0 {
                 return HttpResponse::NotFound().json(
@@ -186,17 +169,30 @@ pub async fn get_events(db:Data<EventRepo>, path:Path<(i64, i64)>) -> impl Respo
 }
 
 #[allow(non_snake_case)]
-pub async fn add_video_link(db:Data<EventRepo>, path:Path<String>, requestData:Json<CreateFileDataDTO>) -> impl Responder {
+pub async fn add_video_link(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>, requestData:Json<CreateFileDataDTO>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
+            let embed = match video_link::parse_video_link(&requestData.file_path) {
+                Ok(embed) => embed,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    );
+                },
+            };
 
             let fileData = FileData {
+                id: Some(ObjectId::new()),
                 file_type: requestData.file_type.to_owned(),
-                file_path: requestData.file_path.to_owned(),
+                file_path: requestData.file_path.trim().to_owned(),
+                caption: requestData.caption.clone().filter(|c| !c.is_empty()),
+                is_cover: false,
+                embed: Some(embed),
+                variants: None,
                 created_at: Some(bson::DateTime::now()),
             };
 
-            match db.add_file_data(objId, fileData).await {
+            match db.add_file_data(objId, vec![fileData.clone()], &scope).await {
                 Ok(updateResult) => {
                     if updateResult.matched_count == 0 {
                         return HttpResponse::NotFound().json(
@@ -205,9 +201,9 @@ pub async fn add_video_link(db:Data<EventRepo>, path:Path<String>, requestData:J
                     }
 
                     HttpResponse::Ok().json(
-                        ResponseBuilder::<()>::SuccessResponse(
+                        ResponseBuilder::SuccessResponse(
                             Messages::DataUpdateSuccess.to_string(),
-                            None,
+                            Some(GetFileData::init(fileData)),
                         )
                     )
                 },
@@ -227,10 +223,10 @@ pub async fn add_video_link(db:Data<EventRepo>, path:Path<String>, requestData:J
 } 
 
 #[allow(non_snake_case)]
-pub async fn get_event(db:Data<EventRepo>, path:Path<String>) -> impl Responder {
+pub async fn get_event(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
-            match db.get_event(objId).await {
+            match db.get_event(objId, &scope).await {
                 Ok(event) => {
                     let evenet_dto = GetEventsDTO::init(event);
                     HttpResponse::Ok().json(
@@ -256,10 +252,10 @@ pub async fn get_event(db:Data<EventRepo>, path:Path<String>) -> impl Responder
 }
 
 #[allow(non_snake_case)]
-pub async fn delete_event(db:Data<EventRepo>, path:Path<String>) -> impl Responder {
+pub async fn delete_event(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) =>{
-            match db.delete_event(objId).await {
+            match db.delete_event(objId, &scope).await {
                 Ok(result) =>{
                     if result.deleted_count == 0 {
                         return HttpResponse::BadRequest().json(
@@ -290,37 +286,38 @@ pub async fn delete_event(db:Data<EventRepo>, path:Path<String>) -> impl Respond
 }
 
 #[allow(non_snake_case)]
-pub async fn update_event(db:Data<EventRepo>, path:Path<String>, mut request:Json<UpdateEventDTO>) -> impl Responder {
+pub async fn update_event(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>, request:Json<UpdateEventDTO>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
-            let start_date =  match NaiveDateTime::parse_from_str(&request.start_date, "%Y%m%d%H%M") {
-                Ok(datetime) => {
-                    let datetime_utc: DateTime<Utc> = chrono::TimeZone::from_utc_datetime(&Utc, &datetime);
-                    datetime_utc
-                },
-                Err(_) => {
-                   return  HttpResponse::BadRequest().json(
-                        ResponseBuilder::<()>::FailedResponse("Invalid event date".to_string())
+            let event = match db.get_event(objId, &scope).await {
+                Ok(event) => event,
+                Err(e) => {
+                    return HttpResponse::NotFound().json(
+                        ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
                     );
                 },
             };
-        
-            let end_date = match NaiveDateTime::parse_from_str(&request.end_date, "%Y%m%d%H%M") {
-                Ok(datetime) => {
-                    let datetime_utc: DateTime<Utc> = chrono::TimeZone::from_utc_datetime(&Utc, &datetime);
-                    datetime_utc
-                },
-                Err(_) => {
-                    return  HttpResponse::BadRequest().json(
-                        ResponseBuilder::<()>::FailedResponse("Invalid event date".to_string())
+
+            let branches = request.branches.as_ref().unwrap_or(&event.branches);
+            let zone = match event_timezone(&db, request.timezone.as_deref(), event.timezone.as_deref(), branches).await {
+                Ok(zone) => zone,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
                     );
                 },
             };
 
-            request.start_date = start_date.to_string();
-            request.end_date = end_date.to_string();
+            let (start_date, end_date) = match event_dates(&request.start_date, &request.end_date, zone) {
+                Ok(dates) => dates,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    );
+                },
+            };
 
-            match db.update_event(objId, request.into_inner()).await {
+            match db.update_event(objId, request.into_inner(), start_date, end_date, zone.name().to_string(), &scope).await {
                 Ok(result) => {
                     if result.matched_count == 0 {
                         return HttpResponse::BadRequest().json(
@@ -330,7 +327,7 @@ pub async fn update_event(db:Data<EventRepo>, path:Path<String>, mut request:Jso
 
                     HttpResponse::Ok().json(
                         ResponseBuilder::<()>::SuccessResponse(
-                            Messages::DataDeleteSucess.to_string(),
+                            Messages::DataUpdateSuccess.to_string(),
                             None
                         )
                     )
@@ -351,8 +348,33 @@ pub async fn update_event(db:Data<EventRepo>, path:Path<String>, mut request:Jso
     }
 }
 
-pub async fn total_event(db:Data<EventRepo>) -> impl Responder {
-    match db.total_event().await {
+// The zone sent with the request, else the zone already on the event, else
+// the zone of the first branch that has one, else the academy's zone.
+async fn event_timezone(db:&Tenant<EventRepo>, requested:Option<&str>, current:Option<&str>, branches:&[String]) -> Result<Tz, AppError> {
+    if let Some(name) = requested.or(current) {
+        return timezone::parse_timezone(name);
+    }
+
+    for branch in branches {
+        if let Some(name) = db.branch_timezone(branch).await? {
+            return timezone::parse_timezone(&name);
+        }
+    }
+
+    Ok(db.settings().await?.timezone())
+}
+
+fn event_dates(start:&str, end:&str, zone:Tz) -> Result<(bson::DateTime, bson::DateTime), AppError> {
+    let start_date = timezone::parse_datetime(start, zone)?;
+    let end_date = timezone::parse_datetime(end, zone)?;
+    if end_date <= start_date {
+        return Err(AppError::CustomError("end date should be after the start date".to_string()));
+    }
+    Ok((start_date, end_date))
+}
+
+pub async fn total_event(db:Tenant<EventRepo>, scope:BranchScope) -> impl Responder {
+    match db.total_event(&scope).await {
         Ok(count) => {
             HttpResponse::Ok().json(
                 ResponseBuilder::SuccessResponse(
@@ -367,4 +389,1148 @@ pub async fn total_event(db:Data<EventRepo>) -> impl Responder {
             )
         },
     }
-}
\ No newline at end of file
+}
+
+// ------------------------------ REGISTRATION ------------------------------------- //
+#[allow(non_snake_case)]
+pub async fn set_event_registration(db:Tenant<EventRepo>, scope:BranchScope, req:HttpRequest, path:Path<String>, request:Json<EventRegistrationSettingsDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let eventId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let zone = match db.get_event(eventId, &scope).await {
+        Ok(event) => event_zone(&event),
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
+            );
+        },
+    };
+
+    let academy = match db.settings().await {
+        Ok(academy) => academy,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let settings = match registration_settings(&request, zone, &academy) {
+        Ok(settings) => settings,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    match db.set_registration(eventId, settings, &scope).await {
+        Ok(result) => {
+            if result.matched_count == 0 {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(format!("Event {}", AppError::DataNotFoundError))
+                );
+            }
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Staff register any student, a student login only registers itself.
+#[allow(non_snake_case)]
+pub async fn register_for_event(db:Tenant<EventRepo>, scope:BranchScope, req:HttpRequest, path:Path<String>, request:Json<RegisterEventDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let (eventId, studentId) = match (ObjectId::parse_str(path.into_inner()), ObjectId::parse_str(&request.student_id)) {
+        (Ok(eventId), Ok(studentId)) => (eventId, studentId),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let user = match JwtService::current_user(&req) {
+        Some(user) if user.is_staff() || user.id.as_deref() == Some(request.student_id.as_str()) => user,
+        _ => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse("students can only register themselves".to_string())
+            );
+        },
+    };
+
+    let event = match db.get_event(eventId, &scope).await {
+        Ok(event) => event,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
+            );
+        },
+    };
+
+    let settings = match event.registration.as_ref() {
+        Some(settings) if event.is_active != Some(false) => settings,
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("event is not open for registration".to_string())
+            );
+        },
+    };
+
+    if let Err(e) = registration_window(&event, settings) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let student = match db.studentRepo.get_student(studentId, &scope).await {
+        Ok(student) => student,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Student {}", e))
+            );
+        },
+    };
+
+    let today = Utc::now().with_timezone(&event_zone(&event)).date_naive();
+    let unmet = unmet_eligibility(settings, &student, today);
+    if !unmet.is_empty() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(format!("student is not eligible: {}", unmet.join(", ")))
+        );
+    }
+
+    let fee = settings.fee.clone().filter(|fee| fee.amount_minor > 0);
+    let registration = EventRegistrations {
+        id: None,
+        event_id: eventId,
+        student_id: studentId,
+        status: RegistrationStatus::REGISTERED.to_string(),
+        fee_paid: fee.is_none(),
+        fee,
+        registered_by: user.name,
+        created_at: bson::DateTime::now(),
+        updated_at: bson::DateTime::now(),
+    };
+
+    // the place is taken against the capacity atomically, a full event is refused
+    match db.register_student(registration, settings.capacity).await {
+        Ok(registration) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(EventRegistrationDTO::init(registration))
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn cancel_event_registration(db:Tenant<EventRepo>, scope:BranchScope, req:HttpRequest, path:Path<String>) -> impl Responder {
+    let registrationId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let registration = match db.get_registration(registrationId).await {
+        Ok(registration) => registration,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if let Err(e) = db.get_event(registration.event_id, &scope).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
+        );
+    }
+
+    let studentId = registration.student_id.to_hex();
+    if !JwtService::current_user(&req).is_some_and(|user| user.is_staff() || user.id.as_deref() == Some(studentId.as_str())) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse("students can only cancel their own registration".to_string())
+        );
+    }
+
+    if registration.status != RegistrationStatus::REGISTERED.to_string() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("registration is already cancelled".to_string())
+        );
+    }
+
+    match db.cancel_registration(registrationId).await {
+        Ok(false) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("registration is already cancelled".to_string())
+            )
+        },
+        Ok(true) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn mark_registration_paid(db:Tenant<EventRepo>, scope:BranchScope, req:HttpRequest, path:Path<String>) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let registrationId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let registration = match db.get_registration(registrationId).await {
+        Ok(registration) => registration,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if let Err(e) = db.get_event(registration.event_id, &scope).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
+        );
+    }
+
+    match db.update_registration(registrationId, doc! { "fee_paid":true }).await {
+        Ok(result) => {
+            if result.matched_count == 0 {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+                );
+            }
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Participant list for admins, ?format=csv exports it.
+#[allow(non_snake_case)]
+pub async fn event_participants(db:Tenant<EventRepo>, scope:BranchScope, req:HttpRequest, path:Path<String>, query:Query<ParticipantQueryDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let eventId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    if let Err(e) = db.get_event(eventId, &scope).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
+        );
+    }
+
+    let mut filter = doc! { "event_id":eventId };
+    if !query.all {
+        filter.insert("status", RegistrationStatus::REGISTERED.to_string());
+    }
+
+    let registrations = match db.list_registrations(filter).await {
+        Ok(registrations) => registrations,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let mut participants:Vec<ParticipantDTO> = Vec::new();
+    for registration in registrations {
+        // a deleted student still shows up so the list matches the head count
+        let student = db.studentRepo.get_student(registration.student_id, &BranchScope::All).await.ok();
+        participants.push(ParticipantDTO {
+            registration_id: registration.id.map(|id| id.to_hex()).unwrap_or_default(),
+            student_id: registration.student_id.to_hex(),
+            name: student.as_ref().map(|s| s.name.to_string()).unwrap_or_default(),
+            level: student.as_ref().and_then(|s| s.level.clone()).unwrap_or_default(),
+            age: student.as_ref().map(|s| s.age).unwrap_or_default(),
+            branch: student.as_ref().and_then(|s| s.class_branch.clone()).unwrap_or_default(),
+            status: registration.status,
+            fee_paid: registration.fee_paid,
+            registered_at: registration.created_at.to_string(),
+        });
+    }
+
+    if query.is_csv() {
+        let mut csv = CsvBuilder::new(&["Registration Id", "Student Id", "Name", "Level", "Age", "Branch", "Status", "Fee Paid", "Registered At"]);
+        for p in participants {
+            csv.add_row(vec![p.registration_id, p.student_id, p.name, p.level, p.age.to_string(), p.branch, p.status, p.fee_paid.to_string(), p.registered_at]);
+        }
+        return csv.into_response("event-participants.csv");
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(participants)
+        )
+    )
+}
+
+// Events a student is registered for, shown on the student profile.
+#[allow(non_snake_case)]
+pub async fn student_events(db:Tenant<EventRepo>, scope:BranchScope, req:HttpRequest, path:Path<String>) -> impl Responder {
+    let student = path.into_inner();
+    let studentId = match ObjectId::parse_str(&student) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    if !JwtService::current_user(&req).is_some_and(|user| user.is_staff() || user.id.as_deref() == Some(student.as_str())) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse("students can only see their own events".to_string())
+        );
+    }
+
+    if let Err(e) = db.studentRepo.get_student(studentId, &scope).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Student {}", e))
+        );
+    }
+
+    let filter = doc! { "student_id":studentId, "status":RegistrationStatus::REGISTERED.to_string() };
+    let registrations = match db.list_registrations(filter).await {
+        Ok(registrations) => registrations,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let events = match db.get_events_by_ids(registrations.iter().map(|r| r.event_id).collect()).await {
+        Ok(events) => events,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let mut registrations = registrations;
+    let mut student_events:Vec<StudentEventDTO> = Vec::new();
+    for event in events {
+        if let Some(index) = registrations.iter().position(|r| Some(r.event_id) == event.id) {
+            student_events.push(StudentEventDTO {
+                registration: EventRegistrationDTO::init(registrations.swap_remove(index)),
+                event: GetEventsDTO::init(event),
+            });
+        }
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(student_events)
+        )
+    )
+}
+
+fn registration_settings(request:&EventRegistrationSettingsDTO, zone:Tz, academy:&AcademySettings) -> Result<RegistrationSettings, AppError> {
+    let opens_at = request.opens_at.as_deref().map(|date| timezone::parse_datetime(date, zone)).transpose()?;
+    let closes_at = request.closes_at.as_deref().map(|date| timezone::parse_datetime(date, zone)).transpose()?;
+    if let (Some(opens_at), Some(closes_at)) = (opens_at, closes_at) {
+        if closes_at <= opens_at {
+            return Err(AppError::CustomError("registration should close after it opens".to_string()));
+        }
+    }
+
+    if let (Some(min_age), Some(max_age)) = (request.min_age, request.max_age) {
+        if min_age > max_age {
+            return Err(AppError::CustomError("min_age can not be above max_age".to_string()));
+        }
+    }
+
+    let fee = match request.fee.as_deref() {
+        Some(fee) => Some(Money::parse(fee, &request_currency(request.currency.as_deref(), academy.currency())?)?),
+        None => None,
+    };
+
+    let mut levels:Vec<String> = Vec::new();
+    for level in request.levels.iter() {
+        match academy.level_key(level) {
+            Some(level) => levels.push(level),
+            None => return Err(AppError::CustomError(format!("Invalid Student Level: {}", level))),
+        }
+    }
+
+    Ok(RegistrationSettings {
+        capacity: request.capacity,
+        opens_at,
+        closes_at,
+        fee,
+        levels,
+        min_age: request.min_age,
+        max_age: request.max_age,
+        branches: request.branches.clone(),
+    })
+}
+
+// registration closes when the event starts unless an earlier close is set
+fn registration_window(event:&Events, settings:&RegistrationSettings) -> Result<(), AppError> {
+    let now = bson::DateTime::now();
+    if settings.opens_at.is_some_and(|opens_at| now < opens_at) {
+        return Err(AppError::CustomError("registration has not opened yet".to_string()));
+    }
+    if settings.closes_at.or(event.start_date).is_some_and(|closes_at| now >= closes_at) {
+        return Err(AppError::CustomError("registration is closed".to_string()));
+    }
+    Ok(())
+}
+
+// human readable list of the filters the student fails, empty when eligible
+fn unmet_eligibility(settings:&RegistrationSettings, student:&Students, today:NaiveDate) -> Vec<String> {
+    let mut unmet:Vec<String> = Vec::new();
+    let age = student.age_on(today);
+
+    if !settings.levels.is_empty() {
+        let level = student.level.as_deref().map(lookup_key).filter(|level| !level.is_empty());
+        if !settings.levels.iter().any(|l| Some(lookup_key(l)) == level) {
+            unmet.push(format!("belt {}", settings.levels.join(" or ")));
+        }
+    }
+    if let Some(min_age) = settings.min_age {
+        if age < min_age {
+            unmet.push(format!("at least {} years old", min_age));
+        }
+    }
+    if let Some(max_age) = settings.max_age {
+        if age > max_age {
+            unmet.push(format!("at most {} years old", max_age));
+        }
+    }
+    if !settings.branches.is_empty() && !student.class_branch.as_ref().is_some_and(|b| settings.branches.contains(b)) {
+        unmet.push(format!("branch {}", settings.branches.join(" or ")));
+    }
+
+    unmet
+}
+
+// ------------------------------ RECURRENCE ------------------------------------- //
+// Sets or clears the rule of an event. Exceptions of occurrences the new rule
+// still has are kept, the others are dropped.
+#[allow(non_snake_case)]
+pub async fn set_event_recurrence(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>, request:Json<RecurrenceDTO>) -> impl Responder {
+    let eventId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let event = match db.get_event(eventId, &scope).await {
+        Ok(event) => event,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
+            );
+        },
+    };
+
+    let zone = event_zone(&event);
+    let recurrence = match request.rrule.as_deref().map(|rule| RRule::parse(rule).and_then(|rule| stored_rule(rule, zone))) {
+        Some(Ok(rule)) => Some(Recurrence {
+            rrule: rule.to_string(),
+            exceptions: event.recurrence.map(|r| r.exceptions).unwrap_or_default()
+                .into_iter()
+                .filter(|exception| event.start_date.is_some_and(|start| series_includes(&rule, start, zone, to_naive(exception.occurrence_start))))
+                .collect(),
+        }),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+        None => None,
+    };
+
+    match db.set_recurrence(eventId, recurrence, &scope).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Every occurrence between two dates with series expanded and exceptions applied.
+pub async fn event_occurrences(db:Tenant<EventRepo>, scope:BranchScope, query:Query<OccurrenceQueryDTO>) -> impl Responder {
+    let (from, to) = match (NaiveDate::parse_from_str(&query.from, "%Y%m%d"), NaiveDate::parse_from_str(&query.to, "%Y%m%d")) {
+        (Ok(from), Ok(to)) if from <= to && (to - from).num_days() <= 366 => (from.and_time(Default::default()), (to + Duration::days(1)).and_time(Default::default())),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("from and to should be dates (YYYYMMDD) at most a year apart".to_string())
+            );
+        },
+    };
+
+    let events = match db.events_between(from_naive(from), from_naive(to), &scope).await {
+        Ok(events) => events,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let mut occurrences:Vec<(NaiveDateTime, OccurrenceDTO)> = Vec::new();
+    for event in events.iter() {
+        occurrences.extend(expand_event(event, from, to));
+    }
+    occurrences.sort_by_key(|(start, _)| *start);
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(occurrences.into_iter().map(|(_, occurrence)| occurrence).collect::<Vec<_>>())
+        )
+    )
+}
+
+// Moves or renames one occurrence. Editing a cancelled occurrence restores it.
+#[allow(non_snake_case)]
+pub async fn update_occurrence(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>, request:Json<UpdateOccurrenceDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let (eventId, occurrence, previous, duration, zone) = match series_occurrence(&db, &scope, path.into_inner(), &request.occurrence).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let start_date = match request.start_date.as_deref().map(|date| timezone::parse_datetime(date, zone)) {
+        Some(Ok(start_date)) => Some(to_naive(start_date)),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+        None => previous.as_ref().and_then(|p| p.start_date).map(to_naive),
+    };
+    let end_date = match request.end_date.as_deref().map(|date| timezone::parse_datetime(date, zone)) {
+        Some(Ok(end_date)) => Some(to_naive(end_date)),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+        None if request.start_date.is_some() => start_date.map(|start| start + duration),
+        None => previous.as_ref().and_then(|p| p.end_date).map(to_naive),
+    };
+
+    let effective_start = start_date.unwrap_or(occurrence);
+    let effective_end = end_date.unwrap_or(effective_start + duration);
+    if effective_end <= effective_start {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("end date should be after the start date".to_string())
+        );
+    }
+
+    let exception = OccurrenceException {
+        occurrence_start: from_naive(occurrence),
+        cancelled: false,
+        title: request.title.clone().or(previous.as_ref().and_then(|p| p.title.clone())),
+        location: request.location.clone().or(previous.as_ref().and_then(|p| p.location.clone())),
+        start_date: (start_date.is_some() || end_date.is_some()).then(|| from_naive(effective_start)),
+        end_date: (start_date.is_some() || end_date.is_some()).then(|| from_naive(effective_end)),
+        updated_at: Some(bson::DateTime::now()),
+    };
+
+    save_occurrence_exception(&db, &scope, eventId, exception).await
+}
+
+#[allow(non_snake_case)]
+pub async fn cancel_occurrence(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>, request:Json<CancelOccurrenceDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let (eventId, occurrence, previous, _, _) = match series_occurrence(&db, &scope, path.into_inner(), &request.occurrence).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let exception = match previous {
+        Some(previous) => OccurrenceException { cancelled: true, updated_at: Some(bson::DateTime::now()), ..previous },
+        None => OccurrenceException {
+            occurrence_start: from_naive(occurrence),
+            cancelled: true,
+            title: None,
+            location: None,
+            start_date: None,
+            end_date: None,
+            updated_at: Some(bson::DateTime::now()),
+        },
+    };
+
+    save_occurrence_exception(&db, &scope, eventId, exception).await
+}
+
+#[allow(non_snake_case)]
+async fn save_occurrence_exception(db:&EventRepo, scope:&BranchScope, eventId:ObjectId, exception:OccurrenceException) -> HttpResponse {
+    match db.set_occurrence_exception(eventId, exception, scope).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// resolves an occurrence key of a series to the event, the occurrence start,
+// its current exception, the duration of an occurrence and the event timezone
+#[allow(non_snake_case)]
+async fn series_occurrence(db:&EventRepo, scope:&BranchScope, eventId:String, occurrence:&str) -> Result<(ObjectId, NaiveDateTime, Option<OccurrenceException>, Duration, Tz), HttpResponse> {
+    let eventId = ObjectId::parse_str(eventId).map_err(|_| {
+        HttpResponse::BadRequest().json(ResponseBuilder::<()>::InValidIdResponse())
+    })?;
+
+    let event = db.get_event(eventId, scope).await.map_err(|e| {
+        HttpResponse::NotFound().json(ResponseBuilder::<()>::FailedResponse(format!("Event {}", e)))
+    })?;
+
+    let occurrence = parse_event_date(occurrence).map_err(|e| {
+        HttpResponse::BadRequest().json(ResponseBuilder::<()>::FailedResponse(e.to_string()))
+    })?;
+
+    let zone = event_zone(&event);
+    let (recurrence, start, end) = match (event.recurrence, event.start_date, event.end_date) {
+        (Some(recurrence), Some(start), Some(end)) => (recurrence, to_naive(start), to_naive(end)),
+        _ => return Err(HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("event is not a recurring event".to_string())
+        )),
+    };
+
+    let rule = RRule::parse(&recurrence.rrule).map_err(|e| {
+        HttpResponse::BadRequest().json(ResponseBuilder::<()>::FailedResponse(e.to_string()))
+    })?;
+    if !series_includes(&rule, from_naive(start), zone, occurrence) {
+        return Err(HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("no occurrence of the event starts at that time".to_string())
+        ));
+    }
+
+    let previous = recurrence.exception(from_naive(occurrence)).cloned();
+    Ok((eventId, occurrence, previous, end - start, zone))
+}
+
+// occurrences of one event overlapping [from, to) with their start for sorting
+fn expand_event(event:&Events, from:NaiveDateTime, to:NaiveDateTime) -> Vec<(NaiveDateTime, OccurrenceDTO)> {
+    let (start, end) = match (event.start_date, event.end_date) {
+        (Some(start), Some(end)) => (to_naive(start), to_naive(end)),
+        _ => return Vec::new(),
+    };
+    let duration = end - start;
+    let zone = event_zone(event);
+
+    let recurrence = match event.recurrence.as_ref() {
+        Some(recurrence) => recurrence,
+        None => return vec![(start, occurrence_dto(event, start, start, end, None))],
+    };
+    let rule = match RRule::parse(&recurrence.rrule) {
+        Ok(rule) => rule,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut occurrences:Vec<(NaiveDateTime, OccurrenceDTO)> = Vec::new();
+    for occurrence in series_occurrences(&rule, from_naive(start), zone, from_naive(from - duration), from_naive(to)) {
+        match recurrence.exception(from_naive(occurrence)) {
+            // cancelled, or moved and listed below at its new time
+            Some(exception) if exception.cancelled || exception.start_date.is_some() => continue,
+            exception => occurrences.push((occurrence, occurrence_dto(event, occurrence, occurrence, occurrence + duration, exception))),
+        }
+    }
+
+    for exception in recurrence.exceptions.iter().filter(|e| !e.cancelled) {
+        if let (Some(moved_start), Some(moved_end)) = (exception.start_date.map(to_naive), exception.end_date.map(to_naive)) {
+            let occurrence = to_naive(exception.occurrence_start);
+            if moved_start < to && moved_end >= from && series_includes(&rule, from_naive(start), zone, occurrence) {
+                occurrences.push((moved_start, occurrence_dto(event, occurrence, moved_start, moved_end, Some(exception))));
+            }
+        }
+    }
+
+    occurrences
+}
+
+fn occurrence_dto(event:&Events, occurrence:NaiveDateTime, start:NaiveDateTime, end:NaiveDateTime, exception:Option<&OccurrenceException>) -> OccurrenceDTO {
+    OccurrenceDTO {
+        event_id: event.id.map(|id| id.to_hex()).unwrap_or_default(),
+        occurrence: occurrence.format("%Y%m%d%H%M").to_string(),
+        title: exception.and_then(|e| e.title.clone()).unwrap_or(event.title.to_string()),
+        discription: event.discription.to_string(),
+        location: exception.and_then(|e| e.location.clone()).unwrap_or(event.location.to_string()),
+        start_date: from_naive(start).to_string(),
+        end_date: from_naive(end).to_string(),
+        is_recurring: event.recurrence.is_some(),
+        is_modified: exception.is_some(),
+    }
+}
+
+// The rule is expanded in the event timezone so a series keeps its local time
+// across daylight saving changes. Occurrences come back as UTC.
+fn series_occurrences(rule:&RRule, start:bson::DateTime, zone:Tz, from:bson::DateTime, to:bson::DateTime) -> Vec<NaiveDateTime> {
+    local_rule(rule, zone).occurrences(timezone::to_local(start, zone), timezone::to_local(from, zone), timezone::to_local(to, zone))
+        .into_iter()
+        // a local time skipped by a clock change has no occurrence
+        .filter_map(|local| timezone::from_local(local, zone).ok())
+        .map(to_naive)
+        .collect()
+}
+
+fn series_includes(rule:&RRule, start:bson::DateTime, zone:Tz, occurrence:NaiveDateTime) -> bool {
+    local_rule(rule, zone).includes(timezone::to_local(start, zone), timezone::to_local(from_naive(occurrence), zone))
+}
+
+// A date-only UNTIL is the end of that day where the event takes place, stored
+// in UTC like any other UNTIL.
+fn stored_rule(mut rule:RRule, zone:Tz) -> Result<RRule, AppError> {
+    if rule.until_date {
+        rule.until = match rule.until {
+            Some(until) => Some(to_naive(timezone::from_local(until, zone)?)),
+            None => None,
+        };
+        rule.until_date = false;
+    }
+    Ok(rule)
+}
+
+// UNTIL is stored in UTC
+fn local_rule(rule:&RRule, zone:Tz) -> RRule {
+    let mut rule = rule.clone();
+    rule.until = rule.until.map(|until| timezone::to_local(from_naive(until), zone));
+    rule
+}
+
+pub(crate) fn event_zone(event:&Events) -> Tz {
+    event.timezone.as_deref()
+        .and_then(|name| timezone::parse_timezone(name).ok())
+        .unwrap_or(Tz::UTC)
+}
+
+fn parse_event_date(value:&str) -> Result<NaiveDateTime, AppError> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M")
+        .map_err(|_| AppError::CustomError(format!("Invalid event date: {}", value)))
+}
+
+pub(crate) fn to_naive(date:bson::DateTime) -> NaiveDateTime {
+    DateTime::from_timestamp_millis(date.timestamp_millis()).unwrap_or_default().naive_utc()
+}
+
+pub(crate) fn from_naive(date:NaiveDateTime) -> bson::DateTime {
+    bson::DateTime::from_millis(date.and_utc().timestamp_millis())
+}
+
+// ------------------------------ MEDIA ------------------------------------- //
+// Removes one gallery item and its file, video links only leave the gallery.
+#[allow(non_snake_case)]
+pub async fn delete_media(db:Tenant<EventRepo>, scope:BranchScope, path:Path<(String, String)>) -> impl Responder {
+    let (eventId, mediaId, gallery) = match event_media(&db, &scope, path.into_inner()).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let (removed, rest):(Vec<FileData>, Vec<FileData>) = gallery.iter().cloned().partition(|f| f.id == Some(mediaId));
+
+    match db.set_file_data(eventId, &gallery, rest, &scope).await {
+        Ok(_) => {
+            remove_media_files(&removed).await;
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataDeleteSucess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// The new order must list every item of the gallery once.
+#[allow(non_snake_case)]
+pub async fn reorder_media(db:Tenant<EventRepo>, scope:BranchScope, path:Path<String>, request:Json<ReorderMediaDTO>) -> impl Responder {
+    let eventId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let gallery = match db.get_event(eventId, &scope).await {
+        Ok(event) => event.file_data.unwrap_or_default(),
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))
+            );
+        },
+    };
+
+    let mut ordered:Vec<FileData> = Vec::new();
+    for id in request.media_ids.iter() {
+        match gallery.iter().find(|f| f.id.is_some_and(|mediaId| mediaId.to_hex() == *id)) {
+            Some(item) if !ordered.iter().any(|o| o.id == item.id) => ordered.push(item.clone()),
+            _ => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse(format!("Invalid or repeated media id: {}", id))
+                );
+            },
+        }
+    }
+    if ordered.len() != gallery.len() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("media_ids should list every item of the gallery".to_string())
+        );
+    }
+
+    match db.set_file_data(eventId, &gallery, ordered, &scope).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn caption_media(db:Tenant<EventRepo>, scope:BranchScope, path:Path<(String, String)>, request:Json<CaptionMediaDTO>) -> impl Responder {
+    let (eventId, mediaId) = path.into_inner();
+    let (eventId, mediaId) = match (ObjectId::parse_str(eventId), ObjectId::parse_str(mediaId)) {
+        (Ok(eventId), Ok(mediaId)) => (eventId, mediaId),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let caption = request.caption.as_ref().map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    match db.set_media_caption(eventId, mediaId, caption, &scope).await {
+        Ok(result) => {
+            if result.matched_count == 0 {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+                );
+            }
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Only an uploaded image can be the cover, one per event.
+#[allow(non_snake_case)]
+pub async fn set_cover_media(db:Tenant<EventRepo>, scope:BranchScope, path:Path<(String, String)>) -> impl Responder {
+    let (eventId, mediaId, gallery) = match event_media(&db, &scope, path.into_inner()).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    if gallery.iter().any(|f| f.id == Some(mediaId) && (f.embed.is_some() || !is_image(f))) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("only an image can be the cover".to_string())
+        );
+    }
+
+    let updated:Vec<FileData> = gallery.iter().cloned()
+        .map(|f| FileData { is_cover: f.id == Some(mediaId), ..f })
+        .collect();
+
+    match db.set_file_data(eventId, &gallery, updated, &scope).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// the event id, media id and gallery, when the media belongs to the event
+#[allow(non_snake_case)]
+async fn event_media(db:&EventRepo, scope:&BranchScope, (eventId, mediaId):(String, String)) -> Result<(ObjectId, ObjectId, Vec<FileData>), HttpResponse> {
+    let (eventId, mediaId) = match (ObjectId::parse_str(eventId), ObjectId::parse_str(mediaId)) {
+        (Ok(eventId), Ok(mediaId)) => (eventId, mediaId),
+        _ => return Err(HttpResponse::BadRequest().json(ResponseBuilder::<()>::InValidIdResponse())),
+    };
+
+    let gallery = db.get_event(eventId, scope).await
+        .map_err(|e| HttpResponse::NotFound().json(ResponseBuilder::<()>::FailedResponse(format!("Event {}", e))))?
+        .file_data
+        .unwrap_or_default();
+
+    if !gallery.iter().any(|f| f.id == Some(mediaId)) {
+        return Err(HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Media {}", AppError::DataNotFoundError))
+        ));
+    }
+
+    Ok((eventId, mediaId, gallery))
+}
+
+fn is_image(file:&FileData) -> bool {
+    let extension = file.file_path.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+    file.file_type.to_ascii_lowercase().contains("image") || ["jpg", "jpeg", "png", "gif", "webp"].contains(&extension.as_str())
+}
+
+// deletes the uploaded files of gallery items, links have nothing in storage
+async fn remove_media_files(files:&[FileData]) {
+    for file in files.iter().filter(|f| f.embed.is_none()) {
+        upload::remove_upload(&file.file_path, file.variants.as_ref()).await;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bson::oid::ObjectId;
+    use chrono::{NaiveDate, NaiveDateTime};
+    use chrono_tz::Tz;
+
+    use crate::{config::db_config::DBConfig, helper::{branch_scope::BranchScope, rrule::RRule, timezone}, models::events::{EventRegistrations, Events, RegistrationSettings, RegistrationStatus}, repo::events_repo::EventRepo};
+
+    use super::{from_naive, series_occurrences, stored_rule};
+
+    fn local(zone:Tz, date:&str) -> bson::DateTime {
+        timezone::from_local(NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap(), zone).unwrap()
+    }
+
+    // local dates of a daily series that starts at `time` and runs until 30 June
+    fn last_days(zone:Tz, time:&str) -> Vec<NaiveDate> {
+        let rule = stored_rule(RRule::parse("FREQ=DAILY;UNTIL=20260630").unwrap(), zone).unwrap();
+        let rule = RRule::parse(&rule.to_string()).unwrap();
+        series_occurrences(&rule, local(zone, &format!("2026-06-28 {}", time)), zone, local(zone, "2026-06-01 00:00"), local(zone, "2026-08-01 00:00"))
+            .into_iter()
+            .map(|occurrence| timezone::to_local(from_naive(occurrence), zone).date())
+            .collect()
+    }
+
+    #[test]
+    fn date_only_until_ends_with_the_local_day() {
+        let days = |zone:Tz, time:&str| last_days(zone, time).iter().map(|d| d.format("%d").to_string()).collect::<Vec<_>>();
+
+        // early morning east of UTC is still the previous UTC day
+        assert_eq!(days(Tz::Asia__Kolkata, "03:00"), ["28", "29", "30"]);
+        assert_eq!(days(Tz::Asia__Kolkata, "23:30"), ["28", "29", "30"]);
+        // evening west of UTC is already the next UTC day
+        assert_eq!(days(Tz::America__Los_Angeles, "19:00"), ["28", "29", "30"]);
+        assert_eq!(days(Tz::UTC, "23:00"), ["28", "29", "30"]);
+
+        let stored = stored_rule(RRule::parse("FREQ=DAILY;UNTIL=20260630").unwrap(), Tz::Asia__Kolkata).unwrap();
+        assert_eq!(stored.to_string(), "FREQ=DAILY;UNTIL=20260630T182959Z");
+        // a date-time UNTIL is already UTC
+        let exact = stored_rule(RRule::parse("FREQ=DAILY;UNTIL=20260630T120000Z").unwrap(), Tz::Asia__Kolkata).unwrap();
+        assert_eq!(exact.to_string(), "FREQ=DAILY;UNTIL=20260630T120000Z");
+    }
+
+    // Runs against the database at MONGOURI in a throwaway database:
+    // cargo test -- --ignored
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGOURI"]
+    async fn concurrent_registrations_respect_the_capacity() {
+        let client = DBConfig::client().await.unwrap();
+        let db = client.database(&format!("k_admin_test_{}", ObjectId::new().to_hex()));
+        let repo = EventRepo::init(db.clone()).await;
+
+        let event_id = repo.add_event(Events {
+            id: None,
+            title: "Grading".to_string(),
+            discription: "test".to_string(),
+            location: "Dojo".to_string(),
+            is_active: Some(true),
+            file_data: None,
+            start_date: Some(bson::DateTime::now()),
+            end_date: Some(bson::DateTime::now()),
+            timezone: None,
+            branches: Vec::new(),
+            registration: None,
+            // an event created before places were counted, seeded on first use
+            registered_count: None,
+            recurrence: None,
+            created_at: Some(bson::DateTime::now()),
+            updated_at: Some(bson::DateTime::now()),
+        }, &BranchScope::All).await.unwrap().inserted_id.as_object_id().unwrap();
+        repo.set_registration(event_id, RegistrationSettings {
+            capacity: Some(2),
+            opens_at: None,
+            closes_at: None,
+            fee: None,
+            levels: Vec::new(),
+            min_age: None,
+            max_age: None,
+            branches: Vec::new(),
+        }, &BranchScope::All).await.unwrap();
+
+        let make_registration = |student_id:ObjectId| EventRegistrations {
+            id: None,
+            event_id,
+            student_id,
+            status: RegistrationStatus::REGISTERED.to_string(),
+            fee: None,
+            fee_paid: true,
+            registered_by: "admin".to_string(),
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        };
+
+        // the same student twice at once gets one registration and one place
+        let student_id = ObjectId::new();
+        let (first, second) = futures::join!(repo.register_student(make_registration(student_id), Some(2)), repo.register_student(make_registration(student_id), Some(2)));
+        assert!(first.is_ok() != second.is_ok());
+        let registration_id = first.or(second).unwrap().id.unwrap();
+
+        // one place is left for two other students
+        let (first, second) = futures::join!(repo.register_student(make_registration(ObjectId::new()), Some(2)), repo.register_student(make_registration(ObjectId::new()), Some(2)));
+        assert!(first.is_ok() != second.is_ok());
+        assert_eq!(repo.count_registrations(event_id).await.unwrap(), 2);
+
+        // cancelling gives the place back once
+        assert!(repo.cancel_registration(registration_id).await.unwrap());
+        assert!(!repo.cancel_registration(registration_id).await.unwrap());
+        assert!(repo.register_student(make_registration(ObjectId::new()), Some(2)).await.is_ok());
+        assert!(repo.register_student(make_registration(ObjectId::new()), Some(2)).await.is_err());
+
+        db.drop(None).await.unwrap();
+    }
+}
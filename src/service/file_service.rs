@@ -0,0 +1,130 @@
+use std::{collections::HashSet, env};
+
+use actix_files::NamedFile;
+use actix_web::{http::header::{HeaderValue, CACHE_CONTROL}, web::{Data, Path, Query}, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Utc};
+
+use crate::{dto::app_dto::{FileGcQueryDTO, FileGcReportDTO, OrphanFileDTO, SignedFileQueryDTO}, helper::{app_errors::{AppError, Messages}, response::ResponseBuilder, storage::{self, LocalStorage}}, repo::tenant_repo::TenantRegistry};
+
+use super::jwt_service::JwtService;
+
+// an upload is written before the document that points to it, younger files
+// may still be waiting for that update
+const DEFAULT_GC_GRACE_HOURS:i64 = 24;
+
+// Private files of the local storage. The signature in the url is the
+// authorization, so the link works in an <img> tag without a token.
+pub async fn download_file(req:HttpRequest, path:Path<String>, query:Query<SignedFileQueryDTO>) -> impl Responder {
+    let key = path.into_inner();
+    if !storage::is_private(&key) || !storage::verify_signature(&key, query.expires, &query.signature) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse("File link is invalid or has expired".to_string())
+        );
+    }
+
+    let file = match LocalStorage::init().path(&key).map(NamedFile::open) {
+        Ok(Ok(file)) => file,
+        _ => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("File {}", AppError::DataNotFoundError))
+            );
+        },
+    };
+
+    let mut response = file.into_response(&req);
+    // shared caches must not keep a student's file
+    response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("private, max-age=3600"));
+    response
+}
+
+// Reports stored files no document points to and referenced files that are
+// gone. Dry run unless dry_run=false, then expired orphans are deleted.
+// Storage is shared by every academy, so this is a super admin task.
+pub async fn gc_files(req:HttpRequest, registry:Data<TenantRegistry>, query:Query<FileGcQueryDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_super_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    match collect_garbage(&registry, query.dry_run.unwrap_or(true)).await {
+        Ok(report) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(report)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// A file is referenced if a document of any academy, active or not, points to it.
+// Storage is shared, so the run stops if any academy can not be read.
+pub async fn collect_garbage(registry:&TenantRegistry, dry_run:bool) -> Result<FileGcReportDTO, AppError> {
+    let grace_hours = env::var("FILE_GC_GRACE_HOURS").ok()
+        .and_then(|hours| hours.parse::<i64>().ok())
+        .filter(|hours| *hours >= 0)
+        .unwrap_or(DEFAULT_GC_GRACE_HOURS);
+    let cutoff = Utc::now() - Duration::hours(grace_hours);
+
+    let mut references:Vec<String> = Vec::new();
+    for repos in registry.every().await? {
+        let read = |e:AppError| AppError::CustomError(format!("[{}] file references could not be read {}", repos.slug, e));
+        references.extend(repos.student.file_references().await.map_err(read)?);
+        references.extend(repos.event.media_file_references().await.map_err(read)?);
+        references.extend(repos.app.facility_file_references().await.map_err(read)?);
+        references.extend(repos.settings.file_references().await.map_err(read)?);
+    }
+    let referenced:HashSet<String> = references.iter()
+        .filter_map(|value| storage::key_of(value))
+        .map(String::from)
+        .collect();
+
+    let store = storage::storage();
+    let mut stored:Vec<storage::StoredObject> = Vec::new();
+    for prefix in storage::UPLOAD_PREFIXES {
+        stored.extend(store.list(&format!("{}/", prefix)).await?);
+    }
+    let stored_keys:HashSet<&str> = stored.iter().map(|object| object.key.as_str()).collect();
+
+    let mut missing:Vec<String> = referenced.iter()
+        .filter(|key| !stored_keys.contains(key.as_str()))
+        .cloned()
+        .collect();
+    missing.sort();
+
+    let mut orphans:Vec<OrphanFileDTO> = Vec::new();
+    let mut deleted = 0;
+    for object in stored.iter().filter(|object| !referenced.contains(&object.key)) {
+        let expired = object.modified < cutoff;
+        if expired && !dry_run {
+            match store.delete(&object.key).await {
+                Ok(()) => deleted += 1,
+                Err(e) => println!("Could not remove orphaned file {} {}", object.key, e),
+            }
+        }
+        orphans.push(OrphanFileDTO {
+            key: object.key.to_string(),
+            size: object.size,
+            modified: object.modified.to_rfc3339(),
+            expired,
+        });
+    }
+    orphans.sort_by(|a, b| a.key.cmp(&b.key));
+
+    Ok(FileGcReportDTO {
+        dry_run,
+        grace_hours,
+        scanned: stored.len(),
+        referenced: referenced.len(),
+        orphans,
+        missing,
+        deleted,
+    })
+}
@@ -0,0 +1,1190 @@
+use actix_web::{web::{Bytes, Data, Json, Path, Query}, HttpRequest, HttpResponse, Responder};
+use bson::oid::ObjectId;
+use chrono::{Duration, NaiveDate, Utc};
+use validator::Validate;
+
+use std::collections::BTreeMap;
+
+use crate::{dto::{finance_dto::{CreateCreditNoteDTO, CreateInvoiceDTO, CreateRefundDTO, CreditNoteDTO, ExportQueryDTO, GstSummaryDTO, InvoiceDTO, PaymentOrderDTO, ProRataDTO, ProRataQueryDTO, RefundDTO, RefundQueryDTO, StudentLedgerDTO}}, helper::{app_errors::{AppError, Messages}, branch_scope::BranchScope, csv::CsvBuilder, response::ResponseBuilder}, models::{finance::{pro_rata_unused, CreditNotes, InvoiceStatus, Invoices, Payments, RefundStatus, Refunds, TaxLine, TaxTypes}, money::{request_currency, Money}, settings::AcademySettings}, repo::{finance_repo::FinanceRepo, settings_repo::SettingsRepo}};
+use crate::{helper::tenant::Tenant, repo::tenant_repo::TenantRegistry};
+
+use super::{jwt_service::JwtService, payment_gateway::{GatewayPayment, PaymentGateway}};
+
+// ------------------------------ INVOICES ------------------------------------- //
+#[allow(non_snake_case)]
+pub async fn add_invoice(db:Tenant<FinanceRepo>, scope:BranchScope, settingsDb:Tenant<SettingsRepo>, request:Json<CreateInvoiceDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let (studentId, feeId) = match (
+        ObjectId::parse_str(request.student_id.as_ref().unwrap()),
+        ObjectId::parse_str(request.fee_id.as_ref().unwrap())
+    ) {
+        (Ok(studentId), Ok(feeId)) => (studentId, feeId),
+        _ => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let due_date = match request.due_date.as_ref() {
+        Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => bson::DateTime::from_millis(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis()),
+            Err(_) => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse("Invalid due date".to_string())
+                );
+            },
+        },
+        None => bson::DateTime::from_millis((Utc::now() + Duration::days(7)).timestamp_millis()),
+    };
+
+    if let Err(e) = db.studentRepo.get_student(studentId, &scope).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Student {}", e))
+        );
+    }
+
+    let fee = match db.get_fee(feeId).await {
+        Ok(fee) => fee,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Fee {}", e))
+            );
+        },
+    };
+
+    let courseId = match request.course_id.as_ref().map(ObjectId::parse_str) {
+        Some(Ok(objId)) => match db.get_course(objId).await {
+            Ok(_) => Some(objId),
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(format!("Course {}", e))
+                );
+            },
+        },
+        Some(Err(_)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+        None => None,
+    };
+
+    let settings = match settingsDb.get_settings().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let taxable_amount = fee.net_amount();
+    let discount_amount = Some(Money::new(fee.fee_amount.amount_minor - taxable_amount.amount_minor, &taxable_amount.currency))
+        .filter(|discount| discount.amount_minor > 0);
+    let mut tax_lines:Vec<TaxLine> = Vec::new();
+    let mut place_of_supply:Option<String> = None;
+
+    if let Some(tax) = fee.tax.as_ref() {
+        let academy_state = match settings.state_code.as_ref() {
+            Some(state_code) if settings.gstin.is_some() => state_code.to_string(),
+            _ => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse("academy GSTIN is not configured".to_string())
+                );
+            },
+        };
+
+        let buyer_state = request.place_of_supply.clone().unwrap_or(academy_state.to_string());
+        if buyer_state.len() != 2 || !buyer_state.chars().all(|c| c.is_ascii_digit()) {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("Invalid place of supply".to_string())
+            );
+        }
+
+        tax_lines = tax.tax_lines(&taxable_amount, buyer_state == academy_state);
+        place_of_supply = Some(buyer_state);
+    }
+
+    let mut amount = taxable_amount.clone();
+    for line in tax_lines.iter() {
+        amount = match amount.checked_add(&line.amount) {
+            Ok(amount) => amount,
+            Err(e) => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        };
+    }
+
+    let invoice = Invoices {
+        id: None,
+        // numbered by the repository from the academy's counter
+        invoice_number: String::new(),
+        student_id: studentId,
+        fee_id: feeId,
+        fee_type: fee.fee_type.to_string(),
+        course_id: courseId,
+        amount,
+        discount_amount,
+        taxable_amount: Some(taxable_amount),
+        hsn_sac: fee.tax.as_ref().map(|t| t.hsn_sac.to_string()),
+        gstin: fee.tax.as_ref().and(settings.gstin),
+        place_of_supply,
+        tax_lines,
+        status: InvoiceStatus::PENDING.to_string(),
+        gateway_order_id: None,
+        gateway_order_ids: Vec::new(),
+        due_date,
+        paid_at: None,
+        created_at: bson::DateTime::now(),
+        updated_at: bson::DateTime::now(),
+    };
+
+    match db.add_invoice(invoice).await {
+        Ok(result) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(result)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn list_invoices(db:Tenant<FinanceRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            match db.list_invoices(objId, &scope).await {
+                Ok(invoices) => {
+                    if invoices.is_empty() {
+                        return HttpResponse::NotFound().json(
+                            ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+                        );
+                    }
+
+                    let invoice_dto:Vec<InvoiceDTO> = invoices.into_iter().map(InvoiceDTO::init).collect();
+
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::SuccessResponse(
+                            Messages::DataFetchSuccess.to_string(),
+                            Some(invoice_dto)
+                        )
+                    )
+                },
+                Err(e) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+
+// Monthly HSN/SAC wise GST summary for filing, path is the month as YYYY-MM.
+pub async fn gst_summary(db:Tenant<FinanceRepo>, scope:BranchScope, path:Path<String>, query:Query<ExportQueryDTO>) -> impl Responder {
+    let month = path.into_inner();
+    let from = match NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d") {
+        Ok(date) => date,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("month should be YYYY-MM".to_string())
+            );
+        },
+    };
+    let to = from.checked_add_months(chrono::Months::new(1)).unwrap();
+
+    let (from, to) = (
+        bson::DateTime::from_millis(from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis()),
+        bson::DateTime::from_millis(to.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis())
+    );
+
+    let (invoices, credit_notes) = match (db.paid_invoices_between(from, to, &scope).await, db.credit_notes_between(from, to, &scope).await) {
+        (Ok(invoices), Ok(credit_notes)) => (invoices, credit_notes),
+        (Err(e), _) | (_, Err(e)) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    // credit notes reduce the supplies of the invoices they were raised against,
+    // which may be from an earlier month
+    let credited = match db.invoices_by_ids(credit_notes.iter().filter_map(|note| note.invoice_id).collect()).await {
+        Ok(credited) => credited,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let (rows, currency) = gst_rows(&invoices, &credit_notes, &credited);
+    let amount = |value:i64| Money::new(value, &currency).to_decimal_string();
+    let summary:Vec<GstSummaryDTO> = rows.into_iter().map(|(hsn_sac, row)| GstSummaryDTO {
+        hsn_sac,
+        invoice_count: row.0,
+        credit_note_count: row.6,
+        taxable_value: amount(row.1),
+        cgst: amount(row.2),
+        sgst: amount(row.3),
+        igst: amount(row.4),
+        total_value: amount(row.5),
+    }).collect();
+
+    if query.is_csv() {
+        let mut csv = CsvBuilder::new(&["HSN/SAC", "Invoices", "Credit Notes", "Taxable Value", "CGST", "SGST", "IGST", "Total Value"]);
+        for row in summary {
+            csv.add_row(vec![row.hsn_sac, row.invoice_count.to_string(), row.credit_note_count.to_string(), row.taxable_value, row.cgst, row.sgst, row.igst, row.total_value]);
+        }
+        return csv.into_response(&format!("gst-summary-{}.csv", month));
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(summary)
+        )
+    )
+}
+
+// hsn_sac => (invoice count, taxable, cgst, sgst, igst, total, credit note count) in
+// minor units. Paid invoices add to their row, a credit note takes the same share
+// of its invoice's taxable value and taxes off the row of that invoice.
+type GstRow = (u64, i64, i64, i64, i64, i64, u64);
+
+fn gst_rows(invoices:&[Invoices], credit_notes:&[CreditNotes], credited:&[Invoices]) -> (BTreeMap<String, GstRow>, String) {
+    let mut rows:BTreeMap<String, GstRow> = BTreeMap::new();
+    let mut currency = String::new();
+
+    for invoice in invoices.iter().filter(|i| i.hsn_sac.is_some()) {
+        add_gst_row(&mut rows, invoice, &|amount| amount.amount_minor).0 += 1;
+        currency = invoice.amount.currency.to_string();
+    }
+
+    for note in credit_notes {
+        let invoice = match credited.iter().find(|i| i.id.is_some() && i.id == note.invoice_id) {
+            Some(invoice) if invoice.hsn_sac.is_some() && invoice.amount.amount_minor != 0 => invoice,
+            _ => continue,
+        };
+        let credit = |amount:&Money| -amount.mul_ratio(note.amount.amount_minor, invoice.amount.amount_minor).amount_minor;
+        add_gst_row(&mut rows, invoice, &credit).6 += 1;
+        currency = invoice.amount.currency.to_string();
+    }
+
+    (rows, currency)
+}
+
+fn add_gst_row<'a>(rows:&'a mut BTreeMap<String, GstRow>, invoice:&Invoices, share:&dyn Fn(&Money) -> i64) -> &'a mut GstRow {
+    let row = rows.entry(invoice.hsn_sac.clone().unwrap()).or_default();
+    row.1 += invoice.taxable_amount.as_ref().map(share).unwrap_or_default();
+    for line in invoice.tax_lines.iter() {
+        if line.tax_type == TaxTypes::CGST.to_string() {
+            row.2 += share(&line.amount);
+        } else if line.tax_type == TaxTypes::SGST.to_string() {
+            row.3 += share(&line.amount);
+        } else {
+            row.4 += share(&line.amount);
+        }
+    }
+    row.5 += share(&invoice.amount);
+    row
+}
+
+// ------------------------------ PAYMENTS ------------------------------------- //
+#[allow(non_snake_case)]
+pub async fn create_payment_order(db:Tenant<FinanceRepo>, scope:BranchScope, gateway:Data<dyn PaymentGateway>, path:Path<String>) -> impl Responder {
+    let invoiceId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let invoice = match db.get_invoice(invoiceId, &scope).await {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if invoice.status != InvoiceStatus::PENDING.to_string() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("invoice is already paid".to_string())
+        );
+    }
+
+    let order = match gateway.create_order(&invoice).await {
+        Ok(order) => order,
+        Err(e) => {
+            return HttpResponse::BadGateway().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    match db.set_gateway_order(invoiceId, order.order_id.to_string()).await {
+        Ok(result) => {
+            if result.matched_count == 0 {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse(Messages::DataUpdateFailed.to_string())
+                );
+            }
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(PaymentOrderDTO {
+                        invoice_id: invoiceId.to_hex(),
+                        gateway: gateway.name().to_string(),
+                        order_id: order.order_id,
+                        amount: order.amount.to_decimal_string(),
+                        currency: order.amount.currency,
+                        checkout: order.checkout,
+                    })
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Provider callback. Unauthenticated, so the HMAC signature over the raw body is
+// the only thing that proves the call came from the provider.
+// The provider does not know our academies, the invoice is looked up in each of them.
+pub async fn payment_webhook(registry:Data<TenantRegistry>, gateway:Data<dyn PaymentGateway>, req:HttpRequest, body:Bytes) -> impl Responder {
+    let signature = req.headers()
+        .get(gateway.signature_header())
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if signature.is_empty() || !gateway.verify_signature(&body, signature) {
+        return HttpResponse::Unauthorized().json(
+            ResponseBuilder::<()>::FailedResponse("Invalid webhook signature".to_string())
+        );
+    }
+
+    let payment = match gateway.parse_webhook(&body) {
+        Ok(Some(payment)) => payment,
+        Ok(None) => {
+            return HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse("Event ignored".to_string(), None)
+            );
+        },
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let tenants = match registry.all().await {
+        Ok(tenants) => tenants,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let mut tenant = None;
+    for repos in tenants {
+        if repos.finance.get_invoice_by_order(&payment.order_id).await.is_ok() {
+            tenant = Some(repos);
+            break;
+        }
+    }
+    let tenant = match tenant {
+        Some(tenant) => tenant,
+        None => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(AppError::DataNotFoundError.to_string())
+            );
+        },
+    };
+
+    match apply_gateway_payment(&tenant.finance, gateway.name(), payment).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+pub async fn reconcile_payments(db:Tenant<FinanceRepo>, gateway:Data<dyn PaymentGateway>) -> impl Responder {
+    match reconcile(&db, gateway.get_ref()).await {
+        Ok(count) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::CustomMessage("Payments reconciled".to_string()).to_string(),
+                    Some(count)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// Asks the provider about every pending invoice that has an order, so payments
+// whose webhook never arrived still get recorded. Returns how many were applied.
+pub async fn reconcile(db:&FinanceRepo, gateway:&dyn PaymentGateway) -> Result<u64, AppError> {
+    let mut applied = 0;
+    for invoice in db.pending_gateway_invoices().await? {
+        for order_id in invoice.order_ids() {
+            match gateway.fetch_payment(&order_id).await {
+                Ok(Some(payment)) => {
+                    if apply_gateway_payment(db, gateway.name(), payment).await? {
+                        applied += 1;
+                        break;
+                    }
+                },
+                Ok(None) => {},
+                Err(e) => println!("Reconciliation failed for order {} : {}", order_id, e),
+            }
+        }
+    }
+
+    Ok(applied)
+}
+
+pub async fn apply_gateway_payment(db:&FinanceRepo, gateway:&str, payment:GatewayPayment) -> Result<bool, AppError> {
+    let invoice = db.get_invoice_by_order(&payment.order_id).await?;
+
+    if payment.amount != invoice.amount {
+        return Err(AppError::CustomError(format!(
+            "paid amount {} {} does not match invoice amount {} {}",
+            payment.amount.to_decimal_string(), payment.amount.currency,
+            invoice.amount.to_decimal_string(), invoice.amount.currency
+        )));
+    }
+
+    db.record_payment(Payments {
+        id: None,
+        invoice_id: invoice.id.unwrap(),
+        student_id: invoice.student_id,
+        amount: payment.amount,
+        gateway: gateway.to_string(),
+        gateway_order_id: payment.order_id,
+        gateway_payment_id: payment.payment_id,
+        refunded_minor: Some(0),
+        created_at: bson::DateTime::now(),
+    }).await
+}
+
+// ------------------------------ REFUNDS ------------------------------------- //
+// Unused portion of a paid invoice if the student leaves on the given date.
+// The fee period is taken to start on the invoice date.
+#[allow(non_snake_case)]
+pub async fn pro_rata_refund(db:Tenant<FinanceRepo>, scope:BranchScope, path:Path<String>, query:Query<ProRataQueryDTO>) -> impl Responder {
+    let invoiceId = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let leave_date = match parse_leave_date(query.leave_date.as_deref()) {
+        Ok(date) => date,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let invoice = match db.get_invoice(invoiceId, &scope).await {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    match db.settings().await.and_then(|settings| unused_portion(&invoice, leave_date, &settings)) {
+        Ok((period_start, period_end, unused)) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(ProRataDTO {
+                        invoice_id: invoiceId.to_hex(),
+                        fee_type: invoice.fee_type,
+                        period_start: period_start.to_string(),
+                        period_end: period_end.to_string(),
+                        leave_date: leave_date.to_string(),
+                        paid_amount: invoice.amount.to_decimal_string(),
+                        unused_amount: unused.to_decimal_string(),
+                        currency: unused.currency,
+                    })
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn request_refund(db:Tenant<FinanceRepo>, scope:BranchScope, req:HttpRequest, request:Json<CreateRefundDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let paymentId = match ObjectId::parse_str(request.payment_id.as_ref().unwrap()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let payment = match db.get_payment(paymentId, &scope).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            return HttpResponse::NotFound().json(
+                ResponseBuilder::<()>::FailedResponse(format!("Payment {}", e))
+            );
+        },
+    };
+
+    let amount = match (request.amount.as_ref(), request.leave_date.as_ref()) {
+        (Some(amount), _) => Money::parse(amount, &payment.amount.currency),
+        (None, Some(leave_date)) => match (parse_leave_date(Some(leave_date)), db.get_invoice(payment.invoice_id, &scope).await) {
+            (Ok(leave_date), Ok(invoice)) => db.settings().await
+                .and_then(|settings| unused_portion(&invoice, leave_date, &settings))
+                .map(|(_, _, unused)| unused),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        },
+        (None, None) => Err(AppError::CustomError("either amount or leave_date is required".to_string())),
+    };
+
+    let amount = match amount {
+        Ok(amount) if amount.amount_minor > 0 => amount,
+        Ok(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("nothing left to refund".to_string())
+            );
+        },
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let refund = Refunds {
+        id: None,
+        payment_id: paymentId,
+        invoice_id: payment.invoice_id,
+        student_id: payment.student_id,
+        amount,
+        reason: request.reason.clone().unwrap(),
+        status: RefundStatus::REQUESTED.to_string(),
+        requested_by: JwtService::current_user(&req).map(|user| user.name).unwrap_or_default(),
+        approved_by: None,
+        approved_at: None,
+        credit_note_id: None,
+        created_at: bson::DateTime::now(),
+        updated_at: bson::DateTime::now(),
+    };
+
+    // the amount is reserved on the payment, concurrent requests can not over-refund
+    match db.add_refund(&payment, refund).await {
+        Ok(result) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(result)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+pub async fn list_refunds(db:Tenant<FinanceRepo>, scope:BranchScope, query:Query<RefundQueryDTO>) -> impl Responder {
+    match db.list_refunds(query.into_inner().status.map(|s| s.to_uppercase()), &scope).await {
+        Ok(refunds) => {
+            let refund_dto:Vec<RefundDTO> = refunds.into_iter().map(RefundDTO::init).collect();
+
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(refund_dto)
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn approve_refund(db:Tenant<FinanceRepo>, req:HttpRequest, path:Path<String>) -> impl Responder {
+    let admin = match JwtService::require_admin(&req) {
+        Ok(admin) => admin,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let refund = match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => match db.get_refund(objId).await {
+            Ok(refund) => refund,
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(e.to_string())
+                );
+            },
+        },
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    let creditNote = CreditNotes {
+        id: None,
+        credit_note_number: String::new(),
+        student_id: refund.student_id,
+        invoice_id: Some(refund.invoice_id),
+        refund_id: refund.id,
+        amount: refund.amount.clone(),
+        reason: refund.reason.to_string(),
+        issued_by: admin.name.to_string(),
+        created_at: bson::DateTime::now(),
+    };
+
+    match db.approve_refund(&refund, &admin.name, creditNote).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn reject_refund(db:Tenant<FinanceRepo>, req:HttpRequest, path:Path<String>) -> impl Responder {
+    let admin = match JwtService::require_admin(&req) {
+        Ok(admin) => admin,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            match db.reject_refund(objId, &admin.name).await {
+                Ok(true) => {
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
+                    )
+                },
+                Ok(false) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse("refund is not awaiting approval".to_string())
+                    )
+                },
+                Err(e) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+
+// ------------------------------ CREDIT NOTES ------------------------------------- //
+#[allow(non_snake_case)]
+pub async fn add_credit_note(db:Tenant<FinanceRepo>, scope:BranchScope, req:HttpRequest, request:Json<CreateCreditNoteDTO>) -> impl Responder {
+    let admin = match JwtService::require_admin(&req) {
+        Ok(admin) => admin,
+        Err(e) => {
+            return HttpResponse::Forbidden().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let studentId = match ObjectId::parse_str(request.student_id.as_ref().unwrap()) {
+        Ok(objId) => objId,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+    };
+
+    if let Err(e) = db.studentRepo.get_student(studentId, &scope).await {
+        return HttpResponse::NotFound().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Student {}", e))
+        );
+    }
+
+    let invoiceId = match request.invoice_id.as_ref().map(ObjectId::parse_str) {
+        Some(Ok(objId)) => match db.get_invoice(objId, &scope).await {
+            Ok(invoice) if invoice.student_id == studentId => Some(objId),
+            Ok(_) => {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::FailedResponse("invoice belongs to another student".to_string())
+                );
+            },
+            Err(e) => {
+                return HttpResponse::NotFound().json(
+                    ResponseBuilder::<()>::FailedResponse(format!("Invoice {}", e))
+                );
+            },
+        },
+        Some(Err(_)) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            );
+        },
+        None => None,
+    };
+
+    let settings = match db.settings().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let amount = match request_currency(request.currency.as_deref(), settings.currency()).and_then(|currency| Money::parse(&request.amount, &currency)) {
+        Ok(amount) if amount.amount_minor > 0 => amount,
+        Ok(_) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("amount should be greater than zero".to_string())
+            );
+        },
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let creditNote = CreditNotes {
+        id: None,
+        credit_note_number: String::new(),
+        student_id: studentId,
+        invoice_id: invoiceId,
+        refund_id: None,
+        amount,
+        reason: request.reason.clone().unwrap(),
+        issued_by: admin.name,
+        created_at: bson::DateTime::now(),
+    };
+
+    match db.add_credit_note(creditNote).await {
+        Ok(objId) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataAddedSuccess.to_string(),
+                    Some(objId.to_hex())
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+#[allow(non_snake_case)]
+pub async fn list_credit_notes(db:Tenant<FinanceRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            match db.list_credit_notes(objId, &scope).await {
+                Ok(credit_notes) => {
+                    let credit_note_dto:Vec<CreditNoteDTO> = credit_notes.into_iter().map(CreditNoteDTO::init).collect();
+
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::SuccessResponse(
+                            Messages::DataFetchSuccess.to_string(),
+                            Some(credit_note_dto)
+                        )
+                    )
+                },
+                Err(e) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+
+// ------------------------------ LEDGER ------------------------------------- //
+#[allow(non_snake_case)]
+pub async fn student_ledger(db:Tenant<FinanceRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
+    match ObjectId::parse_str(path.into_inner()) {
+        Ok(objId) => {
+            match (db.student_ledger(objId, &scope).await, db.settings().await) {
+                (Ok(entries), Ok(settings)) => {
+                    let currency = entries.first().map(|e| e.amount.currency.to_string()).unwrap_or(settings.currency().to_string());
+
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::SuccessResponse(
+                            Messages::DataFetchSuccess.to_string(),
+                            Some(StudentLedgerDTO::init(objId.to_hex(), &currency, entries))
+                        )
+                    )
+                },
+                (Err(e), _) | (_, Err(e)) => {
+                    HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            }
+        },
+        Err(_) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::InValidIdResponse()
+            )
+        },
+    }
+}
+
+fn parse_leave_date(date:Option<&str>) -> Result<NaiveDate, AppError> {
+    match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| AppError::CustomError("Invalid leave date".to_string())),
+        None => Ok(Utc::now().date_naive()),
+    }
+}
+
+// (period start, period end, unused amount) of a paid invoice
+fn unused_portion(invoice:&Invoices, leave_date:NaiveDate, settings:&AcademySettings) -> Result<(NaiveDate, NaiveDate, Money), AppError> {
+    if invoice.status != InvoiceStatus::PAID.to_string() {
+        return Err(AppError::CustomError("invoice is not paid".to_string()));
+    }
+
+    let months = settings.fee_cycle(&invoice.fee_type).map(|cycle| cycle.months)
+        .ok_or(AppError::CustomError(format!("unknown fee period {}", invoice.fee_type)))?;
+
+    let period_start = chrono::DateTime::from_timestamp_millis(invoice.created_at.timestamp_millis()).unwrap_or_default().date_naive();
+    let period_end = period_start.checked_add_months(chrono::Months::new(months)).unwrap_or(period_start);
+
+    Ok((period_start, period_end, pro_rata_unused(&invoice.amount, period_start, months, leave_date)))
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::oid::ObjectId;
+
+    use crate::{config::db_config::DBConfig, helper::branch_scope::BranchScope, models::{finance::{CreditNotes, InvoiceStatus, Invoices, Payments, RefundStatus, Refunds, TaxLine, TaxTypes}, money::Money}, repo::{finance_repo::FinanceRepo, student_repo::StudentRepo}, service::payment_gateway::{LocalGateway, PaymentGateway}};
+
+    use super::{apply_gateway_payment, gst_rows};
+
+    fn gst_invoice(taxable:i64, hsn_sac:&str) -> Invoices {
+        let tax = |tax_type:TaxTypes| TaxLine { tax_type: tax_type.to_string(), rate: 900, amount: Money::new(taxable * 9 / 100, "INR") };
+        Invoices {
+            id: Some(ObjectId::new()),
+            invoice_number: "INV-1".to_string(),
+            student_id: ObjectId::new(),
+            fee_id: ObjectId::new(),
+            fee_type: "MONTHLY".to_string(),
+            course_id: None,
+            amount: Money::new(taxable * 118 / 100, "INR"),
+            discount_amount: None,
+            taxable_amount: Some(Money::new(taxable, "INR")),
+            tax_lines: vec![tax(TaxTypes::CGST), tax(TaxTypes::SGST)],
+            hsn_sac: Some(hsn_sac.to_string()),
+            gstin: None,
+            place_of_supply: None,
+            status: InvoiceStatus::PAID.to_string(),
+            gateway_order_id: None,
+            gateway_order_ids: Vec::new(),
+            due_date: bson::DateTime::now(),
+            paid_at: None,
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        }
+    }
+
+    #[test]
+    fn credit_notes_reduce_the_gst_row_of_their_invoice() {
+        let this_month = gst_invoice(100_000, "999293");
+        let earlier = gst_invoice(200_000, "999293");
+        let note = CreditNotes {
+            id: None,
+            credit_note_number: "CN-1".to_string(),
+            student_id: earlier.student_id,
+            invoice_id: earlier.id,
+            refund_id: None,
+            // half of the earlier invoice
+            amount: Money::new(118_000, "INR"),
+            reason: "left".to_string(),
+            issued_by: "admin".to_string(),
+            created_at: bson::DateTime::now(),
+        };
+
+        let (rows, currency) = gst_rows(&[this_month], &[note], &[earlier]);
+        let row = rows["999293"];
+        assert_eq!(currency, "INR");
+        assert_eq!((row.0, row.6), (1, 1));
+        assert_eq!(row.1, 0);
+        assert_eq!((row.2, row.3, row.4), (0, 0, 0));
+        assert_eq!(row.5, 0);
+    }
+
+    // Runs against the database at MONGOURI in a throwaway database:
+    // cargo test -- --ignored
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGOURI"]
+    async fn signed_local_webhook_pays_the_invoice_once() {
+        let client = DBConfig::client().await.unwrap();
+        let db = client.database(&format!("k_admin_test_{}", ObjectId::new().to_hex()));
+        let repo = FinanceRepo::init(db.clone(), StudentRepo::init(db.clone())).await;
+        let gateway = LocalGateway::new("test-secret".to_string());
+
+        let number = format!("INV-TEST-{}", ObjectId::new().to_hex());
+        let (student_id, fee_id) = (ObjectId::new(), ObjectId::new());
+        let make_invoice = || Invoices {
+            id: None,
+            invoice_number: number.clone(),
+            student_id,
+            fee_id,
+            fee_type: "MONTHLY".to_string(),
+            course_id: None,
+            amount: Money::new(150_000, "INR"),
+            discount_amount: None,
+            taxable_amount: None,
+            tax_lines: Vec::new(),
+            hsn_sac: None,
+            gstin: None,
+            place_of_supply: None,
+            status: InvoiceStatus::PENDING.to_string(),
+            gateway_order_id: None,
+            gateway_order_ids: Vec::new(),
+            due_date: bson::DateTime::now(),
+            paid_at: None,
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        };
+        let invoice_id = repo.add_invoice(make_invoice()).await.unwrap().inserted_id.as_object_id().unwrap();
+        let invoice = Invoices { id: Some(invoice_id), ..make_invoice() };
+
+        // a second order replaces the first, a payment on the first one still matches
+        let first = gateway.create_order(&invoice).await.unwrap();
+        repo.set_gateway_order(invoice_id, first.order_id.clone()).await.unwrap();
+        let second = gateway.create_order(&invoice).await.unwrap();
+        repo.set_gateway_order(invoice_id, second.order_id.clone()).await.unwrap();
+
+        let (body, signature) = gateway.simulate_payment(&first.order_id).unwrap();
+        assert!(gateway.verify_signature(&body, &signature));
+        let payment = gateway.parse_webhook(&body).unwrap().unwrap();
+
+        assert!(apply_gateway_payment(&repo, gateway.name(), payment).await.unwrap());
+        let paid = repo.get_invoice(invoice_id, &BranchScope::All).await.unwrap();
+        assert_eq!(paid.status, InvoiceStatus::PAID.to_string());
+
+        // a replayed webhook changes nothing
+        let replay = gateway.parse_webhook(&body).unwrap().unwrap();
+        assert!(!apply_gateway_payment(&repo, gateway.name(), replay).await.unwrap());
+
+        // a payment recorded without its invoice update is finished by the replay
+        db.collection::<bson::Document>("invoices").update_one(
+            bson::doc! { "_id":invoice_id },
+            bson::doc! { "$set": { "status":InvoiceStatus::PENDING.to_string() } },
+            None
+        ).await.unwrap();
+        let replay = gateway.parse_webhook(&body).unwrap().unwrap();
+        assert!(apply_gateway_payment(&repo, gateway.name(), replay).await.unwrap());
+        let repaired = repo.get_invoice(invoice_id, &BranchScope::All).await.unwrap();
+        assert_eq!(repaired.status, InvoiceStatus::PAID.to_string());
+
+        db.drop(None).await.unwrap();
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGOURI"]
+    async fn concurrent_refunds_never_exceed_the_payment() {
+        let client = DBConfig::client().await.unwrap();
+        let db = client.database(&format!("k_admin_test_{}", ObjectId::new().to_hex()));
+        let repo = FinanceRepo::init(db.clone(), StudentRepo::init(db.clone())).await;
+
+        let (invoice_id, student_id) = (ObjectId::new(), ObjectId::new());
+        let gateway_payment_id = format!("pay_test_{}", ObjectId::new().to_hex());
+        repo.record_payment(Payments {
+            id: None,
+            invoice_id,
+            student_id,
+            amount: Money::new(100_000, "INR"),
+            gateway: "local".to_string(),
+            gateway_order_id: "order_test".to_string(),
+            gateway_payment_id: gateway_payment_id.clone(),
+            refunded_minor: Some(0),
+            created_at: bson::DateTime::now(),
+        }).await.unwrap();
+        let payment:Payments = bson::from_document(db.collection::<bson::Document>("payments")
+            .find_one(bson::doc! { "gateway_payment_id":&gateway_payment_id }, None).await.unwrap().unwrap()).unwrap();
+
+        let make_refund = |minor:i64| Refunds {
+            id: None,
+            payment_id: payment.id.unwrap(),
+            invoice_id,
+            student_id,
+            amount: Money::new(minor, "INR"),
+            reason: "left the course".to_string(),
+            status: RefundStatus::REQUESTED.to_string(),
+            requested_by: "admin".to_string(),
+            approved_by: None,
+            approved_at: None,
+            credit_note_id: None,
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        };
+
+        // two requests for more than half the payment race, only one is held
+        let (first, second) = futures::join!(repo.add_refund(&payment, make_refund(60_000)), repo.add_refund(&payment, make_refund(60_000)));
+        assert!(first.is_ok() != second.is_ok());
+        let held = first.or(second).unwrap().inserted_id.as_object_id().unwrap();
+
+        // rejecting gives the amount back
+        assert!(repo.reject_refund(held, "admin").await.unwrap());
+        assert!(!repo.reject_refund(held, "admin").await.unwrap());
+        let refund_id = repo.add_refund(&payment, make_refund(60_000)).await.unwrap().inserted_id.as_object_id().unwrap();
+
+        let make_credit_note = || CreditNotes {
+            id: None,
+            credit_note_number: String::new(),
+            student_id,
+            invoice_id: Some(invoice_id),
+            refund_id: Some(refund_id),
+            amount: Money::new(60_000, "INR"),
+            reason: "left the course".to_string(),
+            issued_by: "admin".to_string(),
+            created_at: bson::DateTime::now(),
+        };
+        repo.approve_refund(&repo.get_refund(refund_id).await.unwrap(), "admin", make_credit_note()).await.unwrap();
+        assert!(repo.approve_refund(&repo.get_refund(refund_id).await.unwrap(), "admin", make_credit_note()).await.is_err());
+
+        // an approval that stopped before linking its credit note is finished with the same note
+        db.collection::<bson::Document>("refunds").update_one(
+            bson::doc! { "_id":refund_id },
+            bson::doc! { "$unset": { "credit_note_id":"" } },
+            None
+        ).await.unwrap();
+        repo.approve_refund(&repo.get_refund(refund_id).await.unwrap(), "admin", make_credit_note()).await.unwrap();
+        assert_eq!(repo.list_credit_notes(student_id, &BranchScope::All).await.unwrap().len(), 1);
+
+        db.drop(None).await.unwrap();
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGOURI"]
+    async fn invoice_numbers_come_from_the_counter_and_skip_taken_ones() {
+        let client = DBConfig::client().await.unwrap();
+        let db = client.database(&format!("k_admin_test_{}", ObjectId::new().to_hex()));
+        let repo = FinanceRepo::init(db.clone(), StudentRepo::init(db.clone())).await;
+        let student_id = ObjectId::new();
+        let month = chrono::Utc::now().format("%Y%m").to_string();
+
+        // an older invoice already holds the first number of the month
+        let legacy = Invoices { id: None, invoice_number: format!("INV-{}-000001", month), student_id, ..gst_invoice(100_000, "999293") };
+        repo.add_invoice(legacy).await.unwrap();
+
+        let make_invoice = || Invoices { id: None, invoice_number: String::new(), student_id, ..gst_invoice(100_000, "999293") };
+        let (first, second) = futures::join!(repo.add_invoice(make_invoice()), repo.add_invoice(make_invoice()));
+        first.unwrap();
+        second.unwrap();
+
+        let mut numbers:Vec<String> = repo.list_invoices(student_id, &BranchScope::All).await.unwrap()
+            .into_iter().map(|invoice| invoice.invoice_number).collect();
+        numbers.sort();
+        assert_eq!(numbers, vec![
+            format!("INV-{}-000001", month),
+            format!("INV-{}-000002", month),
+            format!("INV-{}-000003", month),
+        ]);
+
+        db.drop(None).await.unwrap();
+    }
+}
@@ -1,8 +1,11 @@
 use std::env;
+use actix_web::{HttpMessage, HttpRequest};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use dotenv::dotenv;
 
+use crate::{helper::app_errors::AppError, models::user_models::UserTypes, repo::tenant_repo::DEFAULT_TENANT};
+
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation, errors::Error};
 
 pub struct JwtService{
@@ -11,9 +14,42 @@ pub struct JwtService{
     expired_at:i64
 }
 
+// The caller as described by the token the auth middleware validated.
+pub struct AuthUser {
+    pub id:Option<String>,
+    pub name:String,
+    pub user_type:String,
+    // branch ids a SUBADMIN is assigned to
+    pub branches:Vec<String>,
+    // academy the token was issued by
+    pub tenant:String
+}
+
+impl AuthUser {
+    pub fn is_admin(&self) -> bool {
+        self.user_type == UserTypes::ADMIN.to_string() || self.is_super_admin()
+    }
+
+    // manages the academies of the deployment, only from the default academy
+    pub fn is_super_admin(&self) -> bool {
+        self.user_type == UserTypes::SUPERADMIN.to_string() && self.tenant == DEFAULT_TENANT
+    }
+
+    pub fn is_sub_admin(&self) -> bool {
+        self.user_type == UserTypes::SUBADMIN.to_string()
+    }
+
+    // academy users carry a user type, student logins do not
+    pub fn is_staff(&self) -> bool {
+        !self.user_type.is_empty()
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct TokenCliams<T>{
     user:Option<T>,
+    #[serde(default)]
+    tenant:String,
     iat:i64,
     exp:i64
 }
@@ -37,7 +73,7 @@ impl JwtService {
 
     }
 
-    pub fn GenerateToken<T>(user:&T) -> String
+    pub fn GenerateToken<T>(user:&T, tenant:&str) -> String
     where 
         T:serde::Serialize
     {
@@ -45,6 +81,7 @@ impl JwtService {
             
         let tokenCliams = TokenCliams{
             user: Some(user),
+            tenant: tenant.to_string(),
             iat: self_obj.issued_at,
             exp: self_obj.expired_at,
         };
@@ -62,4 +99,40 @@ impl JwtService {
         decode::<serde_json::Value>(&token, &DecodingKey::from_secret(self_obj.secret_key.as_ref()), &Validation::default())
     }
 
-}
+    // the caller if it is an ADMIN user, for actions such as approving refunds
+    pub fn require_admin(req:&HttpRequest) -> Result<AuthUser, AppError> {
+        match Self::current_user(req) {
+            Some(user) if user.is_admin() => Ok(user),
+            _ => Err(AppError::CustomError("only an admin user can do this".to_string())),
+        }
+    }
+
+    pub fn require_super_admin(req:&HttpRequest) -> Result<AuthUser, AppError> {
+        match Self::current_user(req) {
+            Some(user) if user.is_super_admin() => Ok(user),
+            _ => Err(AppError::CustomError("only a super admin can do this".to_string())),
+        }
+    }
+
+    pub fn current_user(req:&HttpRequest) -> Option<AuthUser> {
+        let extensions = req.extensions();
+        let claims = extensions.get::<serde_json::Value>()?;
+        let user = claims.get("user")?;
+
+        // users carry a plain id, student tokens carry the raw ObjectId
+        let id = user.get("id").and_then(|id| id.as_str())
+            .or(user.pointer("/_id/$oid").and_then(|id| id.as_str()))
+            .map(|id| id.to_string());
+
+        Some(AuthUser {
+            id,
+            name: user.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string(),
+            user_type: user.get("user_type").and_then(|t| t.as_str()).unwrap_or_default().to_string(),
+            branches: user.get("branches").and_then(|b| b.as_array())
+                .map(|branches| branches.iter().filter_map(|b| b.as_str()).map(|b| b.to_string()).collect())
+                .unwrap_or_default(),
+            tenant: claims.get("tenant").and_then(|t| t.as_str()).filter(|t| !t.is_empty()).unwrap_or(DEFAULT_TENANT).to_string(),
+        })
+    }
+
+}
\ No newline at end of file
@@ -2,4 +2,12 @@ pub mod user_service;
 pub mod jwt_service;
 pub mod student_service;
 pub mod event_service;
-pub mod app_service;
\ No newline at end of file
+pub mod app_service;
+pub mod payment_gateway;
+pub mod finance_service;
+pub mod settings_service;
+pub mod report_service;
+pub mod curriculum_service;
+pub mod calendar_service;
+pub mod file_service;
+pub mod tenant_service;
\ No newline at end of file
@@ -0,0 +1,330 @@
+use std::{collections::HashMap, env, sync::{Arc, Mutex}};
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+
+use crate::{helper::{app_errors::AppError, crypto::Crypto}, models::{finance::Invoices, money::Money}};
+
+pub struct GatewayOrder {
+    pub order_id:String,
+    pub amount:Money,
+    // public values the client needs to open the provider checkout
+    pub checkout:Value
+}
+
+pub struct GatewayPayment {
+    pub order_id:String,
+    pub payment_id:String,
+    pub amount:Money
+}
+
+// Every payment provider sits behind this trait so the finance handlers never
+// talk to a provider API directly and a local fake can stand in without network.
+pub trait PaymentGateway: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn signature_header(&self) -> &'static str;
+
+    fn create_order<'a>(&'a self, invoice:&'a Invoices) -> BoxFuture<'a, Result<GatewayOrder, AppError>>;
+
+    fn verify_signature(&self, payload:&[u8], signature:&str) -> bool;
+
+    // the captured payment carried by a webhook body, None for events we do not act on
+    fn parse_webhook(&self, payload:&[u8]) -> Result<Option<GatewayPayment>, AppError>;
+
+    // the captured payment for an order if the provider has one, used by reconciliation
+    fn fetch_payment<'a>(&'a self, order_id:&'a str) -> BoxFuture<'a, Result<Option<GatewayPayment>, AppError>>;
+}
+
+// PAYMENT_GATEWAY=razorpay uses the Razorpay API. The local fake is only for
+// development, it needs PAYMENT_GATEWAY=local and DEV_MODE=true. Webhooks are
+// not authenticated other than by their signature, so a missing secret is an error.
+pub fn init_gateway() -> Result<Arc<dyn PaymentGateway>, AppError> {
+    match env::var("PAYMENT_GATEWAY").unwrap_or_default().as_str() {
+        "razorpay" => Ok(Arc::new(RazorpayGateway::init()?)),
+        "local" if env::var("DEV_MODE").as_deref() == Ok("true") => {
+            Ok(Arc::new(LocalGateway::new(required_secret("LOCAL_GATEWAY_SECRET")?)))
+        },
+        "local" => Err(AppError::CustomError("the local payment gateway needs DEV_MODE=true".to_string())),
+        _ => Err(AppError::CustomError("set PAYMENT_GATEWAY to razorpay, or to local with DEV_MODE=true".to_string())),
+    }
+}
+
+fn required_secret(name:&str) -> Result<String, AppError> {
+    env::var(name).ok()
+        .filter(|secret| !secret.trim().is_empty())
+        .ok_or_else(|| AppError::CustomError(format!("{} is not set", name)))
+}
+
+fn verify_hmac_signature(secret:&str, payload:&[u8], signature:&str) -> bool {
+    // an empty key would let anyone sign
+    if secret.is_empty() {
+        return false;
+    }
+    let expected = Crypto::hmac_sha256_hex(secret.as_bytes(), payload);
+    Crypto::constant_time_eq(expected.as_bytes(), signature.trim().to_lowercase().as_bytes())
+}
+
+// payment entity as Razorpay (and the local fake) report it
+fn parse_payment_entity(entity:&Value) -> Option<GatewayPayment> {
+    if entity.get("status")?.as_str()? != "captured" {
+        return None;
+    }
+
+    Some(GatewayPayment {
+        order_id: entity.get("order_id")?.as_str()?.to_string(),
+        payment_id: entity.get("id")?.as_str()?.to_string(),
+        amount: Money::new(
+            entity.get("amount")?.as_i64()?,
+            entity.get("currency")?.as_str()?
+        ),
+    })
+}
+
+fn parse_webhook_body(payload:&[u8]) -> Result<Option<GatewayPayment>, AppError> {
+    let body:Value = serde_json::from_slice(payload).map_err(|_| AppError::DeserializationError)?;
+
+    match body.get("event").and_then(|e| e.as_str()) {
+        Some("payment.captured") | Some("order.paid") => {
+            let entity = body.pointer("/payload/payment/entity").ok_or(AppError::DeserializationError)?;
+            Ok(parse_payment_entity(entity))
+        },
+        _ => Ok(None),
+    }
+}
+
+pub struct RazorpayGateway {
+    key_id:String,
+    key_secret:String,
+    webhook_secret:String,
+    base_url:String,
+    client:reqwest::Client
+}
+
+impl RazorpayGateway {
+    pub fn init() -> Result<Self, AppError> {
+        Ok(RazorpayGateway {
+            key_id: required_secret("RAZORPAY_KEY_ID")?,
+            key_secret: required_secret("RAZORPAY_KEY_SECRET")?,
+            webhook_secret: required_secret("RAZORPAY_WEBHOOK_SECRET")?,
+            base_url: env::var("RAZORPAY_BASE_URL").unwrap_or("https://api.razorpay.com/v1".to_string()),
+            client: reqwest::Client::new(),
+        })
+    }
+
+    async fn request(&self, request:reqwest::RequestBuilder) -> Result<Value, AppError> {
+        let response = request
+            .basic_auth(&self.key_id, Some(&self.key_secret))
+            .send()
+            .await
+            .map_err(|e| AppError::CustomError(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(AppError::CustomError(format!("payment gateway returned {}", response.status())));
+        }
+
+        response.json::<Value>().await.map_err(|_| AppError::DeserializationError)
+    }
+}
+
+impl PaymentGateway for RazorpayGateway {
+    fn name(&self) -> &'static str {
+        "razorpay"
+    }
+
+    fn signature_header(&self) -> &'static str {
+        "X-Razorpay-Signature"
+    }
+
+    fn create_order<'a>(&'a self, invoice:&'a Invoices) -> BoxFuture<'a, Result<GatewayOrder, AppError>> {
+        Box::pin(async move {
+            let body = json!({
+                "amount": invoice.amount.amount_minor,
+                "currency": invoice.amount.currency,
+                "receipt": invoice.invoice_number,
+                "notes": { "invoice_id": invoice.id.map(|id| id.to_hex()) }
+            });
+
+            let order = self.request(self.client.post(format!("{}/orders", self.base_url)).json(&body)).await?;
+            let order_id = order.get("id").and_then(|id| id.as_str()).ok_or(AppError::DeserializationError)?;
+
+            Ok(GatewayOrder {
+                order_id: order_id.to_string(),
+                amount: invoice.amount.clone(),
+                checkout: json!({ "key": self.key_id, "order_id": order_id }),
+            })
+        })
+    }
+
+    fn verify_signature(&self, payload:&[u8], signature:&str) -> bool {
+        verify_hmac_signature(&self.webhook_secret, payload, signature)
+    }
+
+    fn parse_webhook(&self, payload:&[u8]) -> Result<Option<GatewayPayment>, AppError> {
+        parse_webhook_body(payload)
+    }
+
+    fn fetch_payment<'a>(&'a self, order_id:&'a str) -> BoxFuture<'a, Result<Option<GatewayPayment>, AppError>> {
+        Box::pin(async move {
+            let payments = self.request(self.client.get(format!("{}/orders/{}/payments", self.base_url, order_id))).await?;
+
+            let captured = payments.get("items")
+                .and_then(|items| items.as_array())
+                .and_then(|items| items.iter().find_map(parse_payment_entity));
+
+            Ok(captured)
+        })
+    }
+}
+
+// In-memory gateway for development and tests, no network access.
+pub struct LocalGateway {
+    secret:String,
+    orders:Mutex<HashMap<String, (Money, Option<String>)>>
+}
+
+impl LocalGateway {
+    pub fn new(secret:String) -> Self {
+        LocalGateway { secret, orders: Mutex::new(HashMap::new()) }
+    }
+
+    // mark an order captured and return the signed webhook body a provider would send
+    pub fn simulate_payment(&self, order_id:&str) -> Result<(Vec<u8>, String), AppError> {
+        let mut orders = self.orders.lock().unwrap();
+        let (amount, payment_id) = orders.get_mut(order_id).ok_or(AppError::DataNotFoundError)?;
+        let payment_id = payment_id.get_or_insert(format!("pay_local_{}", uuid::Uuid::new_v4().simple())).clone();
+
+        let body = json!({
+            "event": "payment.captured",
+            "payload": { "payment": { "entity": {
+                "id": payment_id,
+                "order_id": order_id,
+                "amount": amount.amount_minor,
+                "currency": amount.currency,
+                "status": "captured"
+            }}}
+        }).to_string().into_bytes();
+
+        let signature = Crypto::hmac_sha256_hex(self.secret.as_bytes(), &body);
+        Ok((body, signature))
+    }
+}
+
+impl PaymentGateway for LocalGateway {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn signature_header(&self) -> &'static str {
+        "X-Local-Signature"
+    }
+
+    fn create_order<'a>(&'a self, invoice:&'a Invoices) -> BoxFuture<'a, Result<GatewayOrder, AppError>> {
+        Box::pin(async move {
+            let order_id = format!("order_local_{}", uuid::Uuid::new_v4().simple());
+            self.orders.lock().unwrap().insert(order_id.clone(), (invoice.amount.clone(), None));
+
+            Ok(GatewayOrder {
+                order_id: order_id.clone(),
+                amount: invoice.amount.clone(),
+                checkout: json!({ "order_id": order_id }),
+            })
+        })
+    }
+
+    fn verify_signature(&self, payload:&[u8], signature:&str) -> bool {
+        verify_hmac_signature(&self.secret, payload, signature)
+    }
+
+    fn parse_webhook(&self, payload:&[u8]) -> Result<Option<GatewayPayment>, AppError> {
+        parse_webhook_body(payload)
+    }
+
+    fn fetch_payment<'a>(&'a self, order_id:&'a str) -> BoxFuture<'a, Result<Option<GatewayPayment>, AppError>> {
+        Box::pin(async move {
+            let orders = self.orders.lock().unwrap();
+            Ok(orders.get(order_id).and_then(|(amount, payment_id)| {
+                payment_id.as_ref().map(|payment_id| GatewayPayment {
+                    order_id: order_id.to_string(),
+                    payment_id: payment_id.to_string(),
+                    amount: amount.clone(),
+                })
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::oid::ObjectId;
+
+    use crate::models::{finance::{InvoiceStatus, Invoices}, money::Money};
+
+    use super::*;
+
+    fn invoice(amount_minor:i64) -> Invoices {
+        Invoices {
+            id: Some(ObjectId::new()),
+            invoice_number: "INV-TEST-1".to_string(),
+            student_id: ObjectId::new(),
+            fee_id: ObjectId::new(),
+            fee_type: "MONTHLY".to_string(),
+            course_id: None,
+            amount: Money::new(amount_minor, "INR"),
+            discount_amount: None,
+            taxable_amount: None,
+            tax_lines: Vec::new(),
+            hsn_sac: None,
+            gstin: None,
+            place_of_supply: None,
+            status: InvoiceStatus::PENDING.to_string(),
+            gateway_order_id: None,
+            gateway_order_ids: Vec::new(),
+            due_date: bson::DateTime::now(),
+            paid_at: None,
+            created_at: bson::DateTime::now(),
+            updated_at: bson::DateTime::now(),
+        }
+    }
+
+    #[test]
+    fn hmac_signature_matches_only_the_signed_payload() {
+        let signature = Crypto::hmac_sha256_hex(b"secret", b"payload");
+
+        assert!(verify_hmac_signature("secret", b"payload", &signature));
+        assert!(verify_hmac_signature("secret", b"payload", &format!(" {} ", signature.to_uppercase())));
+        assert!(!verify_hmac_signature("secret", b"payload!", &signature));
+        assert!(!verify_hmac_signature("other", b"payload", &signature));
+        assert!(!verify_hmac_signature("", b"payload", &Crypto::hmac_sha256_hex(b"", b"payload")));
+    }
+
+    #[actix_web::test]
+    async fn local_gateway_webhook_round_trip() {
+        let gateway = LocalGateway::new("test-secret".to_string());
+        let invoice = invoice(150_000);
+
+        let order = gateway.create_order(&invoice).await.unwrap();
+        assert!(gateway.fetch_payment(&order.order_id).await.unwrap().is_none());
+
+        let (body, signature) = gateway.simulate_payment(&order.order_id).unwrap();
+        assert!(gateway.verify_signature(&body, &signature));
+
+        let payment = gateway.parse_webhook(&body).unwrap().unwrap();
+        assert_eq!(payment.order_id, order.order_id);
+        assert_eq!(payment.amount, invoice.amount);
+
+        // reconciliation sees the same payment as the webhook
+        let fetched = gateway.fetch_payment(&order.order_id).await.unwrap().unwrap();
+        assert_eq!(fetched.payment_id, payment.payment_id);
+
+        let forged = String::from_utf8(body).unwrap().replace("150000", "1");
+        assert!(!gateway.verify_signature(forged.as_bytes(), &signature));
+    }
+
+    #[test]
+    fn webhook_events_other_than_a_capture_are_ignored() {
+        let body = br#"{"event":"payment.failed","payload":{"payment":{"entity":{"id":"pay_1","order_id":"order_1","amount":100,"currency":"INR","status":"failed"}}}}"#;
+        assert!(parse_webhook_body(body).unwrap().is_none());
+        assert!(parse_webhook_body(b"not json").is_err());
+    }
+}
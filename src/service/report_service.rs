@@ -0,0 +1,216 @@
+use actix_web::{web::Query, HttpResponse, Responder};
+use chrono::{Duration, NaiveDate};
+
+use crate::{dto::report_dto::{AgeingReportDTO, CollectionReportDTO, ConversionReportDTO, DiscountReportDTO, ReportQueryDTO, RevenueReportDTO}, helper::{app_errors::{AppError, Messages}, branch_scope::BranchScope, csv::CsvBuilder, response::ResponseBuilder}, repo::report_repo::{ReportFilter, ReportRepo}};
+use crate::helper::tenant::Tenant;
+
+// ------------------------------ COLLECTIONS ------------------------------------- //
+// group_by: day (default), month or branch
+pub async fn collections_report(db:Tenant<ReportRepo>, scope:BranchScope, query:Query<ReportQueryDTO>) -> impl Responder {
+    let filter = match report_filter(&query, scope) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let rows = match db.collections(&filter, query.group_by.as_deref().unwrap_or("day")).await {
+        Ok(rows) => rows.iter().map(CollectionReportDTO::init).collect::<Vec<_>>(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if query.is_csv() {
+        let mut csv = CsvBuilder::new(&["Period/Branch", "Currency", "Payments", "Collected", "Refunded", "Net"]);
+        for row in rows {
+            csv.add_row(vec![row.key, row.currency, row.payment_count.to_string(), row.collected, row.refunded, row.net]);
+        }
+        return csv.into_response("collections-report.csv");
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(rows)
+        )
+    )
+}
+
+// ------------------------------ DUES ------------------------------------- //
+// Pending invoices aged as of the `to` date, today when it is not given.
+pub async fn dues_ageing_report(db:Tenant<ReportRepo>, scope:BranchScope, query:Query<ReportQueryDTO>) -> impl Responder {
+    let filter = match report_filter(&query, scope) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    // the filter bound is the start of the day after `to`, age on the last moment of `to`
+    let as_of = filter.to
+        .map(|to| bson::DateTime::from_millis(to.timestamp_millis() - 1))
+        .unwrap_or(bson::DateTime::now());
+    let rows = match db.dues_ageing(&filter, as_of).await {
+        Ok(rows) => rows.iter().map(AgeingReportDTO::init).collect::<Vec<_>>(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if query.is_csv() {
+        let mut csv = CsvBuilder::new(&["Days Overdue", "Currency", "Invoices", "Outstanding"]);
+        for row in rows {
+            csv.add_row(vec![row.bucket, row.currency, row.invoice_count.to_string(), row.outstanding]);
+        }
+        return csv.into_response("dues-ageing-report.csv");
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(rows)
+        )
+    )
+}
+
+// ------------------------------ REVENUE ------------------------------------- //
+// group_by: course (default) or fee_type
+pub async fn revenue_report(db:Tenant<ReportRepo>, scope:BranchScope, query:Query<ReportQueryDTO>) -> impl Responder {
+    let filter = match report_filter(&query, scope) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let rows = match db.revenue(&filter, query.group_by.as_deref().unwrap_or("course")).await {
+        Ok(rows) => rows.iter().map(RevenueReportDTO::init).collect::<Vec<_>>(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if query.is_csv() {
+        let mut csv = CsvBuilder::new(&["Course/Fee Type", "Currency", "Invoices", "Invoiced", "Credited", "Net"]);
+        for row in rows {
+            csv.add_row(vec![row.key, row.currency, row.invoice_count.to_string(), row.invoiced, row.credited, row.net]);
+        }
+        return csv.into_response("revenue-report.csv");
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(rows)
+        )
+    )
+}
+
+pub async fn discount_report(db:Tenant<ReportRepo>, scope:BranchScope, query:Query<ReportQueryDTO>) -> impl Responder {
+    let filter = match report_filter(&query, scope) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let rows = match db.discounts(&filter).await {
+        Ok(rows) => rows.iter().map(DiscountReportDTO::init).collect::<Vec<_>>(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if query.is_csv() {
+        let mut csv = CsvBuilder::new(&["Fee Type", "Currency", "Invoices", "Discount"]);
+        for row in rows {
+            csv.add_row(vec![row.fee_type, row.currency, row.invoice_count.to_string(), row.discount]);
+        }
+        return csv.into_response("discount-report.csv");
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(rows)
+        )
+    )
+}
+
+// ------------------------------ ENQUIRIES ------------------------------------- //
+// Enquiries received in the period and the students registered from them.
+// group_by: source (default), branch or month
+pub async fn conversion_report(db:Tenant<ReportRepo>, scope:BranchScope, query:Query<ReportQueryDTO>) -> impl Responder {
+    let filter = match report_filter(&query, scope) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let rows = match db.conversions(&filter, query.group_by.as_deref().unwrap_or("source")).await {
+        Ok(rows) => rows.iter().map(ConversionReportDTO::init).collect::<Vec<_>>(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if query.is_csv() {
+        let mut csv = CsvBuilder::new(&["Source/Branch/Month", "Enquiries", "Converted", "Registered", "Lost", "Conversion %"]);
+        for row in rows {
+            csv.add_row(vec![row.key, row.enquiry_count.to_string(), row.converted.to_string(), row.registered.to_string(), row.lost.to_string(), row.conversion_rate]);
+        }
+        return csv.into_response("enquiry-conversion-report.csv");
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataFetchSuccess.to_string(),
+            Some(rows)
+        )
+    )
+}
+
+fn report_filter(query:&ReportQueryDTO, scope:BranchScope) -> Result<ReportFilter, AppError> {
+    let parse = |date:&str| NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| AppError::CustomError(format!("Invalid date {}, expected YYYY-MM-DD", date)));
+    let to_bson = |date:NaiveDate| bson::DateTime::from_millis(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis());
+
+    let from = query.from.as_deref().map(parse).transpose()?;
+    // the end date is inclusive, so the bound is the start of the next day
+    let to = query.to.as_deref().map(parse).transpose()?.map(|date| date + Duration::days(1));
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from >= to {
+            return Err(AppError::CustomError("from date should not be after to date".to_string()));
+        }
+    }
+
+    Ok(ReportFilter {
+        from: from.map(to_bson),
+        to: to.map(to_bson),
+        branch: query.branch.clone().filter(|branch| !branch.is_empty()),
+        scope,
+    })
+}
@@ -0,0 +1,244 @@
+use actix_multipart::Multipart;
+use actix_web::{web::Json, HttpRequest, HttpResponse, Responder};
+use validator::Validate;
+
+use crate::{dto::settings_dto::{SettingsDTO, UpdateAcademyProfileDTO, UpdateBeltsDTO, UpdateFeeCyclesDTO, UpdateLocaleDTO, UpdateRegistrationOptionsDTO, UpdateTaxSettingsDTO}, helper::{app_errors::{AppError, Messages}, response::ResponseBuilder, timezone, upload}, models::{money::is_iso_currency, settings::lookup_key}, repo::settings_repo::SettingsRepo};
+use crate::helper::tenant::Tenant;
+
+use super::jwt_service::JwtService;
+
+pub async fn get_settings(db:Tenant<SettingsRepo>) -> impl Responder {
+    match db.get_settings().await {
+        Ok(settings) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(SettingsDTO::init(settings))
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+pub async fn update_tax_settings(db:Tenant<SettingsRepo>, request:Json<UpdateTaxSettingsDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let gstin = request.into_inner().gstin.unwrap();
+    let state_code = gstin[..2].to_string();
+
+    match db.update_tax_settings(gstin, state_code).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// name and contact of the academy, each tenant keeps its own
+pub async fn update_academy_profile(db:Tenant<SettingsRepo>, request:Json<UpdateAcademyProfileDTO>) -> impl Responder {
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let request = request.into_inner();
+    match db.update_academy_profile(request.academy_name, request.contact_email, request.contact_phone).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+pub async fn update_locale(db:Tenant<SettingsRepo>, req:HttpRequest, request:Json<UpdateLocaleDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    if !is_iso_currency(&request.currency) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(format!("Invalid currency: {}", request.currency))
+        );
+    }
+
+    let zone = match timezone::parse_timezone(&request.timezone) {
+        Ok(zone) => zone,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    settings_updated(db.update_locale(request.currency.to_string(), zone.name().to_string()).await)
+}
+
+// replaces the logo, the old one is left to the file cleanup
+pub async fn upload_logo(db:Tenant<SettingsRepo>, req:HttpRequest, payload:Multipart) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let form = match upload::save_multipart(payload, &upload::ACADEMY_LOGO).await {
+        Ok(form) => form,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+    let logo = match form.files.first() {
+        Some(logo) => logo,
+        None => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse("file is required".to_string())
+            );
+        },
+    };
+
+    let result = db.set_logo(&logo.key, logo.variants.as_ref()).await;
+    if result.is_err() {
+        form.discard().await;
+    }
+    settings_updated(result)
+}
+
+// A belt students still hold can not be taken off the ladder.
+pub async fn update_belts(db:Tenant<SettingsRepo>, req:HttpRequest, request:Json<UpdateBeltsDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let belts = match request.validate().map_err(|e| AppError::CustomError(e.to_string())).and_then(|_| request.to_belts()) {
+        Ok(belts) => belts,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let in_use = match db.repos().student.levels_in_use().await {
+        Ok(levels) => levels,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+    if let Some(level) = in_use.iter().find(|level| !belts.iter().any(|b| lookup_key(&b.key) == lookup_key(level))) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(format!("students still hold the {} belt", level))
+        );
+    }
+
+    settings_updated(db.update_belts(&belts).await)
+}
+
+// A cycle with fees set up for it has to stay.
+pub async fn update_fee_cycles(db:Tenant<SettingsRepo>, req:HttpRequest, request:Json<UpdateFeeCyclesDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let fee_cycles = match request.validate().map_err(|e| AppError::CustomError(e.to_string())).and_then(|_| request.to_fee_cycles()) {
+        Ok(fee_cycles) => fee_cycles,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let in_use = match db.repos().app.fee_types_in_use().await {
+        Ok(fee_types) => fee_types,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+    if let Some(fee_type) = in_use.iter().find(|fee_type| !fee_cycles.iter().any(|c| lookup_key(&c.key) == lookup_key(fee_type))) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(format!("fees are still set up for {}", fee_type))
+        );
+    }
+
+    settings_updated(db.update_fee_cycles(&fee_cycles).await)
+}
+
+pub async fn update_registration_options(db:Tenant<SettingsRepo>, req:HttpRequest, request:Json<UpdateRegistrationOptionsDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    settings_updated(db.update_registration_options(&request.to_options()).await)
+}
+
+fn settings_updated<T>(result:Result<T, AppError>) -> HttpResponse {
+    match result {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(
+                    Messages::DataUpdateSuccess.to_string(),
+                    None
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
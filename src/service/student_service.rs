@@ -1,31 +1,41 @@
-use std::{path::PathBuf};
 extern crate hex;
-use actix_multipart::form::MultipartForm;
-use actix_web::{ web::{Data, Path ,Json}, HttpResponse, Responder};
+use actix_multipart::Multipart;
+use actix_web::{ web::{Path ,Json}, HttpResponse, Responder};
 use bson::oid::ObjectId;
 use validator::validate_email;
-extern crate sanitize_filename;
-use crate::{dto::student_dto::{CreateParentDTO, CreateStudentDTO, StudentsDTO, UploadProfileDTO}, helper::{self, app_errors::{AppError, Messages}, response::ResponseBuilder}, models::student_model::{Parents, Students}, repo::student_repo::StudentRepo};
+use crate::{dto::student_dto::{CreateParentDTO, CreateStudentDTO, StudentsDTO}, helper::{self, app_errors::{AppError, Messages}, branch_scope::BranchScope, response::ResponseBuilder, upload}, models::student_model::{Parents, Students}, repo::student_repo::StudentRepo};
+use crate::helper::tenant::Tenant;
 
 use super::jwt_service;
 
 #[allow(non_snake_case)]
-pub async fn add_student(db:Data<StudentRepo>, request:Json<CreateStudentDTO>) -> impl Responder {
+pub async fn add_student(db:Tenant<StudentRepo>, request:Json<CreateStudentDTO>) -> impl Responder {
     if request.name.is_empty() {
         return HttpResponse::BadRequest().json(
             ResponseBuilder::<()>::FailedResponse("Invalid request params".to_string())
         );
     };
 
-    if request.geneder.to_string().to_lowercase() != "male" && request.geneder.to_string().to_lowercase() != "female" {
-        return HttpResponse::BadRequest().json(
-            ResponseBuilder::<()>::FailedResponse("Invalid gender".to_string())
-        )
-    }
+    let settings = match db.settings().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    let level = match request.validate_registration(&settings) {
+        Ok(level) => level,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    };
 
-    let seq = helper::helper::Helper::generate_unique_number();
+    let seq = helper::unique_number::Helper::generate_unique_number();
 
-    let req_level = request.level.to_string();
     let mut class_branch = String::new();
     if !request.class_branch.is_none() {
         class_branch = request.class_branch.as_ref().unwrap().to_string();
@@ -42,8 +52,9 @@ pub async fn add_student(db:Data<StudentRepo>, request:Json<CreateStudentDTO>) -
         created_at: Some(bson::DateTime::now()),
         updated_at: Some(bson::DateTime::now()),
         profile_pic: None,
-        level:Some( req_level.to_lowercase()),
-        nationality: Some("INDIAN".to_string()),
+        profile_pic_variants: None,
+        level: Some(level),
+        nationality: Some(settings.registration().default_nationality),
         blood_group: Some(request.blood_group.to_string()),
         weight: Some(request.weight.into()),
         school_name: Some(request.school_name.to_string()),
@@ -51,6 +62,7 @@ pub async fn add_student(db:Data<StudentRepo>, request:Json<CreateStudentDTO>) -
         geneder: Some(request.geneder.to_string()),
         student_id: Some(seq.to_string()),
         registration_status: Some("PENDING".to_string()),
+        enquiry_id: None,
     };
 
     match db.add_student(student).await {
@@ -69,9 +81,9 @@ pub async fn add_student(db:Data<StudentRepo>, request:Json<CreateStudentDTO>) -
     }
 }
 
-pub async fn get_students(db:Data<StudentRepo>, path:Path<(i64, i64, String)>) -> impl Responder {
+pub async fn get_students(db:Tenant<StudentRepo>, scope:BranchScope, path:Path<(i64, i64, String)>) -> impl Responder {
     let (skip, limit,level) = path.into_inner();
-    match db.get_students(skip, limit, level).await {
+    match db.get_students(skip, limit, level, &scope).await {
         Ok(students) => {
             if students.len() == 0 {
                 return HttpResponse::NotFound().json(
@@ -100,8 +112,8 @@ pub async fn get_students(db:Data<StudentRepo>, path:Path<(i64, i64, String)>) -
     }
 }
 
-pub async fn total_students(db:Data<StudentRepo>) -> impl Responder {
-    let result = db.total_students().await;
+pub async fn total_students(db:Tenant<StudentRepo>, scope:BranchScope) -> impl Responder {
+    let result = db.total_students(&scope).await;
     HttpResponse::Ok().json(
         ResponseBuilder::SuccessResponse(
             format!("Total Count fetched"),
@@ -111,10 +123,10 @@ pub async fn total_students(db:Data<StudentRepo>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn get_student(db:Data<StudentRepo>, path:Path<String>) -> impl Responder {
+pub async fn get_student(db:Tenant<StudentRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
-            match db.get_student(objId).await {
+            match db.get_student(objId, &scope).await {
                 Ok(student) => {
                     HttpResponse::Ok().json(
                         ResponseBuilder::SuccessResponse(
@@ -139,10 +151,10 @@ pub async fn get_student(db:Data<StudentRepo>, path:Path<String>) -> impl Respon
 }
 
 #[allow(non_snake_case)]
-pub async fn delete_student(db:Data<StudentRepo>, path:Path<String>) -> impl Responder {
+pub async fn delete_student(db:Tenant<StudentRepo>, scope:BranchScope, path:Path<String>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objeId) => {
-            match db.delete_student(objeId).await {
+            match db.delete_student(objeId, &scope).await {
                 Ok(result) => {
                     if result.deleted_count == 0 {
                         return HttpResponse::BadRequest().json(
@@ -172,72 +184,70 @@ pub async fn delete_student(db:Data<StudentRepo>, path:Path<String>) -> impl Res
     }
 }
 
+// Part of the public registration flow, the route is open so there is no token
+// to scope by and any student can be reached.
 #[allow(non_snake_case)]
-pub async fn upload_profile(db:Data<StudentRepo>, path:Path<String> , payload:MultipartForm<UploadProfileDTO>) -> impl Responder {
-    
+pub async fn upload_profile(db:Tenant<StudentRepo>, path:Path<String> , payload:Multipart) -> impl Responder {
+    let scope = BranchScope::All;
+
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
-                    
-            let f_file_path = format!("/static/student/");
-        
-            let temp_file_path = payload.file.file.path();
-            let file_name: &str = payload
-                .file
-                .file_name
-                .as_ref()
-                .map(|m| m.as_ref())
-                .unwrap_or("null");
-        
-            let mut file_path = PathBuf::from(format!(".{}",f_file_path));
-            file_path.push(&sanitize_filename::sanitize(&file_name));
-            match std::fs::rename(temp_file_path, file_path.clone()) {
-                Ok(_) => {
-
-                    // save the old profile pic path if the user have
-                    let student = match delete_old_profile_pic(db.clone(), objId).await {
-                        Ok(s) => s,
-                        Err(e) =>{
-                            return HttpResponse::BadRequest().json(
-                                ResponseBuilder::<()>::FailedResponse(e.to_string())
-                            )
-                        },
-                    };
+            // keep the old profile pic path if the student has one
+            let student = match get_student_before_upload(&db, objId, &scope).await {
+                Ok(s) => s,
+                Err(e) =>{
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            };
 
-                    match db.update_profile_pic(format!("{}{}",f_file_path, file_name), objId).await {
-                        Ok(result) => {
-                            if result.matched_count == 0 {
-                                let _ = std::fs::remove_file(file_path);
-                                return HttpResponse::BadRequest().json(
-                                    ResponseBuilder::<()>::FailedResponse(Messages::DataUpdateFailed.to_string())
-                                )
-                            }
-
-                            // check if old profile pic there then remove old file
-                            if !student.profile_pic.is_none() {
-                                let res = std::fs::remove_file(format!(".{}",student.profile_pic.unwrap().to_string()));
-                                if res.is_err() {
-                                    println!("Error while deleting a old")
-                                }
-                            }
-
-                            HttpResponse::Ok().json(
-                                ResponseBuilder::<()>::SuccessResponse(
-                                    Messages::DataUpdateSuccess.to_string(),
-                                    None
-                                )
-                            )
-                        },
-                        Err(e) => {
-                            let _ = std::fs::remove_file(file_path);
-                            HttpResponse::InternalServerError().json(
-                                ResponseBuilder::<()>::FailedResponse(e.to_string())
-                            )
-                        },
+            let form = match upload::save_multipart(payload, &upload::PROFILE_PICTURE).await {
+                Ok(form) => form,
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
+                },
+            };
+
+            let (file_path, variants) = match form.files.first() {
+                Some(file) => (file.key.to_string(), file.variants.clone()),
+                None => {
+                    return HttpResponse::BadRequest().json(
+                        ResponseBuilder::<()>::FailedResponse("file is required".to_string())
+                    )
+                },
+            };
+
+            match db.update_profile_pic(file_path, variants, objId, &scope).await {
+                Ok(result) => {
+                    if result.matched_count == 0 {
+                        form.discard().await;
+                        return HttpResponse::BadRequest().json(
+                            ResponseBuilder::<()>::FailedResponse(Messages::DataUpdateFailed.to_string())
+                        )
+                    }
+
+                    // check if old profile pic there then remove old file
+                    if let Some(profile_pic) = student.profile_pic {
+                        upload::remove_upload(&profile_pic, student.profile_pic_variants.as_ref()).await;
                     }
+
+                    HttpResponse::Ok().json(
+                        ResponseBuilder::<()>::SuccessResponse(
+                            Messages::DataUpdateSuccess.to_string(),
+                            None
+                        )
+                    )
+                },
+                Err(e) => {
+                    form.discard().await;
+                    HttpResponse::InternalServerError().json(
+                        ResponseBuilder::<()>::FailedResponse(e.to_string())
+                    )
                 },
-                Err(e) => return HttpResponse::InternalServerError().json(e.to_string()),
             }
-        
         },
         Err(_) => {
             HttpResponse::BadRequest().json(
@@ -248,10 +258,10 @@ pub async fn upload_profile(db:Data<StudentRepo>, path:Path<String> , payload:Mu
 
 }
 
-// will check if the use have a profile already then delete old pic
+// the student before the upload, its old profile pic is removed once the new one is saved
 #[allow(non_snake_case)]
-pub async fn delete_old_profile_pic(db:Data<StudentRepo>,studentId:ObjectId) -> Result<Students, AppError> {
-    match db.get_student(studentId).await {
+pub async fn get_student_before_upload(db:&StudentRepo, studentId:ObjectId, scope:&BranchScope) -> Result<Students, AppError> {
+    match db.get_student(studentId, scope).await {
         Ok(student) => {
             Ok(student)
         },
@@ -260,7 +270,7 @@ pub async fn delete_old_profile_pic(db:Data<StudentRepo>,studentId:ObjectId) ->
 }
 
 #[allow(non_snake_case)]
-pub async fn add_parent(db:Data<StudentRepo>, request:Json<CreateParentDTO>) -> impl Responder {
+pub async fn add_parent(db:Tenant<StudentRepo>, scope:BranchScope, request:Json<CreateParentDTO>) -> impl Responder {
     if !request.email.is_none() {
         if !validate_email(request.email.as_ref().unwrap().to_string()) {
             return HttpResponse::BadRequest().json(
@@ -281,7 +291,7 @@ pub async fn add_parent(db:Data<StudentRepo>, request:Json<CreateParentDTO>) ->
                 updated_at: Some(bson::DateTime::now()),
             };
 
-            match db.add_parent(parent).await {
+            match db.add_parent(parent, &scope).await {
                 Ok(result) => {
                     if result.matched_count == 0 {
                         return HttpResponse::NotFound().json(
@@ -314,10 +324,10 @@ pub async fn add_parent(db:Data<StudentRepo>, request:Json<CreateParentDTO>) ->
 }
 
 #[allow(non_snake_case)]
-pub async fn update_student(db:Data<StudentRepo>, path:Path<String>, request:Json<CreateStudentDTO>) -> impl Responder {
+pub async fn update_student(db:Tenant<StudentRepo>, scope:BranchScope, path:Path<String>, request:Json<CreateStudentDTO>) -> impl Responder {
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objeId) => {
-            match db.update_student(objeId, request.into_inner()).await {
+            match db.update_student(objeId, request.into_inner(), &scope).await {
                 Ok(result) => {
                     if result.matched_count == 0 {
                         return HttpResponse::BadRequest().json(
@@ -349,8 +359,8 @@ pub async fn update_student(db:Data<StudentRepo>, path:Path<String>, request:Jso
 
 
 #[allow(non_snake_case)]
-pub async fn get_pending_registration(db:Data<StudentRepo>) -> impl Responder {
-    match db.pending_registration().await {
+pub async fn get_pending_registration(db:Tenant<StudentRepo>, scope:BranchScope) -> impl Responder {
+    match db.pending_registration(&scope).await {
         Ok(students) => {
             
             if students.len() == 0 {
@@ -381,7 +391,7 @@ pub async fn get_pending_registration(db:Data<StudentRepo>) -> impl Responder {
 } 
 
 #[allow(non_snake_case)]
-pub async fn studnent_login(db:Data<StudentRepo>, path:Path<String>) -> impl Responder {
+pub async fn studnent_login(db:Tenant<StudentRepo>, path:Path<String>) -> impl Responder {
     let studentId = path.into_inner();
 
     if studentId.is_empty() {
@@ -392,7 +402,7 @@ pub async fn studnent_login(db:Data<StudentRepo>, path:Path<String>) -> impl Res
 
     match db.student_login(studentId).await {
         Ok(student) => {
-            let access_token = jwt_service::JwtService::GenerateToken(&student);
+            let access_token = jwt_service::JwtService::GenerateToken(&student, db.slug());
             let studenDto = StudentsDTO::init(student, access_token);
 
             HttpResponse::Ok().json(
@@ -411,3 +421,89 @@ pub async fn studnent_login(db:Data<StudentRepo>, path:Path<String>) -> impl Res
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use actix_web::{test, web::Data, App};
+    use image::{ImageFormat, RgbImage};
+
+    use crate::{config::db_config::DBConfig, middleware::auth_middeleware::Authentication, repo::tenant_repo::{TenantRegistry, DEFAULT_TENANT}, router::student_routers::student_router};
+
+    use super::*;
+
+    fn student() -> Students {
+        Students {
+            id: None,
+            student_id: None,
+            name: "Test".to_string(),
+            age: 9,
+            date_of_birth: "2017-01-01".to_string(),
+            address: String::new(),
+            is_active_student: true,
+            profile_pic: None,
+            profile_pic_variants: None,
+            // a branch no token could be scoped to
+            class_branch: Some(ObjectId::new().to_hex()),
+            parent: None,
+            level: None,
+            nationality: None,
+            blood_group: None,
+            weight: None,
+            school_name: None,
+            addhar_number: None,
+            geneder: None,
+            registration_status: None,
+            enquiry_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    // Runs against the database at MONGOURI in a throwaway database:
+    // cargo test -- --ignored
+    #[actix_web::test]
+    #[ignore = "needs MongoDB at MONGOURI"]
+    #[allow(non_snake_case)]
+    async fn profile_pictures_upload_without_a_token() {
+        let client = DBConfig::client().await.unwrap();
+        let registry = TenantRegistry::init(client.clone()).await;
+        let db = client.database(&format!("k_admin_test_{}", ObjectId::new().to_hex()));
+        let repos = registry.open(DEFAULT_TENANT, db.clone()).await;
+        let studentId = repos.student.add_student(student()).await.unwrap().inserted_id.as_object_id().unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(registry))
+                .service(student_router())
+                .wrap(Authentication)
+        ).await;
+
+        let mut png = Vec::new();
+        RgbImage::new(4, 4).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+        let boundary = "k-admin-test";
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"me.png\"\r\nContent-Type: image/png\r\n\r\n",
+            boundary
+        ).into_bytes();
+        body.extend(png);
+        body.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+
+        let request = test::TestRequest::post()
+            .uri(&format!("/api/student/upload-profile/{}", studentId.to_hex()))
+            .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+            .set_payload(body)
+            .to_request();
+        let status = test::call_service(&app, request).await.status();
+
+        let saved = repos.student.get_student(studentId, &BranchScope::All).await.unwrap();
+        if let Some(profile_pic) = &saved.profile_pic {
+            upload::remove_upload(profile_pic, saved.profile_pic_variants.as_ref()).await;
+        }
+        db.drop(None).await.unwrap();
+
+        assert!(status.is_success(), "upload answered {}", status);
+        assert!(saved.profile_pic.is_some());
+    }
+}
@@ -0,0 +1,150 @@
+use actix_web::{web::{Data, Json, Path}, HttpRequest, HttpResponse, Responder};
+use bcrypt::{hash, DEFAULT_COST};
+use validator::Validate;
+
+use crate::{dto::tenant_dto::{CreateTenantDTO, GetTenantDTO, UpdateTenantDTO}, helper::{app_errors::Messages, response::ResponseBuilder}, models::{tenant::Tenants, user_models::{UserTypes, Users}}, repo::tenant_repo::{TenantRegistry, DEFAULT_TENANT}};
+
+use super::jwt_service::JwtService;
+
+// Registers an academy, opens its database and seeds its settings and first admin.
+pub async fn add_tenant(req:HttpRequest, registry:Data<TenantRegistry>, request:Json<CreateTenantDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_super_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    if request.slug == DEFAULT_TENANT {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse("tenant slug is already used".to_string())
+        );
+    }
+
+    let password = match hash(&request.admin.password, DEFAULT_COST) {
+        Ok(password) => password,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse("Somethink went's wrong".to_string())
+            );
+        },
+    };
+
+    let request = request.into_inner();
+    let mut tenant = Tenants {
+        id: None,
+        slug: request.slug.clone(),
+        name: request.name.clone(),
+        database: TenantRegistry::database_name(&request.slug),
+        is_active: true,
+        created_at: Some(bson::DateTime::now()),
+        updated_at: Some(bson::DateTime::now()),
+    };
+
+    match registry.add_tenant(tenant.clone()).await {
+        Ok(result) => tenant.id = result.inserted_id.as_object_id(),
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    }
+
+    let repos = match registry.repos(&tenant.slug).await {
+        Ok(repos) => repos,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            );
+        },
+    };
+
+    if let Err(e) = repos.settings.update_academy_profile(request.name, request.contact_email, request.contact_phone).await {
+        return HttpResponse::InternalServerError().json(
+            ResponseBuilder::<()>::FailedResponse(format!("academy created, settings failed {}", e))
+        );
+    }
+
+    let admin = Users {
+        id: None,
+        name: request.admin.name,
+        email: request.admin.email,
+        mobile_number: request.admin.mobile_number,
+        password,
+        user_type: UserTypes::ADMIN,
+        is_active: true,
+        branches: Vec::new(),
+        created_at: Some(bson::DateTime::now()),
+        updated_at: Some(bson::DateTime::now()),
+    };
+    if let Err(e) = repos.user.add_user(admin).await {
+        return HttpResponse::InternalServerError().json(
+            ResponseBuilder::<()>::FailedResponse(format!("academy created, admin user failed {}", e))
+        );
+    }
+
+    HttpResponse::Ok().json(
+        ResponseBuilder::SuccessResponse(
+            Messages::DataAddedSuccess.to_string(),
+            Some(GetTenantDTO::init(tenant))
+        )
+    )
+}
+
+pub async fn list_tenants(req:HttpRequest, registry:Data<TenantRegistry>) -> impl Responder {
+    if let Err(e) = JwtService::require_super_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    match registry.get_tenants().await {
+        Ok(tenants) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::SuccessResponse(
+                    Messages::DataFetchSuccess.to_string(),
+                    Some(tenants.into_iter().map(GetTenantDTO::init).collect::<Vec<_>>())
+                )
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
+
+// renames an academy or switches it off, an inactive academy answers 404
+pub async fn update_tenant(req:HttpRequest, registry:Data<TenantRegistry>, path:Path<String>, request:Json<UpdateTenantDTO>) -> impl Responder {
+    if let Err(e) = JwtService::require_super_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
+    let request = request.into_inner();
+    match registry.update_tenant(&path.into_inner(), request.name, request.is_active).await {
+        Ok(_) => {
+            HttpResponse::Ok().json(
+                ResponseBuilder::<()>::SuccessResponse(Messages::DataUpdateSuccess.to_string(), None)
+            )
+        },
+        Err(e) => {
+            HttpResponse::BadRequest().json(
+                ResponseBuilder::<()>::FailedResponse(e.to_string())
+            )
+        },
+    }
+}
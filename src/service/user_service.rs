@@ -1,16 +1,28 @@
 use bson::oid::ObjectId;
 
-use actix_web::{web::{Data, Json, Path}, HttpResponse, Responder};
+use actix_web::{web::{Json, Path}, HttpRequest, HttpResponse, Responder};
 use bcrypt::{hash,verify, DEFAULT_COST};
 use crate::dto::user_dto::*;
+use crate::{helper::tenant::Tenant, repo::tenant_repo::DEFAULT_TENANT};
 use validator::*;
 
-use crate::{helper::{app_errors::Messages, response::ResponseBuilder}, models::user_models::{UserTypes, Users}, repo::user_repo::UserRepo};
+use crate::{helper::{app_errors::{AppError, Messages}, response::ResponseBuilder}, models::user_models::{UserTypes, Users}, repo::user_repo::UserRepo};
 
 use super::jwt_service;
 
-pub async fn add_user(db:Data<UserRepo>, user:Json<Users>) -> impl Responder {
-    
+// Only a super admin can add another one, admins add the academy's other users.
+// The first super admin is created with `k_admin create-super-admin`.
+pub async fn add_user(req:HttpRequest, db:Tenant<UserRepo>, user:Json<Users>) -> impl Responder {
+    let allowed = match user.user_type {
+        UserTypes::SUPERADMIN => jwt_service::JwtService::require_super_admin(&req),
+        _ => jwt_service::JwtService::require_admin(&req),
+    };
+    if let Err(e) = allowed {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
     let req_password = user.password.to_string();
     if req_password.is_empty() {
         let respose = ResponseBuilder::<()>::FailedResponse("password can't be empty".to_string());
@@ -30,7 +42,20 @@ pub async fn add_user(db:Data<UserRepo>, user:Json<Users>) -> impl Responder {
         return HttpResponse::BadRequest().json(res);
     }
 
+    if user.branches.iter().any(|b| ObjectId::parse_str(b).is_err()) {
+        return HttpResponse::BadRequest().json(
+            ResponseBuilder::<()>::InValidIdResponse()
+        );
+    }
+
+    // super admins belong to the default academy, tenants can not make their own
+    if matches!(user.user_type, UserTypes::SUPERADMIN) && db.slug() != DEFAULT_TENANT {
+        let res = ResponseBuilder::<()>::FailedResponse("a super admin can only be added to the default academy".to_string());
+        return HttpResponse::BadRequest().json(res);
+    }
+
     let user_type = match user.user_type {
+        UserTypes::SUPERADMIN => UserTypes::SUPERADMIN,
         UserTypes::ADMIN => UserTypes::ADMIN,
         UserTypes::SUBADMIN => UserTypes::SUBADMIN,
         UserTypes::ENDUSER => UserTypes::ENDUSER
@@ -44,6 +69,7 @@ pub async fn add_user(db:Data<UserRepo>, user:Json<Users>) -> impl Responder {
         password:pass,
         user_type,
         is_active:user.is_active.to_owned(),
+        branches:user.branches.clone(),
         created_at:Some(bson::DateTime::now()),
         updated_at:Some(bson::DateTime::now())
     };
@@ -62,7 +88,7 @@ pub async fn add_user(db:Data<UserRepo>, user:Json<Users>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn get_users(db:Data<UserRepo>) -> impl Responder {
+pub async fn get_users(db:Tenant<UserRepo>) -> impl Responder {
     match db.get_users().await {
         Ok(users) =>{
             let mut userData:Vec<GetUserDTO> = Vec::new();
@@ -83,7 +109,14 @@ pub async fn get_users(db:Data<UserRepo>) -> impl Responder {
 }
 
 #[allow(non_snake_case)]
-pub async fn update_user(db:Data<UserRepo>, path:Path<String>, userData:Json<UpdateUserRequestDTO> ) -> impl Responder {
+pub async fn update_user(req:HttpRequest, db:Tenant<UserRepo>, path:Path<String>, userData:Json<UpdateUserRequestDTO> ) -> impl Responder {
+    // branches and is_active decide what a user can reach
+    if let Err(e) = jwt_service::JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
     match ObjectId::parse_str(path.into_inner()) {
         Ok(obj_id) => {
             if !validate_email(&userData.email) || userData.mobile_number.is_empty() {
@@ -92,6 +125,12 @@ pub async fn update_user(db:Data<UserRepo>, path:Path<String>, userData:Json<Upd
                 )
             }
 
+            if userData.branches.iter().flatten().any(|b| ObjectId::parse_str(b).is_err()) {
+                return HttpResponse::BadRequest().json(
+                    ResponseBuilder::<()>::InValidIdResponse()
+                )
+            }
+
             match db.update_user(obj_id, userData.into_inner()).await {
                 Ok(result) => {
                     println!("Match Count : {}", result.matched_count);
@@ -121,7 +160,13 @@ pub async fn update_user(db:Data<UserRepo>, path:Path<String>, userData:Json<Upd
 }
 
 #[allow(non_snake_case)]
-pub async fn delete_user(db:Data<UserRepo>, path:Path<String>) -> impl Responder {
+pub async fn delete_user(req:HttpRequest, db:Tenant<UserRepo>, path:Path<String>) -> impl Responder {
+    if let Err(e) = jwt_service::JwtService::require_admin(&req) {
+        return HttpResponse::Forbidden().json(
+            ResponseBuilder::<()>::FailedResponse(e.to_string())
+        );
+    }
+
     match ObjectId::parse_str(path.into_inner()) {
         Ok(objId) => {
             match db.delete_user(objId).await {
@@ -152,7 +197,7 @@ pub async fn delete_user(db:Data<UserRepo>, path:Path<String>) -> impl Responder
 }
 
 #[allow(non_snake_case)]
-pub async fn login(db:Data<UserRepo>, request:Json<LoginRequestDTO>) -> impl Responder {
+pub async fn login(db:Tenant<UserRepo>, request:Json<LoginRequestDTO>) -> impl Responder {
     
     if !validate_email(request.email.to_string()) || request.email.is_empty() || request.password.is_empty() {
         let res = ResponseBuilder::<()>::FailedResponse("Invalid request params".to_string());
@@ -179,10 +224,36 @@ pub async fn login(db:Data<UserRepo>, request:Json<LoginRequestDTO>) -> impl Res
     }
     
     let mut user_dto = GetUserDTO::init(user, String::new());
-    let access_token = jwt_service::JwtService::GenerateToken(&user_dto);
+    let access_token = jwt_service::JwtService::GenerateToken(&user_dto, db.slug());
 
     user_dto.access_token = Some(access_token);
 
     let res = ResponseBuilder::SuccessResponse(Messages::DataFetchSuccess.to_string(), Some(user_dto));
     HttpResponse::Ok().json(res)
+}
+
+// Creates a super admin on the default academy, run from the command line on a
+// fresh install since adding one over the API needs a super admin already.
+pub async fn create_super_admin(db:&UserRepo, name:&str, email:&str, password:&str) -> Result<(), AppError> {
+    if !validate_email(email) || password.is_empty() {
+        return Err(AppError::CustomError("a valid email and a password are required".to_string()));
+    }
+    if db.get_user_by_mail(email.to_string()).await.is_ok() {
+        return Err(AppError::CustomError(format!("a user with email {} already exists", email)));
+    }
+
+    let password = hash(password, DEFAULT_COST).map_err(|e| AppError::CustomError(e.to_string()))?;
+    let user = Users {
+        id: None,
+        name: name.to_string(),
+        email: email.to_string(),
+        mobile_number: String::new(),
+        password,
+        user_type: UserTypes::SUPERADMIN,
+        is_active: true,
+        branches: Vec::new(),
+        created_at: Some(bson::DateTime::now()),
+        updated_at: Some(bson::DateTime::now()),
+    };
+    db.add_user(user).await.map(|_| ())
 }
\ No newline at end of file